@@ -0,0 +1,64 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use db_tree::{
+    blake3_hasher::{blake3, Blake3Leaf},
+    leaf_index::LeafIndex,
+    merkle_tree::MerkleTree,
+    mock_db::MockDB,
+    traits::Leafable,
+};
+use intmax2_zkp::utils::poseidon_hash_out::PoseidonHashOut;
+
+const HEIGHT: usize = 32;
+
+fn setup<V: Leafable>(
+    empty_leaf_hash: <V::Hasher as db_tree::traits::TreeHasher>::HashOut,
+    leaf_hash: impl Fn(u32) -> <V::Hasher as db_tree::traits::TreeHasher>::HashOut,
+) -> (MockDB<V>, MerkleTree<V>) {
+    let mut mock_db = MockDB::<V>::new();
+    let mut merkle_tree = MerkleTree::new(&mut mock_db, HEIGHT, empty_leaf_hash);
+    for i in 0..1000 {
+        merkle_tree.update_leaf_unchecked(
+            &mut mock_db,
+            LeafIndex::new(i as u128, HEIGHT).unwrap(),
+            leaf_hash(i as u32),
+        );
+    }
+    (mock_db, merkle_tree)
+}
+
+// update_leaf on the same tree shape with each hasher
+fn bench_update_leaf_by_hasher(c: &mut Criterion) {
+    let mut group = c.benchmark_group("update_leaf_by_hasher");
+    group.bench_function(BenchmarkId::from_parameter("poseidon"), |b| {
+        let leaf_hash = 12345u32.hash();
+        b.iter_batched_ref(
+            || setup::<u32>(PoseidonHashOut::hash_inputs_u32(&[]), |i| i.hash()),
+            |(mock_db, merkle_tree)| {
+                merkle_tree.update_leaf_unchecked(
+                    mock_db,
+                    LeafIndex::new(500, HEIGHT).unwrap(),
+                    leaf_hash,
+                )
+            },
+            BatchSize::LargeInput,
+        );
+    });
+    group.bench_function(BenchmarkId::from_parameter("blake3"), |b| {
+        let leaf_hash = blake3(&12345u32.to_le_bytes());
+        b.iter_batched_ref(
+            || setup::<Blake3Leaf>([0; 32], |i| blake3(&i.to_le_bytes())),
+            |(mock_db, merkle_tree)| {
+                merkle_tree.update_leaf_unchecked(
+                    mock_db,
+                    LeafIndex::new(500, HEIGHT).unwrap(),
+                    leaf_hash,
+                )
+            },
+            BatchSize::LargeInput,
+        );
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_update_leaf_by_hasher);
+criterion_main!(benches);
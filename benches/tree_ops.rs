@@ -0,0 +1,170 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use db_tree::{
+    buffered_store::BufferedStore,
+    leaf_index::LeafIndex,
+    merkle_tree::MerkleTree,
+    mock_db::MockDB,
+    node_store::NodeStore,
+    ref_counted_db::RefCountedDB,
+    traits::{Leafable, TreeHasher},
+    workload::{Distribution, Workload, WorkloadConfig},
+};
+
+type Leaf = u32;
+type HashOut = <<Leaf as Leafable>::Hasher as TreeHasher>::HashOut;
+
+const HEIGHTS: [usize; 4] = [16, 24, 32, 40];
+const LEAVES: usize = 1000;
+const BATCH: usize = 100;
+
+// A tree of `LEAVES` leaves at seeded random positions, followed by one more
+// batch of `BATCH` inserts so that `old_root` is a past version.
+struct Fixture {
+    tree: MerkleTree<Leaf>,
+    db: MockDB<Leaf>,
+    old_root: HashOut,
+    batch: Vec<(LeafIndex, HashOut)>,
+    indices: Vec<LeafIndex>,
+}
+
+impl Fixture {
+    fn new(height: usize) -> Self {
+        let mut workload = Workload::new(WorkloadConfig {
+            seed: 42,
+            height,
+            distribution: Distribution::Uniform,
+            insert_weight: 1,
+            update_weight: 0,
+            prove_weight: 0,
+        });
+        let mut db = MockDB::new();
+        let mut tree = MerkleTree::new(&mut db, height, Leaf::empty_leaf().hash());
+        for op in workload.by_ref().take(LEAVES) {
+            op.apply(&mut tree, &mut db, |value| (value as u32).hash())
+                .unwrap();
+        }
+        let old_root = tree.get_root();
+        for op in workload.by_ref().take(BATCH) {
+            op.apply(&mut tree, &mut db, |value| (value as u32).hash())
+                .unwrap();
+        }
+        // a batch overwriting leaves of both versions
+        let indices = workload.inserted().to_vec();
+        let batch = indices
+            .iter()
+            .step_by(indices.len() / BATCH)
+            .map(|index| (*index, (index.index() as u32 ^ 1).hash()))
+            .collect();
+        Self {
+            tree,
+            db,
+            old_root,
+            batch,
+            indices,
+        }
+    }
+
+    // an index set in both versions
+    fn index(&self) -> LeafIndex {
+        self.indices[LEAVES / 2]
+    }
+}
+
+// Operations that go through the store, for the store made by `wrap` from a
+// copy of the fixture's nodes.
+fn bench_store<S: NodeStore<Leaf>>(c: &mut Criterion, backend: &str, wrap: fn(MockDB<Leaf>) -> S) {
+    let mut group = c.benchmark_group(backend);
+    for height in HEIGHTS {
+        let fixture = Fixture::new(height);
+        let leaf_hash = 12345u32.hash();
+        group.bench_with_input(
+            BenchmarkId::new("update_leaf", height),
+            &fixture,
+            |b, fixture| {
+                b.iter_batched_ref(
+                    || (fixture.tree.clone(), wrap(fixture.db.clone())),
+                    |(tree, db)| tree.update_leaf_unchecked(db, fixture.index(), leaf_hash),
+                    BatchSize::LargeInput,
+                );
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("update_leaves_100", height),
+            &fixture,
+            |b, fixture| {
+                b.iter_batched_ref(
+                    || (fixture.tree.clone(), wrap(fixture.db.clone())),
+                    |(tree, db)| tree.update_leaves_unchecked(db, &fixture.batch),
+                    BatchSize::LargeInput,
+                );
+            },
+        );
+        let db = wrap(fixture.db.clone());
+        let root = fixture.tree.get_root();
+        group.bench_with_input(
+            BenchmarkId::new("prove_from_store", height),
+            &fixture,
+            |b, fixture| {
+                b.iter(|| {
+                    fixture
+                        .tree
+                        .prove_with_given_root(&db, root, fixture.index())
+                        .unwrap()
+                });
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("prove_historical", height),
+            &fixture,
+            |b, fixture| {
+                b.iter(|| {
+                    fixture
+                        .tree
+                        .prove_with_given_root(&db, fixture.old_root, fixture.index())
+                        .unwrap()
+                });
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bench_mock_db(c: &mut Criterion) {
+    bench_store(c, "mock_db", |db| db);
+}
+
+fn bench_ref_counted_db(c: &mut Criterion) {
+    bench_store(c, "ref_counted_db", RefCountedDB::new);
+}
+
+fn bench_buffered_store(c: &mut Criterion) {
+    bench_store(c, "buffered_store", |db| BufferedStore::new(db, 10_000));
+}
+
+// Proving from the in-memory tree and verifying do not touch a store.
+fn bench_in_memory(c: &mut Criterion) {
+    let mut group = c.benchmark_group("in_memory");
+    for height in HEIGHTS {
+        let fixture = Fixture::new(height);
+        let index = fixture.index();
+        let root = fixture.tree.get_root();
+        let leaf_hash = fixture.tree.get_node_hash_unchecked(index.to_node_key());
+        let proof = fixture.tree.prove_unchecked(index);
+        group.bench_with_input(BenchmarkId::new("prove", height), &fixture, |b, fixture| {
+            b.iter(|| fixture.tree.prove_unchecked(index));
+        });
+        group.bench_with_input(BenchmarkId::new("verify", height), &proof, |b, proof| {
+            b.iter(|| proof.verify_hash(leaf_hash, index, root).unwrap());
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_mock_db,
+    bench_ref_counted_db,
+    bench_buffered_store,
+    bench_in_memory
+);
+criterion_main!(benches);
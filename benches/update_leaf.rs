@@ -0,0 +1,63 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use db_tree::{leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable};
+use intmax2_zkp::utils::poseidon_hash_out::PoseidonHashOut;
+
+type Leaf = u32;
+
+fn setup(height: usize) -> (MockDB<Leaf>, MerkleTree<Leaf>) {
+    let mut mock_db = MockDB::<Leaf>::new();
+    let empty_leaf_hash = PoseidonHashOut::hash_inputs_u32(&[]);
+    let mut merkle_tree = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+    for i in 0..1000 {
+        let leaf = i as u32;
+        merkle_tree.update_leaf_unchecked(
+            &mut mock_db,
+            LeafIndex::new(i as u128, height).unwrap(),
+            leaf.hash(),
+        );
+    }
+    (mock_db, merkle_tree)
+}
+
+fn bench_update_leaf(c: &mut Criterion) {
+    let mut group = c.benchmark_group("update_leaf");
+    for height in [16, 32, 64] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(height),
+            &height,
+            |b, &height| {
+                let leaf_hash = 12345u32.hash();
+                b.iter_batched_ref(
+                    || setup(height),
+                    |(mock_db, merkle_tree)| {
+                        merkle_tree.update_leaf_unchecked(
+                            mock_db,
+                            LeafIndex::new(500, height).unwrap(),
+                            leaf_hash,
+                        )
+                    },
+                    BatchSize::LargeInput,
+                );
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bench_prove(c: &mut Criterion) {
+    let mut group = c.benchmark_group("prove");
+    for height in [16, 32, 64] {
+        let (_, merkle_tree) = setup(height);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(height),
+            &height,
+            |b, &height| {
+                b.iter(|| merkle_tree.prove_unchecked(LeafIndex::new(500, height).unwrap()));
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_update_leaf, bench_prove);
+criterion_main!(benches);
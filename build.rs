@@ -0,0 +1,5 @@
+fn main() {
+    // the gRPC server is generated from proto/db_tree.proto; needs `protoc`
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/db_tree.proto").expect("cannot compile db_tree.proto");
+}
@@ -0,0 +1,6 @@
+#![no_main]
+
+use intmax2_zkp::utils::poseidon_hash_out::PoseidonHashOut;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| db_tree::fuzzing::proof_bytes::<PoseidonHashOut>(data));
@@ -0,0 +1,120 @@
+use crate::{
+    batch_hasher::BatchHasher,
+    error::DbTreeError,
+    leaf_index::LeafIndex,
+    merkle_tree::MerkleTree,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+// Append-only use: leaves are written at `next_free_index`. Pushing the empty
+// leaf hash does not move `next_free_index`, so the next push reuses its index.
+impl<V: Leafable> MerkleTree<V> {
+    // Writes `leaf_hash` at the next free index and returns that index.
+    pub fn push<S: NodeStore<V>>(
+        &mut self,
+        db: &mut S,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Result<u128, DbTreeError> {
+        let index = self.append_index(1)?;
+        self.update_leaf(db, LeafIndex::new(index, self.height)?, leaf_hash)?;
+        Ok(index)
+    }
+
+    // Writes `leaf_hashes` at consecutive indices starting at the next free
+    // index as one batch update, and returns the first index. Nothing is
+    // written if they do not all fit.
+    pub fn push_many<S: NodeStore<V>>(
+        &mut self,
+        db: &mut S,
+        leaf_hashes: &[<V::Hasher as TreeHasher>::HashOut],
+    ) -> Result<u128, DbTreeError>
+    where
+        V::Hasher: BatchHasher,
+    {
+        let start = self.append_index(leaf_hashes.len() as u128)?;
+        let leaves = leaf_hashes
+            .iter()
+            .zip(start..)
+            .map(|(h, i)| Ok((LeafIndex::new(i, self.height)?, h.clone())))
+            .collect::<Result<Vec<_>, DbTreeError>>()?;
+        self.update_leaves(db, &leaves)?;
+        Ok(start)
+    }
+
+    // The next free index, if `count` leaves fit from there.
+    fn append_index(&self, count: u128) -> Result<u128, DbTreeError> {
+        let full = DbTreeError::TreeFull {
+            count,
+            height: self.height,
+        };
+        let start = match self.last_leaf {
+            None => 0,
+            Some(last) => last.checked_add(1).ok_or(full)?,
+        };
+        if count > 0 {
+            let last = start.checked_add(count - 1).ok_or(full)?;
+            LeafIndex::new(last, self.height).map_err(|_| full)?;
+        }
+        Ok(start)
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{error::DbTreeError, merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable};
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_push() {
+        let height = 3;
+        let mut mock_db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = Leaf::empty_leaf().hash();
+        let mut tree = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+        let mut expected = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+
+        assert_eq!(tree.push(&mut mock_db, 1u32.hash()).unwrap(), 0);
+        assert_eq!(tree.push(&mut mock_db, 2u32.hash()).unwrap(), 1);
+        let hashes: Vec<_> = (3..8u32).map(|i| i.hash()).collect();
+        assert_eq!(tree.push_many(&mut mock_db, &hashes).unwrap(), 2);
+        for i in 0..7u32 {
+            expected
+                .update_leaf_at(&mut mock_db, i as u64, (i + 1).hash())
+                .unwrap();
+        }
+        assert_eq!(tree.get_root(), expected.get_root());
+        assert_eq!(tree.next_free_index(), 7);
+
+        // two leaves do not fit into the last slot
+        assert_eq!(
+            tree.push_many(&mut mock_db, &hashes[..2]),
+            Err(DbTreeError::TreeFull { count: 2, height })
+        );
+        assert_eq!(tree.get_root(), expected.get_root());
+        assert_eq!(tree.push(&mut mock_db, 8u32.hash()).unwrap(), 7);
+        assert_eq!(
+            tree.push(&mut mock_db, 9u32.hash()),
+            Err(DbTreeError::TreeFull { count: 1, height })
+        );
+        assert_eq!(tree.push_many(&mut mock_db, &[]).unwrap(), 8);
+
+        // a compacted tree over a store without its nodes fails without
+        // changing anything
+        let mut tree = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+        tree.push_many(&mut mock_db, &hashes).unwrap();
+        tree.compact(&mock_db, 1).unwrap();
+        let root = tree.get_root();
+        let mut empty_db = MockDB::<Leaf>::new();
+        assert!(matches!(
+            tree.push(&mut empty_db, 8u32.hash()),
+            Err(DbTreeError::MissingNode { .. })
+        ));
+        assert!(matches!(
+            tree.push_many(&mut empty_db, &hashes[..2]),
+            Err(DbTreeError::MissingNode { .. })
+        ));
+        assert_eq!(tree.get_root(), root);
+        assert_eq!(tree.next_free_index(), 5);
+    }
+}
@@ -0,0 +1,265 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+};
+
+use hashbrown::HashSet;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    leaf_index::LeafIndex,
+    merkle_tree::{MerkleProof, MerkleTree},
+    mock_db::MockDB,
+    node::Node,
+    node_key::MAX_HEIGHT,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+    traversal::NodeWalk,
+};
+
+pub const ARCHIVE_FORMAT_VERSION: u32 = 1;
+
+// An archive is a one line JSON header followed by the JSON body. The header
+// carries the format version and the CRC32 of the body bytes.
+#[derive(Serialize, Deserialize)]
+struct ArchiveHeader {
+    format_version: u32,
+    checksum: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ArchiveBody<H> {
+    height: usize,
+    empty_leaf_hash: H,
+    root: H,
+    leaves: Vec<(Vec<bool>, H)>, // (little endian index bits, leaf hash)
+    nodes: Vec<(H, H, H)>,       // (hash, left, right)
+}
+
+type Reachable<H> = (Vec<(Vec<bool>, H)>, Vec<(H, H, H)>);
+
+// Walks every non-empty subtree below `root` and returns its non-empty leaves
+// (sorted by index bits) and its nodes.
+pub(crate) fn collect_reachable<V: Leafable, S: NodeStore<V>>(
+    db: &S,
+    zero_hashes: &[<V::Hasher as TreeHasher>::HashOut],
+    root: <V::Hasher as TreeHasher>::HashOut,
+) -> anyhow::Result<Reachable<<V::Hasher as TreeHasher>::HashOut>> {
+    let height = zero_hashes.len() - 1;
+    let mut leaves = vec![];
+    let mut nodes = vec![];
+    if height == 0 {
+        if root != zero_hashes[0] {
+            leaves.push((vec![], root));
+        }
+        return Ok((leaves, nodes));
+    }
+    let mut visited = HashSet::new();
+    for visit in NodeWalk::<V, S>::new(db, zero_hashes, root, false).all_positions() {
+        let visit = visit?;
+        if visit.key.depth() == height - 1 {
+            for (is_right, hash) in [(false, &visit.left), (true, &visit.right)] {
+                if *hash != zero_hashes[height] {
+                    let index = LeafIndex::from(visit.key.child(is_right));
+                    leaves.push((index.to_le_bits(), hash.clone()));
+                }
+            }
+        }
+        if visited.insert(visit.hash.clone()) {
+            nodes.push((visit.hash, visit.left, visit.right));
+        }
+    }
+    leaves.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok((leaves, nodes))
+}
+
+impl<V: Leafable> MerkleTree<V>
+where
+    <V::Hasher as TreeHasher>::HashOut: Serialize + DeserializeOwned,
+{
+    // Writes all leaves and nodes reachable from `root` as a portable archive
+    // that can be loaded with `ArchivedTree::load`.
+    pub fn export_archive<S: NodeStore<V>, W: Write>(
+        &self,
+        db: &S,
+        root: <V::Hasher as TreeHasher>::HashOut,
+        mut writer: W,
+    ) -> anyhow::Result<()> {
+        let (leaves, nodes) = collect_reachable(db, &self.zero_hashes, root.clone())?;
+        let body = ArchiveBody {
+            height: self.height,
+            empty_leaf_hash: self.zero_hashes[self.height].clone(),
+            root,
+            leaves,
+            nodes,
+        };
+        let body = serde_json::to_vec(&body)?;
+        let header = ArchiveHeader {
+            format_version: ARCHIVE_FORMAT_VERSION,
+            checksum: crc32fast::hash(&body),
+        };
+        writer.write_all(&serde_json::to_vec(&header)?)?;
+        writer.write_all(b"\n")?;
+        writer.write_all(&body)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+// Read-only tree reconstructed from an archive.
+#[derive(Clone, Debug)]
+pub struct ArchivedTree<V: Leafable> {
+    tree: MerkleTree<V>,
+    db: MockDB<V>,
+    root: <V::Hasher as TreeHasher>::HashOut,
+    leaves: HashMap<Vec<bool>, <V::Hasher as TreeHasher>::HashOut>,
+}
+
+impl<V: Leafable> ArchivedTree<V>
+where
+    <V::Hasher as TreeHasher>::HashOut: Serialize + DeserializeOwned,
+{
+    pub fn load<R: Read>(mut reader: R) -> anyhow::Result<Self> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        let split = bytes
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(|| anyhow::anyhow!("invalid archive: missing header"))?;
+        let header: ArchiveHeader = serde_json::from_slice(&bytes[..split])?;
+        anyhow::ensure!(
+            header.format_version == ARCHIVE_FORMAT_VERSION,
+            "unsupported archive format version {}",
+            header.format_version
+        );
+        let body = &bytes[split + 1..];
+        anyhow::ensure!(
+            crc32fast::hash(body) == header.checksum,
+            "invalid archive: checksum mismatch"
+        );
+        let body: ArchiveBody<<V::Hasher as TreeHasher>::HashOut> = serde_json::from_slice(body)?;
+        anyhow::ensure!(
+            body.height <= MAX_HEIGHT,
+            "invalid archive: height {} is larger than {}",
+            body.height,
+            MAX_HEIGHT
+        );
+
+        let mut db = MockDB::new();
+        let tree = MerkleTree::new(&mut db, body.height, body.empty_leaf_hash);
+        for (hash, left, right) in body.nodes {
+            anyhow::ensure!(
+                <V::Hasher as TreeHasher>::two_to_one(left.clone(), right.clone()) == hash,
+                "invalid archive: node hash mismatch"
+            );
+            db.insert(hash, Node { left, right });
+        }
+        let (leaves, _) = collect_reachable(&db, &tree.zero_hashes, body.root.clone())?;
+        anyhow::ensure!(
+            leaves == body.leaves,
+            "invalid archive: leaves do not match nodes"
+        );
+        Ok(Self {
+            tree,
+            db,
+            root: body.root,
+            leaves: leaves.into_iter().collect(),
+        })
+    }
+}
+
+impl<V: Leafable> ArchivedTree<V> {
+    pub fn root(&self) -> <V::Hasher as TreeHasher>::HashOut {
+        self.root.clone()
+    }
+
+    pub fn height(&self) -> usize {
+        self.tree.height()
+    }
+
+    // number of non-empty leaves
+    pub fn num_leaves(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn get_leaf_hash(&self, index: impl Into<LeafIndex>) -> <V::Hasher as TreeHasher>::HashOut {
+        let index = index.into();
+        assert_eq!(index.height(), self.height());
+        match self.leaves.get(&index.to_le_bits()) {
+            Some(h) => h.clone(),
+            None => self.tree.zero_hashes[self.height()].clone(),
+        }
+    }
+
+    pub fn prove(&self, index: impl Into<LeafIndex>) -> MerkleProof<V> {
+        // every reachable node was verified by `load`
+        self.tree
+            .prove_with_given_root(&self.db, self.root.clone(), index)
+            .expect("archive is missing a node")
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use intmax2_zkp::utils::poseidon_hash_out::PoseidonHashOut;
+
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable,
+    };
+
+    use super::ArchivedTree;
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_archive_roundtrip() {
+        let height = 16;
+
+        let mut mock_db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = PoseidonHashOut::hash_inputs_u32(&[]);
+        let mut merkle_tree = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+        for i in 0..10 {
+            let leaf = i as u32 + 1;
+            merkle_tree
+                .update_leaf(
+                    &mut mock_db,
+                    LeafIndex::new(i as u128, height).unwrap(),
+                    leaf.hash(),
+                )
+                .unwrap();
+        }
+        let root = merkle_tree.get_root();
+        // later updates are not part of the archive
+        merkle_tree
+            .update_leaf(
+                &mut mock_db,
+                LeafIndex::new(20, height).unwrap(),
+                20u32.hash(),
+            )
+            .unwrap();
+
+        let mut bytes = vec![];
+        merkle_tree
+            .export_archive(&mock_db, root, &mut bytes)
+            .unwrap();
+        let archived = ArchivedTree::<Leaf>::load(bytes.as_slice()).unwrap();
+        assert_eq!(archived.root(), root);
+        assert_eq!(archived.num_leaves(), 10);
+        assert_eq!(
+            archived.get_leaf_hash(LeafIndex::new(3, height).unwrap()),
+            4u32.hash()
+        );
+        assert_eq!(
+            archived.get_leaf_hash(LeafIndex::new(20, height).unwrap()),
+            empty_leaf_hash
+        );
+        let leaf_index = LeafIndex::new(3, height).unwrap();
+        let proof = archived.prove(leaf_index);
+        assert_eq!(proof.get_root(&4, leaf_index), root);
+
+        // corrupting the body is detected
+        let last = bytes.len() - 2;
+        bytes[last] ^= 1;
+        assert!(ArchivedTree::<Leaf>::load(bytes.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,209 @@
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+
+use crate::{
+    batch_hasher::BatchHasher,
+    error::DbTreeError,
+    leaf_index::LeafIndex,
+    merkle_tree::{MerkleProof, MerkleTree},
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+type Reply<T> = oneshot::Sender<Result<T, DbTreeError>>;
+
+enum Request<V: Leafable> {
+    UpdateLeaf {
+        index: LeafIndex,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        reply: Reply<<V::Hasher as TreeHasher>::HashOut>,
+    },
+    UpdateLeaves {
+        leaves: Vec<(LeafIndex, <V::Hasher as TreeHasher>::HashOut)>,
+        reply: Reply<<V::Hasher as TreeHasher>::HashOut>,
+    },
+    GetRoot {
+        reply: Reply<<V::Hasher as TreeHasher>::HashOut>,
+    },
+    Prove {
+        index: LeafIndex,
+        reply: Reply<(MerkleProof<V>, <V::Hasher as TreeHasher>::HashOut)>,
+    },
+}
+
+// Client handle of a tree owned by an actor. The actor runs on a blocking
+// thread of the tokio runtime, so hashing never stalls async tasks, and
+// handles requests one at a time in queue order. Handles are cheap to clone;
+// the actor stops once every handle is dropped and hands the tree and its
+// store back through the `JoinHandle` returned by `spawn`.
+pub struct AsyncMerkleTree<V: Leafable> {
+    requests: mpsc::Sender<Request<V>>,
+}
+
+impl<V: Leafable> Clone for AsyncMerkleTree<V> {
+    fn clone(&self) -> Self {
+        Self {
+            requests: self.requests.clone(),
+        }
+    }
+}
+
+impl<V> AsyncMerkleTree<V>
+where
+    V: Leafable + Send + 'static,
+    V::Hasher: BatchHasher,
+    <V::Hasher as TreeHasher>::HashOut: Send,
+{
+    // Must be called from within a tokio runtime. `queue_size` bounds the
+    // number of requests waiting for the actor; senders wait when it is full.
+    pub fn spawn<S: NodeStore<V> + Send + 'static>(
+        mut tree: MerkleTree<V>,
+        mut db: S,
+        queue_size: usize,
+    ) -> (Self, JoinHandle<(MerkleTree<V>, S)>) {
+        let (requests, mut queue) = mpsc::channel(queue_size);
+        let actor = tokio::task::spawn_blocking(move || {
+            while let Some(request) = queue.blocking_recv() {
+                handle(&mut tree, &mut db, request);
+            }
+            (tree, db)
+        });
+        (Self { requests }, actor)
+    }
+
+    // Returns the new root.
+    pub async fn update_leaf(
+        &self,
+        index: impl Into<LeafIndex>,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> anyhow::Result<<V::Hasher as TreeHasher>::HashOut> {
+        let index = index.into();
+        self.call(|reply| Request::UpdateLeaf {
+            index,
+            leaf_hash,
+            reply,
+        })
+        .await
+    }
+
+    // Applies the leaves in one batch and returns the new root.
+    pub async fn update_leaves(
+        &self,
+        leaves: Vec<(LeafIndex, <V::Hasher as TreeHasher>::HashOut)>,
+    ) -> anyhow::Result<<V::Hasher as TreeHasher>::HashOut> {
+        self.call(|reply| Request::UpdateLeaves { leaves, reply })
+            .await
+    }
+
+    pub async fn get_root(&self) -> anyhow::Result<<V::Hasher as TreeHasher>::HashOut> {
+        self.call(|reply| Request::GetRoot { reply }).await
+    }
+
+    // A proof of `index` together with the root it verifies against.
+    pub async fn prove(
+        &self,
+        index: impl Into<LeafIndex>,
+    ) -> anyhow::Result<(MerkleProof<V>, <V::Hasher as TreeHasher>::HashOut)> {
+        let index = index.into();
+        self.call(|reply| Request::Prove { index, reply }).await
+    }
+
+    // Fails if the actor has stopped (it panicked) or the tree rejected the
+    // request; tree errors are `DbTreeError`s.
+    async fn call<T>(&self, request: impl FnOnce(Reply<T>) -> Request<V>) -> anyhow::Result<T> {
+        let (reply, response) = oneshot::channel();
+        if self.requests.send(request(reply)).await.is_err() {
+            anyhow::bail!("merkle tree actor stopped");
+        }
+        let result = response
+            .await
+            .map_err(|_| anyhow::anyhow!("merkle tree actor stopped"))?;
+        Ok(result?)
+    }
+}
+
+// A dropped reply means the caller stopped waiting, which is not an error
+// for the actor.
+fn handle<V: Leafable, S: NodeStore<V>>(tree: &mut MerkleTree<V>, db: &mut S, request: Request<V>)
+where
+    V::Hasher: BatchHasher,
+{
+    match request {
+        Request::UpdateLeaf {
+            index,
+            leaf_hash,
+            reply,
+        } => {
+            let result = tree
+                .update_leaf(db, index, leaf_hash)
+                .map(|_| tree.get_root());
+            let _ = reply.send(result);
+        }
+        Request::UpdateLeaves { leaves, reply } => {
+            let result = tree.update_leaves(db, &leaves).map(|_| tree.get_root());
+            let _ = reply.send(result);
+        }
+        Request::GetRoot { reply } => {
+            let _ = reply.send(Ok(tree.get_root()));
+        }
+        Request::Prove { index, reply } => {
+            let result = tree.prove(index).map(|proof| (proof, tree.get_root()));
+            let _ = reply.send(result);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        error::DbTreeError, leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB,
+        traits::Leafable,
+    };
+
+    use super::AsyncMerkleTree;
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_async_tree() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let height = 8;
+            let mut db = MockDB::<Leaf>::new();
+            let tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+            let (client, actor) = AsyncMerkleTree::spawn(tree, db, 16);
+            let index = |i| LeafIndex::new(i, height).unwrap();
+
+            client.update_leaf(index(3), 7u32.hash()).await.unwrap();
+            let leaves = (10..20).map(|i| (index(i), (i as u32).hash())).collect();
+            let root = client.clone().update_leaves(leaves).await.unwrap();
+            assert_eq!(client.get_root().await.unwrap(), root);
+
+            let (proof, proven_root) = client.prove(index(3)).await.unwrap();
+            assert_eq!(proven_root, root);
+            proof.verify(&7u32, index(3), root).unwrap();
+
+            let err = client
+                .prove(LeafIndex::new(3, height + 1).unwrap())
+                .await
+                .unwrap_err();
+            assert_eq!(
+                err.downcast_ref::<DbTreeError>(),
+                Some(&DbTreeError::InvalidIndexLength {
+                    expected: height,
+                    actual: height + 1
+                })
+            );
+
+            drop(client);
+            let (tree, _db) = actor.await.unwrap();
+            assert_eq!(tree.get_root(), root);
+            assert_eq!(tree.len(), 11);
+        });
+    }
+}
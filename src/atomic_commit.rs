@@ -0,0 +1,560 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context as _;
+use hashbrown::HashMap;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    batch_hasher::BatchHasher,
+    compaction::{insert_live_nodes, CompactionStats},
+    durability::{Durability, SyncedFile},
+    error::CorruptNode,
+    leaf_index::LeafIndex,
+    merkle_tree::MerkleTree,
+    node::Node,
+    node_key::NodeKey,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+    wal::{append_record, read_records},
+};
+
+// Backends that can write the nodes of a batch together with the record of
+// the new root in one transaction, e.g. a RocksDB write batch or an SQL
+// transaction: after a crash either all of it is there or none of it is.
+pub trait TransactionalStore<V: Leafable>: NodeStore<V> {
+    // Writes `nodes` and records `root` as the committed root, all or
+    // nothing, and returns once both are durable.
+    fn commit_transaction(
+        &mut self,
+        nodes: Vec<(<V::Hasher as TreeHasher>::HashOut, Node<V>)>,
+        root: <V::Hasher as TreeHasher>::HashOut,
+    ) -> anyhow::Result<()>;
+
+    // Root of the last committed transaction.
+    fn committed_root(&self) -> Option<<V::Hasher as TreeHasher>::HashOut>;
+}
+
+// (index, old leaf hash, new leaf hash)
+type LeafChange<H> = (u128, H, H);
+
+impl<V: Leafable> MerkleTree<V> {
+    // `update_leaves` that commits the new nodes and the new root to `db` in
+    // one transaction and returns the new root once it is durable. The tree
+    // only moves to the new root, and subscribers only hear of the batch, if
+    // the transaction succeeds; otherwise the tree is left as it was and the
+    // error is returned. Nothing is written if an index is invalid.
+    pub fn commit_leaves<S: TransactionalStore<V>>(
+        &mut self,
+        db: &mut S,
+        leaves: &[(LeafIndex, <V::Hasher as TreeHasher>::HashOut)],
+    ) -> anyhow::Result<<V::Hasher as TreeHasher>::HashOut>
+    where
+        V::Hasher: BatchHasher,
+    {
+        let keys = self.check_leaf_indices(leaves)?;
+        self.check_update_paths(&*db, keys)?;
+        // the leaf changes in batch order, and the cached hashes the batch
+        // overwrites, to undo it if the transaction fails
+        let mut current = HashMap::new();
+        let mut changes = vec![];
+        let mut undo = HashMap::new();
+        for (index, leaf_hash) in leaves {
+            let key = index.to_node_key();
+            let old = current
+                .get(&key)
+                .cloned()
+                .unwrap_or_else(|| self.get_node_hash_with_store_unchecked(&*db, key));
+            current.insert(key, leaf_hash.clone());
+            changes.push((key.index, old, leaf_hash.clone()));
+            let mut key = key;
+            loop {
+                undo.entry(key)
+                    .or_insert_with(|| self.node_hashes.get(&key).cloned());
+                if key.is_root() {
+                    break;
+                }
+                key = key.parent();
+            }
+        }
+        let (num_leaves, last_leaf) = (self.num_leaves, self.last_leaf);
+        let subscribers = std::mem::take(&mut self.subscribers);
+
+        let mut staged = StagedStore {
+            db: &*db,
+            nodes: HashMap::new(),
+        };
+        self.update_leaves_unchecked(&mut staged, leaves);
+        let nodes = staged.nodes.into_iter().collect();
+        let root = self.get_root();
+        self.subscribers = subscribers;
+        if let Err(e) = db.commit_transaction(nodes, root.clone()) {
+            self.rollback(undo, &changes, num_leaves, last_leaf);
+            return Err(e);
+        }
+        for (index, old, new) in changes {
+            if old != new {
+                db.remove_leaf_metadata_at(old.clone(), index);
+            }
+            self.subscribers.record(index, old, new);
+        }
+        self.subscribers.notify(root.clone());
+        Ok(root)
+    }
+
+    fn rollback(
+        &mut self,
+        undo: HashMap<NodeKey, Option<<V::Hasher as TreeHasher>::HashOut>>,
+        changes: &[LeafChange<<V::Hasher as TreeHasher>::HashOut>],
+        num_leaves: u128,
+        last_leaf: Option<u128>,
+    ) {
+        for (key, hash) in undo {
+            match hash {
+                Some(hash) => self.node_hashes.insert(key, hash),
+                None => self.node_hashes.remove(&key),
+            };
+        }
+        if let Some(reverse_index) = self.reverse_index.as_mut() {
+            for (index, old, new) in changes.iter().rev() {
+                reverse_index.update(*index, new.clone(), old.clone());
+            }
+        }
+        self.num_leaves = num_leaves;
+        self.last_leaf = last_leaf;
+        self.metrics.set_leaves(num_leaves);
+    }
+}
+
+// Reads from a store and keeps the nodes written to it, so that a batch can
+// be computed before anything reaches the store. Only node writes are
+// expected, the rest panics.
+struct StagedStore<'a, V: Leafable, S> {
+    db: &'a S,
+    nodes: HashMap<<V::Hasher as TreeHasher>::HashOut, Node<V>>,
+}
+
+impl<V: Leafable, S: NodeStore<V>> NodeStore<V> for StagedStore<'_, V, S> {
+    fn insert(&mut self, key: <V::Hasher as TreeHasher>::HashOut, node: Node<V>) {
+        self.nodes.insert(key, node);
+    }
+
+    fn get(&self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>> {
+        match self.nodes.get(&key) {
+            Some(node) => Some(node.clone()),
+            None => self.db.get(key),
+        }
+    }
+
+    fn remove(&mut self, _key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>> {
+        unreachable!("staged batches do not remove nodes")
+    }
+
+    fn insert_leaf_data(&mut self, _leaf_hash: <V::Hasher as TreeHasher>::HashOut, _data: Vec<u8>) {
+        unreachable!("staged batches do not write leaf data")
+    }
+
+    fn get_leaf_data(&self, leaf_hash: <V::Hasher as TreeHasher>::HashOut) -> Option<Vec<u8>> {
+        self.db.get_leaf_data(leaf_hash)
+    }
+
+    fn remove_leaf_data(
+        &mut self,
+        _leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Option<Vec<u8>> {
+        unreachable!("staged batches do not write leaf data")
+    }
+
+    fn insert_leaf_metadata(
+        &mut self,
+        _leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        _index: u128,
+        _metadata: Vec<u8>,
+    ) {
+        unreachable!("staged batches do not write leaf data")
+    }
+
+    fn get_leaf_metadata(
+        &self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+    ) -> Option<Vec<u8>> {
+        self.db.get_leaf_metadata(leaf_hash, index)
+    }
+
+    fn remove_leaf_metadata(&mut self, _leaf_hash: <V::Hasher as TreeHasher>::HashOut) {
+        unreachable!("staged batches do not write leaf data")
+    }
+
+    // `commit_leaves` clears the metadata of overwritten leaves once the
+    // transaction succeeds
+    fn remove_leaf_metadata_at(
+        &mut self,
+        _leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        _index: u128,
+    ) {
+    }
+
+    fn with_node<R>(
+        &self,
+        key: <V::Hasher as TreeHasher>::HashOut,
+        f: impl FnOnce(&Node<V>) -> R,
+    ) -> Option<R> {
+        match self.nodes.get(&key) {
+            Some(node) => Some(f(node)),
+            None => self.db.with_node(key, f),
+        }
+    }
+
+    fn try_with_node<R>(
+        &self,
+        key: <V::Hasher as TreeHasher>::HashOut,
+        f: impl FnOnce(&Node<V>) -> R,
+    ) -> Result<Option<R>, CorruptNode<<V::Hasher as TreeHasher>::HashOut>> {
+        match self.nodes.get(&key) {
+            Some(node) => Ok(Some(f(node))),
+            None => self.db.try_with_node(key, f),
+        }
+    }
+
+    fn insert_batch(&mut self, nodes: Vec<(<V::Hasher as TreeHasher>::HashOut, Node<V>)>) {
+        self.nodes.extend(nodes);
+    }
+}
+
+// One transaction in the journal: (hash, left, right) of each node, then
+// the root.
+#[derive(Serialize, Deserialize)]
+struct Transaction<H> {
+    nodes: Vec<(H, H, H)>,
+    root: H,
+}
+
+// A `TransactionalStore` over any `NodeStore`, made durable by a journal
+// file. Each transaction is one checksummed record, written before it is
+// applied to the inner store, so a crash during a commit loses the whole
+// transaction and never part of it. Reopening replays the journal into a
+// fresh inner store.
+//
+// Records are synced as set by `set_durability`, every one by default, in
+// which case a commit is durable once it returns. With a laxer policy a
+// crash of the machine can lose the last commits, but still only whole ones.
+//
+// Only transactions are journaled: plain `NodeStore` writes, leaf data
+// included, go to the inner store alone and do not survive a reopen. The
+// journal is never shortened; checkpoint the tree and start a new journal
+// to bound it.
+#[derive(Debug)]
+pub struct JournaledStore<V: Leafable, S> {
+    inner: S,
+    file: SyncedFile,
+    path: PathBuf,
+    root: Option<<V::Hasher as TreeHasher>::HashOut>,
+}
+
+impl<V: Leafable, S: NodeStore<V>> JournaledStore<V, S>
+where
+    <V::Hasher as TreeHasher>::HashOut: Serialize + DeserializeOwned,
+{
+    // Opens or creates the journal at `path` and replays its transactions
+    // into `inner`.
+    pub fn open<P: AsRef<Path>>(mut inner: S, path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("cannot open journal {}", path.display()))?;
+        let mut root = None;
+        for body in read_records(&mut file, &path)? {
+            let transaction: Transaction<<V::Hasher as TreeHasher>::HashOut> =
+                serde_json::from_slice(&body)?;
+            inner.insert_batch(
+                transaction
+                    .nodes
+                    .into_iter()
+                    .map(|(hash, left, right)| (hash, Node { left, right }))
+                    .collect(),
+            );
+            root = Some(transaction.root);
+        }
+        Ok(Self {
+            inner,
+            file: SyncedFile::new(file, Durability::default()),
+            path,
+            root,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn durability(&self) -> Durability {
+        self.file.durability()
+    }
+
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.file.set_durability(durability);
+    }
+
+    // Syncs every committed transaction to disk, whatever the policy.
+    pub fn sync(&mut self) -> anyhow::Result<()> {
+        Ok(self.file.sync()?)
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    // Drops the nodes that only superseded versions use: the nodes reachable
+    // from `roots` and the committed root go into `fresh`, an empty store,
+    // and into a new journal holding them as one transaction, and both are
+    // swapped in. The new journal is written next to the old one and renamed
+    // over it, so a crash during compaction leaves the old journal whole.
+    pub fn compact(
+        &mut self,
+        tree: &MerkleTree<V>,
+        roots: &[<V::Hasher as TreeHasher>::HashOut],
+        mut fresh: S,
+    ) -> anyhow::Result<CompactionStats> {
+        let mut roots = roots.to_vec();
+        roots.extend(self.root.clone());
+        let live = tree.live_nodes(&self.inner, &roots)?;
+        if let Some(root) = self.root.clone() {
+            let transaction = Transaction {
+                nodes: live
+                    .nodes
+                    .iter()
+                    .map(|(hash, node)| (hash.clone(), node.left.clone(), node.right.clone()))
+                    .collect(),
+                root,
+            };
+            let tmp_path = self.path.with_extension("tmp");
+            let mut file = File::create(&tmp_path)?;
+            append_record(&mut file, &serde_json::to_vec(&transaction)?)?;
+            file.sync_all()?;
+            std::fs::rename(&tmp_path, &self.path)?;
+            file.seek(SeekFrom::End(0))?;
+            self.file = SyncedFile::new(file, self.file.durability());
+        }
+        let stats = insert_live_nodes(&self.inner, &mut fresh, live);
+        self.inner = fresh;
+        Ok(stats)
+    }
+}
+
+impl<V: Leafable, S: NodeStore<V>> TransactionalStore<V> for JournaledStore<V, S>
+where
+    <V::Hasher as TreeHasher>::HashOut: Serialize + DeserializeOwned,
+{
+    fn commit_transaction(
+        &mut self,
+        nodes: Vec<(<V::Hasher as TreeHasher>::HashOut, Node<V>)>,
+        root: <V::Hasher as TreeHasher>::HashOut,
+    ) -> anyhow::Result<()> {
+        let transaction = Transaction {
+            nodes: nodes
+                .iter()
+                .map(|(hash, node)| (hash.clone(), node.left.clone(), node.right.clone()))
+                .collect(),
+            root: root.clone(),
+        };
+        let body = serde_json::to_vec(&transaction)?;
+        self.file.commit_with(|file| append_record(file, &body))?;
+        self.inner.insert_batch(nodes);
+        self.root = Some(root);
+        Ok(())
+    }
+
+    fn committed_root(&self) -> Option<<V::Hasher as TreeHasher>::HashOut> {
+        self.root.clone()
+    }
+}
+
+impl<V: Leafable, S: NodeStore<V>> NodeStore<V> for JournaledStore<V, S> {
+    fn insert(&mut self, key: <V::Hasher as TreeHasher>::HashOut, node: Node<V>) {
+        self.inner.insert(key, node)
+    }
+
+    fn get(&self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>> {
+        self.inner.get(key)
+    }
+
+    fn remove(&mut self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>> {
+        self.inner.remove(key)
+    }
+
+    fn contains(&self, key: <V::Hasher as TreeHasher>::HashOut) -> bool {
+        self.inner.contains(key)
+    }
+
+    fn insert_leaf_data(&mut self, leaf_hash: <V::Hasher as TreeHasher>::HashOut, data: Vec<u8>) {
+        self.inner.insert_leaf_data(leaf_hash, data)
+    }
+
+    fn get_leaf_data(&self, leaf_hash: <V::Hasher as TreeHasher>::HashOut) -> Option<Vec<u8>> {
+        self.inner.get_leaf_data(leaf_hash)
+    }
+
+    fn remove_leaf_data(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Option<Vec<u8>> {
+        self.inner.remove_leaf_data(leaf_hash)
+    }
+
+    fn insert_leaf_metadata(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+        metadata: Vec<u8>,
+    ) {
+        self.inner.insert_leaf_metadata(leaf_hash, index, metadata)
+    }
+
+    fn get_leaf_metadata(
+        &self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+    ) -> Option<Vec<u8>> {
+        self.inner.get_leaf_metadata(leaf_hash, index)
+    }
+
+    fn remove_leaf_metadata(&mut self, leaf_hash: <V::Hasher as TreeHasher>::HashOut) {
+        self.inner.remove_leaf_metadata(leaf_hash)
+    }
+
+    fn remove_leaf_metadata_at(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+    ) {
+        self.inner.remove_leaf_metadata_at(leaf_hash, index)
+    }
+
+    fn with_node<R>(
+        &self,
+        key: <V::Hasher as TreeHasher>::HashOut,
+        f: impl FnOnce(&Node<V>) -> R,
+    ) -> Option<R> {
+        self.inner.with_node(key, f)
+    }
+
+    fn try_with_node<R>(
+        &self,
+        key: <V::Hasher as TreeHasher>::HashOut,
+        f: impl FnOnce(&Node<V>) -> R,
+    ) -> Result<Option<R>, CorruptNode<<V::Hasher as TreeHasher>::HashOut>> {
+        self.inner.try_with_node(key, f)
+    }
+
+    fn num_nodes(&self) -> Option<usize> {
+        self.inner.num_nodes()
+    }
+
+    fn insert_batch(&mut self, nodes: Vec<(<V::Hasher as TreeHasher>::HashOut, Node<V>)>) {
+        self.inner.insert_batch(nodes)
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        faulty_store::{Fault, FaultRule, FaultyStore, StoreOp},
+        leaf_index::LeafIndex,
+        merkle_tree::MerkleTree,
+        mock_db::MockDB,
+        traits::Leafable,
+    };
+
+    use super::{append_record, JournaledStore, Transaction, TransactionalStore};
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_commit_leaves() {
+        let height = 8;
+        let dir = std::env::temp_dir().join(format!("db_tree_commit_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tree.journal");
+        let index = move |i: u128| LeafIndex::new(i, height).unwrap();
+
+        let journaled = JournaledStore::open(MockDB::<Leaf>::new(), &path).unwrap();
+        let mut db = FaultyStore::new(journaled);
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        let receiver = tree.subscribe();
+        let leaves: Vec<_> = (0..5)
+            .map(|i| (index(i * 3), (i as u32 + 1).hash()))
+            .collect();
+        let root = tree.commit_leaves(&mut db, &leaves).unwrap();
+        assert_eq!(root, tree.get_root());
+        assert_eq!(db.committed_root(), Some(root));
+        assert_eq!(receiver.try_iter().count(), 5);
+        let bad = [(LeafIndex::new(1, height + 1).unwrap(), 1u32.hash())];
+        assert!(tree.commit_leaves(&mut db, &bad).is_err());
+
+        // a failed transaction leaves the tree and the store as they were
+        let num_nodes = db.inner().inner().len();
+        let before = tree.clone();
+        let batch = [(index(0), 9u32.hash()), (index(200), 9u32.hash())];
+        db.inject(FaultRule::new(StoreOp::Commit, Fault::Fail).times(1));
+        assert!(tree.commit_leaves(&mut db, &batch).is_err());
+        assert_eq!(tree, before);
+        assert_eq!(tree.len(), 5);
+        assert_eq!(db.inner().inner().len(), num_nodes);
+        assert_eq!(db.committed_root(), Some(root));
+        assert_eq!(receiver.try_iter().count(), 0);
+
+        // a journal write that fails after writing a whole record, e.g. in
+        // the sync, leaves nothing to replay
+        let other = Transaction {
+            nodes: vec![],
+            root: 7u32.hash(),
+        };
+        let body = serde_json::to_vec(&other).unwrap();
+        let mut journaled = db.into_inner();
+        let len = journaled.file.metadata().unwrap().len();
+        assert!(journaled
+            .file
+            .commit_with(|file| {
+                append_record(file, &body)?;
+                anyhow::bail!("injected failure of the journal sync")
+            })
+            .is_err());
+        assert_eq!(journaled.file.metadata().unwrap().len(), len);
+        let reopened = JournaledStore::<Leaf, _>::open(MockDB::new(), &path).unwrap();
+        assert_eq!(reopened.committed_root(), Some(root));
+        let mut db = FaultyStore::new(journaled);
+
+        // the committed nodes survive a reopen
+        let root = tree.commit_leaves(&mut db, &batch).unwrap();
+        drop(db);
+        let db = JournaledStore::<Leaf, _>::open(MockDB::new(), &path).unwrap();
+        assert_eq!(db.committed_root(), Some(root));
+        let proof = tree.prove_with_given_root(&db, root, index(200)).unwrap();
+        proof.verify_hash(9u32.hash(), index(200), root).unwrap();
+
+        // compaction keeps the committed version, also across a reopen
+        let mut db = db;
+        let num_nodes = db.inner().len();
+        let stats = db.compact(&tree, &[], MockDB::new()).unwrap();
+        assert_eq!(stats.leaves, 6);
+        assert!(db.inner().len() < num_nodes);
+        drop(db);
+        let db = JournaledStore::<Leaf, _>::open(MockDB::new(), &path).unwrap();
+        assert_eq!(db.committed_root(), Some(root));
+        assert!(tree.verify_integrity(&db, root).is_ok());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
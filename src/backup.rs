@@ -0,0 +1,268 @@
+use std::io::{BufRead, Write};
+
+use hashbrown::HashSet;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    batch_hasher::BatchHasher,
+    dump::{hasher_id, read_records, write_records, DumpRecord},
+    error::DbTreeError,
+    merkle_tree::MerkleTree,
+    node::Node,
+    node_key::NodeKey,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+
+// An incremental backup takes a tree from `base_root` to `root`: the header,
+// then the records of a dump holding the nodes of `root` that `base_root`
+// does not have at the same position, and every leaf that differs between
+// the two, emptied leaves included. Backups are keyed by the pair of roots,
+// so a chain of them is applied in order onto a full dump of the first base.
+#[derive(Serialize, Deserialize)]
+struct BackupHeader<H> {
+    format_version: u32,
+    height: usize,
+    hasher_id: H,
+    base_root: H,
+    root: H,
+    num_nodes: usize,
+    num_leaves: usize,
+}
+
+impl<V: Leafable> MerkleTree<V>
+where
+    <V::Hasher as TreeHasher>::HashOut: Serialize + DeserializeOwned,
+{
+    // Writes the changes from the version `base_root` to the version `root`
+    // of this tree, both of which must be in `db`, with the serialized
+    // leaves `db` holds. The empty root as `base_root` gives a full backup.
+    pub fn export_backup<S: NodeStore<V>, W: Write>(
+        &self,
+        db: &S,
+        base_root: <V::Hasher as TreeHasher>::HashOut,
+        root: <V::Hasher as TreeHasher>::HashOut,
+        mut writer: W,
+    ) -> anyhow::Result<()> {
+        let mut nodes = vec![];
+        let mut leaves = vec![];
+        let mut written = HashSet::new();
+        let mut stack = vec![(NodeKey::root(), root.clone(), base_root.clone())];
+        while let Some((key, hash, base)) = stack.pop() {
+            if hash == base {
+                continue;
+            }
+            if key.depth() == self.height {
+                leaves.push((key.index, hash));
+                continue;
+            }
+            let node = self.node_or_zero(db, key.depth(), hash.clone())?;
+            let base_node = self.node_or_zero(db, key.depth(), base)?;
+            stack.push((key.child(false), node.left.clone(), base_node.left));
+            stack.push((key.child(true), node.right.clone(), base_node.right));
+            if hash != self.zero_hashes[key.depth()] && written.insert(hash.clone()) {
+                nodes.push((hash, node));
+            }
+        }
+        leaves.sort_by_key(|(index, _)| *index);
+
+        let header = BackupHeader {
+            format_version: BACKUP_FORMAT_VERSION,
+            height: self.height,
+            hasher_id: hasher_id::<V::Hasher>(),
+            base_root,
+            root,
+            num_nodes: nodes.len(),
+            num_leaves: leaves.len(),
+        };
+        serde_json::to_writer(&mut writer, &header)?;
+        writer.write_all(b"\n")?;
+        let records = nodes
+            .into_iter()
+            .map(|(hash, node)| DumpRecord::Node(hash, node.left, node.right))
+            .chain(leaves.into_iter().map(|(index, hash)| DumpRecord::Leaf {
+                index,
+                data: db.get_leaf_data(hash.clone()),
+                hash,
+            }));
+        write_records(&mut writer, records)
+    }
+
+    // Applies a backup written by `export_backup` whose base is the current
+    // root, moving the tree to the root of the backup. Fails without
+    // updating the tree on a backup of another tree or base, or one that
+    // does not check out; `db` may hold part of it then.
+    pub fn apply_backup<S: NodeStore<V>, R: BufRead>(
+        &mut self,
+        db: &mut S,
+        reader: R,
+    ) -> anyhow::Result<()>
+    where
+        V::Hasher: BatchHasher,
+    {
+        let mut lines = reader.lines();
+        let header = lines
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("invalid backup: missing header"))??;
+        let header: BackupHeader<<V::Hasher as TreeHasher>::HashOut> =
+            serde_json::from_str(&header)?;
+        anyhow::ensure!(
+            header.format_version <= BACKUP_FORMAT_VERSION,
+            "unsupported backup format version {}",
+            header.format_version
+        );
+        anyhow::ensure!(
+            header.height == self.height && header.hasher_id == hasher_id::<V::Hasher>(),
+            "invalid backup: made for another tree"
+        );
+        anyhow::ensure!(
+            header.base_root == self.get_root(),
+            "backup of {:?} does not apply to root {:?}",
+            header.base_root,
+            self.get_root()
+        );
+        let (nodes, leaves) = read_records(db, lines, self.height)?;
+        anyhow::ensure!(
+            nodes.len() == header.num_nodes && leaves.len() == header.num_leaves,
+            "invalid backup: expected {} nodes and {} leaves, got {} and {}",
+            header.num_nodes,
+            header.num_leaves,
+            nodes.len(),
+            leaves.len()
+        );
+
+        // compute the new version on a copy, so that a bad backup leaves the
+        // tree as it was
+        let mut tree = self.clone();
+        db.insert_batch(nodes);
+        tree.update_leaves(db, &leaves)?;
+        anyhow::ensure!(
+            tree.get_root() == header.root,
+            "invalid backup: leaves do not match the root"
+        );
+        let changes = leaves
+            .into_iter()
+            .map(|(index, hash)| {
+                let old = self.get_node_hash_with_store(&*db, index.to_node_key())?;
+                Ok((index.index(), old, hash))
+            })
+            .collect::<Result<Vec<_>, DbTreeError>>()?;
+        tree.subscribers = std::mem::take(&mut self.subscribers);
+        *self = tree;
+        for (index, old, new) in changes {
+            self.subscribers.record(index, old, new);
+        }
+        self.subscribers.notify(header.root);
+        Ok(())
+    }
+
+    // Restores a tree from a full dump and the chain of backups taken after
+    // it, oldest first.
+    pub fn restore_backups<S: NodeStore<V>, R: BufRead, B: BufRead>(
+        db: &mut S,
+        base: R,
+        backups: impl IntoIterator<Item = B>,
+    ) -> anyhow::Result<Self>
+    where
+        V::Hasher: BatchHasher,
+    {
+        let mut tree = Self::import_dump(db, base)?;
+        for (i, backup) in backups.into_iter().enumerate() {
+            tree.apply_backup(db, backup)
+                .map_err(|e| e.context(format!("cannot apply backup {}", i)))?;
+        }
+        Ok(tree)
+    }
+
+    // The node `hash` at `depth`, made up for empty subtrees whose nodes may
+    // not be in the store.
+    fn node_or_zero<S: NodeStore<V>>(
+        &self,
+        db: &S,
+        depth: usize,
+        hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> anyhow::Result<Node<V>> {
+        if hash == self.zero_hashes[depth] {
+            let zero = self.zero_hashes[depth + 1].clone();
+            return Ok(Node {
+                left: zero.clone(),
+                right: zero,
+            });
+        }
+        db.get(hash)
+            .ok_or_else(|| anyhow::anyhow!("cannot find node at depth {}", depth))
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, node_store::NodeStore,
+        traits::Leafable,
+    };
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_incremental_backups() {
+        let height = 12;
+        let mut db = MockDB::<Leaf>::new();
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        let index = move |i: u128| LeafIndex::new(i, height).unwrap();
+        for i in 0..20 {
+            tree.update_leaf(&mut db, index(i * 13), (i as u32 + 1).hash())
+                .unwrap();
+        }
+        let mut base = vec![];
+        tree.export_dump(&db, &mut base).unwrap();
+
+        let mut backups = vec![];
+        let mut backed_up = tree.get_root();
+        for round in 0..3u32 {
+            for i in 0..5 {
+                let leaf = round * 100 + i + 1;
+                tree.update_leaf(&mut db, index((round * 5 + i) as u128 * 7), leaf.hash())
+                    .unwrap();
+                db.insert_leaf_data(leaf.hash(), leaf.to_le_bytes().to_vec());
+            }
+            tree.update_leaf(&mut db, index(13), Leaf::empty_leaf().hash())
+                .unwrap();
+            let mut backup = vec![];
+            tree.export_backup(&db, backed_up, tree.get_root(), &mut backup)
+                .unwrap();
+            backed_up = tree.get_root();
+            backups.push(backup);
+        }
+        // a backup holds the changes only
+        let full: usize = std::str::from_utf8(&base).unwrap().lines().count();
+        let first = std::str::from_utf8(&backups[0]).unwrap().lines().count();
+        assert!(first < full);
+
+        let mut restored_db = MockDB::new();
+        let restored = MerkleTree::<Leaf>::restore_backups(
+            &mut restored_db,
+            base.as_slice(),
+            backups.iter().map(|b| b.as_slice()),
+        )
+        .unwrap();
+        assert_eq!(restored.get_root(), tree.get_root());
+        assert_eq!(restored.len(), tree.len());
+        assert_eq!(
+            restored_db.get_leaf_data(203u32.hash()),
+            Some(203u32.to_le_bytes().to_vec())
+        );
+        assert!(restored
+            .verify_integrity(&restored_db, tree.get_root())
+            .is_ok());
+
+        // backups apply in order only
+        let result = MerkleTree::<Leaf>::restore_backups(
+            &mut MockDB::new(),
+            base.as_slice(),
+            backups[1..].iter().map(|b| b.as_slice()),
+        );
+        assert!(result.is_err());
+    }
+}
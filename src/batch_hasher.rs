@@ -0,0 +1,55 @@
+#[cfg(feature = "zkp")]
+use intmax2_zkp::utils::leafable_hasher::PoseidonLeafableHasher;
+
+use crate::traits::TreeHasher;
+
+// Hashers that can hash many independent pairs at once. Bulk updates collect
+// all nodes of a level and hash them with a single `two_to_one_many` call, so
+// a hasher with a vectorized or batched implementation should override it.
+// Other hashers only need an empty impl to use the pairwise default.
+pub trait BatchHasher: TreeHasher {
+    // Returns `two_to_one(left, right)` for every pair, in order.
+    fn two_to_one_many(pairs: &[(Self::HashOut, Self::HashOut)]) -> Vec<Self::HashOut> {
+        pairs
+            .iter()
+            .map(|(left, right)| Self::two_to_one(left.clone(), right.clone()))
+            .collect()
+    }
+}
+
+#[cfg(feature = "zkp")]
+impl BatchHasher for PoseidonLeafableHasher {}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use intmax2_zkp::utils::{
+        leafable_hasher::PoseidonLeafableHasher, poseidon_hash_out::PoseidonHashOut,
+    };
+
+    use crate::{testkit, traits::TreeHasher};
+
+    use super::BatchHasher;
+
+    #[test]
+    fn test_two_to_one_many() {
+        let pairs: Vec<_> = (0..5)
+            .map(|i| {
+                (
+                    PoseidonHashOut::hash_inputs_u32(&[i]),
+                    PoseidonHashOut::hash_inputs_u32(&[i, 1]),
+                )
+            })
+            .collect();
+        let hashes = PoseidonLeafableHasher::two_to_one_many(&pairs);
+        assert_eq!(hashes.len(), pairs.len());
+        for ((left, right), h) in pairs.into_iter().zip(hashes) {
+            assert_eq!(PoseidonLeafableHasher::two_to_one(left, right), h);
+        }
+        assert!(PoseidonLeafableHasher::two_to_one_many(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_poseidon_conformance() {
+        testkit::check_hasher::<PoseidonLeafableHasher>(|i| PoseidonHashOut::hash_inputs_u64(&[i]));
+    }
+}
@@ -0,0 +1,110 @@
+use crate::{error::DbTreeError, leaf_index::LeafIndex};
+
+// Height limit of a `WideTree`, whose indices are `BigIndex`es.
+pub const MAX_WIDE_HEIGHT: usize = 256;
+
+// Index of a leaf in a tree of up to `MAX_WIDE_HEIGHT` levels, e.g. a sparse
+// tree keyed by 256-bit hashes, where `LeafIndex` stops at 128 bits. The
+// bytes are little endian, like the bits of `to_le_bits`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BigIndex {
+    height: u16,
+    bytes: [u8; 32],
+}
+
+impl BigIndex {
+    pub fn new(le_bytes: [u8; 32], height: usize) -> Result<Self, DbTreeError> {
+        let index = Self {
+            height: height as u16,
+            bytes: le_bytes,
+        };
+        if height > MAX_WIDE_HEIGHT || (height..MAX_WIDE_HEIGHT).any(|i| index.bit(i)) {
+            return Err(DbTreeError::BigIndexOutOfRange { height });
+        }
+        Ok(index)
+    }
+
+    // Same as `new` with the big endian bytes of a 256-bit key, e.g. a hash.
+    pub fn from_be_bytes(mut be_bytes: [u8; 32], height: usize) -> Result<Self, DbTreeError> {
+        be_bytes.reverse();
+        Self::new(be_bytes, height)
+    }
+
+    pub fn from_le_bits(index_bits: &[bool]) -> Result<Self, DbTreeError> {
+        let mut bytes = [0u8; 32];
+        if index_bits.len() > MAX_WIDE_HEIGHT {
+            return Err(DbTreeError::BigIndexOutOfRange {
+                height: index_bits.len(),
+            });
+        }
+        for (i, bit) in index_bits.iter().enumerate() {
+            bytes[i / 8] |= (*bit as u8) << (i % 8);
+        }
+        Self::new(bytes, index_bits.len())
+    }
+
+    pub fn height(&self) -> usize {
+        self.height as usize
+    }
+
+    pub fn to_le_bytes(&self) -> [u8; 32] {
+        self.bytes
+    }
+
+    // bit `i` of the index, little endian
+    pub fn bit(&self, i: usize) -> bool {
+        (self.bytes[i / 8] >> (i % 8)) & 1 == 1
+    }
+
+    pub fn to_le_bits(&self) -> Vec<bool> {
+        (0..self.height()).map(|i| self.bit(i)).collect()
+    }
+
+    // the low 128 bits of the index
+    pub fn low_u128(&self) -> u128 {
+        u128::from_le_bytes(self.bytes[..16].try_into().unwrap())
+    }
+
+    // The same index as a `LeafIndex`, if the height fits in one.
+    pub fn to_leaf_index(&self) -> Option<LeafIndex> {
+        LeafIndex::new(self.low_u128(), self.height()).ok()
+    }
+}
+
+impl From<LeafIndex> for BigIndex {
+    fn from(index: LeafIndex) -> Self {
+        let mut bytes = [0u8; 32];
+        bytes[..16].copy_from_slice(&index.index().to_le_bytes());
+        Self {
+            height: index.height() as u16,
+            bytes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{error::DbTreeError, leaf_index::LeafIndex};
+
+    use super::BigIndex;
+
+    #[test]
+    fn test_big_index() {
+        let index = BigIndex::from(LeafIndex::new(6, 4).unwrap());
+        assert_eq!(index.to_le_bits(), vec![false, true, true, false]);
+        assert_eq!(BigIndex::from_le_bits(&index.to_le_bits()), Ok(index));
+        assert_eq!(index.to_leaf_index(), LeafIndex::new(6, 4).ok());
+
+        let mut key = [0u8; 32];
+        key[0] = 0x80;
+        let index = BigIndex::from_be_bytes(key, 256).unwrap();
+        assert!(index.bit(255));
+        assert_eq!(index.low_u128(), 0);
+        assert_eq!(index.to_leaf_index(), None);
+        assert_eq!(
+            BigIndex::from_be_bytes(key, 255),
+            Err(DbTreeError::BigIndexOutOfRange { height: 255 })
+        );
+        assert!(BigIndex::new([0; 32], 257).is_err());
+    }
+}
@@ -0,0 +1,312 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context as _;
+use db_tree::{
+    batch_hasher::BatchHasher,
+    checkpoint_lock::WriterLock,
+    leaf_index::LeafIndex,
+    merkle_tree::{MerkleProof, MerkleTree},
+    mock_db::MockDB,
+    service::{hash_from_hex, hash_to_hex, WireHash},
+    traits::HashLeaf,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+const USAGE: &str = "usage:
+  db_tree create <dir> <height> [hasher]   create an empty tree in <dir>
+  db_tree insert <dir> <leaves-file>       set leaves, one `<index> <leaf hash>` per line
+  db_tree root <dir>                       print the root
+  db_tree prove <dir> <index> <proof-file> write a proof of the leaf at <index>
+  db_tree verify <proof-file>              check a proof written by `prove`
+  db_tree check <dir>                      recompute every node of the tree from its children
+
+hashes are 0x hex strings, indices are decimal or 0x hex
+hashers: poseidon, poseidon2 (zkp), keccak, sha256, blake3 (with the same features),
+  bn254-poseidon (circomlib compatible)";
+
+// A tree lives in a directory holding the hasher name and a checkpoint of
+// the tree and its nodes. Commands that write the checkpoint hold its
+// `WriterLock`, so concurrent inserts never lose updates, while reading
+// commands never wait.
+//
+// The checkpoint is the only backend: every command loads the whole tree
+// into memory, and choosing another store is not supported yet.
+const HASHER_FILE: &str = "hasher";
+const TREE_FILE: &str = "tree.json";
+
+type Tree<H> = MerkleTree<HashLeaf<H>>;
+type Store<H> = MockDB<HashLeaf<H>>;
+
+#[derive(Serialize, Deserialize)]
+struct ProofFile {
+    hasher: String,
+    index: String,
+    leaf_hash: String,
+    root: String,
+    siblings: Vec<String>,
+}
+
+// Calls `$f::<H>($args)` for the hasher named `$name`.
+macro_rules! with_hasher {
+    ($name:expr, $f:ident($($arg:expr),*)) => {
+        match $name {
+            #[cfg(feature = "zkp")]
+            "poseidon" => {
+                $f::<intmax2_zkp::utils::leafable_hasher::PoseidonLeafableHasher>($($arg),*)
+            }
+            #[cfg(feature = "zkp")]
+            "poseidon2" => $f::<db_tree::poseidon2_hasher::Poseidon2Hasher>($($arg),*),
+            "bn254-poseidon" => {
+                $f::<db_tree::bn254_poseidon_hasher::Bn254PoseidonHasher>($($arg),*)
+            }
+            #[cfg(feature = "keccak")]
+            "keccak" => $f::<db_tree::keccak_hasher::Keccak256Hasher>($($arg),*),
+            #[cfg(feature = "sha256")]
+            "sha256" => $f::<db_tree::sha256_hasher::Sha256Hasher>($($arg),*),
+            #[cfg(feature = "blake3")]
+            "blake3" => $f::<db_tree::blake3_hasher::Blake3Hasher>($($arg),*),
+            name => anyhow::bail!("unknown or disabled hasher {}", name),
+        }
+    };
+}
+
+fn parse_index(s: &str) -> anyhow::Result<u128> {
+    let index = match s.strip_prefix("0x") {
+        Some(hex) => u128::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    index.with_context(|| format!("invalid index {}", s))
+}
+
+fn parse_hash<H: WireHash>(s: &str) -> anyhow::Result<H> {
+    hash_from_hex(s).with_context(|| format!("invalid hash {}", s))
+}
+
+fn load<H>(dir: &Path) -> anyhow::Result<(Tree<H>, Store<H>)>
+where
+    H: BatchHasher,
+    H::HashOut: Serialize + DeserializeOwned,
+{
+    let mut db = MockDB::new();
+    let tree = MerkleTree::restore(&mut db, dir.join(TREE_FILE))?;
+    Ok((tree, db))
+}
+
+fn create<H>(dir: &Path, hasher: &str, height: usize) -> anyhow::Result<String>
+where
+    H: BatchHasher,
+    H::HashOut: Serialize + DeserializeOwned + WireHash,
+{
+    let mut db = MockDB::new();
+    let tree = Tree::<H>::try_new(&mut db, height, H::HashOut::default())?;
+    let _lock = WriterLock::acquire(dir.join(TREE_FILE))?;
+    anyhow::ensure!(
+        !dir.join(TREE_FILE).exists(),
+        "{} already holds a tree",
+        dir.display()
+    );
+    // the hasher goes first, so that a checkpoint is never left without it
+    fs::write(dir.join(HASHER_FILE), hasher)?;
+    tree.checkpoint(&db, dir.join(TREE_FILE))?;
+    Ok(hash_to_hex(&tree.get_root()))
+}
+
+fn insert<H>(dir: &Path, leaves_file: &Path) -> anyhow::Result<String>
+where
+    H: BatchHasher,
+    H::HashOut: Serialize + DeserializeOwned + WireHash,
+{
+    let _lock = WriterLock::acquire(dir.join(TREE_FILE))?;
+    let (mut tree, mut db) = load::<H>(dir)?;
+    let mut leaves = vec![];
+    for (i, line) in fs::read_to_string(leaves_file)?.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (index, hash) = line
+            .split_once(char::is_whitespace)
+            .with_context(|| format!("line {}: expected `<index> <leaf hash>`", i + 1))?;
+        let index = LeafIndex::new(parse_index(index)?, tree.height())?;
+        leaves.push((index, parse_hash(hash.trim())?));
+    }
+    tree.update_leaves(&mut db, &leaves)?;
+    tree.checkpoint(&db, dir.join(TREE_FILE))?;
+    Ok(hash_to_hex(&tree.get_root()))
+}
+
+fn root<H>(dir: &Path) -> anyhow::Result<String>
+where
+    H: BatchHasher,
+    H::HashOut: Serialize + DeserializeOwned + WireHash,
+{
+    let (tree, _) = load::<H>(dir)?;
+    Ok(hash_to_hex(&tree.get_root()))
+}
+
+fn check<H>(dir: &Path) -> anyhow::Result<String>
+where
+    H: BatchHasher,
+    H::HashOut: Serialize + DeserializeOwned + WireHash,
+{
+    let (tree, db) = load::<H>(dir)?;
+    let report = tree.verify_integrity(&db, tree.get_root());
+    let issues: Vec<String> = report.issues.iter().map(ToString::to_string).collect();
+    anyhow::ensure!(
+        issues.is_empty(),
+        "{} problems found:\n{}",
+        issues.len(),
+        issues.join("\n")
+    );
+    Ok(format!(
+        "ok: {} nodes, {} leaves",
+        report.nodes_checked, report.non_empty_leaves
+    ))
+}
+
+fn prove<H>(dir: &Path, hasher: &str, index: &str, proof_file: &Path) -> anyhow::Result<String>
+where
+    H: BatchHasher,
+    H::HashOut: Serialize + DeserializeOwned + WireHash,
+{
+    let (tree, db) = load::<H>(dir)?;
+    let index = LeafIndex::new(parse_index(index)?, tree.height())?;
+    let root = tree.get_root();
+    let proof = tree.prove_with_given_root(&db, root.clone(), index)?;
+    let leaf_hash = tree.get_node_hash_with_store(&db, index.to_node_key())?;
+    let proof_file_contents = ProofFile {
+        hasher: hasher.to_string(),
+        index: index.index().to_string(),
+        leaf_hash: hash_to_hex(&leaf_hash),
+        root: hash_to_hex(&root),
+        siblings: proof.siblings.iter().map(hash_to_hex).collect(),
+    };
+    fs::write(proof_file, serde_json::to_vec(&proof_file_contents)?)?;
+    Ok(hash_to_hex(&leaf_hash))
+}
+
+fn verify<H>(proof: &ProofFile) -> anyhow::Result<String>
+where
+    H: BatchHasher,
+    H::HashOut: WireHash,
+{
+    let siblings = proof
+        .siblings
+        .iter()
+        .map(|s| parse_hash(s))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let index = LeafIndex::new(parse_index(&proof.index)?, siblings.len())?;
+    MerkleProof::<HashLeaf<H>> { siblings }
+        .verify_hash(
+            parse_hash(&proof.leaf_hash)?,
+            index,
+            parse_hash(&proof.root)?,
+        )
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+    Ok("ok".to_string())
+}
+
+fn run(args: &[String]) -> anyhow::Result<String> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let hasher_of = |dir: &str| -> anyhow::Result<String> {
+        let hasher = fs::read_to_string(Path::new(dir).join(HASHER_FILE))
+            .with_context(|| format!("{} is not a tree directory", dir))?;
+        Ok(hasher.trim().to_string())
+    };
+    match args.as_slice() {
+        ["create", dir, height, rest @ ..] if rest.len() <= 1 => {
+            let hasher = rest.first().copied().unwrap_or("poseidon");
+            let dir = PathBuf::from(dir);
+            let height = height.parse::<usize>().context("invalid height")?;
+            fs::create_dir_all(&dir)?;
+            with_hasher!(hasher, create(&dir, hasher, height))
+        }
+        ["insert", dir, leaves_file] => {
+            with_hasher!(
+                hasher_of(dir)?.as_str(),
+                insert(Path::new(dir), Path::new(leaves_file))
+            )
+        }
+        ["root", dir] => with_hasher!(hasher_of(dir)?.as_str(), root(Path::new(dir))),
+        ["check", dir] => with_hasher!(hasher_of(dir)?.as_str(), check(Path::new(dir))),
+        ["prove", dir, index, proof_file] => {
+            let hasher = hasher_of(dir)?;
+            with_hasher!(
+                hasher.as_str(),
+                prove(Path::new(dir), &hasher, index, Path::new(proof_file))
+            )
+        }
+        ["verify", proof_file] => {
+            let proof: ProofFile = serde_json::from_slice(&fs::read(proof_file)?)?;
+            with_hasher!(proof.hasher.as_str(), verify(&proof))
+        }
+        _ => anyhow::bail!("{}", USAGE),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(output) => println!("{}", output),
+        Err(e) => {
+            eprintln!("error: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use std::fs;
+
+    use super::run;
+
+    #[test]
+    fn test_cli() {
+        let dir = std::env::temp_dir().join(format!("db_tree_cli_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let tree = dir.join("tree").display().to_string();
+        let path = |name: &str| dir.join(name).display().to_string();
+        let run = |args: &[&str]| run(&args.iter().map(|s| s.to_string()).collect::<Vec<_>>());
+
+        let empty_root = run(&["create", &tree, "8", "poseidon2"]).unwrap();
+        assert!(run(&["create", &tree, "8"]).is_err());
+        let leaf = format!("0x{}", "01".repeat(8)) + &"00".repeat(24);
+        fs::write(
+            path("leaves.txt"),
+            format!("# leaves\n3 {}\n0x10 {}\n", leaf, leaf),
+        )
+        .unwrap();
+        let root = run(&["insert", &tree, &path("leaves.txt")]).unwrap();
+        assert_ne!(root, empty_root);
+        assert_eq!(run(&["root", &tree]).unwrap(), root);
+        assert_eq!(run(&["check", &tree]).unwrap(), "ok: 12 nodes, 2 leaves");
+
+        assert_eq!(
+            run(&["prove", &tree, "16", &path("proof.json")]).unwrap(),
+            leaf
+        );
+        assert_eq!(run(&["verify", &path("proof.json")]).unwrap(), "ok");
+        run(&["prove", &tree, "17", &path("empty.json")]).unwrap();
+        assert_eq!(run(&["verify", &path("empty.json")]).unwrap(), "ok");
+
+        let tampered = fs::read_to_string(path("proof.json"))
+            .unwrap()
+            .replace("\"16\"", "\"17\"");
+        fs::write(path("proof.json"), tampered).unwrap();
+        assert!(run(&["verify", &path("proof.json")]).is_err());
+        assert!(run(&["prove", &tree, "256", &path("proof.json")]).is_err());
+        assert!(run(&["frobnicate"]).is_err());
+
+        // a failed create leaves the directory free for another one
+        let other = dir.join("other").display().to_string();
+        assert!(run(&["create", &other, "129"]).is_err());
+        assert!(run(&["root", &other]).is_err());
+        run(&["create", &other, "8", "poseidon2"]).unwrap();
+        assert_eq!(run(&["root", &other]).unwrap(), empty_root);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,28 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use db_tree::{
+    grpc, merkle_tree::MerkleTree, mock_db::MockDB, service::ProofService, traits::HashLeaf,
+};
+use intmax2_zkp::utils::{
+    leafable_hasher::PoseidonLeafableHasher, poseidon_hash_out::PoseidonHashOut,
+};
+
+type Leaf = HashLeaf<PoseidonLeafableHasher>;
+
+// usage: db-tree-grpc [addr] [height]
+// Serves an in-memory Poseidon tree whose empty leaf is the zero hash.
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let addr: SocketAddr = args
+        .next()
+        .unwrap_or_else(|| "127.0.0.1:50051".to_string())
+        .parse()?;
+    let height: usize = match args.next() {
+        Some(height) => height.parse()?,
+        None => 32,
+    };
+    let mut db = MockDB::<Leaf>::new();
+    let tree = MerkleTree::try_new(&mut db, height, PoseidonHashOut::default())?;
+    grpc::serve(Arc::new(ProofService::new(tree, db)), addr).await
+}
@@ -0,0 +1,82 @@
+use crate::{
+    batch_hasher::BatchHasher,
+    domain::TaggedHasher,
+    traits::{HashLeaf, TreeHasher},
+};
+
+// BLAKE3 of `left || right`. Much faster than Poseidon outside of circuits,
+// for trees that are only used for data integrity.
+#[derive(Clone, Debug)]
+pub struct Blake3Hasher;
+
+// A leaf given by its BLAKE3 hash; the empty leaf is 32 zero bytes.
+pub type Blake3Leaf = HashLeaf<Blake3Hasher>;
+
+pub fn blake3(data: &[u8]) -> [u8; 32] {
+    blake3::hash(data).into()
+}
+
+impl TreeHasher for Blake3Hasher {
+    type HashOut = [u8; 32];
+
+    fn two_to_one(left: [u8; 32], right: [u8; 32]) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&left);
+        hasher.update(&right);
+        hasher.finalize().into()
+    }
+}
+
+impl BatchHasher for Blake3Hasher {}
+
+impl TaggedHasher for Blake3Hasher {
+    fn hash_tagged(tag: u8, left: [u8; 32], right: [u8; 32]) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&[tag]);
+        hasher.update(&left);
+        hasher.update(&right);
+        hasher.finalize().into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        leaf_index::LeafIndex,
+        merkle_tree::MerkleTree,
+        mock_db::MockDB,
+        testkit,
+        traits::{HashLeaf, Leafable},
+    };
+
+    use super::{blake3, Blake3Hasher, Blake3Leaf};
+
+    #[test]
+    fn test_blake3_tree() {
+        assert_eq!(
+            blake3(b"")[..4],
+            [0xaf, 0x13, 0x49, 0xb9],
+            "blake3 of the empty string"
+        );
+
+        let height = 16;
+        let mut mock_db = MockDB::<Blake3Leaf>::new();
+        let empty_leaf_hash = Blake3Leaf::empty_leaf().hash();
+        let leaf_hashes: Vec<_> = (0..100u8).map(|i| blake3(&[i])).collect();
+        let merkle_tree =
+            MerkleTree::from_leaf_hashes(&mut mock_db, height, empty_leaf_hash, &leaf_hashes);
+        let root = merkle_tree.get_root();
+        for i in [0, 42, 99] {
+            let leaf: Blake3Leaf = HashLeaf(leaf_hashes[i]);
+            let proof = merkle_tree
+                .prove_with_given_root(&mock_db, root, LeafIndex::new(i as u128, height).unwrap())
+                .unwrap();
+            proof.verify_at(&leaf, i as u64, root).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_blake3_conformance() {
+        testkit::check_hasher::<Blake3Hasher>(|i| blake3(&i.to_le_bytes()));
+    }
+}
@@ -0,0 +1,491 @@
+use std::{
+    fmt,
+    ops::{Add, Mul},
+    str::FromStr,
+    sync::OnceLock,
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    batch_hasher::BatchHasher,
+    traits::{HashLeaf, TreeHasher},
+};
+
+// Poseidon over the BN254 scalar field with width 3, as in circomlib's
+// `Poseidon(2)` template: `two_to_one(l, r)` is the first element of
+// `P(0, l, r)`. Trees with this hasher can be verified by the usual circom
+// Merkle templates, see `circom` for the witness export.
+#[derive(Clone, Debug)]
+pub struct Bn254PoseidonHasher;
+
+// A leaf given by its BN254 Poseidon hash; the empty leaf is zero, as in
+// circom and zk-kit trees.
+pub type Bn254PoseidonLeaf = HashLeaf<Bn254PoseidonHasher>;
+
+// An element of the BN254 scalar field, as little endian 64 bit limbs that are
+// always reduced. Serialized as a decimal string, the way snarkjs reads
+// circuit inputs.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, PartialOrd, Ord)]
+pub struct Fr([u64; 4]);
+
+// 21888242871839275222246405745257275088548364400416034343698204186575808495617
+const MODULUS: [u64; 4] = [
+    0x43e1f593f0000001,
+    0x2833e84879b97091,
+    0xb85045b68181585d,
+    0x30644e72e131a029,
+];
+const WIDTH: usize = 3;
+const ROUNDS_F: usize = 8;
+const ROUNDS_P: usize = 57;
+
+// -MODULUS^-1 mod 2^64, for Montgomery reduction
+const INV: u64 = {
+    let mut inv = 1u64;
+    let mut i = 0;
+    while i < 63 {
+        inv = inv.wrapping_mul(inv).wrapping_mul(MODULUS[0]);
+        i += 1;
+    }
+    inv.wrapping_neg()
+};
+
+impl Fr {
+    pub const ZERO: Fr = Fr([0; 4]);
+    pub const ONE: Fr = Fr([1, 0, 0, 0]);
+
+    // `None` if `limbs` is not below the modulus.
+    pub fn from_limbs(limbs: [u64; 4]) -> Option<Self> {
+        is_below_modulus(&limbs).then_some(Fr(limbs))
+    }
+
+    pub fn to_limbs(self) -> [u64; 4] {
+        self.0
+    }
+
+    pub fn from_u64(value: u64) -> Self {
+        Fr([value, 0, 0, 0])
+    }
+
+    pub fn to_be_bytes(self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (chunk, limb) in bytes.chunks_mut(8).zip(self.0.iter().rev()) {
+            chunk.copy_from_slice(&limb.to_be_bytes());
+        }
+        bytes
+    }
+
+    // `None` if the big endian integer is not below the modulus.
+    pub fn from_be_bytes(bytes: [u8; 32]) -> Option<Self> {
+        let mut limbs = [0u64; 4];
+        for (limb, chunk) in limbs.iter_mut().rev().zip(bytes.chunks(8)) {
+            *limb = u64::from_be_bytes(chunk.try_into().unwrap());
+        }
+        Self::from_limbs(limbs)
+    }
+
+    pub fn pow(self, mut exp: [u64; 4]) -> Fr {
+        let mut result = Fr::ONE;
+        let mut base = self;
+        for _ in 0..256 {
+            if exp[0] & 1 == 1 {
+                result = result * base;
+            }
+            base = base * base;
+            exp = shr1(exp);
+        }
+        result
+    }
+
+    // `None` for zero.
+    pub fn inverse(self) -> Option<Fr> {
+        if self == Fr::ZERO {
+            return None;
+        }
+        let (exp, _) = sub_limbs(MODULUS, [2, 0, 0, 0]);
+        Some(self.pow(exp))
+    }
+
+    fn sbox(self) -> Fr {
+        let x2 = self * self;
+        x2 * x2 * self
+    }
+}
+
+impl Add for Fr {
+    type Output = Fr;
+
+    fn add(self, other: Fr) -> Fr {
+        let (sum, _) = add_limbs(self.0, other.0);
+        Fr(reduce_once(sum))
+    }
+}
+
+impl Mul for Fr {
+    type Output = Fr;
+
+    fn mul(self, other: Fr) -> Fr {
+        Fr(mont_mul(mont_mul(self.0, other.0), r_squared()))
+    }
+}
+
+impl fmt::Display for Fr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const CHUNK: u64 = 10_000_000_000_000_000_000; // 10^19
+        let mut limbs = self.0;
+        let mut chunks = vec![];
+        loop {
+            let mut rem = 0u128;
+            for limb in limbs.iter_mut().rev() {
+                let cur = (rem << 64) | *limb as u128;
+                *limb = (cur / CHUNK as u128) as u64;
+                rem = cur % CHUNK as u128;
+            }
+            chunks.push(rem as u64);
+            if limbs == [0; 4] {
+                break;
+            }
+        }
+        let mut chunks = chunks.into_iter().rev();
+        write!(f, "{}", chunks.next().unwrap())?;
+        for chunk in chunks {
+            write!(f, "{:019}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Fr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Fr({})", self)
+    }
+}
+
+// Parses a decimal string; values not below the modulus are rejected rather
+// than reduced.
+impl FromStr for Fr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()),
+            "invalid field element {:?}",
+            s
+        );
+        let mut limbs = [0u64; 4];
+        for digit in s.bytes() {
+            let mut carry = (digit - b'0') as u128;
+            for limb in limbs.iter_mut() {
+                let cur = *limb as u128 * 10 + carry;
+                *limb = cur as u64;
+                carry = cur >> 64;
+            }
+            anyhow::ensure!(carry == 0, "field element {} out of range", s);
+        }
+        Fr::from_limbs(limbs).ok_or_else(|| anyhow::anyhow!("field element {} out of range", s))
+    }
+}
+
+impl Serialize for Fr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Fr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+pub struct Bn254PoseidonConstants {
+    // `WIDTH` constants per round
+    pub round_constants: Vec<[Fr; WIDTH]>,
+    pub mds: [[Fr; WIDTH]; WIDTH],
+}
+
+// The constants are generated with the Grain LFSR of the Poseidon reference
+// parameter script, seeded with (prime field, x^5, 254 bit, width 3, 8 full
+// rounds, 57 partial rounds), which is how circomlib's tables were made. The
+// MDS matrix is the Cauchy matrix `1 / (x_i + y_j)` of the next 6 elements.
+pub fn constants() -> &'static Bn254PoseidonConstants {
+    static CONSTANTS: OnceLock<Bn254PoseidonConstants> = OnceLock::new();
+    CONSTANTS.get_or_init(|| {
+        let mut grain = Grain::new();
+        let round_constants = (0..ROUNDS_F + ROUNDS_P)
+            .map(|_| std::array::from_fn(|_| grain.next_field_element()))
+            .collect();
+        let mds = loop {
+            let elements: Vec<Fr> = (0..2 * WIDTH)
+                .map(|_| Fr(reduce_once(grain.next_limbs())))
+                .collect();
+            let (xs, ys) = elements.split_at(WIDTH);
+            let distinct = (0..elements.len())
+                .all(|i| (i + 1..elements.len()).all(|j| elements[i] != elements[j]));
+            let entries: Option<Vec<[Fr; WIDTH]>> = xs
+                .iter()
+                .map(|x| {
+                    let row: Option<Vec<Fr>> = ys.iter().map(|y| (*x + *y).inverse()).collect();
+                    row.map(|row| row.try_into().unwrap())
+                })
+                .collect();
+            if let (true, Some(entries)) = (distinct, entries) {
+                break entries.try_into().unwrap();
+            }
+        };
+        Bn254PoseidonConstants {
+            round_constants,
+            mds,
+        }
+    })
+}
+
+pub fn permute(state: &mut [Fr; WIDTH]) {
+    let constants = constants();
+    for (round, rc) in constants.round_constants.iter().enumerate() {
+        for (x, c) in state.iter_mut().zip(rc) {
+            *x = *x + *c;
+        }
+        if !(ROUNDS_F / 2..ROUNDS_F / 2 + ROUNDS_P).contains(&round) {
+            for x in state.iter_mut() {
+                *x = x.sbox();
+            }
+        } else {
+            state[0] = state[0].sbox();
+        }
+        *state = std::array::from_fn(|i| {
+            constants.mds[i]
+                .iter()
+                .zip(state.iter())
+                .fold(Fr::ZERO, |acc, (m, x)| acc + *m * *x)
+        });
+    }
+}
+
+// circomlib's `Poseidon(2)` of `left` and `right`.
+pub fn poseidon_hash(left: Fr, right: Fr) -> Fr {
+    let mut state = [Fr::ZERO, left, right];
+    permute(&mut state);
+    state[0]
+}
+
+impl TreeHasher for Bn254PoseidonHasher {
+    type HashOut = Fr;
+
+    fn two_to_one(left: Fr, right: Fr) -> Fr {
+        poseidon_hash(left, right)
+    }
+}
+
+impl BatchHasher for Bn254PoseidonHasher {}
+
+fn is_below_modulus(limbs: &[u64; 4]) -> bool {
+    for i in (0..4).rev() {
+        if limbs[i] != MODULUS[i] {
+            return limbs[i] < MODULUS[i];
+        }
+    }
+    false
+}
+
+fn add_limbs(a: [u64; 4], b: [u64; 4]) -> ([u64; 4], bool) {
+    let mut out = [0u64; 4];
+    let mut carry = false;
+    for i in 0..4 {
+        let (s, c1) = a[i].overflowing_add(b[i]);
+        let (s, c2) = s.overflowing_add(carry as u64);
+        out[i] = s;
+        carry = c1 || c2;
+    }
+    (out, carry)
+}
+
+fn sub_limbs(a: [u64; 4], b: [u64; 4]) -> ([u64; 4], bool) {
+    let mut out = [0u64; 4];
+    let mut borrow = false;
+    for i in 0..4 {
+        let (d, b1) = a[i].overflowing_sub(b[i]);
+        let (d, b2) = d.overflowing_sub(borrow as u64);
+        out[i] = d;
+        borrow = b1 || b2;
+    }
+    (out, borrow)
+}
+
+// Reduces a value below twice the modulus.
+fn reduce_once(limbs: [u64; 4]) -> [u64; 4] {
+    if is_below_modulus(&limbs) {
+        limbs
+    } else {
+        sub_limbs(limbs, MODULUS).0
+    }
+}
+
+fn shr1(limbs: [u64; 4]) -> [u64; 4] {
+    std::array::from_fn(|i| (limbs[i] >> 1) | limbs.get(i + 1).map_or(0, |next| next << 63))
+}
+
+// a * b / 2^256 mod p, for reduced `a` and `b`
+fn mont_mul(a: [u64; 4], b: [u64; 4]) -> [u64; 4] {
+    let mut t = [0u64; 6];
+    for &bi in &b {
+        let mut carry = 0u128;
+        for j in 0..4 {
+            let cur = t[j] as u128 + a[j] as u128 * bi as u128 + carry;
+            t[j] = cur as u64;
+            carry = cur >> 64;
+        }
+        let cur = t[4] as u128 + carry;
+        t[4] = cur as u64;
+        t[5] = (cur >> 64) as u64;
+
+        let m = t[0].wrapping_mul(INV);
+        let mut carry = (t[0] as u128 + m as u128 * MODULUS[0] as u128) >> 64;
+        for j in 1..4 {
+            let cur = t[j] as u128 + m as u128 * MODULUS[j] as u128 + carry;
+            t[j - 1] = cur as u64;
+            carry = cur >> 64;
+        }
+        let cur = t[4] as u128 + carry;
+        t[3] = cur as u64;
+        t[4] = t[5] + (cur >> 64) as u64;
+    }
+    let result = [t[0], t[1], t[2], t[3]];
+    if t[4] != 0 {
+        sub_limbs(result, MODULUS).0
+    } else {
+        reduce_once(result)
+    }
+}
+
+// 2^512 mod p, which turns `mont_mul` results back into plain products
+fn r_squared() -> [u64; 4] {
+    static R2: OnceLock<[u64; 4]> = OnceLock::new();
+    *R2.get_or_init(|| {
+        let mut r = [1u64, 0, 0, 0];
+        for _ in 0..512 {
+            let (doubled, _) = add_limbs(r, r);
+            r = reduce_once(doubled);
+        }
+        r
+    })
+}
+
+struct Grain {
+    bits: [bool; 80],
+}
+
+impl Grain {
+    fn new() -> Self {
+        let mut bits = [true; 80];
+        let fields: [(u64, usize); 6] = [
+            (1, 2),                // prime field
+            (0, 4),                // x^alpha s-box
+            (254, 12),             // field size
+            (WIDTH as u64, 12),    // width
+            (ROUNDS_F as u64, 10), // full rounds
+            (ROUNDS_P as u64, 10), // partial rounds
+        ];
+        let mut pos = 0;
+        for (value, len) in fields {
+            for i in (0..len).rev() {
+                bits[pos] = (value >> i) & 1 == 1;
+                pos += 1;
+            }
+        }
+        let mut grain = Self { bits };
+        for _ in 0..160 {
+            grain.step();
+        }
+        grain
+    }
+
+    fn step(&mut self) -> bool {
+        let b = &self.bits;
+        let new_bit = b[62] ^ b[51] ^ b[38] ^ b[23] ^ b[13] ^ b[0];
+        self.bits.copy_within(1.., 0);
+        self.bits[79] = new_bit;
+        new_bit
+    }
+
+    // bits are produced in pairs; the second bit is kept if the first is set
+    fn next_bit(&mut self) -> bool {
+        while !self.step() {
+            self.step();
+        }
+        self.step()
+    }
+
+    // 254 bits, most significant first
+    fn next_limbs(&mut self) -> [u64; 4] {
+        let mut limbs = [0u64; 4];
+        for i in (0..254).rev() {
+            if self.next_bit() {
+                limbs[i / 64] |= 1 << (i % 64);
+            }
+        }
+        limbs
+    }
+
+    fn next_field_element(&mut self) -> Fr {
+        loop {
+            if let Some(x) = Fr::from_limbs(self.next_limbs()) {
+                return x;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        merkle_tree::MerkleTree,
+        mock_db::MockDB,
+        testkit,
+        traits::{HashLeaf, Leafable},
+    };
+
+    use super::{poseidon_hash, Bn254PoseidonHasher, Bn254PoseidonLeaf, Fr};
+
+    #[test]
+    fn test_bn254_poseidon() {
+        // circomlibjs: poseidon([1, 2])
+        assert_eq!(
+            poseidon_hash(Fr::from_u64(1), Fr::from_u64(2)).to_string(),
+            "7853200120776062878684798364095072458815029376092732009249414926327459813530"
+        );
+        let x: Fr = "21888242871839275222246405745257275088548364400416034343698204186575808495616"
+            .parse()
+            .unwrap();
+        assert_eq!(x + Fr::ONE, Fr::ZERO);
+        assert_eq!(x * x, Fr::ONE);
+        assert!(
+            "21888242871839275222246405745257275088548364400416034343698204186575808495617"
+                .parse::<Fr>()
+                .is_err()
+        );
+        assert_eq!(Fr::from_be_bytes(x.to_be_bytes()), Some(x));
+
+        let height = 4;
+        let mut mock_db = MockDB::<Bn254PoseidonLeaf>::new();
+        let empty_leaf_hash = Bn254PoseidonLeaf::empty_leaf().hash();
+        let mut merkle_tree = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+        let leaf: Bn254PoseidonLeaf = HashLeaf(Fr::from_u64(42));
+        merkle_tree
+            .update_leaf_at(&mut mock_db, 3, leaf.hash())
+            .unwrap();
+        let root = merkle_tree.get_root();
+        merkle_tree
+            .prove_at(3)
+            .unwrap()
+            .verify_at(&leaf, 3, root)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_bn254_poseidon_conformance() {
+        testkit::check_hasher::<Bn254PoseidonHasher>(|i| Fr::from_u64(i + 1));
+    }
+}
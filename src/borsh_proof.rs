@@ -0,0 +1,241 @@
+use std::{
+    io::{self, Read, Write},
+    marker::PhantomData,
+};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::{
+    error::VerifyError,
+    leaf_index::LeafIndex,
+    merkle_tree::MerkleProof,
+    service::WireHash,
+    traits::{Leafable, TreeHasher},
+    verify::verify_siblings,
+};
+
+// Borsh encoding of proofs: a proof is the borsh `Vec<Vec<u8>>` of the
+// `WireHash` encodings of its siblings from the leaf up, and a batch of
+// proofs is the borsh `Vec` of its proofs. Hash types come from other crates,
+// so the encoding goes through `WireHash` rather than borsh impls on them.
+//
+// `ProofView` and `ProofBatchView` read proofs in place from these bytes,
+// e.g. an mmap'd archive written by a proof server, so verifying a proof
+// decodes one sibling at a time and allocates nothing for the proof itself.
+
+impl<V: Leafable> BorshSerialize for MerkleProof<V>
+where
+    <V::Hasher as TreeHasher>::HashOut: WireHash,
+{
+    fn serialize<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        (self.siblings.len() as u32).serialize(writer)?;
+        for sibling in &self.siblings {
+            sibling.to_wire().serialize(writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl<V: Leafable> BorshDeserialize for MerkleProof<V>
+where
+    <V::Hasher as TreeHasher>::HashOut: WireHash,
+{
+    fn deserialize_reader<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let siblings = Vec::<Vec<u8>>::deserialize_reader(reader)?
+            .iter()
+            .map(|wire| WireHash::from_wire(wire))
+            .collect::<Option<_>>()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid hash"))?;
+        Ok(MerkleProof { siblings })
+    }
+}
+
+// A borsh encoded `MerkleProof` read in place.
+#[derive(Debug)]
+pub struct ProofView<'a, V: Leafable> {
+    height: usize,
+    // the length prefixed siblings
+    bytes: &'a [u8],
+    _leaf: PhantomData<V>,
+}
+
+impl<V: Leafable> Clone for ProofView<'_, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<V: Leafable> Copy for ProofView<'_, V> {}
+
+impl<'a, V: Leafable> ProofView<'a, V>
+where
+    <V::Hasher as TreeHasher>::HashOut: WireHash,
+{
+    // Reads the proof at the start of `bytes` and advances `bytes` past it.
+    // None if it is not a proof, or a sibling is not the encoding of a hash.
+    pub fn read(bytes: &mut &'a [u8]) -> Option<Self> {
+        let mut rest = *bytes;
+        let height = take_u32(&mut rest)? as usize;
+        let start = rest;
+        for _ in 0..height {
+            let len = take_u32(&mut rest)? as usize;
+            <V::Hasher as TreeHasher>::HashOut::from_wire(take(&mut rest, len)?)?;
+        }
+        let view = Self {
+            height,
+            bytes: &start[..start.len() - rest.len()],
+            _leaf: PhantomData,
+        };
+        *bytes = rest;
+        Some(view)
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // the siblings from the leaf up, decoded one at a time
+    pub fn siblings(&self) -> impl Iterator<Item = <V::Hasher as TreeHasher>::HashOut> + 'a {
+        let mut rest = self.bytes;
+        (0..self.height).map(move |_| {
+            let len = take_u32(&mut rest).unwrap() as usize;
+            WireHash::from_wire(take(&mut rest, len).unwrap()).expect("checked by read")
+        })
+    }
+
+    // `MerkleProof::verify_hash` without decoding the proof first.
+    pub fn verify_hash(
+        &self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: impl Into<LeafIndex>,
+        merkle_root: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Result<(), VerifyError<<V::Hasher as TreeHasher>::HashOut>> {
+        let index = index.into();
+        verify_siblings::<V::Hasher>(
+            leaf_hash,
+            index.index(),
+            index.height(),
+            self.siblings(),
+            merkle_root,
+        )
+    }
+
+    pub fn to_proof(&self) -> MerkleProof<V> {
+        MerkleProof {
+            siblings: self.siblings().collect(),
+        }
+    }
+}
+
+// A borsh encoded batch of `MerkleProof`s read in place.
+#[derive(Debug)]
+pub struct ProofBatchView<'a, V: Leafable> {
+    len: usize,
+    // the proofs
+    bytes: &'a [u8],
+    _leaf: PhantomData<V>,
+}
+
+impl<'a, V: Leafable> ProofBatchView<'a, V>
+where
+    <V::Hasher as TreeHasher>::HashOut: WireHash,
+{
+    // Same as `ProofView::read` for a batch; every proof is checked.
+    pub fn read(bytes: &mut &'a [u8]) -> Option<Self> {
+        let mut rest = *bytes;
+        let len = take_u32(&mut rest)? as usize;
+        let start = rest;
+        for _ in 0..len {
+            ProofView::<V>::read(&mut rest)?;
+        }
+        let view = Self {
+            len,
+            bytes: &start[..start.len() - rest.len()],
+            _leaf: PhantomData,
+        };
+        *bytes = rest;
+        Some(view)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = ProofView<'a, V>> + 'a {
+        let mut rest = self.bytes;
+        (0..self.len).map(move |_| ProofView::read(&mut rest).expect("checked by read"))
+    }
+
+    // the proof at `i`, found by skipping over the proofs before it
+    pub fn get(&self, i: usize) -> Option<ProofView<'a, V>> {
+        self.iter().nth(i)
+    }
+}
+
+fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+    let (head, tail) = bytes.split_at_checked(n)?;
+    *bytes = tail;
+    Some(head)
+}
+
+fn take_u32(bytes: &mut &[u8]) -> Option<u32> {
+    Some(u32::from_le_bytes(take(bytes, 4)?.try_into().unwrap()))
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        leaf_index::LeafIndex,
+        merkle_tree::{MerkleProof, MerkleTree},
+        mock_db::MockDB,
+        traits::Leafable,
+    };
+
+    use super::{ProofBatchView, ProofView};
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_borsh_proof() {
+        let height = 8;
+        let mut db = MockDB::<Leaf>::new();
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        let index = |i: u128| LeafIndex::new(i, height).unwrap();
+        for i in [1u128, 2, 200] {
+            tree.update_leaf(&mut db, index(i), (i as u32).hash())
+                .unwrap();
+        }
+        let root = tree.get_root();
+        let proofs: Vec<_> = [1u128, 2, 200]
+            .into_iter()
+            .map(|i| tree.prove(index(i)).unwrap())
+            .collect();
+
+        let bytes = borsh::to_vec(&proofs[0]).unwrap();
+        assert_eq!(
+            borsh::from_slice::<MerkleProof<Leaf>>(&bytes).unwrap(),
+            proofs[0]
+        );
+        let mut rest = &bytes[..];
+        let view = ProofView::<Leaf>::read(&mut rest).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(view.height(), height);
+        assert!(view.verify_hash(1u32.hash(), index(1), root).is_ok());
+        assert!(view.verify_hash(2u32.hash(), index(1), root).is_err());
+        assert_eq!(view.to_proof(), proofs[0]);
+        assert!(ProofView::<Leaf>::read(&mut &bytes[..bytes.len() - 1]).is_none());
+
+        let bytes = borsh::to_vec(&proofs).unwrap();
+        let batch = ProofBatchView::<Leaf>::read(&mut &bytes[..]).unwrap();
+        assert_eq!(batch.len(), 3);
+        for (view, i) in batch.iter().zip([1u128, 2, 200]) {
+            assert!(view.verify_hash((i as u32).hash(), index(i), root).is_ok());
+        }
+        assert_eq!(batch.get(2).unwrap().to_proof(), proofs[2]);
+        assert!(batch.get(3).is_none());
+    }
+}
@@ -0,0 +1,433 @@
+use std::{
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use hashbrown::HashMap;
+
+use crate::{
+    node::Node,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+// When the background flusher of a `BufferedStore` drains its buffer: every
+// `interval`, and as soon as `max_buffered` writes are waiting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlushPolicy {
+    pub interval: Duration,
+    pub max_buffered: usize,
+}
+
+impl Default for FlushPolicy {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            max_buffered: 10_000,
+        }
+    }
+}
+
+// `BufferedStore` wraps a `NodeStore` and keeps writes in memory until they
+// are flushed to it, so that a slow persistent backend sees few large batches
+// instead of one write per node. Reads see buffered writes first.
+//
+// Without a flusher the buffer is flushed on the writing thread once it holds
+// `max_buffered` writes; with `with_flusher` a background thread does it, so
+// writers never wait for the backend unless a flush is running (flushes hold
+// the store lock). The buffer is also flushed by `sync`, which
+// durability-sensitive callers use after a commit, and when the store is
+// dropped. Writes that are not flushed yet are lost on a crash.
+pub struct BufferedStore<V: Leafable, S: NodeStore<V>> {
+    shared: Arc<Shared<V, S>>,
+    max_buffered: usize,
+    flusher: Option<JoinHandle<()>>,
+}
+
+struct Shared<V: Leafable, S> {
+    state: Mutex<State<V, S>>,
+    // wakes the flusher when the buffer is full or the store is dropped
+    wake: Condvar,
+}
+
+struct State<V: Leafable, S> {
+    buffer: Buffer<V>,
+    backend: S,
+    stop: bool,
+}
+
+// Latest write of each key; `None` is a removal.
+struct Buffer<V: Leafable> {
+    nodes: HashMap<<V::Hasher as TreeHasher>::HashOut, Option<Node<V>>>,
+    leaf_data: HashMap<<V::Hasher as TreeHasher>::HashOut, Option<Vec<u8>>>,
+    metadata: HashMap<<V::Hasher as TreeHasher>::HashOut, MetadataWrites>,
+}
+
+// Metadata of one leaf hash written since the last flush. `cleared` means the
+// backend's metadata is removed first, so older entries are hidden; a `None`
+// entry removes the metadata of one index.
+#[derive(Default)]
+struct MetadataWrites {
+    cleared: bool,
+    entries: HashMap<u128, Option<Vec<u8>>>,
+}
+
+impl<V: Leafable> Buffer<V> {
+    fn new() -> Self {
+        Self {
+            nodes: HashMap::new(),
+            leaf_data: HashMap::new(),
+            metadata: HashMap::new(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.nodes.len() + self.leaf_data.len() + self.metadata.len()
+    }
+}
+
+impl<V: Leafable, S: NodeStore<V>> State<V, S> {
+    fn flush(&mut self) {
+        let mut inserted = Vec::new();
+        for (key, node) in self.buffer.nodes.drain() {
+            match node {
+                Some(node) => inserted.push((key, node)),
+                None => {
+                    self.backend.remove(key);
+                }
+            }
+        }
+        if !inserted.is_empty() {
+            self.backend.insert_batch(inserted);
+        }
+        for (leaf_hash, data) in self.buffer.leaf_data.drain() {
+            match data {
+                Some(data) => self.backend.insert_leaf_data(leaf_hash, data),
+                None => {
+                    self.backend.remove_leaf_data(leaf_hash);
+                }
+            }
+        }
+        for (leaf_hash, writes) in self.buffer.metadata.drain() {
+            if writes.cleared {
+                self.backend.remove_leaf_metadata(leaf_hash.clone());
+            }
+            for (index, metadata) in writes.entries {
+                match metadata {
+                    Some(metadata) => {
+                        self.backend
+                            .insert_leaf_metadata(leaf_hash.clone(), index, metadata)
+                    }
+                    None => self
+                        .backend
+                        .remove_leaf_metadata_at(leaf_hash.clone(), index),
+                }
+            }
+        }
+    }
+}
+
+impl<V: Leafable, S: NodeStore<V>> BufferedStore<V, S> {
+    // A store without a background flusher.
+    pub fn new(backend: S, max_buffered: usize) -> Self {
+        Self {
+            shared: Self::shared(backend),
+            max_buffered,
+            flusher: None,
+        }
+    }
+
+    pub fn with_flusher(backend: S, policy: FlushPolicy) -> Self
+    where
+        V: 'static,
+        <V::Hasher as TreeHasher>::HashOut: Send,
+        S: Send + 'static,
+    {
+        let shared = Self::shared(backend);
+        let flusher = {
+            let shared = shared.clone();
+            thread::spawn(move || run_flusher(&shared, policy))
+        };
+        Self {
+            shared,
+            max_buffered: policy.max_buffered,
+            flusher: Some(flusher),
+        }
+    }
+
+    // Number of writes waiting to be flushed.
+    pub fn num_buffered(&self) -> usize {
+        self.state().buffer.len()
+    }
+
+    // Flushes every buffered write to the backend before returning.
+    pub fn sync(&self) {
+        self.state().flush();
+    }
+
+    // Flushes, stops the flusher and returns the backend.
+    pub fn into_inner(self) -> S {
+        let shared = self.shared.clone();
+        drop(self);
+        let shared =
+            Arc::try_unwrap(shared).unwrap_or_else(|_| unreachable!("the flusher has stopped"));
+        shared
+            .state
+            .into_inner()
+            .expect("buffered store lock poisoned")
+            .backend
+    }
+
+    fn shared(backend: S) -> Arc<Shared<V, S>> {
+        Arc::new(Shared {
+            state: Mutex::new(State {
+                buffer: Buffer::new(),
+                backend,
+                stop: false,
+            }),
+            wake: Condvar::new(),
+        })
+    }
+
+    fn state(&self) -> MutexGuard<'_, State<V, S>> {
+        self.shared
+            .state
+            .lock()
+            .expect("buffered store lock poisoned")
+    }
+
+    fn after_write(&self, mut state: MutexGuard<'_, State<V, S>>) {
+        if state.buffer.len() < self.max_buffered {
+            return;
+        }
+        if self.flusher.is_some() {
+            self.shared.wake.notify_one();
+        } else {
+            state.flush();
+        }
+    }
+}
+
+fn run_flusher<V: Leafable, S: NodeStore<V>>(shared: &Shared<V, S>, policy: FlushPolicy) {
+    let mut state = shared.state.lock().expect("buffered store lock poisoned");
+    while !state.stop {
+        state = shared
+            .wake
+            .wait_timeout_while(state, policy.interval, |state| {
+                !state.stop && state.buffer.len() < policy.max_buffered
+            })
+            .expect("buffered store lock poisoned")
+            .0;
+        state.flush();
+    }
+}
+
+// Flush on drop. A panicked flusher leaves the lock poisoned, in which case
+// the buffer is dropped rather than panicking again.
+impl<V: Leafable, S: NodeStore<V>> Drop for BufferedStore<V, S> {
+    fn drop(&mut self) {
+        if let Some(flusher) = self.flusher.take() {
+            if let Ok(mut state) = self.shared.state.lock() {
+                state.stop = true;
+            }
+            self.shared.wake.notify_one();
+            let _ = flusher.join();
+        }
+        if let Ok(mut state) = self.shared.state.lock() {
+            state.flush();
+        }
+    }
+}
+
+impl<V: Leafable, S: NodeStore<V>> NodeStore<V> for BufferedStore<V, S> {
+    fn insert(&mut self, key: <V::Hasher as TreeHasher>::HashOut, node: Node<V>) {
+        let mut state = self.state();
+        state.buffer.nodes.insert(key, Some(node));
+        self.after_write(state);
+    }
+
+    fn get(&self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>> {
+        let state = self.state();
+        match state.buffer.nodes.get(&key) {
+            Some(node) => node.clone(),
+            None => state.backend.get(key),
+        }
+    }
+
+    fn remove(&mut self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>> {
+        let mut state = self.state();
+        let node = match state.buffer.nodes.get(&key) {
+            Some(node) => node.clone(),
+            None => state.backend.get(key.clone()),
+        };
+        state.buffer.nodes.insert(key, None);
+        self.after_write(state);
+        node
+    }
+
+    fn contains(&self, key: <V::Hasher as TreeHasher>::HashOut) -> bool {
+        let state = self.state();
+        match state.buffer.nodes.get(&key) {
+            Some(node) => node.is_some(),
+            None => state.backend.contains(key),
+        }
+    }
+
+    fn insert_leaf_data(&mut self, leaf_hash: <V::Hasher as TreeHasher>::HashOut, data: Vec<u8>) {
+        let mut state = self.state();
+        state.buffer.leaf_data.insert(leaf_hash, Some(data));
+        self.after_write(state);
+    }
+
+    fn get_leaf_data(&self, leaf_hash: <V::Hasher as TreeHasher>::HashOut) -> Option<Vec<u8>> {
+        let state = self.state();
+        match state.buffer.leaf_data.get(&leaf_hash) {
+            Some(data) => data.clone(),
+            None => state.backend.get_leaf_data(leaf_hash),
+        }
+    }
+
+    fn remove_leaf_data(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Option<Vec<u8>> {
+        let mut state = self.state();
+        let data = match state.buffer.leaf_data.get(&leaf_hash) {
+            Some(data) => data.clone(),
+            None => state.backend.get_leaf_data(leaf_hash.clone()),
+        };
+        state.buffer.leaf_data.insert(leaf_hash, None);
+        self.after_write(state);
+        data
+    }
+
+    fn insert_leaf_metadata(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+        metadata: Vec<u8>,
+    ) {
+        let mut state = self.state();
+        state
+            .buffer
+            .metadata
+            .entry(leaf_hash)
+            .or_default()
+            .entries
+            .insert(index, Some(metadata));
+        self.after_write(state);
+    }
+
+    fn get_leaf_metadata(
+        &self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+    ) -> Option<Vec<u8>> {
+        let state = self.state();
+        if let Some(writes) = state.buffer.metadata.get(&leaf_hash) {
+            if let Some(metadata) = writes.entries.get(&index) {
+                return metadata.clone();
+            }
+            if writes.cleared {
+                return None;
+            }
+        }
+        state.backend.get_leaf_metadata(leaf_hash, index)
+    }
+
+    fn remove_leaf_metadata(&mut self, leaf_hash: <V::Hasher as TreeHasher>::HashOut) {
+        let mut state = self.state();
+        let writes = state.buffer.metadata.entry(leaf_hash).or_default();
+        writes.cleared = true;
+        writes.entries.clear();
+        self.after_write(state);
+    }
+
+    fn remove_leaf_metadata_at(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+    ) {
+        let mut state = self.state();
+        let writes = state.buffer.metadata.entry(leaf_hash).or_default();
+        writes.entries.insert(index, None);
+        self.after_write(state);
+    }
+
+    fn insert_batch(&mut self, nodes: Vec<(<V::Hasher as TreeHasher>::HashOut, Node<V>)>) {
+        let mut state = self.state();
+        for (key, node) in nodes {
+            state.buffer.nodes.insert(key, Some(node));
+        }
+        self.after_write(state);
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use std::{thread, time::Duration};
+
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, node_store::NodeStore,
+        traits::Leafable,
+    };
+
+    use super::{BufferedStore, FlushPolicy};
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_buffered_store() {
+        let height = 8;
+        let index = move |i: u128| LeafIndex::new(i, height).unwrap();
+
+        // without a flusher nothing reaches the backend before `sync`
+        let mut db = BufferedStore::new(MockDB::<Leaf>::new(), usize::MAX);
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        for i in 0..20 {
+            tree.update_leaf(&mut db, index(i), (i as u32).hash())
+                .unwrap();
+        }
+        db.insert_leaf_metadata(3u32.hash(), 3, vec![1]);
+        db.remove_leaf_metadata(3u32.hash());
+        db.insert_leaf_metadata(3u32.hash(), 4, vec![2]);
+        assert!(db.num_buffered() > 0);
+        assert_eq!(db.get_leaf_metadata(3u32.hash(), 3), None);
+        let proof = tree
+            .prove_with_given_root(&db, tree.get_root(), index(5))
+            .unwrap();
+        proof.verify(&5u32, index(5), tree.get_root()).unwrap();
+        db.sync();
+        assert_eq!(db.num_buffered(), 0);
+        let backend = db.into_inner();
+        assert_eq!(
+            tree.prove_with_given_root(&backend, tree.get_root(), index(5))
+                .unwrap(),
+            proof
+        );
+        assert_eq!(backend.get_leaf_metadata(3u32.hash(), 4), Some(vec![2]));
+
+        // the flusher drains a full buffer on its own, and dropping the store
+        // flushes the rest
+        let policy = FlushPolicy {
+            interval: Duration::from_secs(60),
+            max_buffered: 16,
+        };
+        let mut db = BufferedStore::with_flusher(MockDB::<Leaf>::new(), policy);
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        let leaves: Vec<_> = (0..64).map(|i| (index(i), (i as u32).hash())).collect();
+        tree.update_leaves(&mut db, &leaves).unwrap();
+        for _ in 0..200 {
+            if db.num_buffered() < policy.max_buffered {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(db.num_buffered() < policy.max_buffered);
+        tree.update_leaf(&mut db, index(100), 7u32.hash()).unwrap();
+        let backend = db.into_inner();
+        tree.prove_with_given_root(&backend, tree.get_root(), index(100))
+            .unwrap();
+    }
+}
@@ -0,0 +1,171 @@
+use crate::{
+    error::DbTreeError,
+    merkle_tree::MerkleTree,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+    zero_hashes::ZeroHashes,
+};
+
+// Builds a `MerkleTree` with non-default empty leaf semantics. Without any
+// option the empty leaf is `V::empty_leaf()`. If several options are set the
+// last one wins.
+#[derive(Clone, Debug)]
+pub struct MerkleTreeBuilder<V: Leafable> {
+    height: usize,
+    zero_hashes: Option<ZeroHashes<V::Hasher>>,
+}
+
+impl<V: Leafable> MerkleTreeBuilder<V> {
+    pub fn new(height: usize) -> Self {
+        Self {
+            height,
+            zero_hashes: None,
+        }
+    }
+
+    pub fn empty_leaf_hash(mut self, empty_leaf_hash: <V::Hasher as TreeHasher>::HashOut) -> Self {
+        self.zero_hashes = Some(ZeroHashes::new(empty_leaf_hash, self.height));
+        self
+    }
+
+    // Uses `leaf` as the value of every unset leaf; its hash is computed here.
+    pub fn default_leaf(self, leaf: &V) -> Self {
+        self.empty_leaf_hash(leaf.hash())
+    }
+
+    // Reuses a precomputed (possibly shared) zero hash table.
+    pub fn zero_hashes(mut self, zero_hashes: &ZeroHashes<V::Hasher>) -> Self {
+        self.zero_hashes = Some(zero_hashes.clone());
+        self
+    }
+
+    // Explicit zero values per level: `by_level[i]` is the root of an empty
+    // subtree of height i, so `by_level[0]` is the empty leaf hash. Must have
+    // `height + 1` entries, each the hash of two of the level below.
+    pub fn level_zero_hashes(
+        mut self,
+        by_level: Vec<<V::Hasher as TreeHasher>::HashOut>,
+    ) -> Result<Self, DbTreeError> {
+        if by_level.len() != self.height + 1 {
+            return Err(DbTreeError::InvalidZeroHashCount {
+                expected: self.height + 1,
+                actual: by_level.len(),
+            });
+        }
+        self.zero_hashes = Some(ZeroHashes::from_levels(by_level)?);
+        Ok(self)
+    }
+
+    // Panics if the height is too large for the zero hash table; see
+    // `try_build`.
+    pub fn build<S: NodeStore<V>>(self, db: &mut S) -> MerkleTree<V> {
+        self.try_build(db).unwrap()
+    }
+
+    pub fn try_build<S: NodeStore<V>>(self, db: &mut S) -> Result<MerkleTree<V>, DbTreeError> {
+        let zero_hashes = self
+            .zero_hashes
+            .unwrap_or_else(|| ZeroHashes::new(V::empty_leaf().hash(), self.height));
+        MerkleTree::try_with_zero_hashes(db, self.height, &zero_hashes)
+    }
+}
+
+impl<V: Leafable> MerkleTree<V> {
+    pub fn builder(height: usize) -> MerkleTreeBuilder<V> {
+        MerkleTreeBuilder::new(height)
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use intmax2_zkp::utils::poseidon_hash_out::PoseidonHashOut;
+
+    use crate::{
+        archive::ArchivedTree, error::DbTreeError, leaf_index::LeafIndex, merkle_tree::MerkleTree,
+        mock_db::MockDB, traits::Leafable, zero_hashes::ZeroHashes,
+    };
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_builder() {
+        let height = 8;
+        let mut mock_db = MockDB::<Leaf>::new();
+
+        let default = MerkleTree::<Leaf>::builder(height).build(&mut mock_db);
+        let explicit = MerkleTree::<Leaf>::new(&mut mock_db, height, 0u32.hash());
+        assert_eq!(default, explicit);
+        let seven = MerkleTree::builder(height)
+            .default_leaf(&7u32)
+            .build(&mut mock_db);
+        assert_eq!(seven.zero_hashes[height], 7u32.hash());
+
+        // zero values that are not derived from the level below are rejected
+        let zero = PoseidonHashOut::default();
+        assert_eq!(
+            MerkleTree::<Leaf>::builder(height)
+                .level_zero_hashes(vec![zero; height + 1])
+                .unwrap_err(),
+            DbTreeError::InconsistentZeroHash { height: 1 }
+        );
+        assert_eq!(
+            MerkleTree::<Leaf>::builder(height)
+                .level_zero_hashes(vec![zero; height])
+                .unwrap_err(),
+            DbTreeError::InvalidZeroHashCount {
+                expected: height + 1,
+                actual: height
+            }
+        );
+        assert_eq!(
+            ZeroHashes::<<Leaf as Leafable>::Hasher>::from_levels(vec![]).unwrap_err(),
+            DbTreeError::InvalidZeroHashCount {
+                expected: 1,
+                actual: 0
+            }
+        );
+
+        let table = ZeroHashes::<<Leaf as Leafable>::Hasher>::new(7u32.hash(), height);
+        let by_level: Vec<_> = (0..=height).map(|i| table.get(i)).collect();
+        let mut tree = MerkleTree::<Leaf>::builder(height)
+            .level_zero_hashes(by_level)
+            .unwrap()
+            .build(&mut mock_db);
+        assert_eq!(tree, seven);
+
+        // a shared table shorter than the height is an error
+        let short = ZeroHashes::<<Leaf as Leafable>::Hasher>::new(7u32.hash(), height - 1);
+        assert_eq!(
+            MerkleTree::<Leaf>::builder(height)
+                .zero_hashes(&short)
+                .try_build(&mut mock_db)
+                .unwrap_err(),
+            DbTreeError::HeightTooLarge {
+                height,
+                max: height - 1
+            }
+        );
+        let index = LeafIndex::new(5, height).unwrap();
+        tree.update_leaf(&mut mock_db, index, 5u32.hash()).unwrap();
+        let root = tree.get_root();
+        tree.prove(index).unwrap().verify(&5, index, root).unwrap();
+
+        // the stored zero nodes pass the consistency checks of a restore
+        let mut bytes = vec![];
+        tree.export_archive(&mock_db, root, &mut bytes).unwrap();
+        let archived = ArchivedTree::<Leaf>::load(bytes.as_slice()).unwrap();
+        assert_eq!(archived.root(), root);
+        let path = std::env::temp_dir().join("db_tree_test_builder_levels.json");
+        tree.checkpoint(&mock_db, &path).unwrap();
+        let mut restored_db = MockDB::<Leaf>::new();
+        let restored = MerkleTree::<Leaf>::restore(&mut restored_db, &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(restored.get_root(), root);
+        let empty = LeafIndex::new(6, height).unwrap();
+        restored
+            .prove_with_given_root(&restored_db, root, empty)
+            .unwrap()
+            .verify(&7, empty, root)
+            .unwrap();
+    }
+}
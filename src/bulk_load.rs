@@ -0,0 +1,242 @@
+use crate::{
+    merkle_tree::MerkleTree,
+    node::Node,
+    node_key::{NodeKey, MAX_HEIGHT},
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+// number of nodes buffered before they are written to the store
+const FLUSH_BATCH_SIZE: usize = 1 << 16;
+
+// Builds a `MerkleTree` from leaves pushed in strictly increasing index order.
+// Only the right frontier of the tree (at most one pending node per depth) is
+// held in memory; every completed subtree is hashed once and flushed to the
+// store. Node hashes deeper than `keep_depth` are not kept in the tree, so the
+// loaded tree is compacted unless `keep_depth` is the height.
+pub struct BulkLoader<V: Leafable> {
+    tree: MerkleTree<V>,
+    keep_depth: usize,
+    frontier: Vec<(NodeKey, <V::Hasher as TreeHasher>::HashOut)>,
+    batch: Vec<(<V::Hasher as TreeHasher>::HashOut, Node<V>)>,
+    last_index: Option<u128>,
+}
+
+impl<V: Leafable> BulkLoader<V> {
+    pub fn new<S: NodeStore<V>>(
+        db: &mut S,
+        height: usize,
+        empty_leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        keep_depth: usize,
+    ) -> Self {
+        assert!(keep_depth <= height);
+        Self {
+            tree: MerkleTree::new(db, height, empty_leaf_hash),
+            keep_depth,
+            frontier: Vec::with_capacity(height + 1),
+            batch: vec![],
+            last_index: None,
+        }
+    }
+
+    pub fn push<S: NodeStore<V>>(
+        &mut self,
+        db: &mut S,
+        index: u128,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> anyhow::Result<()> {
+        let height = self.tree.height;
+        anyhow::ensure!(
+            height == MAX_HEIGHT || index >> height == 0,
+            "leaf index {} is out of range for height {}",
+            index,
+            height
+        );
+        if let Some(last) = self.last_index {
+            anyhow::ensure!(
+                index > last,
+                "leaf index {} is not larger than the previous index {}",
+                index,
+                last
+            );
+        }
+        self.last_index = Some(index);
+        if leaf_hash != self.tree.zero_hashes[height] {
+            self.tree.num_leaves = self.tree.num_leaves.saturating_add(1);
+            self.tree.last_leaf = Some(index);
+        }
+
+        self.fold_until(Some(index));
+        self.push_node(NodeKey::new(height, index), leaf_hash);
+        if self.batch.len() >= FLUSH_BATCH_SIZE {
+            db.insert_batch(std::mem::take(&mut self.batch));
+        }
+        Ok(())
+    }
+
+    pub fn finish<S: NodeStore<V>>(mut self, db: &mut S) -> MerkleTree<V> {
+        self.fold_until(None);
+        db.insert_batch(std::mem::take(&mut self.batch));
+        self.tree.cache_depth = self.keep_depth;
+        self.tree
+    }
+
+    // Closes every frontier node whose parent does not contain the leaf
+    // `index` (all of them if `index` is None), treating its missing sibling
+    // as empty.
+    fn fold_until(&mut self, index: Option<u128>) {
+        let height = self.tree.height;
+        while let Some((key, _)) = self.frontier.last() {
+            let key = *key;
+            if key.is_root() {
+                break;
+            }
+            let parent = key.parent();
+            if let Some(index) = index {
+                let shift = (height - parent.depth()) as u32;
+                if index.checked_shr(shift).unwrap_or(0) == parent.index {
+                    break;
+                }
+            }
+            let (_, hash) = self.frontier.pop().unwrap();
+            let zero = self.tree.zero_hashes[key.depth()].clone();
+            let (left, right) = if key.is_right() {
+                (zero, hash)
+            } else {
+                (hash, zero)
+            };
+            let h = self.emit(parent, left, right);
+            self.push_node(parent, h);
+        }
+    }
+
+    // Pushes a completed node, merging it with its left sibling as long as
+    // the sibling is waiting on the frontier.
+    fn push_node(&mut self, mut key: NodeKey, mut hash: <V::Hasher as TreeHasher>::HashOut) {
+        if key.depth() <= self.keep_depth && hash != self.tree.zero_hashes[key.depth()] {
+            self.tree.node_hashes.insert(key, hash.clone());
+        }
+        while let Some((top, _)) = self.frontier.last() {
+            if key.is_root() || *top != key.sibling() {
+                break;
+            }
+            let (_, left) = self.frontier.pop().unwrap();
+            key = key.parent();
+            hash = self.emit(key, left, hash);
+            if key.depth() <= self.keep_depth && hash != self.tree.zero_hashes[key.depth()] {
+                self.tree.node_hashes.insert(key, hash.clone());
+            }
+        }
+        self.frontier.push((key, hash));
+    }
+
+    fn emit(
+        &mut self,
+        key: NodeKey,
+        left: <V::Hasher as TreeHasher>::HashOut,
+        right: <V::Hasher as TreeHasher>::HashOut,
+    ) -> <V::Hasher as TreeHasher>::HashOut {
+        let h = self
+            .tree
+            .hash_children(key.depth(), left.clone(), right.clone());
+        if h != self.tree.zero_hashes[key.depth()] {
+            self.batch.push((h.clone(), Node { left, right }));
+        }
+        h
+    }
+}
+
+impl<V: Leafable> MerkleTree<V> {
+    // Loads `leaves` as (leaf index, leaf hash) pairs in strictly increasing
+    // index order with a `BulkLoader`.
+    pub fn from_sorted_leaves<S, I>(
+        db: &mut S,
+        height: usize,
+        empty_leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        keep_depth: usize,
+        leaves: I,
+    ) -> anyhow::Result<Self>
+    where
+        S: NodeStore<V>,
+        I: IntoIterator<Item = (u128, <V::Hasher as TreeHasher>::HashOut)>,
+    {
+        let mut loader = BulkLoader::new(db, height, empty_leaf_hash, keep_depth);
+        for (index, leaf_hash) in leaves {
+            loader.push(db, index, leaf_hash)?;
+        }
+        Ok(loader.finish(db))
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use intmax2_zkp::utils::poseidon_hash_out::PoseidonHashOut;
+
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable,
+    };
+
+    use super::BulkLoader;
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_from_sorted_leaves() {
+        let height = 16;
+        let empty_leaf_hash = PoseidonHashOut::hash_inputs_u32(&[]);
+
+        let indices: Vec<usize> = vec![0, 1, 2, 5, 6, 100, 101, 4096, 40000, (1 << height) - 1];
+        let mut mock_db = MockDB::<Leaf>::new();
+        let mut expected = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+        for &i in &indices {
+            let leaf = i as u32;
+            expected
+                .update_leaf(
+                    &mut mock_db,
+                    LeafIndex::new(i as u128, height).unwrap(),
+                    leaf.hash(),
+                )
+                .unwrap();
+        }
+
+        for keep_depth in [0, 4, height] {
+            let mut mock_db = MockDB::<Leaf>::new();
+            let leaves = indices.iter().map(|&i| (i as u128, (i as u32).hash()));
+            let tree = MerkleTree::from_sorted_leaves(
+                &mut mock_db,
+                height,
+                empty_leaf_hash,
+                keep_depth,
+                leaves,
+            )
+            .unwrap();
+            assert_eq!(tree.get_root(), expected.get_root());
+            assert_eq!(tree.is_compacted(), keep_depth < height);
+            assert!(tree.node_hashes.keys().all(|key| key.depth() <= keep_depth));
+
+            for &i in &indices {
+                let leaf_index = LeafIndex::new(i as u128, height).unwrap();
+                let proof = tree
+                    .prove_with_given_root(&mock_db, tree.get_root(), leaf_index)
+                    .unwrap();
+                assert_eq!(proof, expected.prove(leaf_index).unwrap());
+            }
+        }
+
+        let mut mock_db = MockDB::<Leaf>::new();
+        let empty = MerkleTree::<Leaf>::from_sorted_leaves(
+            &mut mock_db,
+            height,
+            empty_leaf_hash,
+            0,
+            std::iter::empty(),
+        )
+        .unwrap();
+        assert_eq!(empty.get_root(), empty.zero_hashes[0]);
+
+        let mut loader = BulkLoader::<Leaf>::new(&mut mock_db, height, empty_leaf_hash, 0);
+        loader.push(&mut mock_db, 3, 3u32.hash()).unwrap();
+        assert!(loader.push(&mut mock_db, 3, 4u32.hash()).is_err());
+        assert!(loader.push(&mut mock_db, 1 << height, 4u32.hash()).is_err());
+    }
+}
@@ -0,0 +1,215 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write as _},
+    path::Path,
+};
+
+use hashbrown::HashSet;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    integrity::IntegrityIssue,
+    merkle_tree::MerkleTree,
+    metrics::MetricsHook,
+    node::Node,
+    node_key::{NodeKey, MAX_HEIGHT},
+    node_store::NodeStore,
+    subscription::Subscribers,
+    traits::{Leafable, TreeHasher},
+};
+
+// On-disk snapshot of a `MerkleTree` together with the store nodes reachable
+// from its current root, so that the tree can be reloaded without replaying
+// every leaf update.
+#[derive(Serialize, Deserialize)]
+struct Checkpoint<H> {
+    height: usize,
+    cache_depth: usize,
+    zero_hashes: Vec<H>,
+    node_hashes: Vec<(NodeKey, H)>,
+    nodes: Vec<(H, H, H)>, // (hash, left, right)
+}
+
+impl<V: Leafable> MerkleTree<V>
+where
+    <V::Hasher as TreeHasher>::HashOut: Serialize + DeserializeOwned,
+{
+    // Writes the tree state to `path`. The file is written next to `path` first
+    // and then renamed, so an interrupted checkpoint never clobbers the
+    // previous one.
+    pub fn checkpoint<S: NodeStore<V>, P: AsRef<Path>>(
+        &self,
+        db: &S,
+        path: P,
+    ) -> anyhow::Result<()> {
+        let mut nodes = vec![];
+        let mut visited = HashSet::new();
+        let mut stack = vec![self.get_root()];
+        stack.extend(self.zero_hashes.iter().cloned());
+        while let Some(hash) = stack.pop() {
+            if !visited.insert(hash.clone()) {
+                continue;
+            }
+            if let Some(node) = db.get(hash.clone()) {
+                stack.push(node.left.clone());
+                stack.push(node.right.clone());
+                nodes.push((hash, node.left, node.right));
+            }
+        }
+        let checkpoint = Checkpoint {
+            height: self.height,
+            cache_depth: self.cache_depth,
+            zero_hashes: self.zero_hashes.clone(),
+            node_hashes: self
+                .node_hashes
+                .iter()
+                .map(|(key, hash)| (*key, hash.clone()))
+                .collect(),
+            nodes,
+        };
+
+        let path = path.as_ref();
+        let tmp_path = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        serde_json::to_writer(&mut writer, &checkpoint)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    // Loads a tree written by `checkpoint` and inserts its nodes into `db`.
+    pub fn restore<S: NodeStore<V>, P: AsRef<Path>>(db: &mut S, path: P) -> anyhow::Result<Self> {
+        Self::restore_from_reader(db, BufReader::new(File::open(path)?))
+    }
+
+    // Same as `restore`, for a checkpoint that is not in a file. Fails on
+    // any input that is not a consistent checkpoint.
+    pub fn restore_from_reader<S: NodeStore<V>, R: Read>(
+        db: &mut S,
+        reader: R,
+    ) -> anyhow::Result<Self> {
+        let checkpoint: Checkpoint<<V::Hasher as TreeHasher>::HashOut> =
+            serde_json::from_reader(reader)?;
+        anyhow::ensure!(
+            checkpoint.height <= MAX_HEIGHT,
+            "invalid checkpoint: height {} is larger than {}",
+            checkpoint.height,
+            MAX_HEIGHT
+        );
+        anyhow::ensure!(
+            checkpoint.zero_hashes.len() == checkpoint.height + 1,
+            "invalid checkpoint: expected {} zero hashes, got {}",
+            checkpoint.height + 1,
+            checkpoint.zero_hashes.len()
+        );
+        anyhow::ensure!(
+            checkpoint.cache_depth <= checkpoint.height,
+            "invalid checkpoint: cache depth larger than height"
+        );
+        for (hash, left, right) in checkpoint.nodes {
+            anyhow::ensure!(
+                <V::Hasher as TreeHasher>::two_to_one(left.clone(), right.clone()) == hash,
+                "invalid checkpoint: node hash mismatch"
+            );
+            db.insert(hash, Node { left, right });
+        }
+        let mut node_hashes = HashMap::new();
+        for (key, hash) in checkpoint.node_hashes {
+            anyhow::ensure!(
+                key.is_valid() && key.depth() <= checkpoint.height,
+                "invalid checkpoint: invalid node key"
+            );
+            node_hashes.insert(key, hash);
+        }
+        let mut tree = Self {
+            height: checkpoint.height,
+            node_hashes,
+            zero_hashes: checkpoint.zero_hashes,
+            cache_depth: checkpoint.cache_depth,
+            num_leaves: 0,
+            last_leaf: None,
+            reverse_index: None,
+            subscribers: Subscribers::default(),
+            metrics: MetricsHook::default(),
+        };
+        // reads below a cached node would otherwise panic on a missing node
+        let cached: Vec<_> = tree
+            .node_hashes
+            .iter()
+            .map(|(key, hash)| (*key, hash.clone()))
+            .collect();
+        let issues = tree.verify_subtrees(&*db, cached);
+        if let Some(missing) = issues
+            .iter()
+            .find(|issue| matches!(issue, IntegrityIssue::MissingNode { .. }))
+        {
+            anyhow::bail!("invalid checkpoint: {}", missing);
+        }
+        tree.recount_leaves(&*db)?;
+        Ok(tree)
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use intmax2_zkp::utils::poseidon_hash_out::PoseidonHashOut;
+
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable,
+    };
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_checkpoint_restore() {
+        let height = 16;
+
+        let mut mock_db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = PoseidonHashOut::hash_inputs_u32(&[]);
+        let mut merkle_tree = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+        for i in 0..10 {
+            let leaf = i as u32;
+            merkle_tree
+                .update_leaf(
+                    &mut mock_db,
+                    LeafIndex::new(i as u128, height).unwrap(),
+                    leaf.hash(),
+                )
+                .unwrap();
+        }
+
+        let path = std::env::temp_dir().join("db_tree_test_checkpoint_restore.json");
+        merkle_tree.checkpoint(&mock_db, &path).unwrap();
+
+        let mut restored_db = MockDB::<Leaf>::new();
+        let mut restored = MerkleTree::<Leaf>::restore(&mut restored_db, &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(restored.get_root(), merkle_tree.get_root());
+
+        let root = restored.get_root();
+        let leaf_index = LeafIndex::new(7, height).unwrap();
+        let proof = restored
+            .prove_with_given_root(&restored_db, root, leaf_index)
+            .unwrap();
+        assert_eq!(proof.get_root(&7, leaf_index), root);
+
+        // the restored tree keeps working like the original
+        merkle_tree
+            .update_leaf(
+                &mut mock_db,
+                LeafIndex::new(3, height).unwrap(),
+                33u32.hash(),
+            )
+            .unwrap();
+        restored
+            .update_leaf(
+                &mut restored_db,
+                LeafIndex::new(3, height).unwrap(),
+                33u32.hash(),
+            )
+            .unwrap();
+        assert_eq!(restored.get_root(), merkle_tree.get_root());
+    }
+}
@@ -0,0 +1,205 @@
+use std::{
+    fs::{File, Metadata, OpenOptions, TryLockError},
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context as _;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    merkle_tree::MerkleTree,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+// Advisory lock that makes one process the only writer of the checkpoint at
+// `path`, held until it is dropped. The lock is taken on a `.lock` file next
+// to the checkpoint, which is created if needed and never removed. Readers
+// do not take it: `checkpoint` publishes a file with a rename, so a reader
+// always opens a whole checkpoint and never blocks the writer. Locks are
+// advisory, so every writer has to go through `WriterLock`.
+#[derive(Debug)]
+pub struct WriterLock {
+    file: File,
+}
+
+impl WriterLock {
+    // Waits until no other process holds the lock.
+    pub fn acquire<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let file = open_lock_file(path.as_ref())?;
+        file.lock()?;
+        Ok(Self { file })
+    }
+
+    // Returns None if another process holds the lock.
+    pub fn try_acquire<P: AsRef<Path>>(path: P) -> anyhow::Result<Option<Self>> {
+        let file = open_lock_file(path.as_ref())?;
+        match file.try_lock() {
+            Ok(()) => Ok(Some(Self { file })),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+}
+
+impl Drop for WriterLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+fn open_lock_file(path: &Path) -> anyhow::Result<File> {
+    let lock_path = path.with_extension("lock");
+    OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .with_context(|| format!("cannot open lock file {}", lock_path.display()))
+}
+
+// Read side of a checkpoint written by another process, e.g. a proof server
+// next to the process that updates the tree. The tree is restored into a
+// fresh store whenever the writer publishes a new checkpoint.
+pub struct CheckpointFollower<V: Leafable, S: NodeStore<V>> {
+    path: PathBuf,
+    new_store: fn() -> S,
+    // the loaded checkpoint, kept open so that its file identity cannot be
+    // reused by a later checkpoint
+    loaded: File,
+    tree: MerkleTree<V>,
+    db: S,
+}
+
+impl<V: Leafable, S: NodeStore<V>> CheckpointFollower<V, S>
+where
+    <V::Hasher as TreeHasher>::HashOut: Serialize + DeserializeOwned,
+{
+    pub fn open<P: AsRef<Path>>(path: P, new_store: fn() -> S) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut db = new_store();
+        let (loaded, tree) = load(&mut db, &path)?;
+        Ok(Self {
+            path,
+            new_store,
+            loaded,
+            tree,
+            db,
+        })
+    }
+
+    pub fn tree(&self) -> &MerkleTree<V> {
+        &self.tree
+    }
+
+    pub fn store(&self) -> &S {
+        &self.db
+    }
+
+    // Reloads the tree if a checkpoint was published since it was loaded and
+    // returns whether it did. On error the previous tree is kept.
+    pub fn reload_if_changed(&mut self) -> anyhow::Result<bool> {
+        let current = std::fs::metadata(&self.path)
+            .with_context(|| format!("cannot read checkpoint {}", self.path.display()))?;
+        if same_file(&self.loaded.metadata()?, &current)? {
+            return Ok(false);
+        }
+        let mut db = (self.new_store)();
+        let (loaded, tree) = load(&mut db, &self.path)?;
+        self.loaded = loaded;
+        self.tree = tree;
+        self.db = db;
+        Ok(true)
+    }
+}
+
+// Restores from the opened file, so that the returned file is the one the
+// tree was read from even if a checkpoint is published meanwhile.
+fn load<V: Leafable, S: NodeStore<V>>(
+    db: &mut S,
+    path: &Path,
+) -> anyhow::Result<(File, MerkleTree<V>)>
+where
+    <V::Hasher as TreeHasher>::HashOut: Serialize + DeserializeOwned,
+{
+    let file =
+        File::open(path).with_context(|| format!("cannot read checkpoint {}", path.display()))?;
+    let tree = MerkleTree::restore_from_reader(db, BufReader::new(&file))?;
+    Ok((file, tree))
+}
+
+// `checkpoint` publishes every checkpoint as a new file, so a same-length
+// rewrite within the timestamp granularity is still a different file.
+#[cfg(unix)]
+fn same_file(loaded: &Metadata, current: &Metadata) -> anyhow::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    Ok(loaded.dev() == current.dev() && loaded.ino() == current.ino())
+}
+
+// Without file identities, fall back to the modification time and length.
+#[cfg(not(unix))]
+fn same_file(loaded: &Metadata, current: &Metadata) -> anyhow::Result<bool> {
+    Ok(loaded.modified()? == current.modified()? && loaded.len() == current.len())
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable,
+    };
+
+    use super::{CheckpointFollower, WriterLock};
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_writer_lock_and_follower() {
+        let height = 8;
+        let dir = std::env::temp_dir().join(format!("db_tree_lock_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tree.json");
+
+        let lock = WriterLock::acquire(&path).unwrap();
+        // flock conflicts between open files, even within one process
+        assert!(WriterLock::try_acquire(&path).unwrap().is_none());
+
+        let mut db = MockDB::<Leaf>::new();
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        tree.checkpoint(&db, &path).unwrap();
+        let mut follower = CheckpointFollower::<Leaf, _>::open(&path, MockDB::new).unwrap();
+        assert!(!follower.reload_if_changed().unwrap());
+
+        let leaves: Vec<_> = (0..20)
+            .map(|i| (LeafIndex::new(i, height).unwrap(), (i as u32).hash()))
+            .collect();
+        tree.update_leaves(&mut db, &leaves).unwrap();
+        tree.checkpoint(&db, &path).unwrap();
+        drop(lock);
+
+        assert!(follower.reload_if_changed().unwrap());
+        let root = follower.tree().get_root();
+        assert_eq!(root, tree.get_root());
+        assert!(!follower.reload_if_changed().unwrap());
+        let index = LeafIndex::new(5, height).unwrap();
+        let proof = follower
+            .tree()
+            .prove_with_given_root(follower.store(), root, index)
+            .unwrap();
+        proof.verify(&5u32, index, root).unwrap();
+
+        // a new checkpoint is picked up even with the old modification time
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        let mut rewritten = tree.clone();
+        rewritten.update_leaf(&mut db, index, 6u32.hash()).unwrap();
+        rewritten.checkpoint(&db, &path).unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(modified).unwrap();
+        assert!(follower.reload_if_changed().unwrap());
+        assert_eq!(follower.tree().get_root(), rewritten.get_root());
+
+        assert!(WriterLock::try_acquire(&path).unwrap().is_some());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
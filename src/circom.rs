@@ -0,0 +1,142 @@
+#[cfg(feature = "zkp")]
+use intmax2_zkp::utils::poseidon_hash_out::PoseidonHashOut;
+use serde_json::{json, Value};
+
+use crate::{
+    bn254_poseidon_hasher::Fr,
+    error::DbTreeError,
+    leaf_index::LeafIndex,
+    merkle_tree::{MerkleProof, MerkleTree},
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+// A hash as a circom input signal, in the form snarkjs reads from
+// `input.json`: a decimal field element string, or an array of them for
+// hashes that span several field elements.
+pub trait CircomHash {
+    fn to_circom(&self) -> Value;
+}
+
+impl CircomHash for Fr {
+    fn to_circom(&self) -> Value {
+        Value::String(self.to_string())
+    }
+}
+
+// 4 Goldilocks elements, for circuits that emulate the plonky2 field
+#[cfg(feature = "zkp")]
+impl CircomHash for PoseidonHashOut {
+    fn to_circom(&self) -> Value {
+        Value::Array(
+            self.to_u64_vec()
+                .into_iter()
+                .map(|x| Value::String(x.to_string()))
+                .collect(),
+        )
+    }
+}
+
+// `pathIndices` of the leaf at `index`, from the leaf level up: 1 if the
+// node on the path is a right child, i.e. its sibling goes on the left.
+pub fn path_indices(index: LeafIndex) -> Vec<u8> {
+    index.to_le_bits().into_iter().map(u8::from).collect()
+}
+
+impl<V: Leafable> MerkleProof<V>
+where
+    <V::Hasher as TreeHasher>::HashOut: CircomHash,
+{
+    // The witness of `leaf_hash` at `index` in the tree with `root`, shaped
+    // as the inputs of circomlib style inclusion templates and zk-kit proofs:
+    // `{ "root", "leaf", "siblings", "pathIndices" }`, siblings from the leaf
+    // level up. The proof is not checked against `root`.
+    pub fn to_circom(
+        &self,
+        leaf_hash: &<V::Hasher as TreeHasher>::HashOut,
+        index: impl Into<LeafIndex>,
+        root: &<V::Hasher as TreeHasher>::HashOut,
+    ) -> Value {
+        let index = index.into();
+        assert_eq!(
+            index.height(),
+            self.height(),
+            "index height does not match the proof height"
+        );
+        let siblings: Vec<Value> = self.siblings.iter().map(CircomHash::to_circom).collect();
+        json!({
+            "root": root.to_circom(),
+            "leaf": leaf_hash.to_circom(),
+            "siblings": siblings,
+            "pathIndices": path_indices(index),
+        })
+    }
+}
+
+impl<V: Leafable> MerkleTree<V>
+where
+    <V::Hasher as TreeHasher>::HashOut: CircomHash,
+{
+    // `to_circom` of the current leaf at `index` against the current root.
+    pub fn prove_circom<S: NodeStore<V>>(
+        &self,
+        db: &S,
+        index: impl Into<LeafIndex>,
+    ) -> Result<Value, DbTreeError> {
+        let index = index.into();
+        let proof = self.prove(index)?;
+        let leaf_hash = self.get_node_hash_with_store(db, index.to_node_key())?;
+        Ok(proof.to_circom(&leaf_hash, index, &self.get_root()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        bn254_poseidon_hasher::{poseidon_hash, Bn254PoseidonLeaf, Fr},
+        leaf_index::LeafIndex,
+        merkle_tree::MerkleTree,
+        mock_db::MockDB,
+        traits::Leafable,
+    };
+
+    #[test]
+    fn test_circom_witness() {
+        let height = 4;
+        let mut db = MockDB::<Bn254PoseidonLeaf>::new();
+        let empty_leaf_hash = Bn254PoseidonLeaf::empty_leaf().hash();
+        let mut tree = MerkleTree::new(&mut db, height, empty_leaf_hash);
+        for i in 0..6 {
+            tree.update_leaf_at(&mut db, i, Fr::from_u64(i + 10))
+                .unwrap();
+        }
+        let index = LeafIndex::new(5, height).unwrap();
+        let witness = tree.prove_circom(&db, index).unwrap();
+        assert_eq!(witness["leaf"].as_str(), Some("15"));
+        assert_eq!(
+            witness["root"].as_str(),
+            Some(tree.get_root().to_string().as_str())
+        );
+        let path_indices: Vec<u64> = witness["pathIndices"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|bit| bit.as_u64().unwrap())
+            .collect();
+        assert_eq!(path_indices, vec![1, 0, 1, 0]);
+
+        // what the circuit computes from the exported signals
+        let mut node: Fr = witness["leaf"].as_str().unwrap().parse().unwrap();
+        let siblings = witness["siblings"].as_array().unwrap();
+        assert_eq!(siblings.len(), height);
+        for (sibling, bit) in siblings.iter().zip(path_indices) {
+            let sibling: Fr = sibling.as_str().unwrap().parse().unwrap();
+            node = if bit == 1 {
+                poseidon_hash(sibling, node)
+            } else {
+                poseidon_hash(node, sibling)
+            };
+        }
+        assert_eq!(node, tree.get_root());
+    }
+}
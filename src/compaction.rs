@@ -0,0 +1,159 @@
+use hashbrown::HashSet;
+
+use crate::{
+    merkle_tree::MerkleTree,
+    node::Node,
+    node_key::NodeKey,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+// What a compaction kept: the live nodes, and the non-empty leaves under the
+// kept roots by position.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CompactionStats {
+    pub nodes: usize,
+    pub leaves: usize,
+}
+
+// Nodes reachable from a set of roots, each once, and the non-empty leaves
+// under them by position.
+pub(crate) struct LiveNodes<V: Leafable> {
+    pub(crate) nodes: Vec<(<V::Hasher as TreeHasher>::HashOut, Node<V>)>,
+    pub(crate) leaves: Vec<(u128, <V::Hasher as TreeHasher>::HashOut)>,
+}
+
+impl<V: Leafable> MerkleTree<V> {
+    // Copies into `fresh` the nodes reachable from `roots`, together with the
+    // data and metadata of their leaves, so that swapping `fresh` in for `db`
+    // drops every node only superseded versions use. `roots` are versions of
+    // this tree, i.e. of its height and empty leaf; the zero hashes are always
+    // kept. Fails if a node under one of the roots is missing from `db`, in
+    // which case `fresh` is left as it was.
+    pub fn copy_live_nodes<S: NodeStore<V>, T: NodeStore<V>>(
+        &self,
+        db: &S,
+        fresh: &mut T,
+        roots: &[<V::Hasher as TreeHasher>::HashOut],
+    ) -> anyhow::Result<CompactionStats> {
+        let live = self.live_nodes(db, roots)?;
+        Ok(insert_live_nodes(db, fresh, live))
+    }
+
+    pub(crate) fn live_nodes<S: NodeStore<V>>(
+        &self,
+        db: &S,
+        roots: &[<V::Hasher as TreeHasher>::HashOut],
+    ) -> anyhow::Result<LiveNodes<V>> {
+        let mut nodes = vec![];
+        let mut leaves = vec![];
+        let mut copied = HashSet::new();
+        for hash in &self.zero_hashes[..self.height] {
+            if let Some(node) = db.get(hash.clone()) {
+                copied.insert(hash.clone());
+                nodes.push((hash.clone(), node));
+            }
+        }
+        // a subtree shared between positions is visited once per position, as
+        // leaf metadata is kept by index
+        let mut visited = HashSet::new();
+        let mut stack: Vec<_> = roots
+            .iter()
+            .map(|root| (NodeKey::root(), root.clone()))
+            .collect();
+        while let Some((key, hash)) = stack.pop() {
+            if hash == self.zero_hashes[key.depth()] || !visited.insert((key, hash.clone())) {
+                continue;
+            }
+            if key.depth() == self.height {
+                leaves.push((key.index, hash));
+                continue;
+            }
+            let node = db
+                .get(hash.clone())
+                .ok_or_else(|| anyhow::anyhow!("cannot find node at depth {}", key.depth()))?;
+            stack.push((key.child(false), node.left.clone()));
+            stack.push((key.child(true), node.right.clone()));
+            if copied.insert(hash.clone()) {
+                nodes.push((hash, node));
+            }
+        }
+        Ok(LiveNodes { nodes, leaves })
+    }
+}
+
+// Inserts `live` into `fresh`, with the data and metadata `db` holds for its
+// leaves.
+pub(crate) fn insert_live_nodes<V: Leafable, S: NodeStore<V>, T: NodeStore<V>>(
+    db: &S,
+    fresh: &mut T,
+    live: LiveNodes<V>,
+) -> CompactionStats {
+    for (index, hash) in &live.leaves {
+        if let Some(data) = db.get_leaf_data(hash.clone()) {
+            fresh.insert_leaf_data(hash.clone(), data);
+        }
+        if let Some(metadata) = db.get_leaf_metadata(hash.clone(), *index) {
+            fresh.insert_leaf_metadata(hash.clone(), *index, metadata);
+        }
+    }
+    let stats = CompactionStats {
+        nodes: live.nodes.len(),
+        leaves: live.leaves.len(),
+    };
+    fresh.insert_batch(live.nodes);
+    stats
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, node_store::NodeStore,
+        traits::Leafable,
+    };
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_copy_live_nodes() {
+        let height = 8;
+        let mut db = MockDB::<Leaf>::new();
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        let index = move |i: u128| LeafIndex::new(i, height).unwrap();
+        tree.update_leaf(&mut db, index(3), 1u32.hash()).unwrap();
+        db.insert_leaf_metadata(1u32.hash(), 3, b"kept".to_vec());
+        let kept = tree.get_root();
+        for i in 0..20 {
+            tree.update_leaf(&mut db, index(i * 11), (i as u32 + 2).hash())
+                .unwrap();
+        }
+        let root = tree.get_root();
+
+        let mut fresh = MockDB::new();
+        let stats = tree
+            .copy_live_nodes(&db, &mut fresh, &[kept, root])
+            .unwrap();
+        assert_eq!(stats.nodes, fresh.len());
+        assert_eq!(stats.leaves, 21);
+        assert!(fresh.len() < db.len());
+        for (version, i) in [(kept, 3), (root, 3), (root, 88)] {
+            assert_eq!(
+                tree.prove_with_given_root(&fresh, version, index(i)),
+                tree.prove_with_given_root(&db, version, index(i))
+            );
+        }
+        assert_eq!(
+            fresh.get_leaf_metadata(1u32.hash(), 3),
+            Some(b"kept".to_vec())
+        );
+        // the new version still updates on the fresh store
+        tree.update_leaf(&mut fresh, index(5), 9u32.hash()).unwrap();
+        assert!(tree.verify_integrity(&fresh, tree.get_root()).is_ok());
+
+        // a root whose nodes are gone is an error
+        let mut empty = MockDB::new();
+        assert!(tree
+            .copy_live_nodes(&empty.clone(), &mut empty, &[root])
+            .is_err());
+    }
+}
@@ -0,0 +1,263 @@
+use std::sync::{RwLock, RwLockWriteGuard};
+
+use crate::{
+    batch_hasher::BatchHasher,
+    error::DbTreeError,
+    leaf_index::LeafIndex,
+    merkle_tree::{MerkleProof, MerkleTree},
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+    zero_hashes::ZeroHashes,
+};
+
+// A `MerkleTree` split at `shard_depth` into `2^shard_depth` subtrees, each
+// with its own store behind its own lock, under a top tree whose leaves are
+// the subtree roots. Reads of any shard run concurrently, writes to
+// different shards only serialize on the short update of the top tree, and
+// every method takes `&self`, so the tree can be shared between threads
+// (e.g. through an `Arc`) whenever the leaves and the store are Send + Sync.
+//
+// Locks are always taken shard first, then top, and a writer keeps its shard
+// locked until the top tree holds the new shard root, so a proof read under
+// both locks is always a proof against the root it is returned with.
+pub struct ConcurrentMerkleTree<V: Leafable, S: NodeStore<V>> {
+    height: usize,
+    shard_depth: usize,
+    shards: Vec<RwLock<(MerkleTree<V>, S)>>,
+    top: RwLock<(MerkleTree<V>, S)>,
+}
+
+// compile time check of the Send + Sync guarantee above
+const _: () = {
+    fn assert_send_sync<T: Send + Sync>() {}
+    #[allow(dead_code)]
+    fn check<V, S>()
+    where
+        V: Leafable + Send + Sync,
+        <V::Hasher as TreeHasher>::HashOut: Send + Sync,
+        S: NodeStore<V> + Send + Sync,
+    {
+        assert_send_sync::<ConcurrentMerkleTree<V, S>>();
+    }
+};
+
+impl<V: Leafable, S: NodeStore<V>> ConcurrentMerkleTree<V, S> {
+    // `new_store` is called once per shard and once for the top tree.
+    pub fn new(
+        height: usize,
+        shard_depth: usize,
+        empty_leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        mut new_store: impl FnMut() -> S,
+    ) -> Self {
+        assert!(shard_depth <= height, "shard depth larger than height");
+        assert!(shard_depth < 32, "too many shards");
+        let shard_height = height - shard_depth;
+        let zero_hashes = ZeroHashes::<V::Hasher>::new(empty_leaf_hash, height);
+        let shards = (0..1usize << shard_depth)
+            .map(|_| {
+                let mut db = new_store();
+                let tree = MerkleTree::with_zero_hashes(&mut db, shard_height, &zero_hashes);
+                RwLock::new((tree, db))
+            })
+            .collect();
+        let mut db = new_store();
+        let top = MerkleTree::new(&mut db, shard_depth, zero_hashes.get(shard_height));
+        Self {
+            height,
+            shard_depth,
+            shards,
+            top: RwLock::new((top, db)),
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn get_root(&self) -> <V::Hasher as TreeHasher>::HashOut {
+        self.top
+            .read()
+            .expect("top tree lock poisoned")
+            .0
+            .get_root()
+    }
+
+    // Returns the new root.
+    pub fn update_leaf(
+        &self,
+        index: impl Into<LeafIndex>,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Result<<V::Hasher as TreeHasher>::HashOut, DbTreeError> {
+        let (shard, local) = self.split_index(index.into())?;
+        let mut guard = self.lock_shard(shard);
+        let (tree, db) = &mut *guard;
+        tree.update_leaf(db, local, leaf_hash)?;
+        Ok(self.publish_shard_root(shard, tree.get_root()))
+    }
+
+    // Updates the leaves shard by shard; each shard is updated in one batch
+    // and its root published once. Fails without changing the tree if an
+    // index is invalid.
+    pub fn update_leaves(
+        &self,
+        leaves: &[(LeafIndex, <V::Hasher as TreeHasher>::HashOut)],
+    ) -> Result<<V::Hasher as TreeHasher>::HashOut, DbTreeError>
+    where
+        V::Hasher: BatchHasher,
+    {
+        let mut by_shard = vec![vec![]; self.shards.len()];
+        for (index, leaf_hash) in leaves {
+            let (shard, local) = self.split_index(*index)?;
+            by_shard[shard].push((local, leaf_hash.clone()));
+        }
+        for (shard, leaves) in by_shard.into_iter().enumerate() {
+            if leaves.is_empty() {
+                continue;
+            }
+            let mut guard = self.lock_shard(shard);
+            let (tree, db) = &mut *guard;
+            tree.update_leaves(db, &leaves)?;
+            self.publish_shard_root(shard, tree.get_root());
+        }
+        Ok(self.get_root())
+    }
+
+    // A proof of `index` together with the root it verifies against.
+    pub fn prove(
+        &self,
+        index: impl Into<LeafIndex>,
+    ) -> Result<(MerkleProof<V>, <V::Hasher as TreeHasher>::HashOut), DbTreeError> {
+        let (shard, local) = self.split_index(index.into())?;
+        let guard = self.shards[shard].read().expect("shard lock poisoned");
+        let mut siblings = guard.0.prove(local)?.siblings;
+        let top = self.top.read().expect("top tree lock poisoned");
+        let top_index = LeafIndex::new(shard as u128, self.shard_depth)?;
+        siblings.extend(top.0.prove(top_index)?.siblings);
+        Ok((MerkleProof { siblings }, top.0.get_root()))
+    }
+
+    pub fn into_shards(self) -> Vec<(MerkleTree<V>, S)> {
+        self.shards
+            .into_iter()
+            .map(|shard| shard.into_inner().expect("shard lock poisoned"))
+            .collect()
+    }
+
+    fn split_index(&self, index: LeafIndex) -> Result<(usize, LeafIndex), DbTreeError> {
+        split_shard_index(index, self.height, self.shard_depth)
+    }
+
+    fn lock_shard(&self, shard: usize) -> RwLockWriteGuard<'_, (MerkleTree<V>, S)> {
+        self.shards[shard].write().expect("shard lock poisoned")
+    }
+
+    // Must be called with the shard still locked.
+    fn publish_shard_root(
+        &self,
+        shard: usize,
+        root: <V::Hasher as TreeHasher>::HashOut,
+    ) -> <V::Hasher as TreeHasher>::HashOut {
+        let mut top = self.top.write().expect("top tree lock poisoned");
+        let (tree, db) = &mut *top;
+        let index = LeafIndex::new(shard as u128, self.shard_depth).unwrap();
+        tree.update_leaf_unchecked(db, index, root);
+        tree.get_root()
+    }
+}
+
+// The shard of `index` in a tree of `height` split at `shard_depth`, and the
+// index within the shard.
+pub(crate) fn split_shard_index(
+    index: LeafIndex,
+    height: usize,
+    shard_depth: usize,
+) -> Result<(usize, LeafIndex), DbTreeError> {
+    if index.height() != height {
+        return Err(DbTreeError::InvalidIndexLength {
+            expected: height,
+            actual: index.height(),
+        });
+    }
+    let shard_height = height - shard_depth;
+    let shard = index.index().checked_shr(shard_height as u32).unwrap_or(0);
+    let local = index.index() ^ shard.checked_shl(shard_height as u32).unwrap_or(0);
+    Ok((shard as usize, LeafIndex::new(local, shard_height)?))
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use std::{sync::Arc, thread};
+
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable,
+    };
+
+    use super::{split_shard_index, ConcurrentMerkleTree};
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_concurrent_tree() {
+        let height = 10;
+        let empty_leaf_hash = Leaf::empty_leaf().hash();
+        let tree = Arc::new(ConcurrentMerkleTree::<Leaf, _>::new(
+            height,
+            2,
+            empty_leaf_hash,
+            MockDB::new,
+        ));
+        let index = move |i: u32| LeafIndex::new(i as u128 * 7 % 1024, height).unwrap();
+
+        let handles: Vec<_> = (0..4u32)
+            .map(|t| {
+                let tree = tree.clone();
+                thread::spawn(move || {
+                    for i in (t * 50)..(t * 50 + 50) {
+                        tree.update_leaf(index(i), (i + 1).hash()).unwrap();
+                        let (proof, root) = tree.prove(index(i)).unwrap();
+                        proof.verify(&(i + 1), index(i), root).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let mut db = MockDB::<Leaf>::new();
+        let mut expected = MerkleTree::new(&mut db, height, empty_leaf_hash);
+        for i in 0..200u32 {
+            expected
+                .update_leaf(&mut db, index(i), (i + 1).hash())
+                .unwrap();
+        }
+        assert_eq!(tree.get_root(), expected.get_root());
+        let (proof, root) = tree.prove(index(3)).unwrap();
+        assert_eq!(root, expected.get_root());
+        assert_eq!(proof, expected.prove(index(3)).unwrap());
+
+        let leaves: Vec<_> = (0..8u32).map(|i| (index(i), 1000u32.hash())).collect();
+        let root = tree.update_leaves(&leaves).unwrap();
+        expected.update_leaves(&mut db, &leaves).unwrap();
+        assert_eq!(root, expected.get_root());
+    }
+
+    #[test]
+    fn test_split_shard_index() {
+        let index = LeafIndex::new(u128::MAX, 128).unwrap();
+        assert_eq!(split_shard_index(index, 128, 0), Ok((0, index)));
+        let index = LeafIndex::new(0b1011, 4).unwrap();
+        assert_eq!(
+            split_shard_index(index, 4, 2),
+            Ok((0b10, LeafIndex::new(0b11, 2).unwrap()))
+        );
+        assert_eq!(
+            split_shard_index(index, 4, 4),
+            Ok((0b1011, LeafIndex::new(0, 0).unwrap()))
+        );
+    }
+}
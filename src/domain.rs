@@ -0,0 +1,133 @@
+use std::marker::PhantomData;
+
+#[cfg(feature = "zkp")]
+use intmax2_zkp::utils::{
+    leafable_hasher::PoseidonLeafableHasher, poseidon_hash_out::PoseidonHashOut,
+};
+
+use crate::{
+    batch_hasher::BatchHasher,
+    traits::{Leafable, TreeHasher},
+};
+
+pub const LEAF_TAG: u8 = 0;
+pub const NODE_TAG: u8 = 1;
+
+// Hashers that can mix a tag into `two_to_one`. Different tags must give
+// independent hash functions.
+pub trait TaggedHasher: TreeHasher {
+    fn hash_tagged(tag: u8, left: Self::HashOut, right: Self::HashOut) -> Self::HashOut;
+}
+
+// `H` with leaves and internal nodes hashed under different tags, so that an
+// internal node can not be presented as a leaf of a shorter tree. Use it
+// through `DomainSeparatedLeaf`, which tags the leaf hashes; the empty leaf and
+// therefore the zero hashes are tagged as well.
+#[derive(Clone, Debug)]
+pub struct DomainSeparated<H>(PhantomData<H>);
+
+impl<H: TaggedHasher> TreeHasher for DomainSeparated<H> {
+    type HashOut = H::HashOut;
+
+    fn two_to_one(left: H::HashOut, right: H::HashOut) -> H::HashOut {
+        H::hash_tagged(NODE_TAG, left, right)
+    }
+}
+
+impl<H: TaggedHasher> BatchHasher for DomainSeparated<H> {}
+
+// A leaf of a `DomainSeparated` tree. Its hash is `leaf_hash` of the hash of
+// `V`, so proofs are verified against the wrapped value.
+#[derive(Clone, Debug)]
+pub struct DomainSeparatedLeaf<V>(pub V);
+
+impl<V: Leafable> Leafable for DomainSeparatedLeaf<V>
+where
+    V::Hasher: TaggedHasher,
+{
+    type Hasher = DomainSeparated<V::Hasher>;
+
+    fn empty_leaf() -> Self {
+        Self(V::empty_leaf())
+    }
+
+    fn hash(&self) -> <Self::Hasher as TreeHasher>::HashOut {
+        leaf_hash::<V::Hasher>(self.0.hash())
+    }
+}
+
+// Tags an untagged leaf hash. Leaf hashes passed to `update_leaf` of a
+// `DomainSeparated` tree have to go through this.
+pub fn leaf_hash<H: TaggedHasher>(hash: H::HashOut) -> H::HashOut {
+    H::hash_tagged(LEAF_TAG, hash, H::HashOut::default())
+}
+
+#[cfg(feature = "zkp")]
+impl TaggedHasher for PoseidonLeafableHasher {
+    fn hash_tagged(tag: u8, left: PoseidonHashOut, right: PoseidonHashOut) -> PoseidonHashOut {
+        let mut inputs = vec![tag as u64];
+        inputs.extend(left.to_u64_vec());
+        inputs.extend(right.to_u64_vec());
+        PoseidonHashOut::hash_inputs_u64(&inputs)
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use intmax2_zkp::utils::leafable_hasher::PoseidonLeafableHasher;
+
+    use crate::{
+        batch_hasher::BatchHasher,
+        merkle_tree::{MerkleProof, MerkleTree},
+        mock_db::MockDB,
+        node_key::NodeKey,
+        traits::{HashLeaf, Leafable, TreeHasher},
+    };
+
+    use super::DomainSeparatedLeaf;
+
+    fn internal_node_as_leaf<V: Leafable>(
+        leaves: &[V],
+        forged: impl Fn(<V::Hasher as TreeHasher>::HashOut) -> V,
+    ) -> bool
+    where
+        V::Hasher: BatchHasher,
+    {
+        let height = 2;
+        let mut mock_db = MockDB::<V>::new();
+        let leaf_hashes: Vec<_> = leaves.iter().map(|leaf| leaf.hash()).collect();
+        let empty_leaf_hash = V::empty_leaf().hash();
+        let merkle_tree =
+            MerkleTree::from_leaf_hashes(&mut mock_db, height, empty_leaf_hash, &leaf_hashes);
+        let root = merkle_tree.get_root();
+
+        // claim that the left child of the root is leaf 0 of a height 1 tree
+        let node = merkle_tree.get_node_hash_unchecked(NodeKey::new(1, 0));
+        let sibling = merkle_tree.get_node_hash_unchecked(NodeKey::new(1, 1));
+        let proof = MerkleProof::<V> {
+            siblings: vec![sibling],
+        };
+        proof.verify_at(&forged(node), 0, root).is_ok()
+    }
+
+    #[test]
+    fn test_domain_separation() {
+        let plain: Vec<_> = (0..4u32)
+            .map(|i| HashLeaf::<PoseidonLeafableHasher>(i.hash()))
+            .collect();
+        assert!(internal_node_as_leaf(&plain, HashLeaf));
+
+        // the forger can only choose the wrapped value, here a pre-hashed leaf
+        // whose hash is the internal node
+        let separated: Vec<_> = (0..4u32)
+            .map(|i| DomainSeparatedLeaf(HashLeaf::<PoseidonLeafableHasher>(i.hash())))
+            .collect();
+        assert!(!internal_node_as_leaf(&separated, |node| {
+            DomainSeparatedLeaf(HashLeaf(node))
+        }));
+
+        let leaf = DomainSeparatedLeaf(5u32);
+        assert_ne!(leaf.hash(), 5u32.hash());
+        assert_ne!(DomainSeparatedLeaf::<u32>::empty_leaf().hash(), 0u32.hash());
+    }
+}
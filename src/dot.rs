@@ -0,0 +1,190 @@
+use std::{fmt::Debug, io::Write};
+
+use hashbrown::{HashMap, HashSet};
+
+use crate::{
+    merkle_tree::MerkleTree,
+    mock_db::MockDB,
+    node_key::NodeKey,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+// Graphviz DOT rendering of the node graph, to inspect structural issues by
+// eye: `dot -Tsvg tree.dot > tree.svg`. Nodes are labelled by the first
+// characters of the `Debug` output of their hash, and drawn once per hash,
+// so a subtree shared between positions shows as a node with several
+// parents.
+
+// characters of the hash shown in a label
+const LABEL_LEN: usize = 12;
+
+impl<V: Leafable> MerkleTree<V> {
+    // Renders the nodes below `root`, labelled by depth. Empty subtrees are
+    // drawn dotted, once per depth. Nodes missing from `db` are drawn dashed
+    // in red instead of failing, so a broken store can be rendered too.
+    pub fn export_dot<S: NodeStore<V>, W: Write>(
+        &self,
+        db: &S,
+        root: <V::Hasher as TreeHasher>::HashOut,
+        mut writer: W,
+    ) -> anyhow::Result<()> {
+        let mut ids = HashMap::new();
+        let mut edges = vec![];
+        let mut stack = vec![(NodeKey::root(), root)];
+        writeln!(writer, "digraph tree {{")?;
+        writeln!(writer, "  node [shape=box, fontname=\"monospace\"];")?;
+        while let Some((key, hash)) = stack.pop() {
+            if ids.contains_key(&hash) {
+                continue;
+            }
+            let id = ids.len();
+            ids.insert(hash.clone(), id);
+            let label = format!("depth {}\\n{}", key.depth(), short(&hash));
+            if hash == self.zero_hashes[key.depth()] {
+                writeln!(
+                    writer,
+                    "  n{} [label=\"depth {}\\nempty\", style=dotted];",
+                    id,
+                    key.depth()
+                )?;
+            } else if key.depth() == self.height {
+                writeln!(
+                    writer,
+                    "  n{} [label=\"leaf\\n{}\", shape=ellipse];",
+                    id,
+                    short(&hash)
+                )?;
+            } else if let Some(node) = db.get(hash.clone()) {
+                writeln!(writer, "  n{} [label=\"{}\"];", id, label)?;
+                stack.push((key.child(true), node.right.clone()));
+                stack.push((key.child(false), node.left.clone()));
+                edges.push((id, node.left, "0"));
+                edges.push((id, node.right, "1"));
+            } else {
+                writeln!(
+                    writer,
+                    "  n{} [label=\"{}\\nmissing\", style=dashed, color=red];",
+                    id, label
+                )?;
+            }
+        }
+        for (parent, child, bit) in edges {
+            writeln!(
+                writer,
+                "  n{} -> n{} [label=\"{}\"];",
+                parent, ids[&child], bit
+            )?;
+        }
+        writeln!(writer, "}}")?;
+        Ok(())
+    }
+}
+
+impl<V: Leafable> MockDB<V> {
+    // Renders every stored node, reachable or not. Depths are not known
+    // without a root, so nodes are labelled by hash only. Nodes that no
+    // stored node points to, roots and orphans, are drawn bold, and child
+    // hashes that are not stored nodes, leaves or missing nodes, as ellipses.
+    pub fn export_dot<W: Write>(&self, mut writer: W) -> anyhow::Result<()> {
+        let mut ids = HashMap::new();
+        let mut children = HashSet::new();
+        for (hash, node) in &self.nodes {
+            let next = ids.len();
+            ids.entry(hash.clone()).or_insert(next);
+            children.insert(node.left.clone());
+            children.insert(node.right.clone());
+        }
+        writeln!(writer, "digraph nodes {{")?;
+        writeln!(writer, "  node [shape=box, fontname=\"monospace\"];")?;
+        for (hash, id) in &ids {
+            let style = if children.contains(hash) {
+                ""
+            } else {
+                ", style=bold"
+            };
+            writeln!(writer, "  n{} [label=\"{}\"{}];", id, short(hash), style)?;
+        }
+        let mut edges = vec![];
+        for (hash, node) in &self.nodes {
+            for (child, bit) in [(&node.left, "0"), (&node.right, "1")] {
+                let id = match ids.get(child) {
+                    Some(id) => *id,
+                    None => {
+                        let id = ids.len();
+                        ids.insert(child.clone(), id);
+                        writeln!(
+                            writer,
+                            "  n{} [label=\"{}\", shape=ellipse];",
+                            id,
+                            short(child)
+                        )?;
+                        id
+                    }
+                };
+                edges.push((ids[hash], id, bit));
+            }
+        }
+        for (parent, child, bit) in edges {
+            writeln!(writer, "  n{} -> n{} [label=\"{}\"];", parent, child, bit)?;
+        }
+        writeln!(writer, "}}")?;
+        Ok(())
+    }
+}
+
+// the first `LABEL_LEN` characters of the `Debug` output of `hash`, escaped
+// for a DOT string
+fn short<H: Debug>(hash: &H) -> String {
+    let debug = format!("{:?}", hash);
+    let mut label: String = debug.chars().take(LABEL_LEN).collect();
+    if label.len() < debug.len() {
+        label.push_str("..");
+    }
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable,
+    };
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_export_dot() {
+        let height = 2;
+        let mut db = MockDB::<Leaf>::new();
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        // both halves hash the same, so the root has one child drawn twice
+        for i in [0u128, 2] {
+            tree.update_leaf(&mut db, LeafIndex::new(i, height).unwrap(), 5u32.hash())
+                .unwrap();
+        }
+        let mut dot = vec![];
+        tree.export_dot(&db, tree.get_root(), &mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.starts_with("digraph tree {"));
+        // the root, the shared node, the leaf and the empty leaf
+        assert_eq!(
+            dot.matches(" [label=\"").count() - dot.matches("->").count(),
+            4
+        );
+        assert_eq!(dot.matches("->").count(), 4);
+        assert_eq!(dot.matches("style=dotted").count(), 1);
+        assert!(!dot.contains("missing"));
+
+        // a store without the root's children
+        let mut dot = vec![];
+        tree.export_dot(&MockDB::new(), tree.get_root(), &mut dot)
+            .unwrap();
+        assert!(String::from_utf8(dot).unwrap().contains("missing"));
+
+        let mut dot = vec![];
+        db.export_dot(&mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.starts_with("digraph nodes {"));
+        assert!(dot.contains("style=bold"));
+    }
+}
@@ -0,0 +1,277 @@
+use std::io::{BufRead, Lines, Write};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    archive::collect_reachable,
+    batch_hasher::BatchHasher,
+    leaf_index::LeafIndex,
+    merkle_tree::MerkleTree,
+    node::Node,
+    node_key::{NodeKey, MAX_HEIGHT},
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+pub const DUMP_FORMAT_VERSION: u32 = 1;
+
+// A dump is a stream of JSON lines: the header, one record per node and per
+// non-empty leaf, and a closing record with the CRC32 of the record lines
+// before it. Unlike an archive it carries the serialized leaves, and it is
+// imported back into a live tree over any store.
+//
+// Dumps of every earlier format version keep being imported, so bump
+// `DUMP_FORMAT_VERSION` and keep reading the old layout when it changes.
+#[derive(Serialize, Deserialize)]
+struct DumpHeader<H> {
+    format_version: u32,
+    height: usize,
+    // `two_to_one(default, default)`: identifies the hash function by what it
+    // computes, so a dump is never imported under a different one, and
+    // renaming or moving the hasher type does not break old dumps
+    hasher_id: H,
+    empty_leaf_hash: H,
+    root: H,
+    num_nodes: usize,
+    num_leaves: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum DumpRecord<H> {
+    // (hash, left, right)
+    Node(H, H, H),
+    Leaf {
+        index: u128,
+        hash: H,
+        data: Option<Vec<u8>>,
+    },
+    End {
+        checksum: u32,
+    },
+}
+
+pub(crate) fn hasher_id<H: TreeHasher>() -> H::HashOut {
+    H::two_to_one(H::HashOut::default(), H::HashOut::default())
+}
+
+impl<V: Leafable> MerkleTree<V>
+where
+    <V::Hasher as TreeHasher>::HashOut: Serialize + DeserializeOwned,
+{
+    // Writes the current version of the tree, with the serialized leaves `db`
+    // holds, as a dump that `import_dump` loads.
+    pub fn export_dump<S: NodeStore<V>, W: Write>(
+        &self,
+        db: &S,
+        mut writer: W,
+    ) -> anyhow::Result<()> {
+        let root = self.get_root();
+        let (leaves, nodes) = collect_reachable(db, &self.zero_hashes, root.clone())?;
+        let header = DumpHeader {
+            format_version: DUMP_FORMAT_VERSION,
+            height: self.height,
+            hasher_id: hasher_id::<V::Hasher>(),
+            empty_leaf_hash: self.zero_hashes[self.height].clone(),
+            root,
+            num_nodes: nodes.len(),
+            num_leaves: leaves.len(),
+        };
+        serde_json::to_writer(&mut writer, &header)?;
+        writer.write_all(b"\n")?;
+
+        let records = nodes
+            .into_iter()
+            .map(|(hash, left, right)| DumpRecord::Node(hash, left, right))
+            .chain(
+                leaves
+                    .into_iter()
+                    .map(|(index_bits, hash)| DumpRecord::Leaf {
+                        index: NodeKey::from_index_bits(&index_bits).index,
+                        data: db.get_leaf_data(hash.clone()),
+                        hash,
+                    }),
+            );
+        write_records(&mut writer, records)
+    }
+
+    // Loads a dump written by `export_dump` into `db` and returns the tree.
+    // Every node is checked against its children and the rebuilt root
+    // against the header, so a dump made with another hasher, a truncated
+    // or a corrupted one is an error. `db` may be left with part of the dump
+    // on error.
+    pub fn import_dump<S: NodeStore<V>, R: BufRead>(db: &mut S, reader: R) -> anyhow::Result<Self>
+    where
+        V::Hasher: BatchHasher,
+    {
+        let mut lines = reader.lines();
+        let header = lines
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("invalid dump: missing header"))??;
+        let header: DumpHeader<<V::Hasher as TreeHasher>::HashOut> = serde_json::from_str(&header)?;
+        anyhow::ensure!(
+            header.format_version <= DUMP_FORMAT_VERSION,
+            "unsupported dump format version {}",
+            header.format_version
+        );
+        anyhow::ensure!(
+            header.height <= MAX_HEIGHT,
+            "invalid dump: height {} is larger than {}",
+            header.height,
+            MAX_HEIGHT
+        );
+        anyhow::ensure!(
+            header.hasher_id == hasher_id::<V::Hasher>(),
+            "invalid dump: made with a different hasher"
+        );
+
+        let (nodes, leaves) = read_records(db, lines, header.height)?;
+        anyhow::ensure!(
+            nodes.len() == header.num_nodes && leaves.len() == header.num_leaves,
+            "invalid dump: expected {} nodes and {} leaves, got {} and {}",
+            header.num_nodes,
+            header.num_leaves,
+            nodes.len(),
+            leaves.len()
+        );
+
+        db.insert_batch(nodes);
+        let mut tree = Self::new(db, header.height, header.empty_leaf_hash);
+        tree.update_leaves(db, &leaves)?;
+        anyhow::ensure!(
+            tree.get_root() == header.root,
+            "invalid dump: leaves do not match the root"
+        );
+        Ok(tree)
+    }
+}
+
+// Writes `records` one per line, then the end record with their checksum.
+pub(crate) fn write_records<H: Serialize, W: Write>(
+    writer: &mut W,
+    records: impl Iterator<Item = DumpRecord<H>>,
+) -> anyhow::Result<()> {
+    let mut checksum = crc32fast::Hasher::new();
+    for record in records {
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+        checksum.update(&line);
+        writer.write_all(&line)?;
+    }
+    let end = DumpRecord::<H>::End {
+        checksum: checksum.finalize(),
+    };
+    serde_json::to_writer(&mut *writer, &end)?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+    Ok(())
+}
+
+type Records<V> = (
+    Vec<(<<V as Leafable>::Hasher as TreeHasher>::HashOut, Node<V>)>,
+    Vec<(LeafIndex, <<V as Leafable>::Hasher as TreeHasher>::HashOut)>,
+);
+
+// Reads the records written by `write_records` for a tree of `height` and
+// returns the nodes, each checked against its children, and the leaves. Leaf
+// data is inserted into `db` as it is read.
+pub(crate) fn read_records<V: Leafable, S: NodeStore<V>, B: BufRead>(
+    db: &mut S,
+    lines: Lines<B>,
+    height: usize,
+) -> anyhow::Result<Records<V>>
+where
+    <V::Hasher as TreeHasher>::HashOut: DeserializeOwned,
+{
+    let mut checksum = crc32fast::Hasher::new();
+    let mut nodes = vec![];
+    let mut leaves = vec![];
+    let mut end = None;
+    for line in lines {
+        let line = line?;
+        anyhow::ensure!(end.is_none(), "invalid dump: records after the end");
+        let record: DumpRecord<<V::Hasher as TreeHasher>::HashOut> = serde_json::from_str(&line)?;
+        match record {
+            DumpRecord::Node(hash, left, right) => {
+                anyhow::ensure!(
+                    <V::Hasher as TreeHasher>::two_to_one(left.clone(), right.clone()) == hash,
+                    "invalid dump: node hash mismatch"
+                );
+                nodes.push((hash, Node { left, right }));
+            }
+            DumpRecord::Leaf { index, hash, data } => {
+                let index = LeafIndex::new(index, height)?;
+                if let Some(data) = data {
+                    db.insert_leaf_data(hash.clone(), data);
+                }
+                leaves.push((index, hash));
+            }
+            DumpRecord::End { checksum } => {
+                end = Some(checksum);
+                continue;
+            }
+        }
+        checksum.update(line.as_bytes());
+        checksum.update(b"\n");
+    }
+    anyhow::ensure!(
+        end == Some(checksum.finalize()),
+        "invalid dump: checksum mismatch or truncated dump"
+    );
+    Ok((nodes, leaves))
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, node_store::NodeStore,
+        traits::Leafable,
+    };
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_dump_roundtrip() {
+        let height = 16;
+        let mut db = MockDB::<Leaf>::new();
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        let index = move |i: u128| LeafIndex::new(i, height).unwrap();
+        for i in 0..10 {
+            let leaf = i as u32 + 1;
+            tree.update_leaf(&mut db, index(i * 7), leaf.hash())
+                .unwrap();
+            db.insert_leaf_data(leaf.hash(), leaf.to_le_bytes().to_vec());
+        }
+
+        let mut dump = vec![];
+        tree.export_dump(&db, &mut dump).unwrap();
+        let mut imported_db = MockDB::new();
+        let imported = MerkleTree::<Leaf>::import_dump(&mut imported_db, dump.as_slice()).unwrap();
+        assert_eq!(imported.get_root(), tree.get_root());
+        assert_eq!(imported.len(), 10);
+        assert_eq!(
+            imported_db.get_leaf_data(4u32.hash()),
+            Some(4u32.to_le_bytes().to_vec())
+        );
+        assert_eq!(
+            imported
+                .prove_with_given_root(&imported_db, imported.get_root(), index(21))
+                .unwrap(),
+            tree.prove(index(21)).unwrap()
+        );
+
+        // a corrupted record, a truncated dump and a newer format are rejected
+        let text = String::from_utf8(dump).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        let swapped = text.replacen(lines[1], lines[2], 1);
+        assert_ne!(swapped, text);
+        for bad in [
+            swapped,
+            lines[..lines.len() - 1].join("\n"),
+            text.replacen("\"format_version\":1", "\"format_version\":2", 1),
+        ] {
+            let result = MerkleTree::<Leaf>::import_dump(&mut MockDB::new(), bad.as_bytes());
+            assert!(result.is_err());
+        }
+    }
+}
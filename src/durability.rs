@@ -0,0 +1,165 @@
+use std::{
+    fs::File,
+    io::{self, Seek, SeekFrom},
+    ops::{Deref, DerefMut},
+    time::{Duration, Instant},
+};
+
+// When a persistent backend syncs its writes to disk. Syncing every commit
+// costs a disk flush per commit, which hot state that can be rebuilt from
+// elsewhere may not want to pay, while an archive of proofs cannot afford to
+// lose what it acknowledged.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Durability {
+    // every commit is on disk before it returns
+    #[default]
+    SyncEveryCommit,
+    // a commit syncs itself and everything before it if the last sync is at
+    // least this old. A crash of the machine can lose the commits since the
+    // last sync; there is no background thread, so these stay unsynced until
+    // the next commit, `sync` or drop.
+    Periodic(Duration),
+    // writes are left to the OS. A crash of the process loses nothing, a
+    // crash of the machine can lose anything since the last `sync`.
+    OsBuffered,
+}
+
+// A file whose writes are synced as its durability policy says, derefs to
+// the file. It syncs on drop, except with `OsBuffered`.
+#[derive(Debug)]
+pub(crate) struct SyncedFile {
+    file: File,
+    durability: Durability,
+    last_sync: Instant,
+    unsynced: bool,
+}
+
+impl SyncedFile {
+    pub(crate) fn new(file: File, durability: Durability) -> Self {
+        Self {
+            file,
+            durability,
+            last_sync: Instant::now(),
+            unsynced: false,
+        }
+    }
+
+    pub(crate) fn durability(&self) -> Durability {
+        self.durability
+    }
+
+    pub(crate) fn set_durability(&mut self, durability: Durability) {
+        self.durability = durability;
+    }
+
+    // Called after each commit written to the file, syncs it if the policy
+    // says so.
+    pub(crate) fn committed(&mut self) -> io::Result<()> {
+        self.unsynced = true;
+        match self.durability {
+            Durability::SyncEveryCommit => self.sync(),
+            Durability::Periodic(interval) if self.last_sync.elapsed() >= interval => self.sync(),
+            _ => Ok(()),
+        }
+    }
+
+    // Appends a commit with `write` and calls `committed`. If either fails,
+    // the file is cut back to where the commit started, so that a commit
+    // reported as failed is never read back.
+    pub(crate) fn commit_with(
+        &mut self,
+        write: impl FnOnce(&mut File) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let start = self.file.seek(SeekFrom::End(0))?;
+        let result = write(&mut self.file).and_then(|()| Ok(self.committed()?));
+        if let Err(e) = result {
+            self.file.set_len(start)?;
+            self.file.seek(SeekFrom::Start(start))?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn sync(&mut self) -> io::Result<()> {
+        if self.unsynced {
+            self.file.sync_data()?;
+            self.unsynced = false;
+        }
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+}
+
+impl Deref for SyncedFile {
+    type Target = File;
+
+    fn deref(&self) -> &File {
+        &self.file
+    }
+}
+
+impl DerefMut for SyncedFile {
+    fn deref_mut(&mut self) -> &mut File {
+        &mut self.file
+    }
+}
+
+impl Drop for SyncedFile {
+    fn drop(&mut self) {
+        if self.durability != Durability::OsBuffered {
+            // nothing to report the error to
+            let _ = self.sync();
+        }
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use std::time::Duration;
+
+    use crate::{
+        atomic_commit::{JournaledStore, TransactionalStore},
+        leaf_index::LeafIndex,
+        merkle_tree::MerkleTree,
+        mock_db::MockDB,
+        traits::Leafable,
+    };
+
+    use super::Durability;
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_durability() {
+        let height = 8;
+        let dir = std::env::temp_dir().join(format!("db_tree_durability_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let index = move |i: u128| LeafIndex::new(i, height).unwrap();
+
+        for (i, durability) in [
+            Durability::SyncEveryCommit,
+            Durability::Periodic(Duration::from_millis(5)),
+            Durability::OsBuffered,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let path = dir.join(format!("tree_{}.journal", i));
+            let mut db = JournaledStore::open(MockDB::<Leaf>::new(), &path).unwrap();
+            db.set_durability(durability);
+            assert_eq!(db.durability(), durability);
+            let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+            for j in 0..3 {
+                tree.commit_leaves(&mut db, &[(index(j), (j as u32 + 1).hash())])
+                    .unwrap();
+                std::thread::sleep(Duration::from_millis(3));
+            }
+            db.sync().unwrap();
+            drop(db);
+            let db = JournaledStore::<Leaf, _>::open(MockDB::new(), &path).unwrap();
+            assert_eq!(db.committed_root(), Some(tree.get_root()));
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
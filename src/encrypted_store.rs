@@ -0,0 +1,274 @@
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Key, XChaCha20Poly1305, XNonce,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    node::Node,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+const NONCE_LEN: usize = 24;
+
+// `EncryptedStore` wraps a `NodeStore` and encrypts what it writes with
+// XChaCha20-Poly1305 under a key supplied by the caller, so that a store on a
+// shared disk holds neither the leaf data nor the shape of the tree in
+// plaintext. Hashes stay in the clear as keys, so lookups work as before and
+// the inner store can be compacted or backed up without the key.
+//
+// The inner store holds one record per hash in its leaf data: the children
+// of the node and the serialized leaf under that hash, encrypted together.
+// Leaf metadata is encrypted under its own hash and index. Each record is
+// bound to its key, so a record moved to another key, tampered with or
+// written under another encryption key fails to decrypt and reads as
+// missing.
+#[derive(Clone)]
+pub struct EncryptedStore<S> {
+    inner: S,
+    cipher: XChaCha20Poly1305,
+}
+
+// what `EncryptedStore` keeps under a hash, before encryption
+#[derive(Default, Serialize, Deserialize)]
+#[serde(bound = "H: Serialize + DeserializeOwned")]
+struct Record<H> {
+    node: Option<(H, H)>,
+    data: Option<Vec<u8>>,
+}
+
+impl<S> EncryptedStore<S> {
+    pub fn new(inner: S, key: &[u8; 32]) -> Self {
+        Self {
+            inner,
+            cipher: XChaCha20Poly1305::new(Key::from_slice(key)),
+        }
+    }
+
+    // The store holding the ciphertexts.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    // nonce followed by the ciphertext
+    fn seal(&self, plaintext: &[u8], aad: &[u8]) -> Vec<u8> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad,
+                },
+            )
+            .expect("encryption does not fail on in-memory buffers");
+        let mut sealed = nonce.as_slice().to_vec();
+        sealed.extend(ciphertext);
+        sealed
+    }
+
+    fn open(&self, sealed: &[u8], aad: &[u8]) -> Option<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad,
+                },
+            )
+            .ok()
+    }
+}
+
+impl<S> std::fmt::Debug for EncryptedStore<S>
+where
+    S: std::fmt::Debug,
+{
+    // leaves the key out
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncryptedStore")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+// the associated data binding a record to where it is stored
+fn record_aad<H: Serialize>(key: &H) -> Vec<u8> {
+    let mut aad = b"record:".to_vec();
+    aad.extend(serde_json::to_vec(key).expect("hashes serialize"));
+    aad
+}
+
+fn metadata_aad<H: Serialize>(key: &H, index: u128) -> Vec<u8> {
+    let mut aad = b"metadata:".to_vec();
+    aad.extend(index.to_le_bytes());
+    aad.extend(serde_json::to_vec(key).expect("hashes serialize"));
+    aad
+}
+
+type HashOf<V> = <<V as Leafable>::Hasher as TreeHasher>::HashOut;
+
+fn read_record<V: Leafable, S: NodeStore<V>>(
+    store: &EncryptedStore<S>,
+    key: &HashOf<V>,
+) -> Option<Record<HashOf<V>>>
+where
+    HashOf<V>: Serialize + DeserializeOwned,
+{
+    let sealed = store.inner.get_leaf_data(key.clone())?;
+    let plaintext = store.open(&sealed, &record_aad(key))?;
+    serde_json::from_slice(&plaintext).ok()
+}
+
+// Writes `record` under `key`, or removes the record once it is empty.
+fn write_record<V: Leafable, S: NodeStore<V>>(
+    store: &mut EncryptedStore<S>,
+    key: HashOf<V>,
+    record: Record<HashOf<V>>,
+) where
+    HashOf<V>: Serialize + DeserializeOwned,
+{
+    if record.node.is_none() && record.data.is_none() {
+        store.inner.remove_leaf_data(key);
+        return;
+    }
+    let plaintext = serde_json::to_vec(&record).expect("records serialize");
+    let sealed = store.seal(&plaintext, &record_aad(&key));
+    store.inner.insert_leaf_data(key, sealed);
+}
+
+impl<V: Leafable, S: NodeStore<V>> NodeStore<V> for EncryptedStore<S>
+where
+    <V::Hasher as TreeHasher>::HashOut: Serialize + DeserializeOwned,
+{
+    fn insert(&mut self, key: <V::Hasher as TreeHasher>::HashOut, node: Node<V>) {
+        let mut record = read_record(self, &key).unwrap_or_default();
+        record.node = Some((node.left, node.right));
+        write_record(self, key, record);
+    }
+
+    fn get(&self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>> {
+        let (left, right) = read_record(self, &key)?.node?;
+        Some(Node { left, right })
+    }
+
+    fn remove(&mut self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>> {
+        let mut record = read_record(self, &key)?;
+        let (left, right) = record.node.take()?;
+        write_record(self, key, record);
+        Some(Node { left, right })
+    }
+
+    fn insert_leaf_data(&mut self, leaf_hash: <V::Hasher as TreeHasher>::HashOut, data: Vec<u8>) {
+        let mut record = read_record(self, &leaf_hash).unwrap_or_default();
+        record.data = Some(data);
+        write_record(self, leaf_hash, record);
+    }
+
+    fn get_leaf_data(&self, leaf_hash: <V::Hasher as TreeHasher>::HashOut) -> Option<Vec<u8>> {
+        read_record(self, &leaf_hash)?.data
+    }
+
+    fn remove_leaf_data(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Option<Vec<u8>> {
+        let mut record = read_record(self, &leaf_hash)?;
+        let data = record.data.take()?;
+        write_record(self, leaf_hash, record);
+        Some(data)
+    }
+
+    fn insert_leaf_metadata(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+        metadata: Vec<u8>,
+    ) {
+        let sealed = self.seal(&metadata, &metadata_aad(&leaf_hash, index));
+        self.inner.insert_leaf_metadata(leaf_hash, index, sealed)
+    }
+
+    fn get_leaf_metadata(
+        &self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+    ) -> Option<Vec<u8>> {
+        let sealed = self.inner.get_leaf_metadata(leaf_hash.clone(), index)?;
+        self.open(&sealed, &metadata_aad(&leaf_hash, index))
+    }
+
+    fn remove_leaf_metadata(&mut self, leaf_hash: <V::Hasher as TreeHasher>::HashOut) {
+        self.inner.remove_leaf_metadata(leaf_hash)
+    }
+
+    fn remove_leaf_metadata_at(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+    ) {
+        self.inner.remove_leaf_metadata_at(leaf_hash, index)
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, node_key::NodeKey,
+        node_store::NodeStore, traits::Leafable,
+    };
+
+    use super::EncryptedStore;
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_encrypted_store() {
+        let height = 8;
+        let key = [7u8; 32];
+        let mut db = EncryptedStore::new(MockDB::<Leaf>::new(), &key);
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        let index = move |i: u128| LeafIndex::new(i, height).unwrap();
+        for i in 0..8 {
+            let leaf = i as u32 + 1;
+            tree.update_leaf_data(&mut db, index(i), &leaf).unwrap();
+        }
+        db.insert_leaf_metadata(3u32.hash(), 2, b"note".to_vec());
+        let root = tree.get_root();
+        assert_eq!(
+            tree.prove_with_given_root(&db, root, index(5)).unwrap(),
+            tree.prove(index(5)).unwrap()
+        );
+        assert_eq!(tree.get_leaf(&db, index(2)).unwrap(), Some(3));
+        assert_eq!(db.get_leaf_metadata(3u32.hash(), 2), Some(b"note".to_vec()));
+
+        // the inner store holds no nodes and no plaintext, only hashes
+        let node_key = tree.get_node_hash(NodeKey::new(7, 0)).unwrap();
+        let inner = db.inner();
+        assert_eq!(inner.len(), 0);
+        assert!(inner.get(node_key).is_none());
+        let sealed = inner.get_leaf_data(3u32.hash()).unwrap();
+        assert_ne!(sealed, serde_json::to_vec(&3u32).unwrap());
+        assert!(inner.get_leaf_metadata(3u32.hash(), 2).unwrap() != b"note");
+
+        // a record moved to another hash, or read under another key, is
+        // missing
+        let mut inner = db.clone().into_inner();
+        inner.insert_leaf_data(node_key, sealed);
+        let moved = EncryptedStore::new(inner, &key);
+        assert!(moved.get(node_key).is_none());
+        let other = EncryptedStore::new(db.into_inner(), &[8u8; 32]);
+        assert!(other.get_leaf_data(3u32.hash()).is_none());
+        assert!(tree.prove_with_given_root(&other, root, index(5)).is_err());
+    }
+}
@@ -0,0 +1,246 @@
+use core::fmt;
+
+use crate::node_key::NodeKey;
+
+// Errors returned by the checked `MerkleTree` API. The `_unchecked` variants
+// of the same methods panic on these conditions instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DbTreeError {
+    // `index_bits` does not have one bit per level of the tree
+    InvalidIndexLength { expected: usize, actual: usize },
+    // the leaf index does not fit in `height` bits
+    IndexOutOfRange { index: u128, height: usize },
+    // the key is deeper than the tree or its index does not fit in its depth
+    InvalidNodeKey { key: NodeKey, height: usize },
+    // the node hash was evicted by `compact`; recover it through the store
+    EvictedNode { key: NodeKey },
+    // appending `count` leaves after the last leaf does not fit in `height` bits
+    TreeFull { count: u128, height: usize },
+    // the `BigIndex` does not fit in `height` bits, or `height` is larger
+    // than `MAX_WIDE_HEIGHT`
+    BigIndexOutOfRange { height: usize },
+    // the node at `key`, whose hash is known from its parent, is not in the
+    // store
+    MissingNode { key: NodeKey },
+    // the node at `key` read from the store does not hash to its key
+    CorruptNode { key: NodeKey },
+    // the zero hash of `height` is not the hash of two zero hashes of the
+    // level below
+    InconsistentZeroHash { height: usize },
+    // the reverse index was not enabled with `enable_reverse_index`
+    ReverseIndexDisabled,
+    // a tree of `height` levels is larger than `max`, the largest height the
+    // tree type or its zero hash table supports
+    HeightTooLarge { height: usize, max: usize },
+    // `arity` is below 2, or `arity^height` leaves do not fit in a u128 index
+    InvalidArity { arity: usize, height: usize },
+    // a table of zero hashes per level has `actual` levels where `expected`
+    // are needed
+    InvalidZeroHashCount { expected: usize, actual: usize },
+}
+
+impl fmt::Display for DbTreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbTreeError::InvalidIndexLength { expected, actual } => write!(
+                f,
+                "index has {} bits but the tree height is {}",
+                actual, expected
+            ),
+            DbTreeError::IndexOutOfRange { index, height } => write!(
+                f,
+                "leaf index {} is out of range for height {}",
+                index, height
+            ),
+            DbTreeError::InvalidNodeKey { key, height } => write!(
+                f,
+                "node key (depth {}, index {}) is invalid for height {}",
+                key.depth, key.index, height
+            ),
+            DbTreeError::EvictedNode { key } => write!(
+                f,
+                "node hash at (depth {}, index {}) was evicted by compact",
+                key.depth, key.index
+            ),
+            DbTreeError::TreeFull { count, height } => write!(
+                f,
+                "cannot append {} leaves to the tree of height {}",
+                count, height
+            ),
+            DbTreeError::BigIndexOutOfRange { height } => {
+                write!(f, "leaf index is out of range for height {}", height)
+            }
+            DbTreeError::MissingNode { key } => write!(
+                f,
+                "cannot find node at (depth {}, index {}) in the store",
+                key.depth, key.index
+            ),
+            DbTreeError::CorruptNode { key } => write!(
+                f,
+                "node at (depth {}, index {}) in the store is corrupted",
+                key.depth, key.index
+            ),
+            DbTreeError::InconsistentZeroHash { height } => write!(
+                f,
+                "zero hash of height {} is not derived from the level below",
+                height
+            ),
+            DbTreeError::ReverseIndexDisabled => write!(f, "reverse index is not enabled"),
+            DbTreeError::HeightTooLarge { height, max } => {
+                write!(f, "tree height {} is larger than {}", height, max)
+            }
+            DbTreeError::InvalidArity { arity, height } => write!(
+                f,
+                "a tree of arity {} and height {} is not supported",
+                arity, height
+            ),
+            DbTreeError::InvalidZeroHashCount { expected, actual } => write!(
+                f,
+                "zero hash table has {} levels, expected {}",
+                actual, expected
+            ),
+        }
+    }
+}
+
+impl core::error::Error for DbTreeError {}
+
+// Errors returned by `MerkleTree::prove_with_given_root`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProofError {
+    // the root is neither the empty root nor a node in the store
+    UnknownRoot,
+    // the node at `depth` on the path is missing from the store
+    MissingNode { depth: usize },
+    // `index_bits` does not reach the leaves of a tree of height `expected`
+    TruncatedPath { expected: usize, actual: usize },
+    // the node at `depth` on the path failed its check on read
+    CorruptNode { depth: usize },
+}
+
+impl fmt::Display for ProofError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProofError::UnknownRoot => write!(f, "root is not in the store"),
+            ProofError::MissingNode { depth } => {
+                write!(f, "cannot find node at depth {} in the store", depth)
+            }
+            ProofError::TruncatedPath { expected, actual } => write!(
+                f,
+                "path has {} bits but the tree height is {}",
+                actual, expected
+            ),
+            ProofError::CorruptNode { depth } => {
+                write!(f, "node at depth {} in the store is corrupted", depth)
+            }
+        }
+    }
+}
+
+impl core::error::Error for ProofError {}
+
+// A node record whose children do not hash to its key, returned by stores
+// that check what they read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CorruptNode<H> {
+    pub key: H,
+    pub computed: H,
+}
+
+impl<H: fmt::Debug> fmt::Display for CorruptNode<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "corrupted node {:?}: its children hash to {:?}",
+            self.key, self.computed
+        )
+    }
+}
+
+impl<H: fmt::Debug> core::error::Error for CorruptNode<H> {}
+
+// Errors returned by `MerkleProof::verify`, generic over the hash type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VerifyError<H> {
+    // `index_bits` does not have one bit per sibling of the proof
+    InvalidIndexLength {
+        expected: usize,
+        actual: usize,
+    },
+    // the leaf index does not fit in the height of the proof
+    IndexOutOfRange {
+        index: u128,
+        height: usize,
+    },
+    // the proof leads to a different root; `index` is the leaf index read from
+    // the little endian `index_bits`, or the low 128 bits of a `BigIndex`
+    RootMismatch {
+        leaf_hash: H,
+        index: u128,
+        computed_root: H,
+        expected_root: H,
+    },
+    // two proofs of a set imply different hashes for the node at `key`, so
+    // they are not proofs of the same tree
+    InconsistentNode {
+        key: NodeKey,
+        first: H,
+        second: H,
+    },
+}
+
+impl<H: fmt::Debug> fmt::Display for VerifyError<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::InvalidIndexLength { expected, actual } => write!(
+                f,
+                "index has {} bits but the proof height is {}",
+                actual, expected
+            ),
+            VerifyError::IndexOutOfRange { index, height } => write!(
+                f,
+                "leaf index {} is out of range for height {}",
+                index, height
+            ),
+            VerifyError::RootMismatch {
+                leaf_hash,
+                index,
+                computed_root,
+                expected_root,
+            } => write!(
+                f,
+                "Merkle proof verification failed: leaf {:?} at index {} leads to root {:?}, expected {:?}",
+                leaf_hash, index, computed_root, expected_root
+            ),
+            VerifyError::InconsistentNode { key, first, second } => write!(
+                f,
+                "proofs disagree on node {:?}: {:?} and {:?}",
+                key, first, second
+            ),
+        }
+    }
+}
+
+impl<H: fmt::Debug> core::error::Error for VerifyError<H> {}
+
+// Errors returned when committing a batch prepared on a `Snapshot`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CommitError<H> {
+    // another batch was committed after the snapshot the batch was prepared
+    // on; prepare it again on the latest snapshot
+    RootMoved { expected: H, actual: H },
+}
+
+impl<H: fmt::Debug> fmt::Display for CommitError<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommitError::RootMoved { expected, actual } => write!(
+                f,
+                "batch was prepared on root {:?} but the current root is {:?}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl<H: fmt::Debug> core::error::Error for CommitError<H> {}
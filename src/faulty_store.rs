@@ -0,0 +1,383 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    atomic_commit::TransactionalStore,
+    error::CorruptNode,
+    node::Node,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+// Store operations a `FaultRule` can match. `with_node` and `contains` count
+// as `Get`, and every node of `insert_batch` as an `Insert`. Leaf data and
+// leaf metadata share `GetLeafData` and `InsertLeafData`, and removing them
+// counts as `InsertLeafData`. `Commit` is a whole transaction, keyed by its
+// root.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StoreOp {
+    Get,
+    Insert,
+    Remove,
+    GetLeafData,
+    InsertLeafData,
+    Commit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    // reads find nothing and writes panic, like a backend that went away;
+    // commits return an error
+    Fail,
+    // reads find nothing and writes are silently lost, commits included
+    Drop,
+    // the operation succeeds after sleeping
+    Delay(Duration),
+}
+
+// Injects `fault` into the operations matching `op` and, if set, `key`,
+// after letting `skip` of them through, at most `times` times.
+#[derive(Clone, Debug)]
+pub struct FaultRule<H> {
+    pub op: StoreOp,
+    pub fault: Fault,
+    pub key: Option<H>,
+    pub skip: usize,
+    pub times: Option<usize>,
+}
+
+impl<H> FaultRule<H> {
+    pub fn new(op: StoreOp, fault: Fault) -> Self {
+        Self {
+            op,
+            fault,
+            key: None,
+            skip: 0,
+            times: None,
+        }
+    }
+
+    pub fn key(mut self, key: H) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    pub fn skip(mut self, skip: usize) -> Self {
+        self.skip = skip;
+        self
+    }
+
+    pub fn times(mut self, times: usize) -> Self {
+        self.times = Some(times);
+        self
+    }
+}
+
+// `FaultyStore` wraps a `NodeStore` and fails, drops or delays the operations
+// matched by its rules, to exercise the paths that handle missing nodes and
+// crashed writers. Rules are checked in the order they were injected and the
+// first one that fires decides the fault; operations matching no rule go to
+// the inner store unchanged. Rules can be injected through a shared
+// reference, e.g. while a tree only borrows the store for reading.
+#[derive(Debug)]
+pub struct FaultyStore<V: Leafable, S> {
+    inner: S,
+    rules: Mutex<Vec<FaultRule<<V::Hasher as TreeHasher>::HashOut>>>,
+    injected: AtomicUsize,
+}
+
+impl<V: Leafable, S: NodeStore<V>> FaultyStore<V, S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            rules: Mutex::new(vec![]),
+            injected: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn inject(&self, rule: FaultRule<<V::Hasher as TreeHasher>::HashOut>) {
+        self.rules
+            .lock()
+            .expect("fault rules lock poisoned")
+            .push(rule);
+    }
+
+    // Removes every rule, so that the store behaves like the inner one again.
+    pub fn heal(&self) {
+        self.rules
+            .lock()
+            .expect("fault rules lock poisoned")
+            .clear();
+    }
+
+    // Number of faults injected so far, delays included.
+    pub fn injected(&self) -> usize {
+        self.injected.load(Ordering::Relaxed)
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    // The fault to inject into `op`, after sleeping for a delay. Only `Fail`
+    // and `Drop` are returned.
+    fn fault(&self, op: StoreOp, key: &<V::Hasher as TreeHasher>::HashOut) -> Option<Fault> {
+        let fault = {
+            let mut rules = self.rules.lock().expect("fault rules lock poisoned");
+            let mut fired = None;
+            for rule in rules.iter_mut() {
+                if rule.op != op
+                    || rule.times == Some(0)
+                    || rule.key.as_ref().is_some_and(|k| k != key)
+                {
+                    continue;
+                }
+                if rule.skip > 0 {
+                    rule.skip -= 1;
+                    continue;
+                }
+                if let Some(times) = &mut rule.times {
+                    *times -= 1;
+                }
+                fired = Some(rule.fault);
+                break;
+            }
+            fired?
+        };
+        self.injected.fetch_add(1, Ordering::Relaxed);
+        match fault {
+            Fault::Delay(delay) => {
+                thread::sleep(delay);
+                None
+            }
+            fault => Some(fault),
+        }
+    }
+
+    fn write_fault(&self, op: StoreOp, key: &<V::Hasher as TreeHasher>::HashOut) -> bool {
+        match self.fault(op, key) {
+            Some(Fault::Fail) => panic!("injected failure of {:?} on {:?}", op, key),
+            Some(_) => true,
+            None => false,
+        }
+    }
+}
+
+impl<V: Leafable, S: NodeStore<V>> NodeStore<V> for FaultyStore<V, S> {
+    fn insert(&mut self, key: <V::Hasher as TreeHasher>::HashOut, node: Node<V>) {
+        if !self.write_fault(StoreOp::Insert, &key) {
+            self.inner.insert(key, node)
+        }
+    }
+
+    fn get(&self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>> {
+        match self.fault(StoreOp::Get, &key) {
+            Some(_) => None,
+            None => self.inner.get(key),
+        }
+    }
+
+    fn remove(&mut self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>> {
+        if self.write_fault(StoreOp::Remove, &key) {
+            return None;
+        }
+        self.inner.remove(key)
+    }
+
+    fn contains(&self, key: <V::Hasher as TreeHasher>::HashOut) -> bool {
+        match self.fault(StoreOp::Get, &key) {
+            Some(_) => false,
+            None => self.inner.contains(key),
+        }
+    }
+
+    fn insert_leaf_data(&mut self, leaf_hash: <V::Hasher as TreeHasher>::HashOut, data: Vec<u8>) {
+        if !self.write_fault(StoreOp::InsertLeafData, &leaf_hash) {
+            self.inner.insert_leaf_data(leaf_hash, data)
+        }
+    }
+
+    fn get_leaf_data(&self, leaf_hash: <V::Hasher as TreeHasher>::HashOut) -> Option<Vec<u8>> {
+        match self.fault(StoreOp::GetLeafData, &leaf_hash) {
+            Some(_) => None,
+            None => self.inner.get_leaf_data(leaf_hash),
+        }
+    }
+
+    fn remove_leaf_data(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Option<Vec<u8>> {
+        if self.write_fault(StoreOp::InsertLeafData, &leaf_hash) {
+            return None;
+        }
+        self.inner.remove_leaf_data(leaf_hash)
+    }
+
+    fn insert_leaf_metadata(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+        metadata: Vec<u8>,
+    ) {
+        if !self.write_fault(StoreOp::InsertLeafData, &leaf_hash) {
+            self.inner.insert_leaf_metadata(leaf_hash, index, metadata)
+        }
+    }
+
+    fn get_leaf_metadata(
+        &self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+    ) -> Option<Vec<u8>> {
+        match self.fault(StoreOp::GetLeafData, &leaf_hash) {
+            Some(_) => None,
+            None => self.inner.get_leaf_metadata(leaf_hash, index),
+        }
+    }
+
+    fn remove_leaf_metadata(&mut self, leaf_hash: <V::Hasher as TreeHasher>::HashOut) {
+        if !self.write_fault(StoreOp::InsertLeafData, &leaf_hash) {
+            self.inner.remove_leaf_metadata(leaf_hash)
+        }
+    }
+
+    fn remove_leaf_metadata_at(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+    ) {
+        if !self.write_fault(StoreOp::InsertLeafData, &leaf_hash) {
+            self.inner.remove_leaf_metadata_at(leaf_hash, index)
+        }
+    }
+
+    fn with_node<R>(
+        &self,
+        key: <V::Hasher as TreeHasher>::HashOut,
+        f: impl FnOnce(&Node<V>) -> R,
+    ) -> Option<R> {
+        match self.fault(StoreOp::Get, &key) {
+            Some(_) => None,
+            None => self.inner.with_node(key, f),
+        }
+    }
+
+    fn try_with_node<R>(
+        &self,
+        key: <V::Hasher as TreeHasher>::HashOut,
+        f: impl FnOnce(&Node<V>) -> R,
+    ) -> Result<Option<R>, CorruptNode<<V::Hasher as TreeHasher>::HashOut>> {
+        match self.fault(StoreOp::Get, &key) {
+            Some(_) => Ok(None),
+            None => self.inner.try_with_node(key, f),
+        }
+    }
+
+    fn num_nodes(&self) -> Option<usize> {
+        self.inner.num_nodes()
+    }
+
+    fn insert_batch(&mut self, nodes: Vec<(<V::Hasher as TreeHasher>::HashOut, Node<V>)>) {
+        let nodes = nodes
+            .into_iter()
+            .filter(|(key, _)| !self.write_fault(StoreOp::Insert, key))
+            .collect();
+        self.inner.insert_batch(nodes)
+    }
+}
+
+impl<V: Leafable, S: TransactionalStore<V>> TransactionalStore<V> for FaultyStore<V, S> {
+    fn commit_transaction(
+        &mut self,
+        nodes: Vec<(<V::Hasher as TreeHasher>::HashOut, Node<V>)>,
+        root: <V::Hasher as TreeHasher>::HashOut,
+    ) -> anyhow::Result<()> {
+        match self.fault(StoreOp::Commit, &root) {
+            Some(Fault::Fail) => anyhow::bail!("injected failure of commit of {:?}", root),
+            Some(_) => Ok(()),
+            None => self.inner.commit_transaction(nodes, root),
+        }
+    }
+
+    fn committed_root(&self) -> Option<<V::Hasher as TreeHasher>::HashOut> {
+        self.inner.committed_root()
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use std::{
+        panic::{catch_unwind, AssertUnwindSafe},
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        error::ProofError, leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB,
+        node_key::NodeKey, traits::Leafable,
+    };
+
+    use super::{Fault, FaultRule, FaultyStore, StoreOp};
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_faulty_store() {
+        let height = 8;
+        let mut db = FaultyStore::new(MockDB::<Leaf>::new());
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        let index = move |i: u128| LeafIndex::new(i, height).unwrap();
+        let leaves: Vec<_> = (0..8).map(|i| (index(i), (i as u32 + 1).hash())).collect();
+        tree.update_leaves(&mut db, &leaves).unwrap();
+        let root = tree.get_root();
+
+        // a lost read of an inner node
+        let node = tree.get_node_hash(NodeKey::new(3, 0)).unwrap();
+        db.inject(FaultRule::new(StoreOp::Get, Fault::Fail).key(node).times(1));
+        assert_eq!(
+            tree.prove_with_given_root(&db, root, index(2)),
+            Err(ProofError::MissingNode { depth: 3 })
+        );
+        assert!(tree.prove_with_given_root(&db, root, index(2)).is_ok());
+
+        // a lost write shows up when reading the version back
+        db.inject(
+            FaultRule::new(StoreOp::Insert, Fault::Drop)
+                .skip(1)
+                .times(1),
+        );
+        tree.update_leaf(&mut db, index(9), 10u32.hash()).unwrap();
+        let report = tree.verify_integrity(&db, tree.get_root());
+        assert_eq!(report.issues.len(), 1);
+
+        // a delayed read still succeeds
+        let delay = Duration::from_millis(20);
+        db.inject(FaultRule::new(StoreOp::Get, Fault::Delay(delay)).times(1));
+        let start = Instant::now();
+        tree.prove_with_given_root(&db, root, index(2)).unwrap();
+        assert!(start.elapsed() >= delay);
+
+        // a failing write panics
+        db.inject(FaultRule::new(StoreOp::Insert, Fault::Fail));
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            tree.update_leaf(&mut db, index(20), 1u32.hash())
+        }));
+        assert!(result.is_err());
+        assert_eq!(db.injected(), 4);
+
+        db.heal();
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        tree.update_leaves(&mut db, &leaves).unwrap();
+        assert_eq!(tree.get_root(), root);
+    }
+}
@@ -0,0 +1,220 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    archive::ArchivedTree,
+    error::VerifyError,
+    leaf_index::LeafIndex,
+    merkle_tree::{MerkleProof, MerkleTree},
+    mock_db::MockDB,
+    node_key::MAX_HEIGHT,
+    service::{ProofResponse, WireHash},
+    traits::{Leafable, TreeHasher},
+};
+
+// Entry points for the cargo-fuzz targets in `fuzz/`, covering the decoders
+// and checks that see untrusted bytes. Each one takes arbitrary input, must
+// not panic, and panics only if an invariant of a successfully decoded value
+// does not hold. They live here rather than in the fuzz crate so that unit
+// tests can run them on a seed corpus.
+
+// Reads fixed size fields from the front of the fuzzer input, padding with
+// zeros once it runs out so that every input is usable.
+struct Input<'a>(&'a [u8]);
+
+impl Input<'_> {
+    fn bytes<const N: usize>(&mut self) -> [u8; N] {
+        let mut out = [0; N];
+        let n = N.min(self.0.len());
+        out[..n].copy_from_slice(&self.0[..n]);
+        self.0 = &self.0[n..];
+        out
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.bytes::<1>()[0]
+    }
+
+    fn u128(&mut self) -> u128 {
+        u128::from_le_bytes(self.bytes())
+    }
+
+    // A hash from 32 bytes, or the default hash for bytes that are not an
+    // encoding.
+    fn hash<H: WireHash + Default>(&mut self) -> H {
+        H::from_wire(&self.bytes::<32>()).unwrap_or_default()
+    }
+}
+
+// A JSON proof that decodes serializes back to an equal proof.
+pub fn proof_json<V: Leafable>(data: &[u8])
+where
+    <V::Hasher as TreeHasher>::HashOut: Serialize + DeserializeOwned,
+{
+    let Ok(proof) = serde_json::from_slice::<MerkleProof<V>>(data) else {
+        return;
+    };
+    let json = serde_json::to_vec(&proof).expect("a decoded proof serializes");
+    let decoded: MerkleProof<V> = serde_json::from_slice(&json).expect("a proof roundtrips");
+    assert_eq!(decoded, proof);
+}
+
+// A binary proof response that decodes is in canonical form, so it encodes
+// back to the same bytes.
+pub fn proof_bytes<H: WireHash>(data: &[u8]) {
+    if let Some(response) = ProofResponse::<H>::from_bytes(data) {
+        assert_eq!(response.to_bytes(), data);
+    }
+}
+
+// Builds a proof and an index of independent heights from the input and
+// checks that verification rejects mismatched lengths with an error and
+// accepts the root the proof computes.
+pub fn verify<V: Leafable>(data: &[u8])
+where
+    <V::Hasher as TreeHasher>::HashOut: WireHash,
+{
+    let mut input = Input(data);
+    let proof_height = (input.u8() as usize) % (MAX_HEIGHT + 1);
+    let index_height = (input.u8() as usize) % (MAX_HEIGHT + 1);
+    let raw_index = input.u128();
+    let leaf_hash = input.hash::<<V::Hasher as TreeHasher>::HashOut>();
+    let root = input.hash();
+    let proof = MerkleProof::<V> {
+        siblings: (0..proof_height).map(|_| input.hash()).collect(),
+    };
+
+    let index = in_range(raw_index, index_height);
+    match proof.verify_hash(leaf_hash.clone(), index, root) {
+        Err(VerifyError::InvalidIndexLength { expected, actual }) => {
+            assert_eq!((expected, actual), (proof_height, index_height))
+        }
+        _ => assert_eq!(proof_height, index_height),
+    }
+    if proof_height == index_height {
+        let computed = proof.get_root_from_hash(leaf_hash.clone(), index);
+        proof
+            .verify_hash(leaf_hash, index, computed)
+            .expect("a proof verifies against the root it computes");
+    }
+}
+
+// A checkpoint that restores gives a tree whose reads do not panic and whose
+// proofs from memory and from the store agree.
+pub fn checkpoint<V: Leafable>(data: &[u8])
+where
+    <V::Hasher as TreeHasher>::HashOut: Serialize + DeserializeOwned,
+{
+    let mut db = MockDB::new();
+    let Ok(tree) = MerkleTree::<V>::restore_from_reader(&mut db, data) else {
+        return;
+    };
+    let root = tree.get_root();
+    for i in [0, 1, u128::MAX] {
+        let index = in_range(i, tree.height());
+        let stored = tree.prove_with_given_root(&db, root.clone(), index);
+        if let (Ok(proof), Ok(stored)) = (tree.prove(index), stored) {
+            assert_eq!(stored, proof);
+        }
+    }
+}
+
+// An archive that loads proves each of its leaves against its root.
+pub fn archive<V: Leafable>(data: &[u8])
+where
+    <V::Hasher as TreeHasher>::HashOut: Serialize + DeserializeOwned,
+{
+    let Ok(archive) = ArchivedTree::<V>::load(data) else {
+        return;
+    };
+    for i in [0, 1, u128::MAX] {
+        let index = in_range(i, archive.height());
+        archive
+            .prove(index)
+            .verify_hash(archive.get_leaf_hash(index), index, archive.root())
+            .expect("an archived leaf verifies");
+    }
+}
+
+fn in_range(index: u128, height: usize) -> LeafIndex {
+    let index = if height == MAX_HEIGHT {
+        index
+    } else {
+        index & ((1 << height) - 1)
+    };
+    LeafIndex::new(index, height).unwrap()
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use intmax2_zkp::utils::poseidon_hash_out::PoseidonHashOut;
+
+    use crate::{
+        leaf_index::LeafIndex,
+        merkle_tree::MerkleTree,
+        mock_db::MockDB,
+        service::{ProofResponse, WireHash},
+        traits::Leafable,
+    };
+
+    type Leaf = u32;
+
+    // Runs every entry point on valid encodings and on their truncations and
+    // single byte corruptions.
+    #[test]
+    fn test_fuzz_entry_points() {
+        let height = 4;
+        let mut db = MockDB::<Leaf>::new();
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        for i in [1, 6] {
+            tree.update_leaf(&mut db, LeafIndex::new(i, height).unwrap(), 5u32.hash())
+                .unwrap();
+        }
+        let proof = tree.prove(LeafIndex::new(6, height).unwrap()).unwrap();
+
+        let dir = std::env::temp_dir().join(format!("db_tree_fuzz_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        tree.checkpoint(&db, dir.join("tree.json")).unwrap();
+        let checkpoint = std::fs::read(dir.join("tree.json")).unwrap();
+        let mut archive = vec![];
+        tree.export_archive(&db, tree.get_root(), &mut archive)
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let response = ProofResponse {
+            root: tree.get_root(),
+            siblings: proof.siblings.clone(),
+        }
+        .to_bytes();
+        let mut verify_input = vec![height as u8, height as u8, 6];
+        verify_input.extend([0; 15]);
+        verify_input.extend(5u32.hash().to_wire());
+        verify_input.extend(tree.get_root().to_wire());
+        for sibling in &proof.siblings {
+            verify_input.extend(sibling.to_wire());
+        }
+
+        let seeds = [
+            serde_json::to_vec(&proof).unwrap(),
+            response,
+            verify_input,
+            checkpoint,
+            archive,
+        ];
+        for seed in &seeds {
+            let mut inputs = vec![seed.clone()];
+            for i in (0..seed.len()).step_by(seed.len() / 64 + 1) {
+                inputs.push(seed[..i].to_vec());
+                let mut corrupted = seed.clone();
+                corrupted[i] ^= 0x5a;
+                inputs.push(corrupted);
+            }
+            for input in &inputs {
+                super::proof_json::<Leaf>(input);
+                super::proof_bytes::<PoseidonHashOut>(input);
+                super::verify::<Leaf>(input);
+                super::checkpoint::<Leaf>(input);
+                super::archive::<Leaf>(input);
+            }
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use hashbrown::HashSet;
+
+use crate::{
+    merkle_tree::MerkleTree,
+    node::Node,
+    node_key::NodeKey,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+// The non-empty nodes and leaves of a subtree, by position in the tree.
+pub(crate) type Subtree<V> = (
+    Vec<(
+        NodeKey,
+        <<V as Leafable>::Hasher as TreeHasher>::HashOut,
+        Node<V>,
+    )>,
+    Vec<(NodeKey, <<V as Leafable>::Hasher as TreeHasher>::HashOut)>,
+);
+
+impl<V: Leafable> MerkleTree<V> {
+    // Replaces the subtree at `path` with the subtree `subtree_root`, whose
+    // nodes are read from `source`, and recomputes the ancestors of `path`.
+    // The subtree is built apart, e.g. by a worker, as a tree of height
+    // `height - path.depth()` with the same empty leaf; its nodes and leaf
+    // data are copied to `db` as they are, so only the ancestors are hashed.
+    // Leaves under `path` that the subtree leaves empty are cleared. Fails,
+    // without changing the tree, if a node of the subtree is missing from
+    // `source`.
+    pub fn graft<S: NodeStore<V>, T: NodeStore<V>>(
+        &mut self,
+        db: &mut S,
+        path: NodeKey,
+        subtree_root: <V::Hasher as TreeHasher>::HashOut,
+        source: &T,
+    ) -> anyhow::Result<()> {
+        self.check_node_key(path)?;
+        let (nodes, leaves) = self.subtree(source, path, subtree_root.clone())?;
+        let current = self.get_node_hash_with_store(&*db, path)?;
+        let (_, old_leaves) = self.subtree(&*db, path, current)?;
+        self.check_update_paths(&*db, vec![path])?;
+
+        for (_, leaf_hash) in &leaves {
+            if let Some(data) = source.get_leaf_data(leaf_hash.clone()) {
+                db.insert_leaf_data(leaf_hash.clone(), data);
+            }
+        }
+        db.insert_batch(
+            nodes
+                .iter()
+                .map(|(_, hash, node)| (hash.clone(), node.clone()))
+                .collect(),
+        );
+
+        // leaf counts, subscribers and the reverse index go through the
+        // leaves, before the cached hashes under `path` are replaced
+        let grafted: HashSet<_> = leaves.iter().map(|(key, _)| *key).collect();
+        let mut rescan = false;
+        for (key, _) in old_leaves {
+            if !grafted.contains(&key) {
+                rescan |= self.set_leaf_hash(db, key, self.zero_hashes[self.height].clone());
+            }
+        }
+        for (key, leaf_hash) in &leaves {
+            rescan |= self.set_leaf_hash(db, *key, leaf_hash.clone());
+        }
+        let cache_depth = self.cache_depth;
+        self.node_hashes
+            .retain(|key, _| *key != path && !key.is_descendant_of(path));
+        self.node_hashes.extend(
+            nodes
+                .into_iter()
+                .map(|(key, hash, _)| (key, hash))
+                .chain(leaves)
+                .filter(|(key, _)| key.depth() <= cache_depth || *key == path),
+        );
+
+        let mut key = path;
+        let mut hash = subtree_root;
+        let mut batch = vec![];
+        while !key.is_root() {
+            let sibling = self.get_node_hash_with_store_unchecked(&*db, key.sibling());
+            let (left, right) = if key.is_right() {
+                (sibling, hash)
+            } else {
+                (hash, sibling)
+            };
+            key = key.parent();
+            hash = self.hash_children(key.depth(), left.clone(), right.clone());
+            self.node_hashes.insert(key, hash.clone());
+            batch.push((hash.clone(), Node { left, right }));
+        }
+        db.insert_batch(batch);
+        self.finish_leaf_updates(&*db, rescan);
+        Ok(())
+    }
+
+    // Walks the subtree `hash` at `path` in `db`, skipping empty subtrees.
+    pub(crate) fn subtree<S: NodeStore<V>>(
+        &self,
+        db: &S,
+        path: NodeKey,
+        hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> anyhow::Result<Subtree<V>> {
+        let mut nodes = vec![];
+        let mut leaves = vec![];
+        let mut stack = vec![(path, hash)];
+        while let Some((key, hash)) = stack.pop() {
+            if hash == self.zero_hashes[key.depth()] {
+                continue;
+            }
+            if key.depth() == self.height {
+                leaves.push((key, hash));
+                continue;
+            }
+            let node = db
+                .get(hash.clone())
+                .ok_or_else(|| anyhow::anyhow!("cannot find node at depth {}", key.depth()))?;
+            stack.push((key.child(false), node.left.clone()));
+            stack.push((key.child(true), node.right.clone()));
+            nodes.push((key, hash, node));
+        }
+        Ok((nodes, leaves))
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, node_key::NodeKey,
+        traits::Leafable,
+    };
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_graft() {
+        let height = 10;
+        let mut db = MockDB::<Leaf>::new();
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        let mut expected = tree.clone();
+        let index = move |i: u128| LeafIndex::new(i, height).unwrap();
+        for i in [1, 100, 130, 140, 1000] {
+            tree.update_leaf(&mut db, index(i), (i as u32).hash())
+                .unwrap();
+            expected
+                .update_leaf(&mut db, index(i), (i as u32).hash())
+                .unwrap();
+        }
+
+        // a worker builds leaves 128..192 as a tree of height 6 under depth 4
+        let mut worker_db = MockDB::new();
+        let mut worker = MerkleTree::<Leaf>::new(&mut worker_db, 6, Leaf::empty_leaf().hash());
+        for i in [0u128, 5, 63] {
+            let leaf = 7 + i as u32;
+            worker
+                .update_leaf_data(&mut worker_db, LeafIndex::new(i, 6).unwrap(), &leaf)
+                .unwrap();
+            expected
+                .update_leaf(&mut db, index(128 + i), leaf.hash())
+                .unwrap();
+        }
+        // the leaves of the subtree it replaces are cleared
+        for i in [130, 140] {
+            expected
+                .update_leaf(&mut db, index(i), Leaf::empty_leaf().hash())
+                .unwrap();
+        }
+
+        let path = NodeKey::new(4, 2);
+        tree.graft(&mut db, path, worker.get_root(), &worker_db)
+            .unwrap();
+        assert_eq!(tree.get_root(), expected.get_root());
+        assert_eq!(tree.len(), expected.len());
+        assert_eq!(tree.len(), 6);
+        assert_eq!(tree.get_leaf(&db, index(133)).unwrap(), Some(12));
+        assert!(tree.verify_integrity(&db, tree.get_root()).is_ok());
+        for i in [1, 128, 130, 191, 1000] {
+            assert_eq!(tree.prove(index(i)), expected.prove(index(i)));
+        }
+
+        // a subtree missing from the source is an error
+        let root = tree.get_root();
+        assert!(tree
+            .graft(&mut db, path, 5u32.hash(), &MockDB::new())
+            .is_err());
+        assert_eq!(tree.get_root(), root);
+        // grafting the empty subtree clears it
+        let empty = MerkleTree::<Leaf>::new(&mut MockDB::new(), 6, Leaf::empty_leaf().hash());
+        tree.graft(&mut db, path, empty.get_root(), &MockDB::new())
+            .unwrap();
+        assert_eq!(tree.len(), 3);
+    }
+}
@@ -0,0 +1,125 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use tonic::{transport::Server, Request, Response, Status};
+
+use crate::{
+    error::DbTreeError,
+    node_store::NodeStore,
+    service::{ProofService, WireHash},
+    traits::{Leafable, TreeHasher},
+};
+
+pub mod pb {
+    tonic::include_proto!("db_tree");
+}
+
+use pb::proof_server::{Proof, ProofServer};
+
+// gRPC transport of a `ProofService`, see proto/db_tree.proto. Leaf indices
+// are u64s on the wire.
+pub struct GrpcProofService<V: Leafable, S: NodeStore<V>> {
+    service: Arc<ProofService<V, S>>,
+}
+
+impl<V: Leafable, S: NodeStore<V>> GrpcProofService<V, S> {
+    pub fn new(service: Arc<ProofService<V, S>>) -> Self {
+        Self { service }
+    }
+}
+
+fn decode<H: WireHash>(bytes: &[u8], field: &str) -> Result<H, Status> {
+    H::from_wire(bytes).ok_or_else(|| Status::invalid_argument(format!("invalid {}", field)))
+}
+
+fn tree_error(e: DbTreeError) -> Status {
+    match e {
+        DbTreeError::IndexOutOfRange { .. } | DbTreeError::InvalidIndexLength { .. } => {
+            Status::out_of_range(e.to_string())
+        }
+        _ => Status::internal(e.to_string()),
+    }
+}
+
+#[tonic::async_trait]
+impl<V, S> Proof for GrpcProofService<V, S>
+where
+    V: Leafable + Send + Sync + 'static,
+    V::Hasher: Send + Sync,
+    <V::Hasher as TreeHasher>::HashOut: WireHash + Send + Sync,
+    S: NodeStore<V> + Send + 'static,
+{
+    async fn update_leaf(
+        &self,
+        request: Request<pb::UpdateLeafRequest>,
+    ) -> Result<Response<pb::UpdateLeafResponse>, Status> {
+        let request = request.into_inner();
+        let leaf_hash = decode(&request.leaf_hash, "leaf_hash")?;
+        let root = self
+            .service
+            .update_leaf(request.index as u128, leaf_hash)
+            .map_err(tree_error)?;
+        Ok(Response::new(pb::UpdateLeafResponse {
+            root: root.to_wire(),
+        }))
+    }
+
+    async fn get_root(
+        &self,
+        _request: Request<pb::GetRootRequest>,
+    ) -> Result<Response<pb::GetRootResponse>, Status> {
+        Ok(Response::new(pb::GetRootResponse {
+            root: self.service.get_root().to_wire(),
+        }))
+    }
+
+    async fn get_proof(
+        &self,
+        request: Request<pb::GetProofRequest>,
+    ) -> Result<Response<pb::GetProofResponse>, Status> {
+        let response = self
+            .service
+            .get_proof(request.into_inner().index as u128)
+            .map_err(tree_error)?;
+        Ok(Response::new(pb::GetProofResponse {
+            root: response.root.to_wire(),
+            siblings: response.siblings.iter().map(WireHash::to_wire).collect(),
+        }))
+    }
+
+    async fn verify_proof(
+        &self,
+        request: Request<pb::VerifyProofRequest>,
+    ) -> Result<Response<pb::VerifyProofResponse>, Status> {
+        let request = request.into_inner();
+        let siblings = request
+            .siblings
+            .iter()
+            .map(|s| decode(s, "sibling"))
+            .collect::<Result<Vec<_>, _>>()?;
+        let valid = self
+            .service
+            .verify_proof(
+                decode(&request.leaf_hash, "leaf_hash")?,
+                request.index as u128,
+                decode(&request.root, "root")?,
+                siblings,
+            )
+            .map_err(tree_error)?;
+        Ok(Response::new(pb::VerifyProofResponse { valid }))
+    }
+}
+
+// Serves `service` on `addr` until the server fails.
+pub async fn serve<V, S>(service: Arc<ProofService<V, S>>, addr: SocketAddr) -> anyhow::Result<()>
+where
+    V: Leafable + Send + Sync + 'static,
+    V::Hasher: Send + Sync,
+    <V::Hasher as TreeHasher>::HashOut: WireHash + Send + Sync,
+    S: NodeStore<V> + Send + 'static,
+{
+    Server::builder()
+        .add_service(ProofServer::new(GrpcProofService::new(service)))
+        .serve(addr)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    error::VerifyError,
+    leaf_index::LeafIndex,
+    merkle_tree::{MerkleProof, MerkleTree},
+    node_store::NodeStore,
+    traits::{HashLeaf, Leafable, TreeHasher},
+};
+
+// The tree of roots a `HistoricalProof` goes through: leaf `v` is the root
+// of version `v` of the proven tree.
+pub type RootHistoryTree<V> = MerkleTree<HashLeaf<<V as Leafable>::Hasher>>;
+
+// A proof that a leaf was in the tree at the root of version `version`, and
+// that this root is leaf `version` of a tree of roots. A light client that
+// only follows the root of the root history checks both with one `verify`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "<V::Hasher as TreeHasher>::HashOut: Serialize",
+    deserialize = "<V::Hasher as TreeHasher>::HashOut: DeserializeOwned"
+))]
+pub struct HistoricalProof<V: Leafable> {
+    pub version: u128,
+    pub root: <V::Hasher as TreeHasher>::HashOut,
+    pub leaf_proof: MerkleProof<V>,
+    pub root_proof: MerkleProof<HashLeaf<V::Hasher>>,
+}
+
+impl<V: Leafable> PartialEq for HistoricalProof<V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.version == other.version
+            && self.root == other.root
+            && self.leaf_proof == other.leaf_proof
+            && self.root_proof == other.root_proof
+    }
+}
+
+impl<V: Leafable> Eq for HistoricalProof<V> {}
+
+impl<V: Leafable> MerkleTree<V> {
+    // Proves `index` at the root that `history` holds for `version`. `db`
+    // holds the nodes of that version and `history_db` those of the current
+    // root of `history`. Fails if `version` has no root in `history` or a
+    // node is missing from either store.
+    pub fn prove_historical<S: NodeStore<V>, T: NodeStore<HashLeaf<V::Hasher>>>(
+        &self,
+        db: &S,
+        history: &RootHistoryTree<V>,
+        history_db: &T,
+        version: u128,
+        index: impl Into<LeafIndex>,
+    ) -> anyhow::Result<HistoricalProof<V>> {
+        let version_index = LeafIndex::new(version, history.height())?;
+        let root = history.get_node_hash_with_store(history_db, version_index.to_node_key())?;
+        anyhow::ensure!(
+            root != HashLeaf::<V::Hasher>::empty_leaf().hash(),
+            "no root for version {}",
+            version
+        );
+        let leaf_proof = self.prove_with_given_root(db, root.clone(), index)?;
+        let root_proof =
+            history.prove_with_given_root(history_db, history.get_root(), version_index)?;
+        Ok(HistoricalProof {
+            version,
+            root,
+            leaf_proof,
+            root_proof,
+        })
+    }
+}
+
+impl<V: Leafable> HistoricalProof<V> {
+    // Checks that `leaf_hash` is at `index` under `root`, and that `root` is
+    // the root of `version` in the root history whose root is
+    // `history_root`.
+    pub fn verify(
+        &self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: impl Into<LeafIndex>,
+        history_root: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Result<(), VerifyError<<V::Hasher as TreeHasher>::HashOut>> {
+        self.leaf_proof
+            .verify_hash(leaf_hash, index, self.root.clone())?;
+        let version = LeafIndex::new(self.version, self.root_proof.height()).map_err(|_| {
+            VerifyError::IndexOutOfRange {
+                index: self.version,
+                height: self.root_proof.height(),
+            }
+        })?;
+        self.root_proof
+            .verify_hash(self.root.clone(), version, history_root)
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use intmax2_zkp::utils::leafable_hasher::PoseidonLeafableHasher;
+
+    use crate::{
+        leaf_index::LeafIndex,
+        merkle_tree::MerkleTree,
+        mock_db::MockDB,
+        traits::{HashLeaf, Leafable},
+    };
+
+    use super::RootHistoryTree;
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_historical_proof() {
+        let height = 8;
+        let mut db = MockDB::<Leaf>::new();
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        let mut history_db = MockDB::new();
+        let mut history: RootHistoryTree<Leaf> = MerkleTree::new(
+            &mut history_db,
+            6,
+            HashLeaf::<PoseidonLeafableHasher>::empty_leaf().hash(),
+        );
+        let index = move |i: u128| LeafIndex::new(i, height).unwrap();
+        for version in 0..5u32 {
+            tree.update_leaf(&mut db, index(version as u128), (version + 1).hash())
+                .unwrap();
+            history
+                .update_leaf(
+                    &mut history_db,
+                    LeafIndex::new(version as u128, 6).unwrap(),
+                    tree.get_root(),
+                )
+                .unwrap();
+        }
+        // leaf 3 is overwritten after version 3
+        tree.update_leaf(&mut db, index(3), 9u32.hash()).unwrap();
+
+        let proof = tree
+            .prove_historical(&db, &history, &history_db, 3, index(3))
+            .unwrap();
+        let history_root = history.get_root();
+        assert!(proof.verify(4u32.hash(), index(3), history_root).is_ok());
+        assert!(proof.verify(9u32.hash(), index(3), history_root).is_err());
+        // the root history is checked too
+        let mut other = proof.clone();
+        other.version = 2;
+        assert!(other.verify(4u32.hash(), index(3), history_root).is_err());
+        let json = serde_json::to_string(&proof).unwrap();
+        assert_eq!(
+            serde_json::from_str::<super::HistoricalProof<Leaf>>(&json).unwrap(),
+            proof
+        );
+
+        // the current root was never recorded
+        assert!(tree
+            .prove_historical(&db, &history, &history_db, 5, index(3))
+            .is_err());
+    }
+}
@@ -0,0 +1,271 @@
+use std::fmt;
+
+use hashbrown::HashSet;
+
+use crate::{
+    merkle_tree::MerkleTree,
+    node_key::NodeKey,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+// A problem found by `verify_integrity`. `key` is the position where the
+// walk met the node; shared subtrees are only checked at their first
+// position.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IntegrityIssue<H> {
+    // the node stored under `hash` has children that hash to `computed`
+    HashMismatch { key: NodeKey, hash: H, computed: H },
+    // no node is stored under `hash`
+    MissingNode { key: NodeKey, hash: H },
+    // the node is its own ancestor, which only a corrupted store can hold
+    Cycle { key: NodeKey, hash: H },
+}
+
+impl<H: fmt::Debug> fmt::Display for IntegrityIssue<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntegrityIssue::HashMismatch {
+                key,
+                hash,
+                computed,
+            } => write!(
+                f,
+                "node {:?} at (depth {}, index {}) hashes to {:?}",
+                hash, key.depth, key.index, computed
+            ),
+            IntegrityIssue::MissingNode { key, hash } => write!(
+                f,
+                "node {:?} at (depth {}, index {}) is missing from the store",
+                hash, key.depth, key.index
+            ),
+            IntegrityIssue::Cycle { key, hash } => write!(
+                f,
+                "node {:?} at (depth {}, index {}) is its own ancestor",
+                hash, key.depth, key.index
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IntegrityReport<H> {
+    pub root: H,
+    // distinct nodes whose children were checked
+    pub nodes_checked: usize,
+    // non-empty leaf positions below the root
+    pub non_empty_leaves: u128,
+    pub issues: Vec<IntegrityIssue<H>>,
+}
+
+impl<H> IntegrityReport<H> {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl<V: Leafable> MerkleTree<V> {
+    // Walks every node reachable from `root` in `db` and recomputes it from
+    // its children, collecting every problem instead of stopping at the
+    // first. `root` may be any version of the tree; empty subtrees are
+    // skipped since their nodes need not be stored. Leaves below a broken
+    // node are not counted.
+    pub fn verify_integrity<S: NodeStore<V>>(
+        &self,
+        db: &S,
+        root: <V::Hasher as TreeHasher>::HashOut,
+    ) -> IntegrityReport<<V::Hasher as TreeHasher>::HashOut> {
+        let mut walk = IntegrityWalk {
+            tree: self,
+            db,
+            checked: HashSet::new(),
+            path: vec![],
+            non_empty_leaves: 0,
+            issues: vec![],
+        };
+        walk.visit(NodeKey::root(), root.clone());
+        IntegrityReport {
+            root,
+            nodes_checked: walk.checked.len(),
+            non_empty_leaves: walk.non_empty_leaves,
+            issues: walk.issues,
+        }
+    }
+
+    // The issues below any of `subtrees`, given by position and hash, found
+    // by the same walk with the work on shared nodes done once.
+    pub(crate) fn verify_subtrees<S: NodeStore<V>>(
+        &self,
+        db: &S,
+        subtrees: impl IntoIterator<Item = (NodeKey, <V::Hasher as TreeHasher>::HashOut)>,
+    ) -> Vec<IntegrityIssue<<V::Hasher as TreeHasher>::HashOut>> {
+        let mut walk = IntegrityWalk {
+            tree: self,
+            db,
+            checked: HashSet::new(),
+            path: vec![],
+            non_empty_leaves: 0,
+            issues: vec![],
+        };
+        for (key, hash) in subtrees {
+            walk.visit(key, hash);
+        }
+        walk.issues
+    }
+}
+
+struct IntegrityWalk<'a, V: Leafable, S> {
+    tree: &'a MerkleTree<V>,
+    db: &'a S,
+    // (hash, depth) of checked subtrees
+    checked: HashSet<(<V::Hasher as TreeHasher>::HashOut, usize)>,
+    // hashes from the root to the current node
+    path: Vec<<V::Hasher as TreeHasher>::HashOut>,
+    non_empty_leaves: u128,
+    issues: Vec<IntegrityIssue<<V::Hasher as TreeHasher>::HashOut>>,
+}
+
+impl<V: Leafable, S: NodeStore<V>> IntegrityWalk<'_, V, S> {
+    // Recursion is bounded by the height of the tree.
+    fn visit(&mut self, key: NodeKey, hash: <V::Hasher as TreeHasher>::HashOut) {
+        let depth = key.depth();
+        if hash == self.tree.zero_hashes[depth] {
+            return;
+        }
+        if depth == self.tree.height {
+            self.non_empty_leaves = self.non_empty_leaves.saturating_add(1);
+            return;
+        }
+        if self.path.contains(&hash) {
+            self.issues.push(IntegrityIssue::Cycle { key, hash });
+            return;
+        }
+        if !self.checked.insert((hash.clone(), depth)) {
+            // a shared subtree, counted once per position
+            self.non_empty_leaves = self
+                .non_empty_leaves
+                .saturating_add(self.count_leaves(key, hash));
+            return;
+        }
+        let Some((left, right)) = self
+            .db
+            .with_node(hash.clone(), |node| (node.left.clone(), node.right.clone()))
+        else {
+            self.issues.push(IntegrityIssue::MissingNode { key, hash });
+            return;
+        };
+        let computed = self.tree.hash_children(depth, left.clone(), right.clone());
+        if computed != hash {
+            self.issues.push(IntegrityIssue::HashMismatch {
+                key,
+                hash: hash.clone(),
+                computed,
+            });
+        }
+        self.path.push(hash);
+        self.visit(key.child(false), left);
+        self.visit(key.child(true), right);
+        self.path.pop();
+    }
+
+    // Non-empty leaves of an already checked subtree, without reporting
+    // anything again.
+    fn count_leaves(&self, key: NodeKey, hash: <V::Hasher as TreeHasher>::HashOut) -> u128 {
+        let mut count = 0u128;
+        let mut stack = vec![(hash, key.depth())];
+        while let Some((hash, depth)) = stack.pop() {
+            if hash == self.tree.zero_hashes[depth] {
+                continue;
+            }
+            if depth == self.tree.height {
+                count = count.saturating_add(1);
+                continue;
+            }
+            if let Some((left, right)) = self
+                .db
+                .with_node(hash, |node| (node.left.clone(), node.right.clone()))
+            {
+                stack.push((left, depth + 1));
+                stack.push((right, depth + 1));
+            }
+        }
+        count
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        leaf_index::LeafIndex,
+        merkle_tree::MerkleTree,
+        mock_db::{MockDB, Node},
+        node_key::NodeKey,
+        traits::Leafable,
+    };
+
+    use super::IntegrityIssue;
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_verify_integrity() {
+        let height = 6;
+        let mut db = MockDB::<Leaf>::new();
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        // the same leaf everywhere makes the subtrees below depth 2 shared
+        let leaves: Vec<_> = (0..64)
+            .filter(|i| i % 16 < 4)
+            .map(|i| (LeafIndex::new(i, height).unwrap(), 7u32.hash()))
+            .collect();
+        tree.update_leaves(&mut db, &leaves).unwrap();
+        let root = tree.get_root();
+        let report = tree.verify_integrity(&db, root);
+        assert!(report.is_ok(), "{:?}", report.issues);
+        assert_eq!(report.non_empty_leaves, 16);
+        assert!(report.nodes_checked < 16);
+
+        let key = NodeKey::new(1, 0);
+        let hash = tree.get_node_hash(key).unwrap();
+        let node = db.get(hash).unwrap();
+
+        // children that do not hash to the key, reported once although both
+        // nodes at depth 1 share it
+        let mut corrupted = db.clone();
+        corrupted.insert(
+            hash,
+            Node {
+                left: node.left,
+                right: tree.zero_hashes[2],
+            },
+        );
+        let report = tree.verify_integrity(&corrupted, root);
+        assert!(matches!(
+            report.issues.as_slice(),
+            [IntegrityIssue::HashMismatch { key: k, .. }] if *k == key
+        ));
+
+        // a node pointing back at itself
+        let mut corrupted = db.clone();
+        corrupted.insert(
+            hash,
+            Node {
+                left: hash,
+                right: node.right,
+            },
+        );
+        let report = tree.verify_integrity(&corrupted, root);
+        assert!(report.issues.contains(&IntegrityIssue::Cycle {
+            key: key.child(false),
+            hash
+        }));
+
+        // a missing node
+        let mut corrupted = db.clone();
+        corrupted.remove(hash);
+        let report = tree.verify_integrity(&corrupted, root);
+        assert_eq!(
+            report.issues,
+            vec![IntegrityIssue::MissingNode { key, hash }]
+        );
+    }
+}
@@ -0,0 +1,351 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::Arc,
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    error::DbTreeError,
+    node_store::NodeStore,
+    service::{hash_from_hex, hash_to_hex, ProofService, WireHash},
+    traits::{Leafable, TreeHasher},
+};
+
+// JSON-RPC 2.0 transport of a `ProofService` with Ethereum style encodings:
+// hashes are `0x` hex strings and indices are numbers or `0x` hex quantities.
+//
+//   tree_getRoot []                                  -> root
+//   tree_getProof [index]                            -> { root, siblings }
+//   tree_updateLeaf [index, leafHash]                -> new root
+//   tree_verifyProof [leafHash, index, root, siblings] -> bool
+pub struct JsonRpcServer<V: Leafable, S: NodeStore<V>> {
+    service: Arc<ProofService<V, S>>,
+}
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+// leaf indices out of range and other tree errors
+const TREE_ERROR: i64 = -32000;
+
+// Larger request bodies are answered with 413 without being read.
+const MAX_BODY_LEN: usize = 1 << 20;
+// The request line and headers together.
+const MAX_HEAD_LEN: u64 = 16 << 10;
+// Connections are served one at a time, so a client that stops sending holds
+// the server for at most this long.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+type RpcError = (i64, String);
+
+fn invalid_params(message: &str) -> RpcError {
+    (INVALID_PARAMS, message.to_string())
+}
+
+fn param(params: &[Value], i: usize) -> Result<&Value, RpcError> {
+    params
+        .get(i)
+        .ok_or_else(|| invalid_params(&format!("missing parameter {}", i)))
+}
+
+fn parse_index(value: &Value) -> Result<u128, RpcError> {
+    let index = match value {
+        Value::Number(n) => n.as_u64().map(u128::from),
+        Value::String(s) => s
+            .strip_prefix("0x")
+            .and_then(|hex| u128::from_str_radix(hex, 16).ok()),
+        _ => None,
+    };
+    index.ok_or_else(|| invalid_params("index must be a number or a 0x hex quantity"))
+}
+
+fn parse_hash<H: WireHash>(value: &Value) -> Result<H, RpcError> {
+    value
+        .as_str()
+        .and_then(hash_from_hex)
+        .ok_or_else(|| invalid_params("hash must be a 0x hex string"))
+}
+
+impl<V, S> JsonRpcServer<V, S>
+where
+    V: Leafable,
+    <V::Hasher as TreeHasher>::HashOut: WireHash,
+    S: NodeStore<V>,
+{
+    pub fn new(service: Arc<ProofService<V, S>>) -> Self {
+        Self { service }
+    }
+
+    // Handles a request or a batch of requests. Returns None if there is
+    // nothing to respond, i.e. every request was a notification.
+    pub fn handle(&self, request: &str) -> Option<String> {
+        let response = match serde_json::from_str::<Value>(request) {
+            Err(e) => Some(error_response(Value::Null, (PARSE_ERROR, e.to_string()))),
+            Ok(Value::Array(batch)) if !batch.is_empty() => {
+                let responses: Vec<_> = batch.iter().filter_map(|r| self.handle_one(r)).collect();
+                (!responses.is_empty()).then_some(Value::Array(responses))
+            }
+            Ok(request) => self.handle_one(&request),
+        };
+        response.map(|r| r.to_string())
+    }
+
+    fn handle_one(&self, request: &Value) -> Option<Value> {
+        let id = request.get("id").cloned();
+        let method = request.get("method").and_then(Value::as_str);
+        let (method, params) = match (request.get("jsonrpc"), method) {
+            (Some(v), Some(method)) if v == "2.0" => (method, request.get("params")),
+            _ => {
+                let error = (INVALID_REQUEST, "invalid request".to_string());
+                return Some(error_response(id.unwrap_or(Value::Null), error));
+            }
+        };
+        let params = match params {
+            None => vec![],
+            Some(Value::Array(params)) => params.clone(),
+            Some(_) => {
+                let error = invalid_params("params must be an array");
+                return id.map(|id| error_response(id, error));
+            }
+        };
+        let result = self.call(method, &params);
+        // notifications get no response, not even an error
+        let id = id?;
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(error) => error_response(id, error),
+        })
+    }
+
+    fn call(&self, method: &str, params: &[Value]) -> Result<Value, RpcError> {
+        let tree_error = |e: DbTreeError| (TREE_ERROR, e.to_string());
+        match method {
+            "tree_getRoot" => Ok(json!(hash_to_hex(&self.service.get_root()))),
+            "tree_getProof" => {
+                let index = parse_index(param(params, 0)?)?;
+                let response = self.service.get_proof(index).map_err(tree_error)?;
+                let siblings: Vec<_> = response.siblings.iter().map(hash_to_hex).collect();
+                Ok(json!({ "root": hash_to_hex(&response.root), "siblings": siblings }))
+            }
+            "tree_updateLeaf" => {
+                let index = parse_index(param(params, 0)?)?;
+                let leaf_hash = parse_hash(param(params, 1)?)?;
+                let root = self
+                    .service
+                    .update_leaf(index, leaf_hash)
+                    .map_err(tree_error)?;
+                Ok(json!(hash_to_hex(&root)))
+            }
+            "tree_verifyProof" => {
+                let leaf_hash = parse_hash(param(params, 0)?)?;
+                let index = parse_index(param(params, 1)?)?;
+                let root = parse_hash(param(params, 2)?)?;
+                let siblings = param(params, 3)?
+                    .as_array()
+                    .ok_or_else(|| invalid_params("siblings must be an array"))?
+                    .iter()
+                    .map(parse_hash)
+                    .collect::<Result<Vec<_>, _>>()?;
+                let valid = self
+                    .service
+                    .verify_proof(leaf_hash, index, root, siblings)
+                    .map_err(tree_error)?;
+                Ok(json!(valid))
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("method {} not found", method))),
+        }
+    }
+
+    // Minimal blocking HTTP transport: answers every POST body on `listener`
+    // with `handle`, one connection at a time, and anything else with 405.
+    pub fn serve_http(&self, listener: TcpListener) -> std::io::Result<()> {
+        for stream in listener.incoming() {
+            // a broken connection only affects its own request
+            let _ = self.handle_http(stream?);
+        }
+        Ok(())
+    }
+
+    fn handle_http(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut head = (&mut reader).take(MAX_HEAD_LEN);
+        let mut request_line = String::new();
+        head.read_line(&mut request_line)?;
+        let mut content_length = Some(0);
+        loop {
+            let mut line = String::new();
+            if head.read_line(&mut line)? == 0 || line == "\r\n" {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().ok();
+                }
+            }
+        }
+        if head.limit() == 0 {
+            return empty_response(&mut stream, "431 Request Header Fields Too Large");
+        }
+        if request_line.split(' ').next() != Some("POST") {
+            return empty_response(&mut stream, "405 Method Not Allowed\r\nAllow: POST");
+        }
+        let content_length = match content_length {
+            Some(len) if len <= MAX_BODY_LEN => len,
+            Some(_) => return empty_response(&mut stream, "413 Payload Too Large"),
+            None => return empty_response(&mut stream, "400 Bad Request"),
+        };
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+        let response = self.handle(&String::from_utf8_lossy(&body));
+        match response {
+            Some(response) => write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                response.len(),
+                response
+            ),
+            None => write!(stream, "HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n"),
+        }
+    }
+}
+
+// `status` may carry extra header lines
+fn empty_response(stream: &mut TcpStream, status: &str) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        status
+    )
+}
+
+fn error_response(id: Value, (code, message): RpcError) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::Arc,
+        thread,
+    };
+
+    use serde_json::{json, Value};
+
+    use crate::{
+        merkle_tree::MerkleTree,
+        mock_db::MockDB,
+        service::{hash_to_hex, ProofService},
+        traits::Leafable,
+    };
+
+    use super::JsonRpcServer;
+
+    type Leaf = u32;
+
+    fn call(server: &JsonRpcServer<Leaf, MockDB<Leaf>>, request: Value) -> Value {
+        serde_json::from_str(&server.handle(&request.to_string()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_jsonrpc() {
+        let mut db = MockDB::<Leaf>::new();
+        let tree = MerkleTree::new(&mut db, 4, Leaf::empty_leaf().hash());
+        let server = JsonRpcServer::new(Arc::new(ProofService::new(tree, db)));
+
+        let leaf = hash_to_hex(&7u32.hash());
+        let root = call(
+            &server,
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "tree_updateLeaf", "params": ["0x5", leaf] }),
+        )["result"]
+            .clone();
+        let batch = json!([
+            { "jsonrpc": "2.0", "id": 2, "method": "tree_getRoot" },
+            { "jsonrpc": "2.0", "id": 3, "method": "tree_getProof", "params": [5] },
+            { "jsonrpc": "2.0", "method": "tree_getRoot" },
+        ]);
+        let responses = call(&server, batch);
+        assert_eq!(responses.as_array().unwrap().len(), 2);
+        assert_eq!(responses[0]["result"], root);
+        let proof = &responses[1]["result"];
+        assert_eq!(proof["root"], root);
+
+        let verify = |index: u64| {
+            let params = json!([leaf, index, root, proof["siblings"]]);
+            call(
+                &server,
+                json!({ "jsonrpc": "2.0", "id": 4, "method": "tree_verifyProof", "params": params }),
+            )["result"]
+                .clone()
+        };
+        assert_eq!(verify(5), json!(true));
+        assert_eq!(verify(6), json!(false));
+
+        let error = |request: &str| {
+            let response: Value = serde_json::from_str(&server.handle(request).unwrap()).unwrap();
+            response["error"]["code"].as_i64().unwrap()
+        };
+        assert_eq!(error("{"), -32700);
+        assert_eq!(error(r#"{"id": 1, "method": "tree_getRoot"}"#), -32600);
+        assert_eq!(
+            error(r#"{"jsonrpc": "2.0", "id": 1, "method": "eth_call"}"#),
+            -32601
+        );
+        assert_eq!(
+            error(r#"{"jsonrpc": "2.0", "id": 1, "method": "tree_getProof", "params": ["5"]}"#),
+            -32602
+        );
+        assert_eq!(
+            error(r#"{"jsonrpc": "2.0", "id": 1, "method": "tree_getProof", "params": [16]}"#),
+            -32000
+        );
+        assert!(server
+            .handle(r#"{"jsonrpc": "2.0", "method": "tree_getRoot"}"#)
+            .is_none());
+    }
+
+    #[test]
+    fn test_jsonrpc_http() {
+        let mut db = MockDB::<Leaf>::new();
+        let tree = MerkleTree::new(&mut db, 4, Leaf::empty_leaf().hash());
+        let server = JsonRpcServer::new(Arc::new(ProofService::new(tree, db)));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let exchange = |request: String| {
+            let client = thread::spawn(move || {
+                let mut stream = TcpStream::connect(addr).unwrap();
+                stream.write_all(request.as_bytes()).unwrap();
+                let mut response = String::new();
+                stream.read_to_string(&mut response).unwrap();
+                response
+            });
+            let (stream, _) = listener.accept().unwrap();
+            server.handle_http(stream).unwrap();
+            client.join().unwrap()
+        };
+
+        let body = json!({ "jsonrpc": "2.0", "id": 1, "method": "tree_getRoot" }).to_string();
+        let response = exchange(format!(
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        ));
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains(&hash_to_hex(&server.service.get_root())));
+
+        let response = exchange("GET / HTTP/1.1\r\n\r\n".to_string());
+        assert!(response.starts_with("HTTP/1.1 405"));
+        // the body is refused without allocating for it
+        let response =
+            exchange("POST / HTTP/1.1\r\nContent-Length: 4294967296\r\n\r\n".to_string());
+        assert!(response.starts_with("HTTP/1.1 413"));
+        let response = exchange("POST / HTTP/1.1\r\nContent-Length: x\r\n\r\n".to_string());
+        assert!(response.starts_with("HTTP/1.1 400"));
+    }
+}
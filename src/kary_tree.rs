@@ -0,0 +1,367 @@
+use std::hash::Hash;
+
+use hashbrown::HashMap;
+#[cfg(feature = "zkp")]
+use intmax2_zkp::utils::{
+    leafable_hasher::PoseidonLeafableHasher, poseidon_hash_out::PoseidonHashOut,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{DbTreeError, VerifyError},
+    traits::{Leafable, TreeHasher},
+};
+
+// Hashers of trees whose nodes have more than two children. A 4-ary tree has
+// half the levels of the binary tree with as many leaves, so a circuit
+// verifying one of its paths hashes half as many nodes.
+pub trait ArityHasher: TreeHasher {
+    // Hash of a node with `children`, in order.
+    fn n_to_one(children: &[Self::HashOut]) -> Self::HashOut;
+}
+
+#[cfg(feature = "zkp")]
+impl ArityHasher for PoseidonLeafableHasher {
+    fn n_to_one(children: &[PoseidonHashOut]) -> PoseidonHashOut {
+        let inputs: Vec<u64> = children.iter().flat_map(|c| c.to_u64_vec()).collect();
+        PoseidonHashOut::hash_inputs_u64(&inputs)
+    }
+}
+
+// Storage of the nodes of a `KaryTree`: the children of each node, keyed by
+// the node hash. `Node` has two children only, so these nodes do not go
+// through `NodeStore`.
+pub trait KaryNodeStore<H> {
+    fn insert_children(&mut self, key: H, children: Vec<H>);
+
+    fn get_children(&self, key: H) -> Option<Vec<H>>;
+
+    // Backends that support batched writes should override this.
+    fn insert_children_batch(&mut self, nodes: Vec<(H, Vec<H>)>) {
+        for (key, children) in nodes {
+            self.insert_children(key, children);
+        }
+    }
+}
+
+impl<H: Clone + Eq + Hash> KaryNodeStore<H> for HashMap<H, Vec<H>> {
+    fn insert_children(&mut self, key: H, children: Vec<H>) {
+        self.insert(key, children);
+    }
+
+    fn get_children(&self, key: H) -> Option<Vec<H>> {
+        self.get(&key).cloned()
+    }
+}
+
+// `KaryTree` is a Merkle tree whose nodes have `arity` children, with
+// `arity^height` leaves indexed by `u128`. Like `WideTree` it keeps only the
+// root in memory and reads paths from the store. Empty subtrees hash to the
+// zero hash of their level and are not stored.
+#[derive(Clone, Debug)]
+pub struct KaryTree<V: Leafable> {
+    arity: usize,
+    height: usize,
+    // by depth, as in `MerkleTree`
+    zero_hashes: Vec<<V::Hasher as TreeHasher>::HashOut>,
+    root: <V::Hasher as TreeHasher>::HashOut,
+}
+
+// A proof of a leaf of a `KaryTree`: for each level from the leaf up, the
+// hashes of the other `arity - 1` children of the node, in order.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "H: Serialize",
+    deserialize = "H: serde::de::DeserializeOwned"
+))]
+pub struct KaryProof<H> {
+    pub siblings: Vec<Vec<H>>,
+}
+
+// For each node on the path of a leaf from the root down, the position of
+// the path among its children and the children.
+type Path<V> = Vec<(usize, Vec<<<V as Leafable>::Hasher as TreeHasher>::HashOut>)>;
+
+impl<V: Leafable> KaryTree<V>
+where
+    V::Hasher: ArityHasher,
+{
+    // Panics if `arity` is below 2 or `arity^height` leaves do not fit in a
+    // `u128` index; see `try_new`.
+    pub fn new(
+        arity: usize,
+        height: usize,
+        empty_leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Self {
+        Self::from_root(arity, height, empty_leaf_hash, None)
+    }
+
+    pub fn try_new(
+        arity: usize,
+        height: usize,
+        empty_leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Result<Self, DbTreeError> {
+        Self::try_from_root(arity, height, empty_leaf_hash, None)
+    }
+
+    // The version `root` of a tree whose nodes are already in the store, or
+    // the empty tree for `None`. Panics on the same shapes as `new`.
+    pub fn from_root(
+        arity: usize,
+        height: usize,
+        empty_leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        root: Option<<V::Hasher as TreeHasher>::HashOut>,
+    ) -> Self {
+        Self::try_from_root(arity, height, empty_leaf_hash, root).unwrap()
+    }
+
+    pub fn try_from_root(
+        arity: usize,
+        height: usize,
+        empty_leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        root: Option<<V::Hasher as TreeHasher>::HashOut>,
+    ) -> Result<Self, DbTreeError> {
+        let fits = u32::try_from(height)
+            .ok()
+            .and_then(|height| (arity as u128).checked_pow(height))
+            .is_some();
+        if arity < 2 || !fits {
+            return Err(DbTreeError::InvalidArity { arity, height });
+        }
+        let mut zero_hashes = vec![empty_leaf_hash];
+        for _ in 0..height {
+            let child = zero_hashes.last().unwrap().clone();
+            zero_hashes.push(<V::Hasher as ArityHasher>::n_to_one(&vec![child; arity]));
+        }
+        zero_hashes.reverse();
+        Ok(Self {
+            arity,
+            height,
+            root: root.unwrap_or_else(|| zero_hashes[0].clone()),
+            zero_hashes,
+        })
+    }
+
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get_root(&self) -> <V::Hasher as TreeHasher>::HashOut {
+        self.root.clone()
+    }
+
+    pub fn get_leaf_hash<S: KaryNodeStore<<V::Hasher as TreeHasher>::HashOut>>(
+        &self,
+        db: &S,
+        index: u128,
+    ) -> anyhow::Result<<V::Hasher as TreeHasher>::HashOut> {
+        let path = self.path(db, index)?;
+        Ok(match path.last() {
+            Some((position, children)) => children[*position].clone(),
+            None => self.root.clone(),
+        })
+    }
+
+    pub fn prove<S: KaryNodeStore<<V::Hasher as TreeHasher>::HashOut>>(
+        &self,
+        db: &S,
+        index: u128,
+    ) -> anyhow::Result<KaryProof<<V::Hasher as TreeHasher>::HashOut>> {
+        let siblings = self
+            .path(db, index)?
+            .into_iter()
+            .rev()
+            .map(|(position, mut children)| {
+                children.remove(position);
+                children
+            })
+            .collect();
+        Ok(KaryProof { siblings })
+    }
+
+    // Fails, without changing the tree, if a node on the path of `index` is
+    // missing from `db`.
+    pub fn update_leaf<S: KaryNodeStore<<V::Hasher as TreeHasher>::HashOut>>(
+        &mut self,
+        db: &mut S,
+        index: u128,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> anyhow::Result<()> {
+        let path = self.path(&*db, index)?;
+        let mut hash = leaf_hash;
+        let mut batch = vec![];
+        for (depth, (position, mut children)) in path.into_iter().enumerate().rev() {
+            children[position] = hash;
+            let zero = &self.zero_hashes[depth + 1];
+            hash = if children.iter().all(|child| child == zero) {
+                self.zero_hashes[depth].clone()
+            } else {
+                let hash = <V::Hasher as ArityHasher>::n_to_one(&children);
+                batch.push((hash.clone(), children));
+                hash
+            };
+        }
+        db.insert_children_batch(batch);
+        self.root = hash;
+        Ok(())
+    }
+
+    fn path<S: KaryNodeStore<<V::Hasher as TreeHasher>::HashOut>>(
+        &self,
+        db: &S,
+        index: u128,
+    ) -> anyhow::Result<Path<V>> {
+        let positions = positions(self.arity, self.height, index)?;
+        let mut path = Vec::with_capacity(self.height);
+        let mut hash = self.root.clone();
+        for (depth, position) in positions.into_iter().enumerate() {
+            let children = if hash == self.zero_hashes[depth] {
+                vec![self.zero_hashes[depth + 1].clone(); self.arity]
+            } else {
+                db.get_children(hash)
+                    .filter(|children| children.len() == self.arity)
+                    .ok_or_else(|| anyhow::anyhow!("cannot find node at depth {}", depth))?
+            };
+            hash = children[position].clone();
+            path.push((position, children));
+        }
+        Ok(path)
+    }
+}
+
+impl<H: Clone + Eq> KaryProof<H> {
+    pub fn height(&self) -> usize {
+        self.siblings.len()
+    }
+
+    pub fn get_root_from_hash<T: ArityHasher<HashOut = H>>(
+        &self,
+        leaf_hash: H,
+        index: u128,
+    ) -> Result<H, VerifyError<H>> {
+        // each level has the arity its siblings give, so the digits of
+        // `index` are read from the leaf up
+        let mut rest = index;
+        let mut state = leaf_hash;
+        for siblings in &self.siblings {
+            let arity = siblings.len() as u128 + 1;
+            let mut children = siblings.clone();
+            children.insert((rest % arity) as usize, state);
+            rest /= arity;
+            state = T::n_to_one(&children);
+        }
+        if rest != 0 {
+            return Err(VerifyError::IndexOutOfRange {
+                index,
+                height: self.height(),
+            });
+        }
+        Ok(state)
+    }
+
+    pub fn verify<T: ArityHasher<HashOut = H>>(
+        &self,
+        leaf_hash: H,
+        index: u128,
+        root: H,
+    ) -> Result<(), VerifyError<H>> {
+        let computed_root = self.get_root_from_hash::<T>(leaf_hash.clone(), index)?;
+        if computed_root != root {
+            return Err(VerifyError::RootMismatch {
+                leaf_hash,
+                index,
+                computed_root,
+                expected_root: root,
+            });
+        }
+        Ok(())
+    }
+}
+
+// The digits of `index` in base `arity`, from the root down.
+fn positions(arity: usize, height: usize, index: u128) -> Result<Vec<usize>, DbTreeError> {
+    let mut rest = index;
+    let mut positions = vec![0; height];
+    for position in positions.iter_mut().rev() {
+        *position = (rest % arity as u128) as usize;
+        rest /= arity as u128;
+    }
+    if rest != 0 {
+        return Err(DbTreeError::IndexOutOfRange { index, height });
+    }
+    Ok(positions)
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use hashbrown::HashMap;
+    use intmax2_zkp::utils::leafable_hasher::PoseidonLeafableHasher;
+
+    use crate::{error::DbTreeError, traits::Leafable};
+
+    use super::{ArityHasher, KaryTree};
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_kary_tree() {
+        let (arity, height) = (4, 5);
+        let mut db = HashMap::new();
+        let mut tree = KaryTree::<Leaf>::new(arity, height, Leaf::empty_leaf().hash());
+        let mut leaves = vec![Leaf::empty_leaf().hash(); 1024];
+        for i in [0u128, 3, 4, 500, 1023] {
+            let leaf_hash = (i as u32 + 1).hash();
+            tree.update_leaf(&mut db, i, leaf_hash).unwrap();
+            leaves[i as usize] = leaf_hash;
+        }
+
+        // the root of the tree hashed level by level
+        let mut level = leaves.clone();
+        while level.len() > 1 {
+            level = level
+                .chunks(arity)
+                .map(PoseidonLeafableHasher::n_to_one)
+                .collect();
+        }
+        assert_eq!(tree.get_root(), level[0]);
+
+        let root = tree.get_root();
+        for i in [0u128, 4, 500, 1023] {
+            let proof = tree.prove(&db, i).unwrap();
+            assert_eq!(proof.height(), height);
+            assert!(proof.siblings.iter().all(|s| s.len() == arity - 1));
+            assert_eq!(tree.get_leaf_hash(&db, i).unwrap(), leaves[i as usize]);
+            assert!(proof
+                .verify::<PoseidonLeafableHasher>(leaves[i as usize], i, root)
+                .is_ok());
+            assert!(proof
+                .verify::<PoseidonLeafableHasher>(leaves[i as usize], i ^ 1, root)
+                .is_err());
+        }
+        assert!(tree.prove(&db, 1024).is_err());
+
+        // reopened from the root, and emptied again
+        let mut reopened =
+            KaryTree::<Leaf>::from_root(arity, height, Leaf::empty_leaf().hash(), Some(root));
+        for i in [0u128, 3, 4, 500, 1023] {
+            reopened
+                .update_leaf(&mut db, i, Leaf::empty_leaf().hash())
+                .unwrap();
+        }
+        assert_eq!(
+            reopened.get_root(),
+            KaryTree::<Leaf>::new(arity, height, Leaf::empty_leaf().hash()).get_root()
+        );
+
+        for (arity, height) in [(1, 4), (4, 64), (4, usize::MAX)] {
+            assert_eq!(
+                KaryTree::<Leaf>::try_new(arity, height, Leaf::empty_leaf().hash()).unwrap_err(),
+                DbTreeError::InvalidArity { arity, height }
+            );
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use tiny_keccak::{Hasher, Keccak};
+
+use crate::{
+    batch_hasher::BatchHasher,
+    domain::TaggedHasher,
+    traits::{HashLeaf, TreeHasher},
+};
+
+// Keccak-256 of `left || right`, the node hash used by Solidity Merkle
+// verifiers, so roots and proofs can be checked on Ethereum.
+#[derive(Clone, Debug)]
+pub struct Keccak256Hasher;
+
+// A leaf given by its Keccak-256 hash; the empty leaf is 32 zero bytes.
+pub type Keccak256Leaf = HashLeaf<Keccak256Hasher>;
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    hasher.update(data);
+    let mut output = [0u8; 32];
+    hasher.finalize(&mut output);
+    output
+}
+
+impl TreeHasher for Keccak256Hasher {
+    type HashOut = [u8; 32];
+
+    fn two_to_one(left: [u8; 32], right: [u8; 32]) -> [u8; 32] {
+        let mut hasher = Keccak::v256();
+        hasher.update(&left);
+        hasher.update(&right);
+        let mut output = [0u8; 32];
+        hasher.finalize(&mut output);
+        output
+    }
+}
+
+impl BatchHasher for Keccak256Hasher {}
+
+impl TaggedHasher for Keccak256Hasher {
+    fn hash_tagged(tag: u8, left: [u8; 32], right: [u8; 32]) -> [u8; 32] {
+        let mut hasher = Keccak::v256();
+        hasher.update(&[tag]);
+        hasher.update(&left);
+        hasher.update(&right);
+        let mut output = [0u8; 32];
+        hasher.finalize(&mut output);
+        output
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        merkle_tree::MerkleTree,
+        mock_db::MockDB,
+        testkit,
+        traits::{HashLeaf, Leafable},
+    };
+
+    use super::{keccak256, Keccak256Hasher, Keccak256Leaf};
+
+    #[test]
+    fn test_keccak256_tree() {
+        assert_eq!(
+            keccak256(b"")[..4],
+            [0xc5, 0xd2, 0x46, 0x01],
+            "keccak256 of the empty string"
+        );
+
+        let height = 2;
+        let mut mock_db = MockDB::<Keccak256Leaf>::new();
+        let empty_leaf_hash = Keccak256Leaf::empty_leaf().hash();
+        let mut merkle_tree = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+        let leaves: Vec<Keccak256Leaf> = (0..4u8).map(|i| HashLeaf(keccak256(&[i]))).collect();
+        for (i, leaf) in leaves.iter().enumerate() {
+            merkle_tree
+                .update_leaf_at(&mut mock_db, i as u64, leaf.hash())
+                .unwrap();
+        }
+
+        let concat = |l: [u8; 32], r: [u8; 32]| keccak256(&[l, r].concat());
+        let expected = concat(
+            concat(leaves[0].0, leaves[1].0),
+            concat(leaves[2].0, leaves[3].0),
+        );
+        assert_eq!(merkle_tree.get_root(), expected);
+        let proof = merkle_tree.prove_at(2).unwrap();
+        assert_eq!(proof.siblings[0], leaves[3].0);
+        proof.verify_at(&leaves[2], 2, expected).unwrap();
+    }
+
+    #[test]
+    fn test_keccak256_conformance() {
+        testkit::check_hasher::<Keccak256Hasher>(|i| keccak256(&i.to_le_bytes()));
+    }
+}
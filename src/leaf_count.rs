@@ -0,0 +1,227 @@
+use crate::{
+    error::DbTreeError,
+    leaf_index::LeafIndex,
+    merkle_tree::MerkleTree,
+    node_key::NodeKey,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+impl<V: Leafable> MerkleTree<V> {
+    // number of non-empty leaves, saturating for a full tree of `MAX_HEIGHT`
+    pub fn len(&self) -> u128 {
+        self.num_leaves
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.num_leaves == 0
+    }
+
+    // One past the last non-empty leaf, i.e. where an append-only user
+    // writes next. Leaves before it may be empty if they were cleared.
+    pub fn next_free_index(&self) -> u128 {
+        self.last_leaf.map_or(0, |last| last.saturating_add(1))
+    }
+
+    // Whether the leaf at `index` is the empty leaf, without building a proof.
+    // Reads `db` only if the leaf hash was evicted by `compact`, and stops at
+    // the first empty ancestor.
+    pub fn is_empty_leaf<S: NodeStore<V>>(
+        &self,
+        db: &S,
+        index: impl Into<LeafIndex>,
+    ) -> Result<bool, DbTreeError> {
+        let index = index.into();
+        self.check_leaf_index(index)?;
+        let leaf_hash = self.get_node_hash_with_store(db, index.to_node_key())?;
+        Ok(leaf_hash == self.zero_hashes[self.height])
+    }
+
+    pub fn contains<S: NodeStore<V>>(
+        &self,
+        db: &S,
+        index: impl Into<LeafIndex>,
+    ) -> Result<bool, DbTreeError> {
+        Ok(!self.is_empty_leaf(db, index)?)
+    }
+
+    // Writes a leaf hash, updates the leaf count and drops the metadata of the
+    // overwritten leaf, so that writing the same leaf back later does not
+    // bring it back. Returns true if the last leaf was cleared, in which case
+    // `find_last_leaf` has to run once the ancestors are updated.
+    pub(crate) fn set_leaf_hash<S: NodeStore<V>>(
+        &mut self,
+        db: &mut S,
+        key: NodeKey,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> bool {
+        let old = self.get_node_hash_with_store_unchecked(&*db, key);
+        if old != leaf_hash {
+            db.remove_leaf_metadata_at(old.clone(), key.index);
+        }
+        let was_empty = old == self.zero_hashes[self.height];
+        let is_empty = leaf_hash == self.zero_hashes[self.height];
+        self.subscribers
+            .record(key.index, old.clone(), leaf_hash.clone());
+        if let Some(reverse_index) = self.reverse_index.as_mut() {
+            reverse_index.update(key.index, old, leaf_hash.clone());
+        }
+        self.node_hashes.insert(key, leaf_hash);
+        match (was_empty, is_empty) {
+            (true, false) => {
+                self.num_leaves = self.num_leaves.saturating_add(1);
+                if self.last_leaf.is_none_or(|last| key.index > last) {
+                    self.last_leaf = Some(key.index);
+                }
+                false
+            }
+            (false, true) => {
+                self.num_leaves -= 1;
+                self.last_leaf == Some(key.index)
+            }
+            _ => false,
+        }
+    }
+
+    // Runs after the ancestors of every leaf written with `set_leaf_hash` are
+    // updated.
+    pub(crate) fn finish_leaf_updates<S: NodeStore<V>>(&mut self, db: &S, rescan: bool) {
+        // if a node on the way is missing from the store the old last leaf
+        // is kept, which is still past every non-empty leaf
+        if rescan {
+            if let Ok(last_leaf) = self.find_last_leaf(db) {
+                self.last_leaf = last_leaf;
+            }
+        }
+        self.metrics.set_leaves(self.num_leaves);
+        let root = self.get_root();
+        self.subscribers.notify(root);
+    }
+
+    // Walks down from the root, going right whenever the right subtree is
+    // not empty.
+    pub(crate) fn find_last_leaf<S: NodeStore<V>>(
+        &self,
+        db: &S,
+    ) -> Result<Option<u128>, DbTreeError> {
+        let mut key = NodeKey::root();
+        if self.get_root() == self.zero_hashes[0] {
+            return Ok(None);
+        }
+        while key.depth() < self.height {
+            let right = key.child(true);
+            key = if self.get_node_hash_with_store(db, right)? != self.zero_hashes[right.depth()] {
+                right
+            } else {
+                key.child(false)
+            };
+        }
+        Ok(Some(key.index))
+    }
+
+    // Recounts the non-empty leaves. Used when a tree is restored from its
+    // nodes.
+    pub(crate) fn recount_leaves<S: NodeStore<V>>(&mut self, db: &S) -> Result<(), DbTreeError> {
+        let mut num_leaves = 0u128;
+        self.visit_leaves(db, |_, _| num_leaves = num_leaves.saturating_add(1))?;
+        self.num_leaves = num_leaves;
+        self.last_leaf = self.find_last_leaf(db)?;
+        Ok(())
+    }
+
+    // Calls `f` with the index and hash of every non-empty leaf in increasing
+    // index order, visiting only non-empty subtrees.
+    pub(crate) fn visit_leaves<S: NodeStore<V>>(
+        &self,
+        db: &S,
+        mut f: impl FnMut(u128, <V::Hasher as TreeHasher>::HashOut),
+    ) -> Result<(), DbTreeError> {
+        let mut stack = vec![NodeKey::root()];
+        while let Some(key) = stack.pop() {
+            let hash = self.get_node_hash_with_store(db, key)?;
+            if hash == self.zero_hashes[key.depth()] {
+                continue;
+            }
+            if key.depth() == self.height {
+                f(key.index, hash);
+            } else {
+                stack.push(key.child(true));
+                stack.push(key.child(false));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable,
+    };
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_leaf_count() {
+        let height = 8;
+
+        let mut mock_db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = Leaf::empty_leaf().hash();
+        let mut tree = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+        assert!(tree.is_empty());
+        assert_eq!(tree.next_free_index(), 0);
+
+        for i in [3u64, 0, 9] {
+            tree.update_leaf_at(&mut mock_db, i, (i as u32 + 1).hash())
+                .unwrap();
+        }
+        // overwriting a leaf does not change the count
+        tree.update_leaf_at(&mut mock_db, 9, 42u32.hash()).unwrap();
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.next_free_index(), 10);
+
+        tree.update_leaf_at(&mut mock_db, 9, empty_leaf_hash)
+            .unwrap();
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree.next_free_index(), 4);
+
+        let leaves: Vec<_> = [(5, 1u32.hash()), (3, empty_leaf_hash), (5, empty_leaf_hash)]
+            .into_iter()
+            .map(|(i, h)| (LeafIndex::new(i, height).unwrap(), h))
+            .collect();
+        tree.update_leaves(&mut mock_db, &leaves).unwrap();
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree.next_free_index(), 1);
+
+        let built = MerkleTree::from_leaf_hashes(
+            &mut mock_db,
+            height,
+            empty_leaf_hash,
+            &[1u32.hash(), empty_leaf_hash, 2u32.hash(), empty_leaf_hash],
+        );
+        assert_eq!(built.len(), 2);
+        assert_eq!(built.next_free_index(), 3);
+        let mut recounted = built.clone();
+        recounted.num_leaves = 0;
+        recounted.recount_leaves(&mock_db).unwrap();
+        assert_eq!(recounted.len(), 2);
+    }
+
+    #[test]
+    fn test_contains() {
+        let height = 8;
+        let mut mock_db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = Leaf::empty_leaf().hash();
+        let mut tree = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+        tree.update_leaf_at(&mut mock_db, 5, 1u32.hash()).unwrap();
+        tree.compact(&mock_db, 2).unwrap();
+
+        let index = |i| LeafIndex::new(i, height).unwrap();
+        assert!(tree.contains(&mock_db, index(5)).unwrap());
+        assert!(tree.is_empty_leaf(&mock_db, index(4)).unwrap());
+        assert!(tree.is_empty_leaf(&mock_db, index(200)).unwrap());
+        assert!(tree
+            .contains(&mock_db, LeafIndex::new(5, 4).unwrap())
+            .is_err());
+    }
+}
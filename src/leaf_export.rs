@@ -0,0 +1,213 @@
+use std::io::{Read, Write};
+
+use crate::{
+    bulk_load::BulkLoader,
+    merkle_tree::MerkleTree,
+    node_key::MAX_HEIGHT,
+    node_store::NodeStore,
+    service::WireHash,
+    traits::{Leafable, TreeHasher},
+};
+
+const MAGIC: &[u8; 4] = b"DBTL";
+pub const LEAF_EXPORT_FORMAT_VERSION: u32 = 2;
+
+// Longest hash encoding `import_leaves` accepts, so that a bad length is
+// rejected before anything is allocated for it.
+const MAX_HASH_LEN: usize = 64;
+
+// Leaf exports are little endian binary:
+//   magic "DBTL", format version (u32), height (u32), empty leaf hash,
+//   number of leaves (u128), then (index (u128), leaf) per leaf
+// with leaves in strictly increasing index order. A hash is its `WireHash`
+// encoding prefixed with its length (u32). A leaf is its hash followed by
+// 1 and the serialized leaf prefixed with its length (u32) if the store has
+// the leaf data, or by 0 if the leaf was set by hash only. Only leaves are
+// exported, as every zero hash is derived from the empty leaf hash.
+
+fn write_hash<H: WireHash, W: Write>(writer: &mut W, hash: &H) -> anyhow::Result<()> {
+    let bytes = hash.to_wire();
+    writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(&bytes)?;
+    Ok(())
+}
+
+fn read_hash<H: WireHash, R: Read>(reader: &mut R) -> anyhow::Result<H> {
+    let len = u32::from_le_bytes(read_array(reader)?) as usize;
+    anyhow::ensure!(
+        len <= MAX_HASH_LEN,
+        "invalid leaf export: hash of {} bytes",
+        len
+    );
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+    H::from_wire(&bytes).ok_or_else(|| anyhow::anyhow!("invalid leaf export: bad hash"))
+}
+
+fn write_leaf<H: WireHash, W: Write>(
+    writer: &mut W,
+    index: u128,
+    hash: &H,
+    data: Option<Vec<u8>>,
+) -> anyhow::Result<()> {
+    writer.write_all(&index.to_le_bytes())?;
+    write_hash(writer, hash)?;
+    match data {
+        Some(data) => {
+            writer.write_all(&[1])?;
+            writer.write_all(&(data.len() as u32).to_le_bytes())?;
+            writer.write_all(&data)?;
+        }
+        None => writer.write_all(&[0])?,
+    }
+    Ok(())
+}
+
+// The leaf data is read as it arrives instead of allocated from its length.
+fn read_leaf_data<R: Read>(reader: &mut R) -> anyhow::Result<Option<Vec<u8>>> {
+    match read_array::<1, _>(reader)? {
+        [0] => Ok(None),
+        [1] => {
+            let len = u32::from_le_bytes(read_array(reader)?) as u64;
+            let mut data = vec![];
+            reader.take(len).read_to_end(&mut data)?;
+            anyhow::ensure!(
+                data.len() as u64 == len,
+                "invalid leaf export: truncated leaf data"
+            );
+            Ok(Some(data))
+        }
+        [flag] => anyhow::bail!("invalid leaf export: bad leaf data flag {}", flag),
+    }
+}
+
+fn read_array<const N: usize, R: Read>(reader: &mut R) -> anyhow::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+impl<V: Leafable> MerkleTree<V>
+where
+    <V::Hasher as TreeHasher>::HashOut: WireHash,
+{
+    // Streams every non-empty leaf to `writer` and returns the number of
+    // exported leaves. Compacted trees read their leaves from `db`.
+    pub fn export_leaves<S: NodeStore<V>, W: Write>(
+        &self,
+        db: &S,
+        mut writer: W,
+    ) -> anyhow::Result<u128> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&LEAF_EXPORT_FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&(self.height as u32).to_le_bytes())?;
+        write_hash(&mut writer, &self.zero_hashes[self.height])?;
+        writer.write_all(&self.num_leaves.to_le_bytes())?;
+        let mut result = Ok(());
+        self.visit_leaves(db, |index, hash| {
+            if result.is_ok() {
+                let data = db.get_leaf_data(hash.clone());
+                result = write_leaf(&mut writer, index, &hash, data);
+            }
+        })?;
+        result?;
+        writer.flush()?;
+        Ok(self.num_leaves)
+    }
+
+    // Rebuilds a tree written by `export_leaves` into `db`, leaf data
+    // included. The result does not depend on where or in which store the
+    // leaves were exported.
+    pub fn import_leaves<S: NodeStore<V>, R: Read>(
+        db: &mut S,
+        mut reader: R,
+    ) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            &read_array::<4, _>(&mut reader)? == MAGIC,
+            "invalid leaf export: bad magic"
+        );
+        let version = u32::from_le_bytes(read_array(&mut reader)?);
+        anyhow::ensure!(
+            version == LEAF_EXPORT_FORMAT_VERSION,
+            "unsupported leaf export format version {}",
+            version
+        );
+        let height = u32::from_le_bytes(read_array(&mut reader)?) as usize;
+        anyhow::ensure!(
+            height <= MAX_HEIGHT,
+            "invalid leaf export: height {} is too large",
+            height
+        );
+        let empty_leaf_hash: <V::Hasher as TreeHasher>::HashOut = read_hash(&mut reader)?;
+        let num_leaves = u128::from_le_bytes(read_array(&mut reader)?);
+        let mut loader = BulkLoader::new(db, height, empty_leaf_hash.clone(), height);
+        for _ in 0..num_leaves {
+            let index = u128::from_le_bytes(read_array(&mut reader)?);
+            let leaf_hash: <V::Hasher as TreeHasher>::HashOut = read_hash(&mut reader)?;
+            anyhow::ensure!(
+                leaf_hash != empty_leaf_hash,
+                "invalid leaf export: empty leaf at index {}",
+                index
+            );
+            if let Some(data) = read_leaf_data(&mut reader)? {
+                db.insert_leaf_data(leaf_hash.clone(), data);
+            }
+            loader.push(db, index, leaf_hash)?;
+        }
+        Ok(loader.finish(db))
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable,
+    };
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_export_import_leaves() {
+        let height = 16;
+        let mut db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = Leaf::empty_leaf().hash();
+        let mut tree = MerkleTree::new(&mut db, height, empty_leaf_hash);
+        for i in [9u64, 0, 700, 65535] {
+            tree.update_leaf_at(&mut db, i, (i as u32 + 1).hash())
+                .unwrap();
+        }
+        let index = LeafIndex::new(3, height).unwrap();
+        tree.update_leaf_data(&mut db, index, &4).unwrap();
+        tree.compact(&db, 4).unwrap();
+
+        let mut bytes = vec![];
+        assert_eq!(tree.export_leaves(&db, &mut bytes).unwrap(), 5);
+        let mut other_db = MockDB::<Leaf>::new();
+        let imported = MerkleTree::<Leaf>::import_leaves(&mut other_db, &bytes[..]).unwrap();
+        assert_eq!(imported.get_root(), tree.get_root());
+        assert_eq!(imported.len(), 5);
+        assert_eq!(imported.next_free_index(), 65536);
+        assert_eq!(imported.get_leaf(&other_db, index).unwrap(), Some(4));
+        assert_eq!(
+            imported
+                .get_leaf(&other_db, LeafIndex::new(9, height).unwrap())
+                .unwrap(),
+            None
+        );
+
+        // exports of the same leaves are identical
+        let mut again = vec![];
+        imported.export_leaves(&other_db, &mut again).unwrap();
+        assert_eq!(again, bytes);
+
+        assert!(
+            MerkleTree::<Leaf>::import_leaves(&mut other_db, &bytes[..bytes.len() - 1]).is_err()
+        );
+        // a hash length from a damaged export is not allocated
+        let mut damaged = bytes.clone();
+        damaged[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(MerkleTree::<Leaf>::import_leaves(&mut other_db, &damaged[..]).is_err());
+        bytes[0] = b'X';
+        assert!(MerkleTree::<Leaf>::import_leaves(&mut other_db, &bytes[..]).is_err());
+    }
+}
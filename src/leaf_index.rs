@@ -0,0 +1,122 @@
+use alloc::vec::Vec;
+
+use crate::{
+    error::DbTreeError,
+    node_key::{NodeKey, MAX_HEIGHT},
+};
+
+// Index of a leaf in a tree of a given height. Index bits are little endian
+// (bit i is the i-th bit of the index, as in `usize_le_bits`) while paths are
+// big endian (the first bit is the direction taken at the root), so
+// conversions to bits always name their endianness.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LeafIndex {
+    height: u8,
+    index: u128,
+}
+
+impl LeafIndex {
+    pub fn new(index: u128, height: usize) -> Result<Self, DbTreeError> {
+        if height > MAX_HEIGHT || (height < MAX_HEIGHT && index >> height != 0) {
+            return Err(DbTreeError::IndexOutOfRange { index, height });
+        }
+        Ok(Self {
+            height: height as u8,
+            index,
+        })
+    }
+
+    // Fails if there are more than `MAX_HEIGHT` bits.
+    pub fn from_le_bits(index_bits: &[bool]) -> Result<Self, DbTreeError> {
+        check_bits(index_bits)?;
+        Ok(NodeKey::from_index_bits(index_bits).into())
+    }
+
+    pub fn from_be_path(path: &[bool]) -> Result<Self, DbTreeError> {
+        check_bits(path)?;
+        Ok(NodeKey::from_path(path).into())
+    }
+
+    pub fn index(&self) -> u128 {
+        self.index
+    }
+
+    pub fn height(&self) -> usize {
+        self.height as usize
+    }
+
+    pub fn to_le_bits(&self) -> Vec<bool> {
+        (0..self.height())
+            .map(|i| (self.index >> i) & 1 == 1)
+            .collect()
+    }
+
+    pub fn to_be_path(&self) -> Vec<bool> {
+        self.to_node_key().to_path()
+    }
+
+    pub fn to_node_key(&self) -> NodeKey {
+        NodeKey {
+            depth: self.height,
+            index: self.index,
+        }
+    }
+}
+
+// the leaf at `key`, i.e. in a tree whose height is the depth of `key`
+impl From<NodeKey> for LeafIndex {
+    fn from(key: NodeKey) -> Self {
+        Self {
+            height: key.depth,
+            index: key.index,
+        }
+    }
+}
+
+fn check_bits(bits: &[bool]) -> Result<(), DbTreeError> {
+    if bits.len() > MAX_HEIGHT {
+        return Err(DbTreeError::InvalidIndexLength {
+            expected: MAX_HEIGHT,
+            actual: bits.len(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::{error::DbTreeError, merkle_tree::usize_le_bits};
+
+    use super::LeafIndex;
+
+    #[test]
+    fn test_leaf_index_endianness() {
+        let index = LeafIndex::new(6, 4).unwrap();
+        assert_eq!(index.to_le_bits(), vec![false, true, true, false]);
+        assert_eq!(index.to_le_bits(), usize_le_bits(6, 4));
+        assert_eq!(index.to_be_path(), vec![false, true, true, false]);
+
+        let index = LeafIndex::new(1, 3).unwrap();
+        assert_eq!(index.to_le_bits(), vec![true, false, false]);
+        assert_eq!(index.to_be_path(), vec![false, false, true]);
+        assert_eq!(LeafIndex::from_le_bits(&index.to_le_bits()), Ok(index));
+        assert_eq!(LeafIndex::from_be_path(&index.to_be_path()), Ok(index));
+        assert_eq!(LeafIndex::from_le_bits(&usize_le_bits(1, 3)), Ok(index));
+        assert_eq!(
+            LeafIndex::from_le_bits(&[false; 129]),
+            Err(DbTreeError::InvalidIndexLength {
+                expected: 128,
+                actual: 129
+            })
+        );
+
+        assert_eq!(
+            LeafIndex::new(8, 3),
+            Err(DbTreeError::IndexOutOfRange {
+                index: 8,
+                height: 3
+            })
+        );
+        assert!(LeafIndex::new(u128::MAX, 128).is_ok());
+    }
+}
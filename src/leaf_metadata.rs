@@ -0,0 +1,115 @@
+use crate::{
+    error::DbTreeError,
+    leaf_index::LeafIndex,
+    merkle_tree::{MerkleProof, MerkleTree},
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+impl<V: Leafable> MerkleTree<V> {
+    // Same as `update_leaf`, but also attaches `metadata` (e.g. an insertion
+    // timestamp or block number) to the new leaf. Metadata is not hashed and
+    // is not stored for the empty leaf.
+    pub fn update_leaf_with_metadata<S: NodeStore<V>>(
+        &mut self,
+        db: &mut S,
+        index: impl Into<LeafIndex>,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        metadata: Vec<u8>,
+    ) -> Result<(), DbTreeError> {
+        let index = index.into();
+        self.check_leaf_index(index)?;
+        self.check_update_paths(&*db, vec![index.to_node_key()])?;
+        if leaf_hash != self.zero_hashes[self.height] {
+            db.insert_leaf_metadata(leaf_hash.clone(), index.index(), metadata);
+        }
+        self.update_leaf_unchecked(db, index, leaf_hash);
+        Ok(())
+    }
+
+    // Metadata attached to the leaf currently at `index`. Writing another
+    // leaf at `index` drops the metadata, also when the same leaf is written
+    // back later.
+    pub fn get_leaf_metadata<S: NodeStore<V>>(
+        &self,
+        db: &S,
+        index: impl Into<LeafIndex>,
+    ) -> Result<Option<Vec<u8>>, DbTreeError> {
+        let index = index.into();
+        self.check_leaf_index(index)?;
+        let leaf_hash = self.get_node_hash_with_store(db, index.to_node_key())?;
+        if leaf_hash == self.zero_hashes[self.height] {
+            return Ok(None);
+        }
+        Ok(db.get_leaf_metadata(leaf_hash, index.index()))
+    }
+
+    // A proof of `index` together with the metadata of the proven leaf.
+    pub fn prove_with_metadata<S: NodeStore<V>>(
+        &self,
+        db: &S,
+        index: impl Into<LeafIndex>,
+    ) -> Result<(Option<Vec<u8>>, MerkleProof<V>), DbTreeError> {
+        let index = index.into();
+        let metadata = self.get_leaf_metadata(db, index)?;
+        Ok((metadata, self.prove(index)?))
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        error::DbTreeError, leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB,
+        node_store::NodeStore, traits::Leafable,
+    };
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_leaf_metadata() {
+        let height = 8;
+        let mut db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = Leaf::empty_leaf().hash();
+        let mut tree = MerkleTree::new(&mut db, height, empty_leaf_hash);
+        let mut plain = MerkleTree::new(&mut db, height, empty_leaf_hash);
+        let index = |i| LeafIndex::new(i, height).unwrap();
+
+        // the same leaf at two indices keeps separate metadata
+        for (i, block) in [(1, 10u64), (2, 20)] {
+            tree.update_leaf_with_metadata(
+                &mut db,
+                index(i),
+                5u32.hash(),
+                block.to_le_bytes().to_vec(),
+            )
+            .unwrap();
+            plain.update_leaf(&mut db, index(i), 5u32.hash()).unwrap();
+        }
+        assert_eq!(tree.get_root(), plain.get_root());
+        let (metadata, proof) = tree.prove_with_metadata(&db, index(2)).unwrap();
+        assert_eq!(metadata, Some(20u64.to_le_bytes().to_vec()));
+        proof.verify(&5, index(2), tree.get_root()).unwrap();
+
+        tree.update_leaf(&mut db, index(1), 6u32.hash()).unwrap();
+        assert_eq!(tree.get_leaf_metadata(&db, index(1)).unwrap(), None);
+        // the same leaf written back does not bring back its old metadata
+        tree.update_leaf(&mut db, index(1), 5u32.hash()).unwrap();
+        assert_eq!(tree.get_leaf_metadata(&db, index(1)).unwrap(), None);
+        tree.update_leaves(&mut db, &[(index(2), 6u32.hash()), (index(2), 5u32.hash())])
+            .unwrap();
+        assert_eq!(tree.get_leaf_metadata(&db, index(2)).unwrap(), None);
+        assert_eq!(tree.get_leaf_metadata(&db, index(3)).unwrap(), None);
+        assert!(tree
+            .get_leaf_metadata(&db, LeafIndex::new(1, 4).unwrap())
+            .is_err());
+
+        // a missing node fails before the metadata is written
+        tree.compact(&db, 1).unwrap();
+        let mut empty_db = MockDB::<Leaf>::new();
+        assert!(matches!(
+            tree.update_leaf_with_metadata(&mut empty_db, index(4), 7u32.hash(), vec![1]),
+            Err(DbTreeError::MissingNode { .. })
+        ));
+        assert_eq!(empty_db.get_leaf_metadata(7u32.hash(), 4), None);
+    }
+}
@@ -0,0 +1,114 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    leaf_index::LeafIndex,
+    merkle_tree::{MerkleProof, MerkleTree},
+    node_store::NodeStore,
+    traits::Leafable,
+};
+
+impl<V: Leafable + Serialize + DeserializeOwned> MerkleTree<V> {
+    // Same as `update_leaf`, but also stores the serialized `leaf` in `db` so
+    // that it can be read back with `get_leaf`.
+    pub fn update_leaf_data<S: NodeStore<V>>(
+        &mut self,
+        db: &mut S,
+        index: impl Into<LeafIndex>,
+        leaf: &V,
+    ) -> anyhow::Result<()> {
+        let index = index.into();
+        self.check_leaf_index(index)?;
+        self.check_update_paths(&*db, vec![index.to_node_key()])?;
+        let leaf_hash = leaf.hash();
+        db.insert_leaf_data(leaf_hash.clone(), serde_json::to_vec(leaf)?);
+        self.update_leaf_unchecked(db, index, leaf_hash);
+        Ok(())
+    }
+
+    // The leaf at `index`, `V::empty_leaf()` if it was never set. Returns
+    // `None` if the leaf was set by hash only and `db` has no data for it.
+    pub fn get_leaf<S: NodeStore<V>>(
+        &self,
+        db: &S,
+        index: impl Into<LeafIndex>,
+    ) -> anyhow::Result<Option<V>> {
+        let index = index.into();
+        self.check_leaf_index(index)?;
+        let leaf_hash = self.get_node_hash_with_store(db, index.to_node_key())?;
+        if leaf_hash == self.zero_hashes[self.height] {
+            return Ok(Some(V::empty_leaf()));
+        }
+        match db.get_leaf_data(leaf_hash) {
+            Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    // A proof of `index` together with the leaf being proven.
+    pub fn prove_with_leaf<S: NodeStore<V>>(
+        &self,
+        db: &S,
+        index: impl Into<LeafIndex>,
+    ) -> anyhow::Result<(Option<V>, MerkleProof<V>)> {
+        let index = index.into();
+        let leaf = self.get_leaf(db, index)?;
+        Ok((leaf, self.prove(index)?))
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, node_store::NodeStore,
+        traits::Leafable,
+    };
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_get_leaf() {
+        let height = 16;
+
+        let mut db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = Leaf::empty_leaf().hash();
+        let mut tree = MerkleTree::new(&mut db, height, empty_leaf_hash);
+        for i in 0..10u32 {
+            let index = LeafIndex::new(i as u128, height).unwrap();
+            tree.update_leaf_data(&mut db, index, &(i + 100)).unwrap();
+        }
+        tree.update_leaf_at(&mut db, 10, 10u32.hash()).unwrap();
+
+        let index = LeafIndex::new(3, height).unwrap();
+        let (leaf, proof) = tree.prove_with_leaf(&db, index).unwrap();
+        assert_eq!(leaf, Some(103));
+        proof.verify(&103, index, tree.get_root()).unwrap();
+        assert_eq!(
+            tree.get_leaf(&db, LeafIndex::new(10, height).unwrap())
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            tree.get_leaf(&db, LeafIndex::new(11, height).unwrap())
+                .unwrap(),
+            Some(0)
+        );
+        assert!(tree.get_leaf(&db, LeafIndex::new(0, 8).unwrap()).is_err());
+
+        // an overwritten leaf is dropped once no live root reaches it
+        let old_root = tree.get_root();
+        tree.update_leaf_data(&mut db, index, &7).unwrap();
+        db.collect_garbage(&[old_root, tree.get_root()]);
+        assert!(db.get_leaf_data(103u32.hash()).is_some());
+        db.collect_garbage(&[tree.get_root()]);
+        assert!(db.get_leaf_data(103u32.hash()).is_none());
+        assert_eq!(tree.get_leaf(&db, index).unwrap(), Some(7));
+
+        // a missing node fails before the leaf data is written
+        tree.compact(&db, 4).unwrap();
+        let root = tree.get_root();
+        let mut empty_db = MockDB::<Leaf>::new();
+        assert!(tree.update_leaf_data(&mut empty_db, index, &8).is_err());
+        assert!(empty_db.get_leaf_data(8u32.hash()).is_none());
+        assert_eq!(tree.get_root(), root);
+    }
+}
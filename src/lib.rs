@@ -1,2 +1,170 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+// Enters a `tracing` span at `$level` (TRACE or DEBUG) until the end of the
+// enclosing block. Expands to nothing without the `tracing` feature.
+#[cfg(feature = "std")]
+macro_rules! enter_span {
+    ($level:ident, $($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::span!(tracing::Level::$level, $($arg)*).entered();
+    };
+}
+
+#[cfg(feature = "std")]
+pub mod append;
+#[cfg(feature = "std")]
+pub mod archive;
+#[cfg(feature = "async")]
+pub mod async_tree;
+#[cfg(feature = "std")]
+pub mod atomic_commit;
+#[cfg(feature = "std")]
+pub mod backup;
+#[cfg(feature = "std")]
+pub mod batch_hasher;
+#[cfg(feature = "std")]
+pub mod big_index;
+#[cfg(feature = "blake3")]
+pub mod blake3_hasher;
+#[cfg(feature = "std")]
+pub mod bn254_poseidon_hasher;
+#[cfg(feature = "borsh")]
+pub mod borsh_proof;
+#[cfg(feature = "std")]
+pub mod buffered_store;
+#[cfg(feature = "std")]
+pub mod builder;
+#[cfg(feature = "std")]
+pub mod bulk_load;
+#[cfg(feature = "std")]
+pub mod checkpoint;
+#[cfg(feature = "std")]
+pub mod checkpoint_lock;
+#[cfg(feature = "std")]
+pub mod circom;
+#[cfg(feature = "std")]
+pub mod compaction;
+#[cfg(feature = "std")]
+pub mod concurrent;
+#[cfg(feature = "std")]
+pub mod domain;
+#[cfg(feature = "std")]
+pub mod dot;
+#[cfg(feature = "std")]
+pub mod dump;
+#[cfg(feature = "std")]
+pub mod durability;
+#[cfg(feature = "encryption")]
+pub mod encrypted_store;
+pub mod error;
+#[cfg(all(feature = "std", any(test, feature = "testkit")))]
+pub mod faulty_store;
+#[cfg(all(feature = "std", any(test, feature = "testkit")))]
+pub mod fuzzing;
+#[cfg(feature = "std")]
+pub mod graft;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "std")]
+pub mod historical_proof;
+#[cfg(feature = "std")]
+pub mod integrity;
+#[cfg(feature = "jsonrpc")]
+pub mod jsonrpc;
+#[cfg(feature = "std")]
+pub mod kary_tree;
+#[cfg(feature = "keccak")]
+pub mod keccak_hasher;
+#[cfg(feature = "std")]
+pub mod leaf_count;
+#[cfg(feature = "std")]
+pub mod leaf_export;
+pub mod leaf_index;
+#[cfg(feature = "std")]
+pub mod leaf_metadata;
+#[cfg(feature = "std")]
+pub mod leaf_store;
+#[cfg(feature = "std")]
+pub mod memory;
+#[cfg(feature = "std")]
 pub mod merkle_tree;
+#[cfg(feature = "std")]
+pub mod metrics;
+#[cfg(feature = "std")]
 pub mod mock_db;
+#[cfg(feature = "std")]
+pub mod node;
+pub mod node_key;
+#[cfg(feature = "std")]
+pub mod node_store;
+#[cfg(feature = "parallel")]
+pub mod parallel;
+#[cfg(feature = "zkp")]
+pub mod poseidon2_hasher;
+pub mod proof;
+#[cfg(feature = "std")]
+pub mod proof_set;
+#[cfg(feature = "std")]
+pub mod ref_counted_db;
+#[cfg(all(feature = "std", any(test, feature = "testkit")))]
+pub mod reference_tree;
+#[cfg(feature = "std")]
+pub mod repair;
+#[cfg(feature = "rest")]
+pub mod rest;
+#[cfg(feature = "std")]
+pub mod reverse_index;
+#[cfg(feature = "std")]
+pub mod root_history;
+#[cfg(feature = "std")]
+pub mod root_index;
+#[cfg(feature = "std")]
+pub mod service;
+#[cfg(feature = "sha256")]
+pub mod sha256_hasher;
+#[cfg(feature = "std")]
+pub mod sharded;
+#[cfg(feature = "std")]
+pub mod shared_tree;
+#[cfg(feature = "std")]
+pub mod sharing;
+#[cfg(feature = "std")]
+pub mod snapshot;
+#[cfg(feature = "std")]
+pub mod sorted_pairs;
+#[cfg(feature = "std")]
+pub mod stats;
+#[cfg(feature = "proptest")]
+pub mod strategies;
+#[cfg(feature = "std")]
+pub mod subscription;
+#[cfg(feature = "std")]
+pub mod subtree;
+#[cfg(all(feature = "std", any(test, feature = "testkit")))]
+pub mod testkit;
+#[cfg(feature = "std")]
+pub mod tiered_store;
+#[cfg(feature = "tracing")]
+pub mod traced_store;
+pub mod traits;
+#[cfg(feature = "std")]
+pub mod traversal;
+pub mod verify;
+#[cfg(feature = "std")]
+pub mod verifying_store;
+#[cfg(feature = "std")]
+pub mod versioned_tree;
+#[cfg(feature = "std")]
+pub mod wal;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "std")]
+pub mod wide_tree;
+#[cfg(feature = "std")]
+pub mod workload;
+#[cfg(feature = "std")]
+pub mod zero_hashes;
+#[cfg(feature = "zkp")]
+pub mod zkp_witness;
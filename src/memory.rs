@@ -0,0 +1,280 @@
+use std::{collections::hash_map::Entry, mem::size_of};
+
+use hashbrown::HashSet;
+
+use crate::{
+    leaf_index::LeafIndex,
+    merkle_tree::MerkleTree,
+    node_key::NodeKey,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryStats {
+    pub node_hash_entries: usize,
+    // rough size of the in-memory maps, including unused capacity
+    pub estimated_bytes: usize,
+    // None if the store cannot count its nodes
+    pub store_nodes: Option<usize>,
+}
+
+impl<V: Leafable> MerkleTree<V> {
+    pub fn memory_stats<S: NodeStore<V>>(&self, db: &S) -> MemoryStats {
+        let hash_size = size_of::<<V::Hasher as TreeHasher>::HashOut>();
+        // one control byte per bucket in the swiss table
+        let entry_size = size_of::<NodeKey>() + hash_size + 1;
+        MemoryStats {
+            node_hash_entries: self.node_hashes.len(),
+            estimated_bytes: self.node_hashes.capacity() * entry_size
+                + self.zero_hashes.capacity() * hash_size,
+            store_nodes: db.num_nodes(),
+        }
+    }
+
+    // Evicts every in-memory node hash that equals the zero hash of its depth
+    // and every node hash deeper than `keep_depth`, which are recovered from
+    // `db` on demand afterwards. Fails without evicting anything if a node at
+    // `keep_depth` is missing from the store. Returns the number of evicted
+    // entries.
+    pub fn compact<S: NodeStore<V>>(&mut self, db: &S, keep_depth: usize) -> anyhow::Result<usize> {
+        anyhow::ensure!(
+            keep_depth <= self.height,
+            "keep depth {} is larger than height {}",
+            keep_depth,
+            self.height
+        );
+        if keep_depth < self.height {
+            // every cached subtree below keep_depth has to be reachable
+            let mut boundary = HashSet::new();
+            for key in self.node_hashes.keys() {
+                if key.depth() >= keep_depth {
+                    boundary.insert(NodeKey::new(
+                        keep_depth,
+                        key.index
+                            .checked_shr((key.depth() - keep_depth) as u32)
+                            .unwrap_or(0),
+                    ));
+                }
+            }
+            for key in boundary {
+                let hash = self.get_node_hash_with_store(db, key)?;
+                anyhow::ensure!(
+                    hash == self.zero_hashes[keep_depth] || db.contains(hash),
+                    "cannot find node at depth {} in the store",
+                    keep_depth
+                );
+            }
+        }
+
+        let before = self.node_hashes.len();
+        let zero_hashes = &self.zero_hashes;
+        self.node_hashes
+            .retain(|key, hash| key.depth() <= keep_depth && *hash != zero_hashes[key.depth()]);
+        self.node_hashes.shrink_to_fit();
+        self.cache_depth = self.cache_depth.min(keep_depth);
+        Ok(before - self.node_hashes.len())
+    }
+
+    // Reads the store nodes on the proof paths of `indices` into the
+    // in-memory node hashes of a compacted tree, so that `prove` answers them
+    // without the store until the next `compact`, e.g. for batch proof
+    // serving after a block commit. Does nothing on a tree that is not
+    // compacted. Returns the number of node hashes loaded.
+    pub fn prefetch<S: NodeStore<V>>(
+        &mut self,
+        db: &S,
+        indices: &[LeafIndex],
+    ) -> anyhow::Result<usize> {
+        let mut loaded = 0;
+        for &index in indices {
+            self.check_leaf_index(index)?;
+            let leaf = index.to_node_key();
+            let path = |depth: usize| {
+                let index = leaf.index.checked_shr((self.height - depth) as u32);
+                NodeKey::new(depth, index.unwrap_or(0))
+            };
+            let mut hash = self.get_node_hash_unchecked(path(self.cache_depth));
+            for depth in self.cache_depth..self.height {
+                let key = path(depth);
+                let cached = (
+                    self.node_hashes.get(&key.child(false)),
+                    self.node_hashes.get(&key.child(true)),
+                );
+                let (left, right) = match cached {
+                    (Some(left), Some(right)) => (left.clone(), right.clone()),
+                    _ if hash == self.zero_hashes[depth] => (
+                        self.zero_hashes[depth + 1].clone(),
+                        self.zero_hashes[depth + 1].clone(),
+                    ),
+                    _ => db
+                        .try_with_node(hash, |node| (node.left.clone(), node.right.clone()))
+                        .map_err(|_| {
+                            anyhow::anyhow!("node at depth {} in the store is corrupted", depth)
+                        })?
+                        .ok_or_else(|| {
+                            anyhow::anyhow!("cannot find node at depth {} in the store", depth)
+                        })?,
+                };
+                for (is_right, child) in [(false, &left), (true, &right)] {
+                    if let Entry::Vacant(entry) = self.node_hashes.entry(key.child(is_right)) {
+                        entry.insert(child.clone());
+                        loaded += 1;
+                    }
+                }
+                hash = if path(depth + 1).is_right() {
+                    right
+                } else {
+                    left
+                };
+            }
+        }
+        Ok(loaded)
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use intmax2_zkp::utils::poseidon_hash_out::PoseidonHashOut;
+
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable,
+    };
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_compact() {
+        let height = 16;
+
+        let mut mock_db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = PoseidonHashOut::hash_inputs_u32(&[]);
+        let mut merkle_tree = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+        for i in 0..50 {
+            let leaf = i as u32;
+            merkle_tree
+                .update_leaf(
+                    &mut mock_db,
+                    LeafIndex::new((i * 3) as u128, height).unwrap(),
+                    leaf.hash(),
+                )
+                .unwrap();
+        }
+        let mut expected = merkle_tree.clone();
+
+        let stats = merkle_tree.memory_stats(&mock_db);
+        assert_eq!(stats.store_nodes, Some(mock_db.len()));
+        let evicted = merkle_tree.compact(&mock_db, 4).unwrap();
+        assert!(evicted > 0);
+        assert!(merkle_tree.is_compacted());
+        assert_eq!(
+            merkle_tree.memory_stats(&mock_db).node_hash_entries,
+            stats.node_hash_entries - evicted
+        );
+        assert_ne!(merkle_tree, expected);
+        assert!(merkle_tree.has_same_root(&expected));
+
+        // updates after compaction recover evicted siblings from the store
+        for i in [0, 1, 2, 100, 1000] {
+            let leaf = i as u32 + 1;
+            merkle_tree
+                .update_leaf(
+                    &mut mock_db,
+                    LeafIndex::new(i as u128, height).unwrap(),
+                    leaf.hash(),
+                )
+                .unwrap();
+            expected
+                .update_leaf(
+                    &mut mock_db,
+                    LeafIndex::new(i as u128, height).unwrap(),
+                    leaf.hash(),
+                )
+                .unwrap();
+            assert_eq!(merkle_tree.get_root(), expected.get_root());
+        }
+        let root = merkle_tree.get_root();
+        let leaf_index = LeafIndex::new(2, height).unwrap();
+        let proof = merkle_tree
+            .prove_with_given_root(&mock_db, root, leaf_index)
+            .unwrap();
+        assert_eq!(proof, expected.prove(leaf_index).unwrap());
+
+        // compaction fails if the store cannot recover the evicted nodes
+        let mut tree = expected.clone();
+        assert!(tree.compact(&MockDB::<Leaf>::new(), 4).is_err());
+        assert!(!tree.is_compacted());
+    }
+
+    #[test]
+    fn test_prefetch() {
+        let height = 16;
+
+        let mut mock_db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = PoseidonHashOut::hash_inputs_u32(&[]);
+        let mut merkle_tree = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+        for i in 0..50 {
+            let leaf = i as u32;
+            merkle_tree
+                .update_leaf(
+                    &mut mock_db,
+                    LeafIndex::new((i * 3) as u128, height).unwrap(),
+                    leaf.hash(),
+                )
+                .unwrap();
+        }
+        let expected = merkle_tree.clone();
+        assert_eq!(merkle_tree.prefetch(&mock_db, &[]).unwrap(), 0);
+        merkle_tree.compact(&mock_db, 4).unwrap();
+        let indices: Vec<LeafIndex> = [3usize, 4, 30_000]
+            .iter()
+            .map(|&i| LeafIndex::new(i as u128, height).unwrap())
+            .collect();
+        assert!(merkle_tree.prove(indices[0]).is_err());
+
+        let loaded = merkle_tree.prefetch(&mock_db, &indices).unwrap();
+        assert!(loaded > 0);
+        assert_eq!(merkle_tree.prefetch(&mock_db, &indices).unwrap(), 0);
+        // the proofs no longer need the store
+        for &index in &indices {
+            assert_eq!(
+                merkle_tree.prove(index).unwrap(),
+                expected.prove(index).unwrap()
+            );
+        }
+        assert!(merkle_tree.has_same_root(&expected));
+
+        // a store without the nodes below the cache fails
+        let mut tree = merkle_tree.clone();
+        tree.compact(&mock_db, 4).unwrap();
+        assert!(tree.prefetch(&MockDB::<Leaf>::new(), &indices).is_err());
+    }
+
+    #[test]
+    fn test_compact_and_prefetch_full_height() {
+        let height = 128;
+
+        let mut mock_db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = PoseidonHashOut::hash_inputs_u32(&[]);
+        let mut merkle_tree = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+        let indices: Vec<LeafIndex> = [0u128, 5, u128::MAX]
+            .iter()
+            .map(|&i| LeafIndex::new(i, height).unwrap())
+            .collect();
+        for (i, &index) in indices.iter().enumerate() {
+            merkle_tree
+                .update_leaf(&mut mock_db, index, (i as u32).hash())
+                .unwrap();
+        }
+        let expected = merkle_tree.clone();
+
+        assert!(merkle_tree.compact(&mock_db, 0).unwrap() > 0);
+        assert!(merkle_tree.prefetch(&mock_db, &indices).unwrap() > 0);
+        for &index in &indices {
+            assert_eq!(
+                merkle_tree.prove(index).unwrap(),
+                expected.prove(index).unwrap()
+            );
+        }
+    }
+}
@@ -1,55 +1,173 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
-use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use crate::{
+    batch_hasher::BatchHasher,
+    error::{DbTreeError, ProofError},
+    leaf_index::LeafIndex,
+    metrics::MetricsHook,
+    node::Node,
+    node_key::{NodeKey, MAX_HEIGHT},
+    node_store::NodeStore,
+    reverse_index::ReverseIndex,
+    subscription::Subscribers,
+    traits::{Leafable, TreeHasher},
+    zero_hashes::ZeroHashes,
+};
 
-use intmax2_zkp::utils::{leafable::Leafable, leafable_hasher::LeafableHasher};
-
-use crate::mock_db::{MockDB, Node};
+pub use crate::proof::MerkleProof;
 
 // `MekleTree`` is a structure of Merkle Tree used for `MerkleTreeWithLeaves`
 // and `SparseMerkleTreeWithLeaves`. It only holds non-zero nodes.
-// All nodes are specified by path: Vec<bool>. The path is big endian.
+// All nodes are specified by `NodeKey` (depth, index), where index is the
+// path from the root read as a big endian integer.
 // Note that this is different from the original plonky2 Merkle Tree which
 // uses little endian path.
+// (left, right) hashes of a node's children
+type ChildHashes<V> = (
+    <<V as Leafable>::Hasher as TreeHasher>::HashOut,
+    <<V as Leafable>::Hasher as TreeHasher>::HashOut,
+);
+
 #[derive(Clone, Debug)]
 pub struct MerkleTree<V: Leafable> {
-    height: usize,
-    node_hashes: HashMap<Vec<bool>, <V::LeafableHasher as LeafableHasher>::HashOut>,
-    zero_hashes: Vec<<V::LeafableHasher as LeafableHasher>::HashOut>,
+    pub(crate) height: usize,
+    pub(crate) node_hashes: HashMap<NodeKey, <V::Hasher as TreeHasher>::HashOut>,
+    pub(crate) zero_hashes: Vec<<V::Hasher as TreeHasher>::HashOut>,
+    // node_hashes is complete up to this depth. Deeper entries may have been
+    // evicted by `compact` and have to be recovered from the node store.
+    pub(crate) cache_depth: usize,
+    // number of non-empty leaves and the index of the last one
+    pub(crate) num_leaves: u128,
+    pub(crate) last_leaf: Option<u128>,
+    // leaf hash -> indices, kept only once `enable_reverse_index` is called
+    pub(crate) reverse_index: Option<ReverseIndex<<V::Hasher as TreeHasher>::HashOut>>,
+    pub(crate) subscribers: Subscribers<<V::Hasher as TreeHasher>::HashOut>,
+    pub(crate) metrics: MetricsHook,
+}
+
+// Two trees are equal if they have the same shape and hold the same non-zero
+// node hashes in memory. Cached zero hashes are ignored, but a compacted tree
+// is never equal to an uncompacted one; compare `get_root` for that.
+impl<V: Leafable> PartialEq for MerkleTree<V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.height == other.height
+            && self.cache_depth == other.cache_depth
+            && self.zero_hashes == other.zero_hashes
+            && self.non_zero_node_hashes().count() == other.non_zero_node_hashes().count()
+            && self
+                .non_zero_node_hashes()
+                .all(|(key, h)| other.node_hashes.get(key) == Some(h))
+    }
 }
 
+impl<V: Leafable> Eq for MerkleTree<V> {}
+
 impl<V: Leafable> MerkleTree<V> {
-    pub fn new(
-        mock_db: &mut MockDB<V>,
+    // Panics if `height` is larger than `MAX_HEIGHT`; see `try_new`.
+    pub fn new<S: NodeStore<V>>(
+        db: &mut S,
         height: usize,
-        empty_leaf_hash: <V::LeafableHasher as LeafableHasher>::HashOut,
+        empty_leaf_hash: <V::Hasher as TreeHasher>::HashOut,
     ) -> Self {
-        // zero_hashes = reverse([H(zero_leaf), H(H(zero_leaf), H(zero_leaf)), ...])
-        let mut zero_hashes = vec![];
-        let mut h = empty_leaf_hash;
-        zero_hashes.push(h.clone());
-        for _ in 0..height {
-            let new_h = <V::LeafableHasher as LeafableHasher>::two_to_one(h, h);
-            zero_hashes.push(new_h);
-            mock_db.insert(
-                new_h,
-                Node {
-                    left: h.clone(),
-                    right: h.clone(),
-                },
-            );
-            h = new_h;
+        Self::try_new(db, height, empty_leaf_hash).unwrap()
+    }
+
+    pub fn try_new<S: NodeStore<V>>(
+        db: &mut S,
+        height: usize,
+        empty_leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Result<Self, DbTreeError> {
+        check_height(height, MAX_HEIGHT)?;
+        Self::try_with_zero_hashes(db, height, &ZeroHashes::new(empty_leaf_hash, height))
+    }
+
+    // Same as `new`, but reuses a precomputed (possibly shared) zero hash
+    // table. Panics if `height` is larger than `MAX_HEIGHT` or the table.
+    pub fn with_zero_hashes<S: NodeStore<V>>(
+        db: &mut S,
+        height: usize,
+        zero_hashes: &ZeroHashes<V::Hasher>,
+    ) -> Self {
+        Self::try_with_zero_hashes(db, height, zero_hashes).unwrap()
+    }
+
+    pub fn try_with_zero_hashes<S: NodeStore<V>>(
+        db: &mut S,
+        height: usize,
+        zero_hashes: &ZeroHashes<V::Hasher>,
+    ) -> Result<Self, DbTreeError> {
+        check_height(height, MAX_HEIGHT)?;
+        check_height(height, zero_hashes.max_height())?;
+        let zero_hashes = zero_hashes.by_depth(height);
+        // the zero nodes are shared by every tree with the same empty leaf, so
+        // skip them if they are already in the store
+        if height > 0 && !db.contains(zero_hashes[0].clone()) {
+            for depth in 0..height {
+                let child = zero_hashes[depth + 1].clone();
+                db.insert(
+                    zero_hashes[depth].clone(),
+                    Node {
+                        left: child.clone(),
+                        right: child,
+                    },
+                );
+            }
         }
-        zero_hashes.reverse();
 
-        let node_hashes: HashMap<Vec<bool>, <V::LeafableHasher as LeafableHasher>::HashOut> =
-            HashMap::new();
+        let node_hashes: HashMap<NodeKey, <V::Hasher as TreeHasher>::HashOut> = HashMap::new();
 
-        Self {
+        Ok(Self {
             height,
             node_hashes,
             zero_hashes,
+            cache_depth: height,
+            num_leaves: 0,
+            last_leaf: None,
+            reverse_index: None,
+            subscribers: Subscribers::default(),
+            metrics: MetricsHook::default(),
+        })
+    }
+
+    // Builds a tree whose first `leaf_hashes.len()` leaves are `leaf_hashes`
+    // (index i holds leaf_hashes[i]) level by level, hashing each node once.
+    pub fn from_leaf_hashes<S: NodeStore<V>>(
+        db: &mut S,
+        height: usize,
+        empty_leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        leaf_hashes: &[<V::Hasher as TreeHasher>::HashOut],
+    ) -> Self
+    where
+        V::Hasher: BatchHasher,
+    {
+        let mut tree = Self::new(db, height, empty_leaf_hash);
+        assert!(height == MAX_HEIGHT || leaf_hashes.len() as u128 <= 1u128 << height);
+        for (i, h) in leaf_hashes.iter().enumerate() {
+            tree.node_hashes
+                .insert(NodeKey::new(height, i as u128), h.clone());
+            if *h != tree.zero_hashes[height] {
+                tree.num_leaves += 1;
+                tree.last_leaf = Some(i as u128);
+            }
         }
+
+        let mut level = leaf_hashes.to_vec();
+        let mut batch = vec![];
+        for depth in (0..height).rev() {
+            let zero = &tree.zero_hashes[depth + 1];
+            let pairs: Vec<_> = level
+                .chunks(2)
+                .map(|pair| (pair[0].clone(), pair.get(1).unwrap_or(zero).clone()))
+                .collect();
+            level = tree.hash_children_many(depth, &pairs);
+            for (i, (h, (left, right))) in level.iter().zip(pairs).enumerate() {
+                tree.node_hashes
+                    .insert(NodeKey::new(depth, i as u128), h.clone());
+                batch.push((h.clone(), Node { left, right }));
+            }
+        }
+        db.insert_batch(batch);
+        tree
     }
 
     pub fn height(&self) -> usize {
@@ -58,167 +176,440 @@ impl<V: Leafable> MerkleTree<V> {
 
     pub fn get_node_hash(
         &self,
-        path: &Vec<bool>,
-    ) -> <V::LeafableHasher as LeafableHasher>::HashOut {
-        assert!(path.len() <= self.height);
-        match self.node_hashes.get(path) {
+        key: NodeKey,
+    ) -> Result<<V::Hasher as TreeHasher>::HashOut, DbTreeError> {
+        self.check_node_key(key)?;
+        if key.depth() > self.cache_depth && !self.node_hashes.contains_key(&key) {
+            return Err(DbTreeError::EvictedNode { key });
+        }
+        Ok(self.get_node_hash_unchecked(key))
+    }
+
+    pub fn get_node_hash_unchecked(&self, key: NodeKey) -> <V::Hasher as TreeHasher>::HashOut {
+        assert!(key.depth() <= self.height);
+        match self.node_hashes.get(&key) {
             Some(h) => h.clone(),
-            None => self.zero_hashes[path.len()].clone(),
+            None => {
+                assert!(
+                    key.depth() <= self.cache_depth,
+                    "node hash was evicted by compact, use get_node_hash_with_store"
+                );
+                self.zero_hashes[key.depth()].clone()
+            }
         }
     }
 
-    pub fn get_root(&self) -> <V::LeafableHasher as LeafableHasher>::HashOut {
-        self.get_node_hash(&vec![])
+    // Same as `get_node_hash`, but recovers hashes evicted by `compact` by
+    // walking down from the deepest cached ancestor through the store. Fails
+    // if a node on the way is missing from the store.
+    pub fn get_node_hash_with_store<S: NodeStore<V>>(
+        &self,
+        db: &S,
+        key: NodeKey,
+    ) -> Result<<V::Hasher as TreeHasher>::HashOut, DbTreeError> {
+        self.check_node_key(key)?;
+        if let Some(h) = self.node_hashes.get(&key) {
+            self.metrics.cache_hit();
+            return Ok(h.clone());
+        }
+        if key.depth() <= self.cache_depth {
+            self.metrics.cache_hit();
+            return Ok(self.zero_hashes[key.depth()].clone());
+        }
+        self.metrics.cache_miss();
+        let mut ancestor = key.parent();
+        while ancestor.depth() > self.cache_depth && !self.node_hashes.contains_key(&ancestor) {
+            ancestor = ancestor.parent();
+        }
+        let mut hash = self.get_node_hash_unchecked(ancestor);
+        for depth in ancestor.depth()..key.depth() {
+            if hash == self.zero_hashes[depth] {
+                return Ok(self.zero_hashes[key.depth()].clone());
+            }
+            let is_right = (key.index >> (key.depth() - depth - 1)) & 1 == 1;
+            let node_key = || {
+                let index = key.index.checked_shr((key.depth() - depth) as u32);
+                NodeKey::new(depth, index.unwrap_or(0))
+            };
+            hash = db
+                .try_with_node(hash, |node| node.child(is_right))
+                .map_err(|_| DbTreeError::CorruptNode { key: node_key() })?
+                .ok_or_else(|| DbTreeError::MissingNode { key: node_key() })?;
+        }
+        Ok(hash)
     }
 
-    fn get_sibling_hash(&self, path: &Vec<bool>) -> <V::LeafableHasher as LeafableHasher>::HashOut {
-        assert!(!path.is_empty());
-        let mut path = path.clone();
-        let last = path.len() - 1;
-        path[last] = !path[last];
-        self.get_node_hash(&path)
+    pub fn get_node_hash_with_store_unchecked<S: NodeStore<V>>(
+        &self,
+        db: &S,
+        key: NodeKey,
+    ) -> <V::Hasher as TreeHasher>::HashOut {
+        self.get_node_hash_with_store(db, key)
+            .unwrap_or_else(|e| panic!("{}", e))
     }
 
-    // index_bits is little endian
-    pub fn update_leaf(
-        &mut self,
-        mock_db: &mut MockDB<V>,
-        index_bits: Vec<bool>,
-        leaf_hash: <V::LeafableHasher as LeafableHasher>::HashOut,
-    ) {
-        assert_eq!(index_bits.len(), self.height);
-        let mut path = index_bits;
-        path.reverse(); // path is big endian
+    // Reads every hash that an update of the leaves at the sorted keys
+    // `keys` reads from the store, so that the update fails before it
+    // changes anything if a node is missing. Only compacted trees read the
+    // store.
+    pub(crate) fn check_update_paths<S: NodeStore<V>>(
+        &self,
+        db: &S,
+        mut keys: Vec<NodeKey>,
+    ) -> Result<(), DbTreeError> {
+        if !self.is_compacted() {
+            return Ok(());
+        }
+        for &key in &keys {
+            self.get_node_hash_with_store(db, key)?;
+        }
+        while keys.first().is_some_and(|key| !key.is_root()) {
+            for key in &keys {
+                if keys.binary_search(&key.sibling()).is_err() {
+                    self.get_node_hash_with_store(db, key.sibling())?;
+                }
+            }
+            keys = parent_keys(keys);
+        }
+        Ok(())
+    }
 
-        let mut h = leaf_hash;
-        self.node_hashes.insert(path.clone(), h.clone()); // leaf node
-
-        while !path.is_empty() {
-            let sibling = self.get_sibling_hash(&path);
-            let b = path.pop().unwrap();
-            let new_h = if b {
-                <V::LeafableHasher as LeafableHasher>::two_to_one(sibling, h)
-            } else {
-                <V::LeafableHasher as LeafableHasher>::two_to_one(h, sibling)
-            };
-            self.node_hashes.insert(path.clone(), new_h.clone());
-            let node = Node {
-                left: if b { sibling } else { h.clone() },
-                right: if b { h.clone() } else { sibling },
-            };
-            mock_db.insert(new_h.clone(), node);
-            h = new_h;
+    pub(crate) fn check_leaf_index(&self, index: LeafIndex) -> Result<(), DbTreeError> {
+        if index.height() != self.height {
+            return Err(DbTreeError::InvalidIndexLength {
+                expected: self.height,
+                actual: index.height(),
+            });
         }
+        Ok(())
     }
 
-    pub fn prove(&self, index_bits: Vec<bool>) -> MerkleProof<V> {
-        assert_eq!(index_bits.len(), self.height);
-        let mut path = index_bits;
-        path.reverse(); // path is big endian
+    // Checks the indices of a bulk update and returns their sorted,
+    // deduplicated keys.
+    pub(crate) fn check_leaf_indices(
+        &self,
+        leaves: &[(LeafIndex, <V::Hasher as TreeHasher>::HashOut)],
+    ) -> Result<Vec<NodeKey>, DbTreeError> {
+        let mut keys = BTreeSet::new();
+        for (index, _) in leaves {
+            self.check_leaf_index(*index)?;
+            keys.insert(index.to_node_key());
+        }
+        Ok(keys.into_iter().collect())
+    }
 
-        let mut siblings = vec![];
-        while !path.is_empty() {
-            siblings.push(self.get_sibling_hash(&path));
-            path.pop();
+    pub(crate) fn check_node_key(&self, key: NodeKey) -> Result<(), DbTreeError> {
+        if key.depth() > self.height || !key.is_valid() {
+            return Err(DbTreeError::InvalidNodeKey {
+                key,
+                height: self.height,
+            });
         }
-        MerkleProof { siblings }
+        Ok(())
     }
 
-    pub fn prove_with_given_root(
+    fn non_zero_node_hashes(
         &self,
-        mock_db: &MockDB<V>,
-        root: <V::LeafableHasher as LeafableHasher>::HashOut,
-        index_bits: Vec<bool>,
-    ) -> MerkleProof<V> {
-        assert_eq!(index_bits.len(), self.height);
-        let mut path = index_bits;
-        let mut siblings = vec![];
-        let mut hash = root;
-        while !path.is_empty() {
-            let node = mock_db.get(hash).expect("cannot find node");
-            let (child, sibling) = if path.pop().unwrap() {
-                (node.right, node.left)
-            } else {
-                (node.left, node.right)
-            };
-            siblings.push(sibling);
-            hash = child;
+    ) -> impl Iterator<Item = (&NodeKey, &<V::Hasher as TreeHasher>::HashOut)> {
+        self.node_hashes
+            .iter()
+            .filter(|(key, h)| **h != self.zero_hashes[key.depth()])
+    }
+
+    // Whether both trees have the same height and root, regardless of what
+    // they cache in memory.
+    pub fn has_same_root(&self, other: &Self) -> bool {
+        self.height == other.height && self.get_root() == other.get_root()
+    }
+
+    pub fn is_compacted(&self) -> bool {
+        self.cache_depth < self.height
+    }
+
+    pub fn get_root(&self) -> <V::Hasher as TreeHasher>::HashOut {
+        self.get_node_hash_unchecked(NodeKey::root())
+    }
+
+    fn get_sibling_hash(&self, key: NodeKey) -> <V::Hasher as TreeHasher>::HashOut {
+        self.get_node_hash_unchecked(key.sibling())
+    }
+
+    pub fn update_leaf<S: NodeStore<V>>(
+        &mut self,
+        db: &mut S,
+        index: impl Into<LeafIndex>,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Result<(), DbTreeError> {
+        let index = index.into();
+        self.check_leaf_index(index)?;
+        self.check_update_paths(&*db, vec![index.to_node_key()])?;
+        self.update_leaf_unchecked(db, index, leaf_hash);
+        Ok(())
+    }
+
+    pub fn update_leaf_unchecked<S: NodeStore<V>>(
+        &mut self,
+        db: &mut S,
+        index: impl Into<LeafIndex>,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) {
+        let _timer = self.metrics.update_timer();
+        let index = index.into();
+        enter_span!(DEBUG, "update_leaf", height = self.height, index = %index.index());
+        assert_eq!(index.height(), self.height);
+        let mut key = index.to_node_key();
+
+        let rescan = self.set_leaf_hash(db, key, leaf_hash.clone());
+        let mut h = leaf_hash;
+
+        while !key.is_root() {
+            let sibling = self.get_node_hash_with_store_unchecked(&*db, key.sibling());
+            let b = key.is_right();
+            key = key.parent();
+            let (left, right) = if b { (sibling, h) } else { (h, sibling) };
+            let new_h = self.hash_children(key.depth(), left.clone(), right.clone());
+            self.node_hashes.insert(key, new_h.clone());
+            db.insert(new_h.clone(), Node { left, right });
+            h = new_h;
         }
-        siblings.reverse();
-        MerkleProof { siblings }
+        self.finish_leaf_updates(&*db, rescan);
     }
-}
 
-#[derive(Clone, Debug)]
-pub struct MerkleProof<V: Leafable> {
-    pub siblings: Vec<<V::LeafableHasher as LeafableHasher>::HashOut>,
-}
+    // Resets the leaf to the empty leaf and drops the node hashes on its path
+    // that became zero hashes, so deleted subtrees take no memory.
+    pub fn delete_leaf<S: NodeStore<V>>(
+        &mut self,
+        db: &mut S,
+        index: impl Into<LeafIndex>,
+    ) -> Result<(), DbTreeError> {
+        let index = index.into();
+        self.update_leaf(db, index, self.zero_hashes[self.height].clone())?;
+        let mut key = index.to_node_key();
+        while self.node_hashes.get(&key) == Some(&self.zero_hashes[key.depth()]) {
+            self.node_hashes.remove(&key);
+            if key.is_root() {
+                break;
+            }
+            key = key.parent();
+        }
+        Ok(())
+    }
 
-impl<V: Leafable> Serialize for MerkleProof<V>
-where
-    <V::LeafableHasher as LeafableHasher>::HashOut: Serialize,
-{
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    // Updates several leaves at once. Every ancestor is recomputed only once and
+    // all new nodes are written to the store in a single batch. If an index
+    // appears more than once, the last leaf hash wins.
+    pub fn update_leaves<S: NodeStore<V>>(
+        &mut self,
+        db: &mut S,
+        leaves: &[(LeafIndex, <V::Hasher as TreeHasher>::HashOut)],
+    ) -> Result<(), DbTreeError>
     where
-        S: Serializer,
+        V::Hasher: BatchHasher,
     {
-        self.siblings.serialize(serializer)
+        let keys = self.check_leaf_indices(leaves)?;
+        self.check_update_paths(&*db, keys)?;
+        self.update_leaves_unchecked(db, leaves);
+        Ok(())
     }
-}
 
-impl<'de, V: Leafable> Deserialize<'de> for MerkleProof<V>
-where
-    <V::LeafableHasher as LeafableHasher>::HashOut: Deserialize<'de>,
-{
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
+    pub fn update_leaves_unchecked<S: NodeStore<V>>(
+        &mut self,
+        db: &mut S,
+        leaves: &[(LeafIndex, <V::Hasher as TreeHasher>::HashOut)],
+    ) where
+        V::Hasher: BatchHasher,
     {
-        let siblings =
-            Vec::<<V::LeafableHasher as LeafableHasher>::HashOut>::deserialize(deserializer)?;
-        Ok(MerkleProof { siblings })
+        let _timer = self.metrics.update_timer();
+        enter_span!(
+            DEBUG,
+            "update_leaves",
+            height = self.height,
+            batch_size = leaves.len()
+        );
+        let (mut dirty, rescan) = self.insert_leaf_hashes(db, leaves);
+        let mut batch = vec![];
+        for depth in (0..self.height).rev() {
+            dirty = parent_keys(dirty);
+            let pairs: Vec<_> = dirty
+                .iter()
+                .map(|&parent| self.child_hashes(&*db, parent))
+                .collect();
+            let hashes = self.hash_children_many(depth, &pairs);
+            for ((&parent, (left, right)), h) in dirty.iter().zip(pairs).zip(hashes) {
+                self.node_hashes.insert(parent, h.clone());
+                batch.push((h, Node { left, right }));
+            }
+        }
+        db.insert_batch(batch);
+        self.finish_leaf_updates(&*db, rescan);
     }
-}
 
-impl<V: Leafable> MerkleProof<V> {
-    pub fn dummy(height: usize) -> Self {
-        Self {
-            siblings: vec![<V::LeafableHasher as LeafableHasher>::HashOut::default(); height],
+    // Writes the leaf hashes of a bulk update and returns their sorted,
+    // deduplicated keys, and whether the last leaf has to be searched again
+    // once the update is done.
+    pub(crate) fn insert_leaf_hashes<S: NodeStore<V>>(
+        &mut self,
+        db: &mut S,
+        leaves: &[(LeafIndex, <V::Hasher as TreeHasher>::HashOut)],
+    ) -> (Vec<NodeKey>, bool) {
+        let mut keys = BTreeSet::new();
+        let mut rescan = false;
+        for (index, leaf_hash) in leaves {
+            assert_eq!(index.height(), self.height);
+            let key = index.to_node_key();
+            rescan |= self.set_leaf_hash(db, key, leaf_hash.clone());
+            keys.insert(key);
         }
+        (keys.into_iter().collect(), rescan)
     }
 
-    pub fn height(&self) -> usize {
-        self.siblings.len()
+    // Current hashes of the left and right children of `parent`.
+    pub(crate) fn child_hashes<S: NodeStore<V>>(&self, db: &S, parent: NodeKey) -> ChildHashes<V> {
+        (
+            self.get_node_hash_with_store_unchecked(db, parent.child(false)),
+            self.get_node_hash_with_store_unchecked(db, parent.child(true)),
+        )
+    }
+
+    // Hash of a node at `depth` with the given children. Two empty children
+    // give the zero hash of `depth`, which is their hash, without hashing.
+    pub(crate) fn hash_children(
+        &self,
+        depth: usize,
+        left: <V::Hasher as TreeHasher>::HashOut,
+        right: <V::Hasher as TreeHasher>::HashOut,
+    ) -> <V::Hasher as TreeHasher>::HashOut {
+        let zero = &self.zero_hashes[depth + 1];
+        if left == *zero && right == *zero {
+            self.zero_hashes[depth].clone()
+        } else {
+            <V::Hasher as TreeHasher>::two_to_one(left, right)
+        }
     }
 
-    pub fn get_root(
+    // `hash_children` for nodes at `depth`, with a single `two_to_one_many`.
+    pub(crate) fn hash_children_many(
         &self,
-        leaf_data: &V,
-        index_bits: Vec<bool>,
-    ) -> <V::LeafableHasher as LeafableHasher>::HashOut {
-        let mut state = leaf_data.hash();
-        for (&bit, sibling) in index_bits.iter().zip(self.siblings.iter()) {
-            state = if bit {
-                <V::LeafableHasher as LeafableHasher>::two_to_one(*sibling, state)
-            } else {
-                <V::LeafableHasher as LeafableHasher>::two_to_one(state, *sibling)
+        depth: usize,
+        pairs: &[ChildHashes<V>],
+    ) -> Vec<<V::Hasher as TreeHasher>::HashOut>
+    where
+        V::Hasher: BatchHasher,
+    {
+        let zero = &self.zero_hashes[depth + 1];
+        let mut hashes = <V::Hasher as BatchHasher>::two_to_one_many(pairs);
+        for (h, (left, right)) in hashes.iter_mut().zip(pairs) {
+            if left == zero && right == zero {
+                *h = self.zero_hashes[depth].clone();
             }
         }
-        state
+        hashes
+    }
+
+    // Fails on a compacted tree if a sibling was evicted; use
+    // `prove_with_given_root` with the current root instead.
+    pub fn prove(&self, index: impl Into<LeafIndex>) -> Result<MerkleProof<V>, DbTreeError> {
+        let index = index.into();
+        self.check_leaf_index(index)?;
+        let mut key = index.to_node_key();
+        while !key.is_root() {
+            self.get_node_hash(key.sibling())?;
+            key = key.parent();
+        }
+        Ok(self.prove_unchecked(index))
+    }
+
+    pub fn prove_unchecked(&self, index: impl Into<LeafIndex>) -> MerkleProof<V> {
+        let _timer = self.metrics.proof_timer();
+        let index = index.into();
+        enter_span!(DEBUG, "prove", height = self.height, index = %index.index());
+        assert_eq!(index.height(), self.height);
+        let mut key = index.to_node_key();
+
+        let mut siblings = Vec::with_capacity(self.height);
+        while !key.is_root() {
+            siblings.push(self.get_sibling_hash(key));
+            key = key.parent();
+        }
+        MerkleProof { siblings }
+    }
+
+    // Same as `update_leaf`, but takes the leaf index as an integer.
+    pub fn update_leaf_at<S: NodeStore<V>>(
+        &mut self,
+        db: &mut S,
+        index: u64,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Result<(), DbTreeError> {
+        let index = LeafIndex::new(index as u128, self.height)?;
+        self.update_leaf(db, index, leaf_hash)
+    }
+
+    // Same as `prove`, but takes the leaf index as an integer.
+    pub fn prove_at(&self, index: u64) -> Result<MerkleProof<V>, DbTreeError> {
+        self.prove(LeafIndex::new(index as u128, self.height)?)
     }
 
-    pub fn verify(
+    pub fn prove_with_given_root<S: NodeStore<V>>(
         &self,
-        leaf_data: &V,
-        index_bits: Vec<bool>, // little endian
-        merkle_root: <V::LeafableHasher as LeafableHasher>::HashOut,
-    ) -> anyhow::Result<()> {
-        anyhow::ensure!(
-            self.get_root(leaf_data, index_bits) == merkle_root,
-            "Merkle proof verification failed"
+        db: &S,
+        root: <V::Hasher as TreeHasher>::HashOut,
+        index: impl Into<LeafIndex>,
+    ) -> Result<MerkleProof<V>, ProofError> {
+        let _timer = self.metrics.proof_timer();
+        let index = index.into();
+        enter_span!(
+            DEBUG,
+            "prove_with_given_root",
+            height = self.height,
+            index = %index.index()
         );
-        Ok(())
+        if index.height() != self.height {
+            return Err(ProofError::TruncatedPath {
+                expected: self.height,
+                actual: index.height(),
+            });
+        }
+        let mut path = index.to_le_bits();
+        let mut siblings = Vec::with_capacity(self.height);
+        let mut hash = root;
+        let mut depth = 0;
+        while let Some(bit) = path.pop() {
+            if hash == self.zero_hashes[depth] {
+                // the rest of the path is inside an empty subtree, whose nodes
+                // may not be in the store
+                siblings.extend_from_slice(&self.zero_hashes[depth + 1..]);
+                break;
+            }
+            let (child, sibling) = db
+                .try_with_node(hash, |node| (node.child(bit), node.child(!bit)))
+                .map_err(|_| ProofError::CorruptNode { depth })?
+                .ok_or(if depth == 0 {
+                    ProofError::UnknownRoot
+                } else {
+                    ProofError::MissingNode { depth }
+                })?;
+            siblings.push(sibling);
+            hash = child;
+            depth += 1;
+        }
+        siblings.reverse();
+        Ok(MerkleProof { siblings })
+    }
+}
+
+pub(crate) fn check_height(height: usize, max: usize) -> Result<(), DbTreeError> {
+    if height > max {
+        return Err(DbTreeError::HeightTooLarge { height, max });
     }
+    Ok(())
+}
+
+// Maps sorted keys to their sorted, deduplicated parent keys.
+pub(crate) fn parent_keys(keys: Vec<NodeKey>) -> Vec<NodeKey> {
+    let mut parents: Vec<NodeKey> = keys.into_iter().map(|key| key.parent()).collect();
+    parents.dedup();
+    parents
 }
 
 pub fn usize_le_bits(num: usize, length: usize) -> Vec<bool> {
@@ -231,13 +622,20 @@ pub fn usize_le_bits(num: usize, length: usize) -> Vec<bool> {
     result
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "zkp"))]
 mod test {
-    use intmax2_zkp::utils::{leafable::Leafable, poseidon_hash_out::PoseidonHashOut};
+    use intmax2_zkp::utils::poseidon_hash_out::PoseidonHashOut;
 
-    use crate::{merkle_tree::usize_le_bits, mock_db::MockDB};
+    use crate::{
+        error::{DbTreeError, ProofError, VerifyError},
+        leaf_index::LeafIndex,
+        mock_db::MockDB,
+        node_key::NodeKey,
+        traits::Leafable,
+        zero_hashes::ZeroHashes,
+    };
 
-    use super::MerkleTree;
+    use super::{MerkleProof, MerkleTree};
 
     type Leaf = u32;
 
@@ -251,20 +649,500 @@ mod test {
 
         for i in 0..10 {
             let leaf = i as u32;
-            let index_bits = super::usize_le_bits(i, height);
-            merkle_tree.update_leaf(&mut mock_db, index_bits, leaf.hash());
+            let leaf_index = LeafIndex::new(i as u128, height).unwrap();
+            merkle_tree
+                .update_leaf(&mut mock_db, leaf_index, leaf.hash())
+                .unwrap();
         }
         let root1 = merkle_tree.get_root();
         for i in 10..20 {
             let leaf_hash = PoseidonHashOut::hash_inputs_u32(&[i as u32]);
-            let index_bits = usize_le_bits(i, height);
-            merkle_tree.update_leaf(&mut mock_db, index_bits, leaf_hash);
+            let leaf_index = LeafIndex::new(i as u128, height).unwrap();
+            merkle_tree
+                .update_leaf(&mut mock_db, leaf_index, leaf_hash)
+                .unwrap();
         }
         let index = 6;
         let leaf = index as u32;
-        let index_bits = super::usize_le_bits(index, height);
-        let proof = merkle_tree.prove_with_given_root(&mock_db, root1, index_bits.clone());
-        let root1_expected = proof.get_root(&leaf, index_bits);
+        let leaf_index = LeafIndex::new(index as u128, height).unwrap();
+        let proof = merkle_tree
+            .prove_with_given_root(&mock_db, root1, leaf_index)
+            .unwrap();
+        let root1_expected = proof.get_root(&leaf, leaf_index);
         assert_eq!(root1, root1_expected);
     }
+
+    #[test]
+    fn test_prove_with_given_root_empty_subtree() {
+        let height = 32;
+
+        let mut mock_db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = Leaf::empty_leaf().hash();
+        let mut merkle_tree = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+        for i in 1..4 {
+            let leaf = i as u32;
+            merkle_tree
+                .update_leaf(
+                    &mut mock_db,
+                    LeafIndex::new(i as u128, height).unwrap(),
+                    leaf.hash(),
+                )
+                .unwrap();
+        }
+        let root = merkle_tree.get_root();
+        merkle_tree
+            .update_leaf(
+                &mut mock_db,
+                LeafIndex::new((1 << 20) as u128, height).unwrap(),
+                5u32.hash(),
+            )
+            .unwrap();
+
+        // zero nodes are never needed to prove against a historical root
+        for zero_hash in merkle_tree.zero_hashes.clone() {
+            mock_db.remove(zero_hash);
+        }
+        let index = 1 << 20;
+        let leaf_index = LeafIndex::new(index as u128, height).unwrap();
+        let proof = merkle_tree
+            .prove_with_given_root(&mock_db, root, leaf_index)
+            .unwrap();
+        assert_eq!(proof.get_root(&Leaf::empty_leaf(), leaf_index), root);
+
+        let empty_root = merkle_tree.zero_hashes[0];
+        let proof = merkle_tree
+            .prove_with_given_root(&mock_db, empty_root, leaf_index)
+            .unwrap();
+        assert_eq!(proof.get_root(&Leaf::empty_leaf(), leaf_index), empty_root);
+    }
+
+    #[test]
+    fn test_update_leaves() {
+        let height = 32;
+
+        let mut mock_db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = PoseidonHashOut::hash_inputs_u32(&[]);
+        let mut sequential = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+        let mut batched = sequential.clone();
+
+        let mut leaves = vec![];
+        for i in [0, 1, 5, 1000, 3, 1] {
+            let leaf = i as u32 + 7;
+            let index = LeafIndex::new(i, height).unwrap();
+            sequential
+                .update_leaf(&mut mock_db, index, leaf.hash())
+                .unwrap();
+            leaves.push((index, leaf.hash()));
+        }
+        batched.update_leaves(&mut mock_db, &leaves).unwrap();
+        assert_eq!(batched, sequential);
+
+        let root = batched.get_root();
+        let leaf_index = LeafIndex::new(1000, height).unwrap();
+        let proof = batched
+            .prove_with_given_root(&mock_db, root, leaf_index)
+            .unwrap();
+        assert_eq!(proof.get_root(&1007, leaf_index), root);
+    }
+
+    #[test]
+    fn test_from_leaf_hashes() {
+        let height = 10;
+        let empty_leaf_hash = PoseidonHashOut::hash_inputs_u32(&[]);
+
+        for n in [0, 1, 2, 7, 1 << height] {
+            let leaf_hashes: Vec<_> = (0..n).map(|i| (i as u32).hash()).collect();
+
+            let mut mock_db = MockDB::<Leaf>::new();
+            let mut expected = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+            let leaves: Vec<_> = leaf_hashes
+                .iter()
+                .enumerate()
+                .map(|(i, h)| (LeafIndex::new(i as u128, height).unwrap(), *h))
+                .collect();
+            expected.update_leaves(&mut mock_db, &leaves).unwrap();
+
+            let mut mock_db = MockDB::<Leaf>::new();
+            let tree =
+                MerkleTree::from_leaf_hashes(&mut mock_db, height, empty_leaf_hash, &leaf_hashes);
+            assert_eq!(tree, expected);
+            if n > 0 {
+                let leaf_index = LeafIndex::new((n - 1) as u128, height).unwrap();
+                let proof = tree
+                    .prove_with_given_root(&mock_db, tree.get_root(), leaf_index)
+                    .unwrap();
+                assert_eq!(proof, expected.prove(leaf_index).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        let height = 8;
+
+        let mut mock_db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = PoseidonHashOut::hash_inputs_u32(&[]);
+        let mut merkle_tree = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+        let root = merkle_tree.get_root();
+
+        let error = DbTreeError::InvalidIndexLength {
+            expected: height,
+            actual: 5,
+        };
+        let index = LeafIndex::new(1, 5).unwrap();
+        assert_eq!(
+            merkle_tree.update_leaf(&mut mock_db, index, 1u32.hash()),
+            Err(error)
+        );
+        let leaves = vec![
+            (LeafIndex::new(0, height).unwrap(), 1u32.hash()),
+            (index, 1u32.hash()),
+        ];
+        assert_eq!(merkle_tree.update_leaves(&mut mock_db, &leaves), Err(error));
+        assert_eq!(merkle_tree.prove(index).unwrap_err(), error);
+        // nothing was written on failure
+        assert_eq!(merkle_tree.get_root(), root);
+
+        let key = NodeKey::new(height + 1, 0);
+        assert_eq!(
+            merkle_tree.get_node_hash(key),
+            Err(DbTreeError::InvalidNodeKey { key, height })
+        );
+
+        assert_eq!(
+            MerkleTree::<Leaf>::try_new(&mut mock_db, 129, empty_leaf_hash).unwrap_err(),
+            DbTreeError::HeightTooLarge {
+                height: 129,
+                max: 128
+            }
+        );
+        let zero_hashes = ZeroHashes::new(empty_leaf_hash, height);
+        assert_eq!(
+            MerkleTree::<Leaf>::try_with_zero_hashes(&mut mock_db, height + 1, &zero_hashes)
+                .unwrap_err(),
+            DbTreeError::HeightTooLarge {
+                height: height + 1,
+                max: height
+            }
+        );
+
+        merkle_tree
+            .update_leaf(
+                &mut mock_db,
+                LeafIndex::new(3, height).unwrap(),
+                3u32.hash(),
+            )
+            .unwrap();
+        merkle_tree.compact(&mock_db, 2).unwrap();
+        let key = NodeKey::new(height, 3);
+        assert_eq!(
+            merkle_tree.get_node_hash(key),
+            Err(DbTreeError::EvictedNode { key })
+        );
+        assert_eq!(
+            merkle_tree.get_node_hash_with_store(&mock_db, key),
+            Ok(3u32.hash())
+        );
+        assert!(merkle_tree
+            .prove(LeafIndex::new(3, height).unwrap())
+            .is_err());
+
+        // a compacted tree over a store without its nodes fails without
+        // changing anything
+        let mut empty_db = MockDB::<Leaf>::new();
+        let root = merkle_tree.get_root();
+        let error = DbTreeError::MissingNode {
+            key: NodeKey::new(2, 0),
+        };
+        assert_eq!(
+            merkle_tree.get_node_hash_with_store(&empty_db, key),
+            Err(error)
+        );
+        assert_eq!(
+            merkle_tree.update_leaf(
+                &mut empty_db,
+                LeafIndex::new(3, height).unwrap(),
+                4u32.hash()
+            ),
+            Err(error)
+        );
+        let leaves = [(LeafIndex::new(1, height).unwrap(), 1u32.hash())];
+        assert_eq!(
+            merkle_tree.update_leaves(&mut empty_db, &leaves),
+            Err(error)
+        );
+        assert_eq!(merkle_tree.get_root(), root);
+        assert_eq!(merkle_tree.len(), 1);
+        assert!(merkle_tree.compact(&empty_db, 1).is_err());
+    }
+
+    #[test]
+    fn test_prove_with_given_root_errors() {
+        let height = 8;
+
+        let mut mock_db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = PoseidonHashOut::hash_inputs_u32(&[]);
+        let mut merkle_tree = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+        merkle_tree
+            .update_leaf(
+                &mut mock_db,
+                LeafIndex::new(3, height).unwrap(),
+                3u32.hash(),
+            )
+            .unwrap();
+        let root = merkle_tree.get_root();
+
+        let leaf_index = LeafIndex::new(3, height).unwrap();
+        assert_eq!(
+            merkle_tree
+                .prove_with_given_root(&mock_db, root, LeafIndex::new(3, height - 1).unwrap())
+                .unwrap_err(),
+            ProofError::TruncatedPath {
+                expected: height,
+                actual: height - 1
+            }
+        );
+        assert_eq!(
+            merkle_tree
+                .prove_with_given_root(&mock_db, 3u32.hash(), leaf_index)
+                .unwrap_err(),
+            ProofError::UnknownRoot
+        );
+
+        let child = mock_db.get(root).unwrap().left;
+        mock_db.remove(child);
+        assert_eq!(
+            merkle_tree
+                .prove_with_given_root(&mock_db, root, leaf_index)
+                .unwrap_err(),
+            ProofError::MissingNode { depth: 1 }
+        );
+    }
+
+    #[test]
+    fn test_index_api() {
+        let height = 10;
+
+        let mut mock_db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = PoseidonHashOut::hash_inputs_u32(&[]);
+        let mut by_index = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+        let mut by_bits = by_index.clone();
+        for i in [0u64, 5, 1023] {
+            let leaf = i as u32;
+            by_index
+                .update_leaf_at(&mut mock_db, i, leaf.hash())
+                .unwrap();
+            by_bits
+                .update_leaf(
+                    &mut mock_db,
+                    LeafIndex::from_le_bits(&super::usize_le_bits(i as usize, height)).unwrap(),
+                    leaf.hash(),
+                )
+                .unwrap();
+        }
+        assert_eq!(by_index, by_bits);
+
+        let root = by_index.get_root();
+        let proof = by_index.prove_at(5).unwrap();
+        assert_eq!(
+            proof,
+            by_bits.prove(LeafIndex::new(5, height).unwrap()).unwrap()
+        );
+        proof.verify_at(&5, 5, root).unwrap();
+        proof
+            .verify_hash(5u32.hash(), LeafIndex::new(5, height).unwrap(), root)
+            .unwrap();
+        assert_eq!(
+            proof.get_root_from_hash(5u32.hash(), LeafIndex::new(5, height).unwrap()),
+            proof.get_root(&5, LeafIndex::new(5, height).unwrap())
+        );
+        assert!(proof
+            .verify_hash(6u32.hash(), LeafIndex::new(5, height).unwrap(), root)
+            .is_err());
+        assert!(proof.verify_at(&5, 6, root).is_err());
+        assert!(proof.verify_at(&5, 1 << height, root).is_err());
+
+        let error = DbTreeError::IndexOutOfRange {
+            index: 1 << height,
+            height,
+        };
+        assert_eq!(
+            by_index.update_leaf_at(&mut mock_db, 1 << height, 1u32.hash()),
+            Err(error)
+        );
+        assert_eq!(by_index.prove_at(1 << height).unwrap_err(), error);
+        assert_eq!(
+            LeafIndex::new(3, 66).unwrap().to_le_bits(),
+            super::usize_le_bits(3, 66)
+        );
+    }
+
+    #[test]
+    fn test_verify_error() {
+        let height = 6;
+
+        let mut mock_db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = PoseidonHashOut::hash_inputs_u32(&[]);
+        let mut merkle_tree = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+        merkle_tree
+            .update_leaf_at(&mut mock_db, 1, 1u32.hash())
+            .unwrap();
+        let stale_root = merkle_tree.get_root();
+        merkle_tree
+            .update_leaf_at(&mut mock_db, 2, 2u32.hash())
+            .unwrap();
+        let proof = merkle_tree.prove_at(1).unwrap();
+
+        // verifying against a stale root reports both roots
+        match proof.verify_at(&1, 1, stale_root).unwrap_err() {
+            VerifyError::RootMismatch {
+                leaf_hash,
+                index,
+                computed_root,
+                expected_root,
+            } => {
+                assert_eq!(leaf_hash, 1u32.hash());
+                assert_eq!(index, 1);
+                assert_eq!(computed_root, merkle_tree.get_root());
+                assert_eq!(expected_root, stale_root);
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+        assert_eq!(
+            proof.verify(&1, LeafIndex::new(1, height - 1).unwrap(), stale_root),
+            Err(VerifyError::InvalidIndexLength {
+                expected: height,
+                actual: height - 1
+            })
+        );
+        assert_eq!(
+            proof.verify_at(&1, 1 << height, stale_root),
+            Err(VerifyError::IndexOutOfRange {
+                index: 1 << height,
+                height
+            })
+        );
+    }
+
+    #[test]
+    fn test_degenerate_heights() {
+        let empty_leaf_hash = PoseidonHashOut::hash_inputs_u32(&[]);
+
+        // a tree of height 0 is a single leaf, which is also the root
+        let mut mock_db = MockDB::<Leaf>::new();
+        let mut merkle_tree = MerkleTree::new(&mut mock_db, 0, empty_leaf_hash);
+        assert_eq!(merkle_tree.get_root(), empty_leaf_hash);
+        assert!(mock_db.is_empty());
+        merkle_tree
+            .update_leaf_at(&mut mock_db, 0, 7u32.hash())
+            .unwrap();
+        assert_eq!(merkle_tree.get_root(), 7u32.hash());
+        let proof = merkle_tree.prove_at(0).unwrap();
+        assert_eq!(proof, MerkleProof::default());
+        proof.verify_at(&7, 0, merkle_tree.get_root()).unwrap();
+        assert_eq!(
+            merkle_tree
+                .prove_with_given_root(&mock_db, 7u32.hash(), LeafIndex::new(0, 0).unwrap())
+                .unwrap(),
+            proof
+        );
+        assert!(merkle_tree.prove_at(1).is_err());
+        let tree = MerkleTree::from_leaf_hashes(&mut mock_db, 0, empty_leaf_hash, &[7u32.hash()]);
+        assert_eq!(tree, merkle_tree);
+
+        // a tree of height 1 has a single internal node
+        let mut mock_db = MockDB::<Leaf>::new();
+        let mut merkle_tree = MerkleTree::new(&mut mock_db, 1, empty_leaf_hash);
+        let leaves = [
+            (LeafIndex::new(1, 1).unwrap(), 1u32.hash()),
+            (LeafIndex::new(0, 1).unwrap(), 0u32.hash()),
+        ];
+        merkle_tree.update_leaves(&mut mock_db, &leaves).unwrap();
+        let root = merkle_tree.get_root();
+        assert_eq!(root, PoseidonHashOut::two_to_one(0u32.hash(), 1u32.hash()));
+        for i in 0..2 {
+            let proof = merkle_tree.prove_at(i).unwrap();
+            assert_eq!(proof.height(), 1);
+            proof.verify_at(&(i as u32), i, root).unwrap();
+            assert_eq!(
+                merkle_tree
+                    .prove_with_given_root(&mock_db, root, LeafIndex::new(i as u128, 1).unwrap())
+                    .unwrap(),
+                proof
+            );
+        }
+        assert!(merkle_tree.prove_at(2).is_err());
+    }
+
+    #[test]
+    fn test_get_node_hash_with_store_full_height() {
+        let height = 128;
+        let mut mock_db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = PoseidonHashOut::hash_inputs_u32(&[]);
+        let mut merkle_tree = MerkleTree::<Leaf>::new(&mut mock_db, height, empty_leaf_hash);
+        let index = LeafIndex::new(u128::MAX, height).unwrap();
+        merkle_tree
+            .update_leaf(&mut mock_db, index, 3u32.hash())
+            .unwrap();
+        merkle_tree.compact(&mock_db, 0).unwrap();
+
+        let key = index.to_node_key();
+        assert_eq!(
+            merkle_tree.get_node_hash_with_store(&mock_db, key),
+            Ok(3u32.hash())
+        );
+        assert_eq!(
+            merkle_tree.get_node_hash_with_store(&MockDB::<Leaf>::new(), key),
+            Err(DbTreeError::MissingNode {
+                key: NodeKey::root()
+            })
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "index height does not match the proof height")]
+    fn test_get_root_length_mismatch() {
+        let mut mock_db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = PoseidonHashOut::hash_inputs_u32(&[]);
+        let merkle_tree = MerkleTree::<Leaf>::new(&mut mock_db, 4, empty_leaf_hash);
+        let proof = merkle_tree.prove_at(3).unwrap();
+        proof.get_root(&3, LeafIndex::new(3, 3).unwrap());
+    }
+
+    #[test]
+    fn test_delete_leaf() {
+        let height = 8;
+        let mut mock_db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = Leaf::empty_leaf().hash();
+        let mut merkle_tree = MerkleTree::<Leaf>::new(&mut mock_db, height, empty_leaf_hash);
+        let empty = merkle_tree.clone();
+        merkle_tree
+            .update_leaf_at(&mut mock_db, 3, 3u32.hash())
+            .unwrap();
+        let one_leaf = merkle_tree.clone();
+        merkle_tree
+            .update_leaf_at(&mut mock_db, 200, 200u32.hash())
+            .unwrap();
+
+        let index = LeafIndex::new(200, height).unwrap();
+        merkle_tree.delete_leaf(&mut mock_db, index).unwrap();
+        assert_eq!(merkle_tree.get_root(), one_leaf.get_root());
+        assert_eq!(merkle_tree.node_hashes.len(), one_leaf.node_hashes.len());
+        let proof = merkle_tree
+            .prove_with_given_root(&mock_db, merkle_tree.get_root(), index)
+            .unwrap();
+        proof
+            .verify_at(&Leaf::empty_leaf(), 200, merkle_tree.get_root())
+            .unwrap();
+
+        merkle_tree.compact(&mock_db, 2).unwrap();
+        merkle_tree
+            .delete_leaf(&mut mock_db, LeafIndex::new(3, height).unwrap())
+            .unwrap();
+        assert_eq!(merkle_tree.get_root(), empty.get_root());
+        assert!(merkle_tree.node_hashes.is_empty());
+        assert!(merkle_tree
+            .delete_leaf(&mut mock_db, LeafIndex::new(3, 4).unwrap())
+            .is_err());
+    }
 }
@@ -0,0 +1,334 @@
+use std::fmt;
+
+#[cfg(feature = "metrics")]
+use prometheus::{Histogram, HistogramOpts, HistogramTimer, IntCounter, IntGauge, Registry};
+
+#[cfg(feature = "metrics")]
+use crate::{
+    error::CorruptNode,
+    merkle_tree::MerkleTree,
+    node::Node,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+// Prometheus metrics of a tree and its store. Handles are cheap to clone and
+// all clones report to the same series, so one set can be shared by a tree,
+// its `MeteredStore` and any number of other trees. The cache hit rate is
+// hits / (hits + misses), where a miss is a node hash that `compact` evicted
+// and had to be read back from the store.
+#[cfg(feature = "metrics")]
+#[derive(Clone, Debug)]
+pub struct TreeMetrics {
+    pub store_gets: IntCounter,
+    pub store_inserts: IntCounter,
+    pub store_nodes: IntGauge,
+    pub cache_hits: IntCounter,
+    pub cache_misses: IntCounter,
+    pub proof_seconds: Histogram,
+    pub update_seconds: Histogram,
+    pub leaves: IntGauge,
+}
+
+#[cfg(feature = "metrics")]
+impl TreeMetrics {
+    // Creates the metrics with names prefixed by `db_tree_` and registers
+    // them. Fails if `registry` already holds them.
+    pub fn register(registry: &Registry) -> anyhow::Result<Self> {
+        let metrics = Self {
+            store_gets: IntCounter::new("db_tree_store_gets_total", "node store reads")?,
+            store_inserts: IntCounter::new(
+                "db_tree_store_inserts_total",
+                "nodes written to the node store",
+            )?,
+            store_nodes: IntGauge::new(
+                "db_tree_store_nodes",
+                "nodes in the node store, if it can count them",
+            )?,
+            cache_hits: IntCounter::new(
+                "db_tree_cache_hits_total",
+                "node hashes read from memory",
+            )?,
+            cache_misses: IntCounter::new(
+                "db_tree_cache_misses_total",
+                "evicted node hashes read from the node store",
+            )?,
+            proof_seconds: Histogram::with_opts(HistogramOpts::new(
+                "db_tree_proof_seconds",
+                "time to build one proof",
+            ))?,
+            update_seconds: Histogram::with_opts(HistogramOpts::new(
+                "db_tree_update_seconds",
+                "time to apply one leaf update or batch",
+            ))?,
+            leaves: IntGauge::new("db_tree_leaves", "non-empty leaves of the tree")?,
+        };
+        registry.register(Box::new(metrics.store_gets.clone()))?;
+        registry.register(Box::new(metrics.store_inserts.clone()))?;
+        registry.register(Box::new(metrics.store_nodes.clone()))?;
+        registry.register(Box::new(metrics.cache_hits.clone()))?;
+        registry.register(Box::new(metrics.cache_misses.clone()))?;
+        registry.register(Box::new(metrics.proof_seconds.clone()))?;
+        registry.register(Box::new(metrics.update_seconds.clone()))?;
+        registry.register(Box::new(metrics.leaves.clone()))?;
+        Ok(metrics)
+    }
+}
+
+// Where a `MerkleTree` reports to. Without the `metrics` feature, or until
+// `enable_metrics` is called, every hook is a no-op. Clones of a tree report
+// to the same metrics.
+#[derive(Clone, Default)]
+pub(crate) struct MetricsHook {
+    #[cfg(feature = "metrics")]
+    metrics: Option<TreeMetrics>,
+}
+
+impl fmt::Debug for MetricsHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        #[cfg(feature = "metrics")]
+        if self.metrics.is_some() {
+            return write!(f, "MetricsHook(enabled)");
+        }
+        write!(f, "MetricsHook(disabled)")
+    }
+}
+
+// Observes the time from its creation to its drop.
+pub(crate) struct Timer {
+    #[cfg(feature = "metrics")]
+    _inner: Option<HistogramTimer>,
+}
+
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+impl MetricsHook {
+    pub(crate) fn update_timer(&self) -> Timer {
+        Timer {
+            #[cfg(feature = "metrics")]
+            _inner: self
+                .metrics
+                .as_ref()
+                .map(|m| m.update_seconds.start_timer()),
+        }
+    }
+
+    pub(crate) fn proof_timer(&self) -> Timer {
+        Timer {
+            #[cfg(feature = "metrics")]
+            _inner: self.metrics.as_ref().map(|m| m.proof_seconds.start_timer()),
+        }
+    }
+
+    pub(crate) fn cache_hit(&self) {
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.cache_hits.inc();
+        }
+    }
+
+    pub(crate) fn cache_miss(&self) {
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.cache_misses.inc();
+        }
+    }
+
+    pub(crate) fn set_leaves(&self, num_leaves: u128) {
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.leaves.set(num_leaves.min(i64::MAX as u128) as i64);
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl<V: Leafable> MerkleTree<V> {
+    // Reports update and proof latencies, cache hits and the number of leaves
+    // to `metrics` from now on.
+    pub fn enable_metrics(&mut self, metrics: &TreeMetrics) {
+        metrics
+            .leaves
+            .set(self.num_leaves.min(i64::MAX as u128) as i64);
+        self.metrics = MetricsHook {
+            metrics: Some(metrics.clone()),
+        };
+    }
+}
+
+// `MeteredStore` wraps a `NodeStore` and counts its node reads and writes.
+#[cfg(feature = "metrics")]
+#[derive(Clone, Debug)]
+pub struct MeteredStore<S> {
+    inner: S,
+    metrics: TreeMetrics,
+}
+
+#[cfg(feature = "metrics")]
+impl<S> MeteredStore<S> {
+    pub fn new(inner: S, metrics: &TreeMetrics) -> Self {
+        Self {
+            inner,
+            metrics: metrics.clone(),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn update_size<V: Leafable>(&self)
+    where
+        S: NodeStore<V>,
+    {
+        if let Some(num_nodes) = self.inner.num_nodes() {
+            self.metrics.store_nodes.set(num_nodes as i64);
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl<V: Leafable, S: NodeStore<V>> NodeStore<V> for MeteredStore<S> {
+    fn insert(&mut self, key: <V::Hasher as TreeHasher>::HashOut, node: Node<V>) {
+        self.metrics.store_inserts.inc();
+        self.inner.insert(key, node);
+        self.update_size();
+    }
+
+    fn get(&self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>> {
+        self.metrics.store_gets.inc();
+        self.inner.get(key)
+    }
+
+    fn remove(&mut self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>> {
+        let node = self.inner.remove(key);
+        self.update_size();
+        node
+    }
+
+    fn contains(&self, key: <V::Hasher as TreeHasher>::HashOut) -> bool {
+        self.metrics.store_gets.inc();
+        self.inner.contains(key)
+    }
+
+    fn insert_leaf_data(&mut self, leaf_hash: <V::Hasher as TreeHasher>::HashOut, data: Vec<u8>) {
+        self.inner.insert_leaf_data(leaf_hash, data)
+    }
+
+    fn get_leaf_data(&self, leaf_hash: <V::Hasher as TreeHasher>::HashOut) -> Option<Vec<u8>> {
+        self.inner.get_leaf_data(leaf_hash)
+    }
+
+    fn remove_leaf_data(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Option<Vec<u8>> {
+        self.inner.remove_leaf_data(leaf_hash)
+    }
+
+    fn insert_leaf_metadata(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+        metadata: Vec<u8>,
+    ) {
+        self.inner.insert_leaf_metadata(leaf_hash, index, metadata)
+    }
+
+    fn get_leaf_metadata(
+        &self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+    ) -> Option<Vec<u8>> {
+        self.inner.get_leaf_metadata(leaf_hash, index)
+    }
+
+    fn remove_leaf_metadata(&mut self, leaf_hash: <V::Hasher as TreeHasher>::HashOut) {
+        self.inner.remove_leaf_metadata(leaf_hash)
+    }
+
+    fn remove_leaf_metadata_at(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+    ) {
+        self.inner.remove_leaf_metadata_at(leaf_hash, index)
+    }
+
+    fn with_node<R>(
+        &self,
+        key: <V::Hasher as TreeHasher>::HashOut,
+        f: impl FnOnce(&Node<V>) -> R,
+    ) -> Option<R> {
+        self.metrics.store_gets.inc();
+        self.inner.with_node(key, f)
+    }
+
+    fn try_with_node<R>(
+        &self,
+        key: <V::Hasher as TreeHasher>::HashOut,
+        f: impl FnOnce(&Node<V>) -> R,
+    ) -> Result<Option<R>, CorruptNode<<V::Hasher as TreeHasher>::HashOut>> {
+        self.metrics.store_gets.inc();
+        self.inner.try_with_node(key, f)
+    }
+
+    fn num_nodes(&self) -> Option<usize> {
+        self.inner.num_nodes()
+    }
+
+    fn insert_batch(&mut self, nodes: Vec<(<V::Hasher as TreeHasher>::HashOut, Node<V>)>) {
+        self.metrics.store_inserts.inc_by(nodes.len() as u64);
+        self.inner.insert_batch(nodes);
+        self.update_size();
+    }
+}
+
+#[cfg(all(test, feature = "metrics", feature = "zkp"))]
+mod test {
+    use prometheus::Registry;
+
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable,
+    };
+
+    use super::{MeteredStore, TreeMetrics};
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_metrics() {
+        let height = 8;
+        let registry = Registry::new();
+        let metrics = TreeMetrics::register(&registry).unwrap();
+        assert!(TreeMetrics::register(&registry).is_err());
+
+        let mut db = MeteredStore::new(MockDB::<Leaf>::new(), &metrics);
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        tree.enable_metrics(&metrics);
+        let index = move |i: u128| LeafIndex::new(i, height).unwrap();
+
+        let leaves: Vec<_> = (0..10).map(|i| (index(i), (i as u32 + 1).hash())).collect();
+        tree.update_leaves(&mut db, &leaves).unwrap();
+        tree.update_leaf(&mut db, index(20), 5u32.hash()).unwrap();
+        assert_eq!(metrics.update_seconds.get_sample_count(), 2);
+        assert_eq!(metrics.leaves.get(), 11);
+        assert!(metrics.store_inserts.get() >= 2 * height as u64);
+        assert_eq!(metrics.store_nodes.get() as usize, db.inner().len());
+
+        tree.prove(index(3)).unwrap();
+        assert_eq!(metrics.proof_seconds.get_sample_count(), 1);
+        let misses = metrics.cache_misses.get();
+        tree.compact(&db, 2).unwrap();
+        let gets = metrics.store_gets.get();
+        tree.prove_with_given_root(&db, tree.get_root(), index(3))
+            .unwrap();
+        assert!(metrics.store_gets.get() > gets);
+        tree.update_leaf(&mut db, index(4), 6u32.hash()).unwrap();
+        assert!(metrics.cache_misses.get() > misses);
+        assert!(metrics.cache_hits.get() > 0);
+    }
+}
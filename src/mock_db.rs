@@ -1,29 +1,246 @@
-use hashbrown::HashMap;
-use intmax2_zkp::utils::{leafable::Leafable, leafable_hasher::LeafableHasher};
+use hashbrown::{HashMap, HashSet};
 
-#[derive(Clone, Debug)]
-pub struct Node<V: Leafable> {
-    pub left: <V::LeafableHasher as LeafableHasher>::HashOut,
-    pub right: <V::LeafableHasher as LeafableHasher>::HashOut,
-}
+use crate::node_store::NodeStore;
+// re-exported so that existing `mock_db::Node` imports keep working
+pub use crate::{
+    node::Node,
+    traits::{Leafable, TreeHasher},
+};
 
 #[derive(Clone, Debug)]
 pub struct MockDB<V: Leafable> {
-    nodes: HashMap<<V::LeafableHasher as LeafableHasher>::HashOut, Node<V>>, // parents hash to node (2 child hashes)
+    pub(crate) nodes: HashMap<<V::Hasher as TreeHasher>::HashOut, Node<V>>, // parents hash to node (2 child hashes)
+    leaves: HashMap<<V::Hasher as TreeHasher>::HashOut, Vec<u8>>, // leaf hash to serialized leaf
+    metadata: HashMap<<V::Hasher as TreeHasher>::HashOut, HashMap<u128, Vec<u8>>>, // leaf hash to metadata by index
 }
 
 impl<V: Leafable> MockDB<V> {
     pub fn new() -> Self {
         MockDB {
             nodes: HashMap::new(),
+            leaves: HashMap::new(),
+            metadata: HashMap::new(),
         }
     }
 
-    pub fn insert(&mut self, key: <V::LeafableHasher as LeafableHasher>::HashOut, node: Node<V>) {
+    pub fn insert(&mut self, key: <V::Hasher as TreeHasher>::HashOut, node: Node<V>) {
         self.nodes.insert(key, node);
     }
 
-    pub fn get(&self, key: <V::LeafableHasher as LeafableHasher>::HashOut) -> Option<Node<V>> {
+    pub fn get(&self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>> {
         self.nodes.get(&key).cloned()
     }
+
+    pub fn get_ref(&self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<&Node<V>> {
+        self.nodes.get(&key)
+    }
+
+    pub fn contains(&self, key: <V::Hasher as TreeHasher>::HashOut) -> bool {
+        self.nodes.contains_key(&key)
+    }
+
+    pub fn remove(&mut self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>> {
+        self.nodes.remove(&key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // Removes all nodes that are not reachable from any of `live_roots` and
+    // returns the number of removed nodes. Roots that are not in the db (e.g.
+    // leaf hashes of a height 0 tree) are ignored. Unreachable leaf data is
+    // removed as well but not counted.
+    pub fn collect_garbage(&mut self, live_roots: &[<V::Hasher as TreeHasher>::HashOut]) -> usize {
+        let mut reachable = HashSet::new();
+        let mut stack = live_roots.to_vec();
+        while let Some(hash) = stack.pop() {
+            if !reachable.insert(hash.clone()) {
+                continue;
+            }
+            if let Some(node) = self.nodes.get(&hash) {
+                stack.push(node.left.clone());
+                stack.push(node.right.clone());
+            }
+        }
+        let before = self.nodes.len();
+        self.nodes.retain(|hash, _| reachable.contains(hash));
+        self.leaves.retain(|hash, _| reachable.contains(hash));
+        self.metadata.retain(|hash, _| reachable.contains(hash));
+        before - self.nodes.len()
+    }
+}
+
+impl<V: Leafable> NodeStore<V> for MockDB<V> {
+    fn insert(&mut self, key: <V::Hasher as TreeHasher>::HashOut, node: Node<V>) {
+        MockDB::insert(self, key, node)
+    }
+
+    fn get(&self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>> {
+        MockDB::get(self, key)
+    }
+
+    fn remove(&mut self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>> {
+        MockDB::remove(self, key)
+    }
+
+    fn insert_leaf_data(&mut self, leaf_hash: <V::Hasher as TreeHasher>::HashOut, data: Vec<u8>) {
+        self.leaves.insert(leaf_hash, data);
+    }
+
+    fn get_leaf_data(&self, leaf_hash: <V::Hasher as TreeHasher>::HashOut) -> Option<Vec<u8>> {
+        self.leaves.get(&leaf_hash).cloned()
+    }
+
+    fn remove_leaf_data(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Option<Vec<u8>> {
+        self.leaves.remove(&leaf_hash)
+    }
+
+    fn insert_leaf_metadata(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+        metadata: Vec<u8>,
+    ) {
+        self.metadata
+            .entry(leaf_hash)
+            .or_default()
+            .insert(index, metadata);
+    }
+
+    fn get_leaf_metadata(
+        &self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+    ) -> Option<Vec<u8>> {
+        self.metadata.get(&leaf_hash)?.get(&index).cloned()
+    }
+
+    fn remove_leaf_metadata(&mut self, leaf_hash: <V::Hasher as TreeHasher>::HashOut) {
+        self.metadata.remove(&leaf_hash);
+    }
+
+    fn remove_leaf_metadata_at(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+    ) {
+        if let Some(metadata) = self.metadata.get_mut(&leaf_hash) {
+            metadata.remove(&index);
+            if metadata.is_empty() {
+                self.metadata.remove(&leaf_hash);
+            }
+        }
+    }
+
+    fn contains(&self, key: <V::Hasher as TreeHasher>::HashOut) -> bool {
+        MockDB::contains(self, key)
+    }
+
+    fn with_node<R>(
+        &self,
+        key: <V::Hasher as TreeHasher>::HashOut,
+        f: impl FnOnce(&Node<V>) -> R,
+    ) -> Option<R> {
+        self.get_ref(key).map(f)
+    }
+
+    fn num_nodes(&self) -> Option<usize> {
+        Some(self.len())
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use intmax2_zkp::utils::{
+        leafable_hasher::PoseidonLeafableHasher, poseidon_hash_out::PoseidonHashOut,
+    };
+
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, node::Node,
+        node_store::NodeStore, testkit, traits::Leafable,
+    };
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_collect_garbage() {
+        let height = 16;
+
+        let mut mock_db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = PoseidonHashOut::hash_inputs_u32(&[]);
+        let mut merkle_tree = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+
+        for i in 0..10 {
+            let leaf = i as u32;
+            merkle_tree
+                .update_leaf(
+                    &mut mock_db,
+                    LeafIndex::new(i as u128, height).unwrap(),
+                    leaf.hash(),
+                )
+                .unwrap();
+        }
+        let root1 = merkle_tree.get_root();
+        for i in 0..10 {
+            let leaf = (i + 100) as u32;
+            merkle_tree
+                .update_leaf(
+                    &mut mock_db,
+                    LeafIndex::new(i as u128, height).unwrap(),
+                    leaf.hash(),
+                )
+                .unwrap();
+        }
+        let root2 = merkle_tree.get_root();
+
+        // intermediate roots of each single update are garbage
+        assert!(mock_db.collect_garbage(&[root1, root2]) > 0);
+        assert_eq!(mock_db.collect_garbage(&[root1, root2]), 0);
+        assert!(mock_db.get(root1).is_some());
+
+        let before = mock_db.len();
+        let removed = mock_db.collect_garbage(&[root2]);
+        assert!(removed > 0);
+        assert_eq!(mock_db.len(), before - removed);
+        assert!(mock_db.get(root1).is_none());
+
+        // the live root can still be proven against
+        let index = 3;
+        let leaf = (index + 100) as u32;
+        let leaf_index = LeafIndex::new(index as u128, height).unwrap();
+        let proof = merkle_tree
+            .prove_with_given_root(&mock_db, root2, leaf_index)
+            .unwrap();
+        assert_eq!(proof.get_root(&leaf, leaf_index), root2);
+    }
+
+    #[test]
+    fn test_with_node() {
+        let mut mock_db = MockDB::<Leaf>::new();
+        let left = 1u32.hash();
+        let right = 2u32.hash();
+        let key = PoseidonHashOut::two_to_one(left, right);
+        mock_db.insert(key, Node { left, right });
+
+        assert!(NodeStore::contains(&mock_db, key));
+        assert!(!NodeStore::contains(&mock_db, left));
+        assert_eq!(mock_db.get_ref(key).map(|node| node.right), Some(right));
+        assert_eq!(mock_db.with_node(key, |node| node.left), Some(left));
+        assert_eq!(mock_db.with_node(left, |node| node.left), None);
+    }
+
+    #[test]
+    fn test_store_conformance() {
+        testkit::check_store::<PoseidonLeafableHasher, _>(MockDB::new, |i| {
+            PoseidonHashOut::hash_inputs_u64(&[i])
+        });
+    }
 }
@@ -0,0 +1,62 @@
+use crate::traits::{Leafable, TreeHasher};
+
+// `Node` is the only node type of the crate: an internal node of a
+// `MerkleTree`, stored in a `NodeStore` under `two_to_one(left, right)`.
+// Leaves are never stored as nodes. A child hash at the leaf level is the
+// leaf hash itself, so whether a child is a leaf or another `Node` follows
+// from its depth, not from the node.
+#[derive(Debug)]
+pub struct Node<V: Leafable> {
+    pub left: <V::Hasher as TreeHasher>::HashOut,
+    pub right: <V::Hasher as TreeHasher>::HashOut,
+}
+
+// implemented by hand because deriving would require the same traits on `V`,
+// as are the common traits of the other types generic over a `Leafable`
+impl<V: Leafable> Clone for Node<V> {
+    fn clone(&self) -> Self {
+        Self {
+            left: self.left.clone(),
+            right: self.right.clone(),
+        }
+    }
+}
+
+impl<V: Leafable> Copy for Node<V> where <V::Hasher as TreeHasher>::HashOut: Copy {}
+
+impl<V: Leafable> PartialEq for Node<V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.left == other.left && self.right == other.right
+    }
+}
+
+impl<V: Leafable> Eq for Node<V> {}
+
+impl<V: Leafable> std::hash::Hash for Node<V> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.left.hash(state);
+        self.right.hash(state);
+    }
+}
+
+impl<V: Leafable> Node<V> {
+    pub fn new(
+        left: <V::Hasher as TreeHasher>::HashOut,
+        right: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Self {
+        Self { left, right }
+    }
+
+    // the key of this node in a `NodeStore`
+    pub fn hash(&self) -> <V::Hasher as TreeHasher>::HashOut {
+        <V::Hasher as TreeHasher>::two_to_one(self.left.clone(), self.right.clone())
+    }
+
+    pub fn child(&self, is_right: bool) -> <V::Hasher as TreeHasher>::HashOut {
+        if is_right {
+            self.right.clone()
+        } else {
+            self.left.clone()
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use alloc::vec::Vec;
+
+use serde::{Deserialize, Serialize};
+
+// Maximum height of a `MerkleTree`, bounded by the width of `NodeKey::index`.
+pub const MAX_HEIGHT: usize = 128;
+
+// Position of a node in a `MerkleTree`. `depth` is the distance from the root
+// and `index` is the big endian path from the root read as an integer, so the
+// key of a leaf is (height, leaf index). Unlike a `Vec<bool>` path, a key is
+// `Copy` and hashing it does not touch the heap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct NodeKey {
+    pub depth: u8,
+    pub index: u128,
+}
+
+impl NodeKey {
+    pub fn new(depth: usize, index: u128) -> Self {
+        assert!(depth <= MAX_HEIGHT);
+        let key = Self {
+            depth: depth as u8,
+            index,
+        };
+        assert!(key.is_valid());
+        key
+    }
+
+    // whether `index` fits in `depth` bits
+    pub fn is_valid(&self) -> bool {
+        self.depth() <= MAX_HEIGHT && (self.depth() == MAX_HEIGHT || self.index >> self.depth == 0)
+    }
+
+    pub fn root() -> Self {
+        Self { depth: 0, index: 0 }
+    }
+
+    // Key of the leaf with little endian `index_bits`.
+    pub fn from_index_bits(index_bits: &[bool]) -> Self {
+        assert!(index_bits.len() <= MAX_HEIGHT);
+        let index = index_bits
+            .iter()
+            .rev()
+            .fold(0u128, |acc, &bit| (acc << 1) | bit as u128);
+        Self {
+            depth: index_bits.len() as u8,
+            index,
+        }
+    }
+
+    // `path` is big endian
+    pub fn from_path(path: &[bool]) -> Self {
+        assert!(path.len() <= MAX_HEIGHT);
+        let index = path
+            .iter()
+            .fold(0u128, |acc, &bit| (acc << 1) | bit as u128);
+        Self {
+            depth: path.len() as u8,
+            index,
+        }
+    }
+
+    // big endian path from the root
+    pub fn to_path(&self) -> Vec<bool> {
+        (0..self.depth())
+            .rev()
+            .map(|i| (self.index >> i) & 1 == 1)
+            .collect()
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth as usize
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.depth == 0
+    }
+
+    // whether this node is the right child of its parent
+    pub fn is_right(&self) -> bool {
+        self.index & 1 == 1
+    }
+
+    pub fn parent(&self) -> Self {
+        assert!(!self.is_root());
+        Self {
+            depth: self.depth - 1,
+            index: self.index >> 1,
+        }
+    }
+
+    pub fn sibling(&self) -> Self {
+        assert!(!self.is_root());
+        Self {
+            depth: self.depth,
+            index: self.index ^ 1,
+        }
+    }
+
+    pub fn child(&self, is_right: bool) -> Self {
+        assert!(self.depth() < MAX_HEIGHT);
+        Self {
+            depth: self.depth + 1,
+            index: (self.index << 1) | is_right as u128,
+        }
+    }
+
+    // whether `self` is strictly below `ancestor`
+    pub fn is_descendant_of(&self, ancestor: NodeKey) -> bool {
+        self.depth > ancestor.depth
+            && self
+                .index
+                .checked_shr((self.depth - ancestor.depth) as u32)
+                .unwrap_or(0)
+                == ancestor.index
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::merkle_tree::usize_le_bits;
+
+    use super::NodeKey;
+
+    #[test]
+    fn test_node_key_paths() {
+        let index_bits = usize_le_bits(6, 4); // [0, 1, 1, 0]
+        let key = NodeKey::from_index_bits(&index_bits);
+        assert_eq!(key, NodeKey::new(4, 6));
+        assert_eq!(key.to_path(), vec![false, true, true, false]);
+        assert_eq!(NodeKey::from_path(&key.to_path()), key);
+        assert_eq!(key.parent(), NodeKey::new(3, 3));
+        assert_eq!(key.sibling(), NodeKey::new(4, 7));
+        assert_eq!(key.parent().child(false), key);
+        assert!(!key.is_right());
+        assert!(key.parent().parent().parent().parent().is_root());
+        assert!(key.is_descendant_of(NodeKey::new(2, 1)));
+        assert!(key.is_descendant_of(NodeKey::root()));
+        assert!(!key.is_descendant_of(key));
+        assert!(!key.is_descendant_of(NodeKey::new(2, 0)));
+    }
+}
@@ -0,0 +1,89 @@
+use crate::{
+    error::CorruptNode,
+    node::Node,
+    traits::{Leafable, TreeHasher},
+};
+
+// `NodeStore` is the storage interface used by `MerkleTree`. Nodes are
+// content addressed: the key is always the hash of the node's two children.
+pub trait NodeStore<V: Leafable> {
+    fn insert(&mut self, key: <V::Hasher as TreeHasher>::HashOut, node: Node<V>);
+
+    fn get(&self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>>;
+
+    fn remove(&mut self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>>;
+
+    // Serialized leaves, keyed by leaf hash like nodes are keyed by node hash,
+    // so that every version of a tree sharing the store can find its leaves.
+    fn insert_leaf_data(&mut self, leaf_hash: <V::Hasher as TreeHasher>::HashOut, data: Vec<u8>);
+
+    fn get_leaf_data(&self, leaf_hash: <V::Hasher as TreeHasher>::HashOut) -> Option<Vec<u8>>;
+
+    fn remove_leaf_data(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Option<Vec<u8>>;
+
+    // Opaque per-leaf metadata, keyed by leaf hash and leaf index. It is not
+    // part of any hash, so it cannot be proven, only returned next to proofs.
+    fn insert_leaf_metadata(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+        metadata: Vec<u8>,
+    );
+
+    fn get_leaf_metadata(
+        &self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+    ) -> Option<Vec<u8>>;
+
+    // Removes the metadata of every index holding `leaf_hash`.
+    fn remove_leaf_metadata(&mut self, leaf_hash: <V::Hasher as TreeHasher>::HashOut);
+
+    // Removes the metadata of `leaf_hash` at `index` only, e.g. once the leaf
+    // at `index` is overwritten.
+    fn remove_leaf_metadata_at(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+    );
+
+    fn contains(&self, key: <V::Hasher as TreeHasher>::HashOut) -> bool {
+        self.get(key).is_some()
+    }
+
+    // Calls `f` with a reference to the node instead of returning a copy.
+    // Backends that keep nodes in memory should override this.
+    fn with_node<R>(
+        &self,
+        key: <V::Hasher as TreeHasher>::HashOut,
+        f: impl FnOnce(&Node<V>) -> R,
+    ) -> Option<R> {
+        self.get(key).map(|node| f(&node))
+    }
+
+    // `with_node` for backends that can tell a corrupted record from a
+    // missing one, by a checksum or by rehashing the children. Wrappers that
+    // read from the store they wrap forward it.
+    fn try_with_node<R>(
+        &self,
+        key: <V::Hasher as TreeHasher>::HashOut,
+        f: impl FnOnce(&Node<V>) -> R,
+    ) -> Result<Option<R>, CorruptNode<<V::Hasher as TreeHasher>::HashOut>> {
+        Ok(self.with_node(key, f))
+    }
+
+    // Number of stored nodes, if the backend can tell cheaply.
+    fn num_nodes(&self) -> Option<usize> {
+        None
+    }
+
+    // Backends that support batched writes should override this.
+    fn insert_batch(&mut self, nodes: Vec<(<V::Hasher as TreeHasher>::HashOut, Node<V>)>) {
+        for (key, node) in nodes {
+            self.insert(key, node);
+        }
+    }
+}
@@ -0,0 +1,360 @@
+use hashbrown::HashMap;
+use rayon::prelude::*;
+
+use crate::{
+    batch_hasher::BatchHasher,
+    error::DbTreeError,
+    leaf_index::LeafIndex,
+    merkle_tree::{parent_keys, MerkleProof, MerkleTree},
+    node::Node,
+    node_key::NodeKey,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+// number of nodes hashed by one `two_to_one_many` call
+const PAR_CHUNK_SIZE: usize = 256;
+
+// subtrees with at most this many dirty leaves are updated by a single task
+const TASK_LEAVES: usize = 1024;
+
+// New hash of a subtree and the node hashes and store nodes that changed
+// below it, which are written back once all tasks are merged.
+struct SubtreeUpdate<V: Leafable> {
+    hash: <V::Hasher as TreeHasher>::HashOut,
+    node_hashes: Vec<(NodeKey, <V::Hasher as TreeHasher>::HashOut)>,
+    nodes: Vec<(<V::Hasher as TreeHasher>::HashOut, Node<V>)>,
+}
+
+impl<V: Leafable> MerkleTree<V>
+where
+    V::Hasher: BatchHasher,
+    <V::Hasher as TreeHasher>::HashOut: Send + Sync,
+{
+    // Same as `update_leaves`, but the nodes of each level are hashed in
+    // parallel chunks. Nodes on the same level belong to disjoint subtrees, so
+    // only the write back of each level is sequential.
+    pub fn par_update_leaves<S: NodeStore<V> + Sync>(
+        &mut self,
+        db: &mut S,
+        leaves: &[(LeafIndex, <V::Hasher as TreeHasher>::HashOut)],
+    ) -> Result<(), DbTreeError> {
+        let keys = self.check_leaf_indices(leaves)?;
+        self.check_update_paths(&*db, keys)?;
+        let _timer = self.metrics.update_timer();
+        enter_span!(
+            DEBUG,
+            "par_update_leaves",
+            height = self.height,
+            batch_size = leaves.len()
+        );
+        let (mut dirty, rescan) = self.insert_leaf_hashes(db, leaves);
+        let mut batch = vec![];
+        for depth in (0..self.height).rev() {
+            dirty = parent_keys(dirty);
+            let pairs: Vec<_> = dirty
+                .par_iter()
+                .map(|&parent| self.child_hashes(&*db, parent))
+                .collect();
+            let hashes: Vec<Vec<_>> = pairs
+                .par_chunks(PAR_CHUNK_SIZE)
+                .map(|chunk| self.hash_children_many(depth, chunk))
+                .collect();
+            let hashes = hashes.into_iter().flatten();
+            for ((&parent, (left, right)), h) in dirty.iter().zip(pairs).zip(hashes) {
+                self.node_hashes.insert(parent, h.clone());
+                batch.push((h, Node { left, right }));
+            }
+        }
+        db.insert_batch(batch);
+        self.finish_leaf_updates(&*db, rescan);
+        Ok(())
+    }
+
+    // Same as `update_leaves`, for very large batches such as applying a
+    // rollup block of a million leaves. The dirty leaves are split at every
+    // node whose two subtrees are both dirty until a subtree holds at most
+    // `TASK_LEAVES` of them; the two halves of every split run as rayon tasks,
+    // so idle threads steal whole subtrees, and each split node is hashed once
+    // both of its halves are done. Within a task the levels are hashed with
+    // one `two_to_one_many` call each.
+    pub fn par_update_subtrees<S: NodeStore<V> + Sync>(
+        &mut self,
+        db: &mut S,
+        leaves: &[(LeafIndex, <V::Hasher as TreeHasher>::HashOut)],
+    ) -> Result<(), DbTreeError> {
+        let keys = self.check_leaf_indices(leaves)?;
+        self.check_update_paths(&*db, keys)?;
+        let _timer = self.metrics.update_timer();
+        enter_span!(
+            DEBUG,
+            "par_update_subtrees",
+            height = self.height,
+            batch_size = leaves.len()
+        );
+        let (dirty, rescan) = self.insert_leaf_hashes(db, leaves);
+        if !dirty.is_empty() {
+            let update = self.update_subtree(&*db, NodeKey::root(), &dirty);
+            self.node_hashes.extend(update.node_hashes);
+            db.insert_batch(update.nodes);
+        }
+        self.finish_leaf_updates(&*db, rescan);
+        Ok(())
+    }
+
+    // `dirty` holds the sorted keys of the updated leaves below `key`.
+    fn update_subtree<S: NodeStore<V> + Sync>(
+        &self,
+        db: &S,
+        key: NodeKey,
+        dirty: &[NodeKey],
+    ) -> SubtreeUpdate<V> {
+        if dirty.is_empty() {
+            return SubtreeUpdate {
+                hash: self.get_node_hash_with_store_unchecked(db, key),
+                node_hashes: vec![],
+                nodes: vec![],
+            };
+        }
+        if dirty.len() <= TASK_LEAVES || key.depth() == self.height {
+            return self.update_subtree_sequential(db, key, dirty.to_vec());
+        }
+        let shift = self.height - key.depth() - 1;
+        let split = dirty.partition_point(|leaf| (leaf.index >> shift) & 1 == 0);
+        let (to_left, to_right) = dirty.split_at(split);
+        let (left, right) = rayon::join(
+            || self.update_subtree(db, key.child(false), to_left),
+            || self.update_subtree(db, key.child(true), to_right),
+        );
+        let hash = self.hash_children(key.depth(), left.hash.clone(), right.hash.clone());
+        let mut node_hashes = append_smaller(left.node_hashes, right.node_hashes);
+        let mut nodes = append_smaller(left.nodes, right.nodes);
+        node_hashes.push((key, hash.clone()));
+        nodes.push((
+            hash.clone(),
+            Node {
+                left: left.hash,
+                right: right.hash,
+            },
+        ));
+        SubtreeUpdate {
+            hash,
+            node_hashes,
+            nodes,
+        }
+    }
+
+    // `update_leaves_unchecked` for the subtree at `key`, with the new node
+    // hashes kept aside instead of written to the tree.
+    fn update_subtree_sequential<S: NodeStore<V>>(
+        &self,
+        db: &S,
+        key: NodeKey,
+        mut dirty: Vec<NodeKey>,
+    ) -> SubtreeUpdate<V> {
+        let mut fresh: HashMap<NodeKey, <V::Hasher as TreeHasher>::HashOut> = HashMap::new();
+        let mut nodes = vec![];
+        let hash_of = |fresh: &HashMap<_, _>, key| {
+            fresh
+                .get(&key)
+                .cloned()
+                .unwrap_or_else(|| self.get_node_hash_with_store_unchecked(db, key))
+        };
+        for depth in (key.depth()..self.height).rev() {
+            dirty = parent_keys(dirty);
+            let pairs: Vec<_> = dirty
+                .iter()
+                .map(|&parent| {
+                    (
+                        hash_of(&fresh, parent.child(false)),
+                        hash_of(&fresh, parent.child(true)),
+                    )
+                })
+                .collect();
+            let hashes = self.hash_children_many(depth, &pairs);
+            for ((&parent, (left, right)), h) in dirty.iter().zip(pairs).zip(hashes) {
+                fresh.insert(parent, h.clone());
+                nodes.push((h, Node { left, right }));
+            }
+        }
+        SubtreeUpdate {
+            hash: hash_of(&fresh, key),
+            node_hashes: fresh.into_iter().collect(),
+            nodes,
+        }
+    }
+
+    // Proofs of `indices`, in the same order, built on the rayon pool. Every
+    // node on a path to one of the indices is read once per call, level by
+    // level from the root, and shared by all proofs below it, so proving many
+    // leaves of a compacted tree does not repeat the store reads of the upper
+    // levels. Fails if an index is invalid or a node is missing from the
+    // store.
+    pub fn prove_many_parallel<S: NodeStore<V> + Sync>(
+        &self,
+        db: &S,
+        indices: &[LeafIndex],
+    ) -> Result<Vec<MerkleProof<V>>, DbTreeError> {
+        for index in indices {
+            self.check_leaf_index(*index)?;
+        }
+        let mut known = HashMap::new();
+        known.insert(NodeKey::root(), self.get_root());
+        for depth in 0..self.height {
+            let shift = (self.height - depth) as u32;
+            let mut parents: Vec<_> = indices
+                .iter()
+                .map(|index| index.index().checked_shr(shift).unwrap_or(0))
+                .collect();
+            parents.sort_unstable();
+            parents.dedup();
+            let children = parents
+                .par_iter()
+                .map(|&parent| {
+                    let hash = &known[&NodeKey::new(depth, parent)];
+                    let child = |is_right| {
+                        let key = NodeKey::new(depth + 1, parent << 1 | is_right as u128);
+                        Ok((key, self.child_hash(db, key, hash, is_right)?))
+                    };
+                    Ok([child(false)?, child(true)?])
+                })
+                .collect::<Result<Vec<_>, DbTreeError>>()?;
+            known.extend(children.into_iter().flatten());
+        }
+        let proofs = indices
+            .par_iter()
+            .map(|index| {
+                let mut key = index.to_node_key();
+                let mut siblings = Vec::with_capacity(self.height);
+                while !key.is_root() {
+                    siblings.push(known[&key.sibling()].clone());
+                    key = key.parent();
+                }
+                MerkleProof { siblings }
+            })
+            .collect();
+        Ok(proofs)
+    }
+
+    // Hash of the child at `key` of the node with `parent_hash`, read from the
+    // store only if it is not cached.
+    fn child_hash<S: NodeStore<V>>(
+        &self,
+        db: &S,
+        key: NodeKey,
+        parent_hash: &<V::Hasher as TreeHasher>::HashOut,
+        is_right: bool,
+    ) -> Result<<V::Hasher as TreeHasher>::HashOut, DbTreeError> {
+        if let Some(h) = self.node_hashes.get(&key) {
+            return Ok(h.clone());
+        }
+        let depth = key.depth();
+        if depth <= self.cache_depth || *parent_hash == self.zero_hashes[depth - 1] {
+            return Ok(self.zero_hashes[depth].clone());
+        }
+        db.try_with_node(parent_hash.clone(), |node| node.child(is_right))
+            .map_err(|_| DbTreeError::CorruptNode { key: key.parent() })?
+            .ok_or(DbTreeError::MissingNode { key: key.parent() })
+    }
+}
+
+fn append_smaller<T>(a: Vec<T>, b: Vec<T>) -> Vec<T> {
+    let (mut larger, mut smaller) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    larger.append(&mut smaller);
+    larger
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use intmax2_zkp::utils::poseidon_hash_out::PoseidonHashOut;
+
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable,
+    };
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_par_update_leaves() {
+        let height = 20;
+
+        let mut mock_db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = PoseidonHashOut::hash_inputs_u32(&[]);
+        let mut sequential = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+        let mut parallel = sequential.clone();
+
+        let leaves: Vec<_> = (0..200)
+            .map(|i| (LeafIndex::new(i * 37, height).unwrap(), (i as u32).hash()))
+            .collect();
+        sequential.update_leaves(&mut mock_db, &leaves).unwrap();
+        parallel.par_update_leaves(&mut mock_db, &leaves).unwrap();
+        assert_eq!(parallel.get_root(), sequential.get_root());
+    }
+
+    #[test]
+    fn test_par_update_subtrees() {
+        let height = 20;
+
+        let mut mock_db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = PoseidonHashOut::hash_inputs_u32(&[]);
+        let mut sequential = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+        let mut stealing = sequential.clone();
+
+        // enough leaves to split into several tasks, with duplicates
+        for round in 0..2u32 {
+            let leaves: Vec<_> = (0..5000u32)
+                .map(|i| {
+                    let index = (i as u128 * 7919 + round as u128) % (1 << height);
+                    (LeafIndex::new(index, height).unwrap(), (i + round).hash())
+                })
+                .chain([(LeafIndex::new(0, height).unwrap(), 9u32.hash())])
+                .collect();
+            sequential.update_leaves(&mut mock_db, &leaves).unwrap();
+            stealing.par_update_subtrees(&mut mock_db, &leaves).unwrap();
+            assert_eq!(stealing.get_root(), sequential.get_root());
+            assert_eq!(stealing.len(), sequential.len());
+        }
+        let index = LeafIndex::new(7919 * 3, height).unwrap();
+        assert_eq!(
+            stealing.prove(index).unwrap(),
+            sequential.prove(index).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_prove_many_parallel() {
+        let height = 12;
+
+        let mut mock_db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = PoseidonHashOut::hash_inputs_u32(&[]);
+        let mut tree = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+        let index = move |i: u128| LeafIndex::new(i, height).unwrap();
+        let leaves: Vec<_> = (0..300)
+            .map(|i| (index(i * 13 % 4096), (i as u32).hash()))
+            .collect();
+        tree.update_leaves(&mut mock_db, &leaves).unwrap();
+
+        // set and empty leaves, with a duplicate
+        let indices: Vec<_> = (0..500)
+            .map(|i| index(i * 7 % 4096))
+            .chain([index(0)])
+            .collect();
+        let expected: Vec<_> = indices.iter().map(|&i| tree.prove(i).unwrap()).collect();
+        assert_eq!(
+            tree.prove_many_parallel(&mock_db, &indices).unwrap(),
+            expected
+        );
+
+        // compacted trees read the evicted levels from the store
+        tree.compact(&mock_db, 4).unwrap();
+        assert_eq!(
+            tree.prove_many_parallel(&mock_db, &indices).unwrap(),
+            expected
+        );
+
+        let bad = LeafIndex::new(0, height + 1).unwrap();
+        assert!(tree
+            .prove_many_parallel(&mock_db, &[index(1), bad])
+            .is_err());
+    }
+}
@@ -0,0 +1,327 @@
+use std::sync::OnceLock;
+
+use intmax2_zkp::utils::poseidon_hash_out::PoseidonHashOut;
+
+use crate::{
+    batch_hasher::BatchHasher,
+    domain::TaggedHasher,
+    kary_tree::ArityHasher,
+    traits::{HashLeaf, TreeHasher},
+};
+
+// Poseidon2 over the Goldilocks field with width 8, used in compression mode:
+// `two_to_one(l, r)` is the first 4 elements of `P(l || r) + (l || r)`. Hash
+// outputs are `PoseidonHashOut`s, so Poseidon and Poseidon2 trees share the
+// same storage types; which one a tree uses is decided by its leaf type.
+#[derive(Clone, Debug)]
+pub struct Poseidon2Hasher;
+
+// A leaf given by its Poseidon2 hash; the empty leaf is the zero hash.
+pub type Poseidon2Leaf = HashLeaf<Poseidon2Hasher>;
+
+const P: u64 = 0xffff_ffff_0000_0001;
+const WIDTH: usize = 8;
+const RATE: usize = 4;
+const ALPHA: u64 = 7;
+const ROUNDS_F: usize = 8;
+const ROUNDS_P: usize = 22;
+
+pub struct Poseidon2Constants {
+    // ROUNDS_F rows, the first half is used before the partial rounds
+    pub external: Vec<[u64; WIDTH]>,
+    pub internal: Vec<u64>,
+    // `d` of the internal matrix `1 + diag(d)`
+    pub internal_diag: [u64; WIDTH],
+}
+
+// `d` of the internal matrix of the reference Goldilocks width 8 instance
+// (`MATRIX_DIAG_8_GOLDILOCKS` in plonky3, `MAT_DIAG8_M_1` in the HorizenLabs
+// implementation). It is not generated by the Grain LFSR.
+const INTERNAL_DIAG: [u64; WIDTH] = [
+    0xa98811a1fed4e3a5,
+    0x1cc48b54f377e2a0,
+    0xe40cd4f6c5609a26,
+    0x11de79ebca97a4a3,
+    0x9177c73d8b7e929c,
+    0x2a6fe8085797e791,
+    0x3de6e93329f8d5ad,
+    0x3f7af9125da962fe,
+];
+
+// The round constants are those of the reference parameter script: its Grain
+// LFSR, seeded with (prime field, x^7, 64 bit, width 8, 8 full rounds, 22
+// partial rounds), draws a full row per round in round order, and partial
+// rounds keep only the first element of theirs. Circuits verifying these
+// trees must use the same table.
+pub fn constants() -> &'static Poseidon2Constants {
+    static CONSTANTS: OnceLock<Poseidon2Constants> = OnceLock::new();
+    CONSTANTS.get_or_init(|| {
+        let mut grain = Grain::new();
+        let mut row = || -> [u64; WIDTH] { std::array::from_fn(|_| grain.next_field_element()) };
+        let mut external: Vec<_> = (0..ROUNDS_F / 2).map(|_| row()).collect();
+        let internal = (0..ROUNDS_P).map(|_| row()[0]).collect();
+        external.extend((0..ROUNDS_F / 2).map(|_| row()));
+        Poseidon2Constants {
+            external,
+            internal,
+            internal_diag: INTERNAL_DIAG,
+        }
+    })
+}
+
+pub fn permute(state: &mut [u64; WIDTH]) {
+    let constants = constants();
+    external_layer(state);
+    for rc in &constants.external[..ROUNDS_F / 2] {
+        full_round(state, rc);
+    }
+    for &rc in &constants.internal {
+        state[0] = sbox(add(state[0], rc));
+        internal_layer(state, &constants.internal_diag);
+    }
+    for rc in &constants.external[ROUNDS_F / 2..] {
+        full_round(state, rc);
+    }
+}
+
+// Sponge hash of `inputs` with 10* padding, or `None` if an input is not a
+// canonical field element. Inputs are not reduced, as `x` and `x + p` would
+// otherwise hash the same.
+pub fn poseidon2_hash(inputs: &[u64]) -> Option<PoseidonHashOut> {
+    inputs.iter().all(|&x| x < P).then(|| sponge(inputs))
+}
+
+// inputs are canonical field elements
+fn sponge(inputs: &[u64]) -> PoseidonHashOut {
+    let mut padded = inputs.to_vec();
+    padded.push(1);
+    padded.resize(padded.len().div_ceil(RATE) * RATE, 0);
+    let mut state = [0u64; WIDTH];
+    for chunk in padded.chunks(RATE) {
+        state[..RATE].copy_from_slice(chunk);
+        permute(&mut state);
+    }
+    PoseidonHashOut::from_u64_vec(&state[..4])
+}
+
+impl TreeHasher for Poseidon2Hasher {
+    type HashOut = PoseidonHashOut;
+
+    fn two_to_one(left: PoseidonHashOut, right: PoseidonHashOut) -> PoseidonHashOut {
+        let mut input = [0u64; WIDTH];
+        input[..4].copy_from_slice(&left.to_u64_vec());
+        input[4..].copy_from_slice(&right.to_u64_vec());
+        let mut state = input;
+        permute(&mut state);
+        let output: Vec<u64> = (0..4).map(|i| add(state[i], input[i])).collect();
+        PoseidonHashOut::from_u64_vec(&output)
+    }
+}
+
+impl BatchHasher for Poseidon2Hasher {}
+
+// a sponge over the children, as 4-ary and wider nodes are more than one
+// permutation wide
+impl ArityHasher for Poseidon2Hasher {
+    fn n_to_one(children: &[PoseidonHashOut]) -> PoseidonHashOut {
+        let inputs: Vec<u64> = children.iter().flat_map(|c| c.to_u64_vec()).collect();
+        sponge(&inputs)
+    }
+}
+
+impl TaggedHasher for Poseidon2Hasher {
+    fn hash_tagged(tag: u8, left: PoseidonHashOut, right: PoseidonHashOut) -> PoseidonHashOut {
+        let mut inputs = vec![tag as u64];
+        inputs.extend(left.to_u64_vec());
+        inputs.extend(right.to_u64_vec());
+        sponge(&inputs)
+    }
+}
+
+fn add(a: u64, b: u64) -> u64 {
+    ((a as u128 + b as u128) % P as u128) as u64
+}
+
+fn mul(a: u64, b: u64) -> u64 {
+    ((a as u128 * b as u128) % P as u128) as u64
+}
+
+fn sbox(x: u64) -> u64 {
+    let mut result = 1;
+    for _ in 0..ALPHA {
+        result = mul(result, x);
+    }
+    result
+}
+
+fn full_round(state: &mut [u64; WIDTH], rc: &[u64; WIDTH]) {
+    for (x, &c) in state.iter_mut().zip(rc) {
+        *x = sbox(add(*x, c));
+    }
+    external_layer(state);
+}
+
+// circ(2 M4, M4) applied to two blocks of 4
+fn external_layer(state: &mut [u64; WIDTH]) {
+    const M4: [[u64; 4]; 4] = [[5, 7, 1, 3], [4, 6, 1, 1], [1, 3, 5, 7], [1, 1, 4, 6]];
+    let mut blocks = [[0u64; 4]; 2];
+    for (block, chunk) in blocks.iter_mut().zip(state.chunks(4)) {
+        for (out, row) in block.iter_mut().zip(M4) {
+            *out = row
+                .iter()
+                .zip(chunk)
+                .fold(0, |acc, (&m, &x)| add(acc, mul(m, x)));
+        }
+    }
+    for i in 0..4 {
+        let sum = add(blocks[0][i], blocks[1][i]);
+        state[i] = add(sum, blocks[0][i]);
+        state[i + 4] = add(sum, blocks[1][i]);
+    }
+}
+
+fn internal_layer(state: &mut [u64; WIDTH], diag: &[u64; WIDTH]) {
+    let sum = state.iter().fold(0, |acc, &x| add(acc, x));
+    for (x, &d) in state.iter_mut().zip(diag) {
+        *x = add(mul(*x, d), sum);
+    }
+}
+
+struct Grain {
+    bits: [bool; 80],
+}
+
+impl Grain {
+    fn new() -> Self {
+        let mut bits = [true; 80];
+        let fields: [(u64, usize); 6] = [
+            (1, 2),                // prime field
+            (0, 4),                // x^alpha s-box
+            (64, 12),              // field size
+            (WIDTH as u64, 12),    // width
+            (ROUNDS_F as u64, 10), // full rounds
+            (ROUNDS_P as u64, 10), // partial rounds
+        ];
+        let mut pos = 0;
+        for (value, len) in fields {
+            for i in (0..len).rev() {
+                bits[pos] = (value >> i) & 1 == 1;
+                pos += 1;
+            }
+        }
+        let mut grain = Self { bits };
+        for _ in 0..160 {
+            grain.step();
+        }
+        grain
+    }
+
+    fn step(&mut self) -> bool {
+        let b = &self.bits;
+        let new_bit = b[62] ^ b[51] ^ b[38] ^ b[23] ^ b[13] ^ b[0];
+        self.bits.copy_within(1.., 0);
+        self.bits[79] = new_bit;
+        new_bit
+    }
+
+    // bits are produced in pairs; the second bit is kept if the first is set
+    fn next_bit(&mut self) -> bool {
+        while !self.step() {
+            self.step();
+        }
+        self.step()
+    }
+
+    fn next_field_element(&mut self) -> u64 {
+        loop {
+            let x = (0..64).fold(0u64, |acc, _| (acc << 1) | self.next_bit() as u64);
+            if x < P {
+                return x;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use intmax2_zkp::utils::{
+        leafable_hasher::PoseidonLeafableHasher, poseidon_hash_out::PoseidonHashOut,
+    };
+
+    use crate::{
+        leaf_index::LeafIndex,
+        merkle_tree::MerkleTree,
+        mock_db::MockDB,
+        testkit,
+        traits::{HashLeaf, Leafable, TreeHasher},
+    };
+
+    use super::{constants, poseidon2_hash, Poseidon2Hasher, Poseidon2Leaf, P};
+
+    #[test]
+    fn test_poseidon2_tree() {
+        let a = poseidon2_hash(&[1, 2, 3]).unwrap();
+        let b = poseidon2_hash(&[1, 2, 3, 0]).unwrap();
+        assert_ne!(a, b, "padding separates trailing zeros");
+        assert_ne!(
+            Poseidon2Hasher::two_to_one(a, b),
+            Poseidon2Hasher::two_to_one(b, a)
+        );
+        assert_ne!(
+            Poseidon2Hasher::two_to_one(a, b),
+            PoseidonLeafableHasher::two_to_one(a, b)
+        );
+
+        let height = 16;
+        let mut mock_db = MockDB::<Poseidon2Leaf>::new();
+        let empty_leaf_hash = Poseidon2Leaf::empty_leaf().hash();
+        assert_eq!(empty_leaf_hash, PoseidonHashOut::default());
+        let leaf_hashes: Vec<_> = (0..100).map(|i| poseidon2_hash(&[i]).unwrap()).collect();
+        let merkle_tree =
+            MerkleTree::from_leaf_hashes(&mut mock_db, height, empty_leaf_hash, &leaf_hashes);
+        let root = merkle_tree.get_root();
+        for i in [0, 42, 99] {
+            let leaf: Poseidon2Leaf = HashLeaf(leaf_hashes[i]);
+            let proof = merkle_tree
+                .prove_with_given_root(&mock_db, root, LeafIndex::new(i as u128, height).unwrap())
+                .unwrap();
+            proof.verify_at(&leaf, i as u64, root).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_poseidon2_conformance() {
+        testkit::check_hasher::<Poseidon2Hasher>(|i| poseidon2_hash(&[i]).unwrap());
+    }
+
+    #[test]
+    fn test_poseidon2_reference_constants() {
+        // the first full round of the reference Goldilocks width 8 table
+        let constants = constants();
+        assert_eq!(
+            constants.external[0],
+            [
+                0xdd5743e7f2a5a5d9,
+                0xcb3a864e58ada44b,
+                0xffa2449ed32f8cdc,
+                0x42025f65d6bd13ee,
+                0x7889175e25506323,
+                0x34b98bb03d24b737,
+                0xbdcc535ecc4faa2a,
+                0x5b20ad869fc0d033,
+            ]
+        );
+        assert_eq!(constants.external.len(), 8);
+        assert_eq!(constants.internal.len(), 22);
+    }
+
+    #[test]
+    fn test_poseidon2_non_canonical_input() {
+        assert!(poseidon2_hash(&[P]).is_none());
+        assert!(poseidon2_hash(&[1, u64::MAX]).is_none());
+        assert_ne!(
+            poseidon2_hash(&[P - 1]).unwrap(),
+            poseidon2_hash(&[0]).unwrap()
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use alloc::{vec, vec::Vec};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    error::VerifyError,
+    leaf_index::LeafIndex,
+    traits::{Leafable, TreeHasher},
+    verify::root_from_siblings,
+};
+
+// A proof of a leaf of a `MerkleTree`: the siblings on its path, from the
+// leaf up. Proofs and their verification only need `alloc`, so they build
+// without the `std` feature.
+#[derive(Clone, Debug)]
+pub struct MerkleProof<V: Leafable> {
+    pub siblings: Vec<<V::Hasher as TreeHasher>::HashOut>,
+}
+
+impl<V: Leafable> PartialEq for MerkleProof<V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.siblings == other.siblings
+    }
+}
+
+impl<V: Leafable> Eq for MerkleProof<V> {}
+
+impl<V: Leafable> core::hash::Hash for MerkleProof<V> {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.siblings.hash(state);
+    }
+}
+
+// the proof of a height 0 tree
+impl<V: Leafable> Default for MerkleProof<V> {
+    fn default() -> Self {
+        Self {
+            siblings: Vec::new(),
+        }
+    }
+}
+
+impl<V: Leafable> Serialize for MerkleProof<V>
+where
+    <V::Hasher as TreeHasher>::HashOut: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.siblings.serialize(serializer)
+    }
+}
+
+impl<'de, V: Leafable> Deserialize<'de> for MerkleProof<V>
+where
+    <V::Hasher as TreeHasher>::HashOut: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let siblings = Vec::<<V::Hasher as TreeHasher>::HashOut>::deserialize(deserializer)?;
+        Ok(MerkleProof { siblings })
+    }
+}
+
+impl<V: Leafable> MerkleProof<V> {
+    pub fn dummy(height: usize) -> Self {
+        Self {
+            siblings: vec![<V::Hasher as TreeHasher>::HashOut::default(); height],
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        self.siblings.len()
+    }
+
+    pub fn get_root(
+        &self,
+        leaf_data: &V,
+        index: impl Into<LeafIndex>,
+    ) -> <V::Hasher as TreeHasher>::HashOut {
+        self.get_root_from_hash(leaf_data.hash(), index)
+    }
+
+    // Same as `get_root`, but starts from an already computed leaf hash.
+    // Panics if `index` is not for a tree of the proof's height; use
+    // `verify_hash` to get an error instead.
+    pub fn get_root_from_hash(
+        &self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: impl Into<LeafIndex>,
+    ) -> <V::Hasher as TreeHasher>::HashOut {
+        let index = index.into();
+        assert_eq!(
+            index.height(),
+            self.height(),
+            "index height does not match the proof height"
+        );
+        root_from_siblings::<V::Hasher>(leaf_hash, index.index(), self.siblings.iter().cloned()).0
+    }
+
+    pub fn verify(
+        &self,
+        leaf_data: &V,
+        index: impl Into<LeafIndex>,
+        merkle_root: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Result<(), VerifyError<<V::Hasher as TreeHasher>::HashOut>> {
+        self.verify_hash(leaf_data.hash(), index, merkle_root)
+    }
+
+    // Same as `verify`, for verifiers that only hold the leaf hash.
+    pub fn verify_hash(
+        &self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: impl Into<LeafIndex>,
+        merkle_root: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Result<(), VerifyError<<V::Hasher as TreeHasher>::HashOut>> {
+        let index = index.into();
+        if index.height() != self.height() {
+            return Err(VerifyError::InvalidIndexLength {
+                expected: self.height(),
+                actual: index.height(),
+            });
+        }
+        let computed_root = self.get_root_from_hash(leaf_hash.clone(), index);
+        if computed_root != merkle_root {
+            return Err(VerifyError::RootMismatch {
+                leaf_hash,
+                index: index.index(),
+                computed_root,
+                expected_root: merkle_root,
+            });
+        }
+        Ok(())
+    }
+
+    // Same as `verify`, but takes the leaf index as an integer.
+    pub fn verify_at(
+        &self,
+        leaf_data: &V,
+        index: u64,
+        merkle_root: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Result<(), VerifyError<<V::Hasher as TreeHasher>::HashOut>> {
+        let index = LeafIndex::new(index as u128, self.height()).map_err(|_| {
+            VerifyError::IndexOutOfRange {
+                index: index as u128,
+                height: self.height(),
+            }
+        })?;
+        self.verify(leaf_data, index, merkle_root)
+    }
+}
@@ -0,0 +1,155 @@
+use hashbrown::HashMap;
+
+use crate::{
+    error::VerifyError,
+    leaf_index::LeafIndex,
+    merkle_tree::MerkleProof,
+    node_key::NodeKey,
+    traits::{Leafable, TreeHasher},
+};
+
+impl<V: Leafable> MerkleProof<V> {
+    // Verifies a set of proofs of the same tree at once. Besides checking
+    // each proof against `merkle_root`, every node that two proofs both imply
+    // a hash for, as a sibling or on a path, must have the same hash in
+    // both; otherwise the set fails with `InconsistentNode` for the first
+    // such node met, walking the proofs in order from the leaf up. This
+    // catches a set mixing proofs of different roots at a node where they
+    // diverge. Repeating an index with another leaf hash fails at the leaf.
+    pub fn verify_set<'a>(
+        proofs: impl IntoIterator<
+            Item = (
+                <V::Hasher as TreeHasher>::HashOut,
+                LeafIndex,
+                &'a MerkleProof<V>,
+            ),
+        >,
+        merkle_root: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Result<(), VerifyError<<V::Hasher as TreeHasher>::HashOut>>
+    where
+        V: 'a,
+    {
+        let mut nodes = HashMap::new();
+        let mut height = None;
+        for (leaf_hash, index, proof) in proofs {
+            let expected = *height.get_or_insert(proof.height());
+            if proof.height() != expected {
+                return Err(VerifyError::InvalidIndexLength {
+                    expected,
+                    actual: proof.height(),
+                });
+            }
+            if index.height() != proof.height() {
+                return Err(VerifyError::InvalidIndexLength {
+                    expected: proof.height(),
+                    actual: index.height(),
+                });
+            }
+            let mut key = index.to_node_key();
+            let mut state = leaf_hash.clone();
+            for sibling in &proof.siblings {
+                insert_node(&mut nodes, key, state.clone())?;
+                insert_node(&mut nodes, key.sibling(), sibling.clone())?;
+                state = if key.is_right() {
+                    <V::Hasher as TreeHasher>::two_to_one(sibling.clone(), state)
+                } else {
+                    <V::Hasher as TreeHasher>::two_to_one(state, sibling.clone())
+                };
+                key = key.parent();
+            }
+            if state != merkle_root {
+                return Err(VerifyError::RootMismatch {
+                    leaf_hash,
+                    index: index.index(),
+                    computed_root: state,
+                    expected_root: merkle_root,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+fn insert_node<H: Clone + Eq>(
+    nodes: &mut HashMap<NodeKey, H>,
+    key: NodeKey,
+    hash: H,
+) -> Result<(), VerifyError<H>> {
+    match nodes.get(&key) {
+        Some(first) if *first != hash => Err(VerifyError::InconsistentNode {
+            key,
+            first: first.clone(),
+            second: hash,
+        }),
+        Some(_) => Ok(()),
+        None => {
+            nodes.insert(key, hash);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        error::VerifyError,
+        leaf_index::LeafIndex,
+        merkle_tree::{MerkleProof, MerkleTree},
+        mock_db::MockDB,
+        node_key::NodeKey,
+        traits::Leafable,
+    };
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_verify_set() {
+        let height = 6;
+        let mut db = MockDB::<Leaf>::new();
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        let index = |i: u128| LeafIndex::new(i, height).unwrap();
+        for i in [1u128, 2, 40] {
+            tree.update_leaf(&mut db, index(i), (i as u32).hash())
+                .unwrap();
+        }
+        let old_proof = tree.prove(index(1)).unwrap();
+        tree.update_leaf(&mut db, index(2), 7u32.hash()).unwrap();
+        let root = tree.get_root();
+        let proofs: Vec<_> = [1u128, 2, 40]
+            .into_iter()
+            .map(|i| tree.prove(index(i)).unwrap())
+            .collect();
+        let set = vec![
+            (1u32.hash(), index(1), &proofs[0]),
+            (7u32.hash(), index(2), &proofs[1]),
+            (40u32.hash(), index(40), &proofs[2]),
+        ];
+        assert!(MerkleProof::verify_set(set, root).is_ok());
+
+        // leaf 1 proven at the old root disagrees with leaf 2 on the parent of
+        // leaf 2, before its root is checked
+        let key = NodeKey::new(height - 1, 1);
+        let mixed = vec![
+            (7u32.hash(), index(2), &proofs[1]),
+            (1u32.hash(), index(1), &old_proof),
+        ];
+        assert_eq!(
+            MerkleProof::verify_set(mixed, root),
+            Err(VerifyError::InconsistentNode {
+                key,
+                first: tree.get_node_hash(key).unwrap(),
+                second: old_proof.siblings[1],
+            })
+        );
+
+        // the same index with two leaves
+        let twice = vec![
+            (7u32.hash(), index(2), &proofs[1]),
+            (8u32.hash(), index(2), &proofs[1]),
+        ];
+        assert!(matches!(
+            MerkleProof::verify_set(twice, root),
+            Err(VerifyError::InconsistentNode { key, .. }) if key == index(2).to_node_key()
+        ));
+    }
+}
@@ -0,0 +1,242 @@
+use hashbrown::HashMap;
+
+use crate::{
+    error::CorruptNode,
+    node::Node,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+// `RefCountedDB` wraps a `NodeStore` and keeps track of how many times each
+// hash is referenced, either as a child of a stored node or as a retained
+// root. Releasing a root deletes exactly the nodes that are no longer
+// referenced, so dropping a version costs O(changed nodes) instead of a full
+// graph walk like `MockDB::collect_garbage`.
+//
+// Roots are not referenced by anything, so callers have to `retain` every
+// root they want to keep (typically the tree root after each update) and
+// `release` it once the version is dropped.
+#[derive(Clone, Debug)]
+pub struct RefCountedDB<V: Leafable, S: NodeStore<V>> {
+    inner: S,
+    ref_counts: HashMap<<V::Hasher as TreeHasher>::HashOut, usize>,
+}
+
+impl<V: Leafable, S: NodeStore<V>> RefCountedDB<V, S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            ref_counts: HashMap::new(),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    pub fn ref_count(&self, hash: <V::Hasher as TreeHasher>::HashOut) -> usize {
+        self.ref_counts.get(&hash).copied().unwrap_or(0)
+    }
+
+    pub fn retain(&mut self, root: <V::Hasher as TreeHasher>::HashOut) {
+        *self.ref_counts.entry(root).or_insert(0) += 1;
+    }
+
+    // Drops one reference to `root` and deletes every node whose reference
+    // count reaches zero as a consequence. Returns the number of deleted
+    // nodes.
+    pub fn release(&mut self, root: <V::Hasher as TreeHasher>::HashOut) -> usize {
+        self.release_all(vec![root])
+    }
+
+    fn release_all(&mut self, mut stack: Vec<<V::Hasher as TreeHasher>::HashOut>) -> usize {
+        let mut removed = 0;
+        while let Some(hash) = stack.pop() {
+            let count = match self.ref_counts.get_mut(&hash) {
+                Some(count) => count,
+                None => continue,
+            };
+            *count -= 1;
+            if *count > 0 {
+                continue;
+            }
+            self.ref_counts.remove(&hash);
+            if let Some(node) = self.inner.remove(hash.clone()) {
+                removed += 1;
+                stack.push(node.left);
+                stack.push(node.right);
+            } else {
+                // no longer referenced leaf
+                self.inner.remove_leaf_data(hash.clone());
+                self.inner.remove_leaf_metadata(hash);
+            }
+        }
+        removed
+    }
+}
+
+impl<V: Leafable, S: NodeStore<V>> NodeStore<V> for RefCountedDB<V, S> {
+    fn insert(&mut self, key: <V::Hasher as TreeHasher>::HashOut, node: Node<V>) {
+        // nodes are content addressed, so an existing node already holds
+        // references to the same children
+        if self.inner.contains(key.clone()) {
+            return;
+        }
+        self.retain(node.left.clone());
+        self.retain(node.right.clone());
+        self.inner.insert(key, node);
+    }
+
+    fn get(&self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>> {
+        self.inner.get(key)
+    }
+
+    // Removes the node regardless of its reference count and releases its
+    // children.
+    fn remove(&mut self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>> {
+        let node = self.inner.remove(key.clone())?;
+        self.ref_counts.remove(&key);
+        self.release_all(vec![node.left.clone(), node.right.clone()]);
+        Some(node)
+    }
+
+    fn contains(&self, key: <V::Hasher as TreeHasher>::HashOut) -> bool {
+        self.inner.contains(key)
+    }
+
+    fn insert_leaf_data(&mut self, leaf_hash: <V::Hasher as TreeHasher>::HashOut, data: Vec<u8>) {
+        self.inner.insert_leaf_data(leaf_hash, data)
+    }
+
+    fn get_leaf_data(&self, leaf_hash: <V::Hasher as TreeHasher>::HashOut) -> Option<Vec<u8>> {
+        self.inner.get_leaf_data(leaf_hash)
+    }
+
+    fn remove_leaf_data(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Option<Vec<u8>> {
+        self.inner.remove_leaf_data(leaf_hash)
+    }
+
+    fn insert_leaf_metadata(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+        metadata: Vec<u8>,
+    ) {
+        self.inner.insert_leaf_metadata(leaf_hash, index, metadata)
+    }
+
+    fn get_leaf_metadata(
+        &self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+    ) -> Option<Vec<u8>> {
+        self.inner.get_leaf_metadata(leaf_hash, index)
+    }
+
+    fn remove_leaf_metadata(&mut self, leaf_hash: <V::Hasher as TreeHasher>::HashOut) {
+        self.inner.remove_leaf_metadata(leaf_hash)
+    }
+
+    fn remove_leaf_metadata_at(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+    ) {
+        self.inner.remove_leaf_metadata_at(leaf_hash, index)
+    }
+
+    fn with_node<R>(
+        &self,
+        key: <V::Hasher as TreeHasher>::HashOut,
+        f: impl FnOnce(&Node<V>) -> R,
+    ) -> Option<R> {
+        self.inner.with_node(key, f)
+    }
+
+    fn try_with_node<R>(
+        &self,
+        key: <V::Hasher as TreeHasher>::HashOut,
+        f: impl FnOnce(&Node<V>) -> R,
+    ) -> Result<Option<R>, CorruptNode<<V::Hasher as TreeHasher>::HashOut>> {
+        self.inner.try_with_node(key, f)
+    }
+
+    fn num_nodes(&self) -> Option<usize> {
+        self.inner.num_nodes()
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use intmax2_zkp::utils::{
+        leafable_hasher::PoseidonLeafableHasher, poseidon_hash_out::PoseidonHashOut,
+    };
+
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, node_store::NodeStore,
+        testkit, traits::Leafable,
+    };
+
+    use super::RefCountedDB;
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_release_version() {
+        let height = 16;
+
+        let mut db = RefCountedDB::new(MockDB::<Leaf>::new());
+        let empty_leaf_hash = PoseidonHashOut::hash_inputs_u32(&[]);
+        let mut merkle_tree = MerkleTree::new(&mut db, height, empty_leaf_hash);
+
+        // keep only the latest root of every update
+        let mut head = merkle_tree.get_root();
+        db.retain(head);
+        for i in 0..10 {
+            let leaf = i as u32;
+            merkle_tree
+                .update_leaf(
+                    &mut db,
+                    LeafIndex::new(i as u128, height).unwrap(),
+                    leaf.hash(),
+                )
+                .unwrap();
+            let root = merkle_tree.get_root();
+            db.retain(root);
+            db.release(head);
+            head = root;
+        }
+
+        // refcounting leaves the store identical to a full GC
+        let mut gc_db = db.inner().clone();
+        assert_eq!(gc_db.collect_garbage(&[head]), 0);
+
+        let index = 4;
+        let leaf_index = LeafIndex::new(index as u128, height).unwrap();
+        let proof = merkle_tree
+            .prove_with_given_root(&db, head, leaf_index)
+            .unwrap();
+        assert_eq!(proof.get_root(&(index as u32), leaf_index), head);
+
+        // releasing the last version deletes everything
+        let before = db.inner().len();
+        assert_eq!(db.release(head), before);
+        assert!(db.inner().is_empty());
+        assert!(db.get(head).is_none());
+    }
+
+    #[test]
+    fn test_store_conformance() {
+        testkit::check_store::<PoseidonLeafableHasher, _>(
+            || RefCountedDB::new(MockDB::new()),
+            |i| PoseidonHashOut::hash_inputs_u64(&[i]),
+        );
+    }
+}
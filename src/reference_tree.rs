@@ -0,0 +1,104 @@
+use crate::{
+    leaf_index::LeafIndex,
+    merkle_tree::MerkleProof,
+    traits::{Leafable, TreeHasher},
+};
+
+// Deliberately naive tree to compare `MerkleTree` against: it keeps every
+// leaf hash in a vector indexed by the leaf index and recomputes all levels
+// from scratch whenever it is asked for a hash, so it has no caching, no
+// bit tricks and no store that could hide a bug. Only for small heights.
+#[derive(Clone, Debug)]
+pub struct ReferenceTree<V: Leafable> {
+    height: usize,
+    leaves: Vec<<V::Hasher as TreeHasher>::HashOut>,
+}
+
+pub const MAX_REFERENCE_HEIGHT: usize = 20;
+
+impl<V: Leafable> ReferenceTree<V> {
+    pub fn new(height: usize) -> Self {
+        assert!(
+            height <= MAX_REFERENCE_HEIGHT,
+            "a reference tree has at most height {}",
+            MAX_REFERENCE_HEIGHT
+        );
+        Self {
+            height,
+            leaves: vec![V::empty_leaf().hash(); 1 << height],
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn update_leaf(&mut self, index: LeafIndex, leaf_hash: <V::Hasher as TreeHasher>::HashOut) {
+        assert_eq!(index.height(), self.height);
+        self.leaves[index.index() as usize] = leaf_hash;
+    }
+
+    pub fn get_leaf(&self, index: LeafIndex) -> <V::Hasher as TreeHasher>::HashOut {
+        self.leaves[index.index() as usize].clone()
+    }
+
+    // All levels from the leaves up to the root, so `levels()[height]` holds
+    // only the root. Node `i` of a level is the parent of nodes `2i` and
+    // `2i + 1` of the level below.
+    pub fn levels(&self) -> Vec<Vec<<V::Hasher as TreeHasher>::HashOut>> {
+        let mut levels = vec![self.leaves.clone()];
+        for _ in 0..self.height {
+            let below = levels.last().unwrap();
+            let level = below
+                .chunks(2)
+                .map(|pair| V::Hasher::two_to_one(pair[0].clone(), pair[1].clone()))
+                .collect();
+            levels.push(level);
+        }
+        levels
+    }
+
+    pub fn get_root(&self) -> <V::Hasher as TreeHasher>::HashOut {
+        self.levels().pop().unwrap().pop().unwrap()
+    }
+
+    pub fn prove(&self, index: LeafIndex) -> MerkleProof<V> {
+        assert_eq!(index.height(), self.height);
+        let levels = self.levels();
+        let mut position = index.index() as usize;
+        let mut siblings = vec![];
+        for level in &levels[..self.height] {
+            siblings.push(level[position ^ 1].clone());
+            position /= 2;
+        }
+        MerkleProof { siblings }
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable,
+    };
+
+    use super::ReferenceTree;
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_reference_tree() {
+        let height = 5;
+        let mut db = MockDB::<Leaf>::new();
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        let mut reference = ReferenceTree::<Leaf>::new(height);
+        assert_eq!(reference.get_root(), tree.get_root());
+        for i in [1u128, 6, 7, 30, 6] {
+            let index = LeafIndex::new(i, height).unwrap();
+            let hash = (i as u32 * 3 + 1).hash();
+            tree.update_leaf(&mut db, index, hash).unwrap();
+            reference.update_leaf(index, hash);
+            assert_eq!(reference.get_root(), tree.get_root());
+            assert_eq!(reference.prove(index), tree.prove(index).unwrap());
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use crate::{
+    batch_hasher::BatchHasher, merkle_tree::MerkleTree, node::Node, node_key::NodeKey,
+    node_store::NodeStore, traits::Leafable,
+};
+
+impl<V: Leafable> MerkleTree<V> {
+    // Recomputes every internal node of the current version from the leaf
+    // hashes held in memory and writes them, with the nodes of the empty
+    // subtrees, to `db`. This restores proofs from the store against the
+    // current root after nodes were lost, pruned or damaged; `db` can also be
+    // a new, empty store. The internal hashes cached in memory are recomputed
+    // as well, so the tree ends at the root its leaves define. Older versions
+    // are not restored. Fails on a compacted tree, whose leaves may only be
+    // in the store. Returns the number of nodes written.
+    pub fn rebuild_from_leaves<S: NodeStore<V>>(&mut self, db: &mut S) -> anyhow::Result<usize>
+    where
+        V::Hasher: BatchHasher,
+    {
+        anyhow::ensure!(
+            self.cache_depth == self.height,
+            "cannot rebuild a compacted tree: leaves below depth {} are not in memory",
+            self.cache_depth
+        );
+        let height = self.height;
+        let mut level: Vec<_> = self
+            .node_hashes
+            .iter()
+            .filter(|(key, hash)| key.depth() == height && **hash != self.zero_hashes[height])
+            .map(|(key, hash)| (*key, hash.clone()))
+            .collect();
+        level.sort_by_key(|(key, _)| key.index);
+
+        let mut batch: Vec<_> = (0..height)
+            .map(|depth| {
+                let zero = self.zero_hashes[depth + 1].clone();
+                (
+                    self.zero_hashes[depth].clone(),
+                    Node {
+                        left: zero.clone(),
+                        right: zero,
+                    },
+                )
+            })
+            .collect();
+        let mut internal = vec![];
+        for depth in (0..height).rev() {
+            let zero = &self.zero_hashes[depth + 1];
+            let mut parents = vec![];
+            let mut pairs = vec![];
+            let mut children = level.into_iter().peekable();
+            while let Some((key, hash)) = children.next() {
+                let pair = if key.is_right() {
+                    (zero.clone(), hash)
+                } else if let Some((_, right)) =
+                    children.next_if(|(next, _)| *next == key.sibling())
+                {
+                    (hash, right)
+                } else {
+                    (hash, zero.clone())
+                };
+                parents.push(key.parent());
+                pairs.push(pair);
+            }
+            let hashes = self.hash_children_many(depth, &pairs);
+            level = parents.into_iter().zip(hashes).collect();
+            for ((left, right), (_, hash)) in pairs.into_iter().zip(&level) {
+                batch.push((hash.clone(), Node { left, right }));
+            }
+            internal.extend(level.iter().cloned());
+        }
+
+        let written = batch.len();
+        db.insert_batch(batch);
+        self.node_hashes.retain(|key, _| key.depth() == height);
+        self.node_hashes.extend(internal);
+        if height > 0 && !self.node_hashes.contains_key(&NodeKey::root()) {
+            // every leaf is empty
+            self.node_hashes
+                .insert(NodeKey::root(), self.zero_hashes[0].clone());
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, node_key::NodeKey,
+        traits::Leafable,
+    };
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_rebuild_from_leaves() {
+        let height = 10;
+        let mut db = MockDB::<Leaf>::new();
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        let index = move |i: u128| LeafIndex::new(i, height).unwrap();
+        for i in [0, 1, 2, 7, 100, 513, 1023] {
+            tree.update_leaf(&mut db, index(i), (i as u32 + 1).hash())
+                .unwrap();
+        }
+        tree.update_leaf(&mut db, index(7), Leaf::empty_leaf().hash())
+            .unwrap();
+        let root = tree.get_root();
+
+        // every node is lost, and a cached hash is damaged
+        let mut fresh = MockDB::new();
+        tree.node_hashes.insert(NodeKey::new(3, 1), 5u32.hash());
+        tree.rebuild_from_leaves(&mut fresh).unwrap();
+        assert_eq!(tree.get_root(), root);
+        assert!(tree.verify_integrity(&fresh, root).is_ok());
+        for i in [0, 2, 7, 513, 1023] {
+            assert_eq!(
+                tree.prove_with_given_root(&fresh, root, index(i)).unwrap(),
+                tree.prove(index(i)).unwrap()
+            );
+        }
+        // and the rebuilt store takes further updates
+        tree.update_leaf(&mut fresh, index(8), 9u32.hash()).unwrap();
+        assert!(tree.verify_integrity(&fresh, tree.get_root()).is_ok());
+
+        tree.compact(&fresh, 4).unwrap();
+        assert!(tree.rebuild_from_leaves(&mut MockDB::new()).is_err());
+    }
+}
@@ -0,0 +1,261 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    batch_hasher::BatchHasher,
+    error::DbTreeError,
+    node_store::NodeStore,
+    service::{hash_from_hex, hash_to_hex, ProofService, WireHash},
+    traits::{Leafable, TreeHasher},
+};
+
+// REST transport of a `ProofService`. Hashes are `0x` hex strings in JSON.
+//
+//   GET  /root            -> { "root" }
+//   GET  /proof/{index}   -> { "root", "siblings" }, or the binary encoding of
+//                            `ProofResponse` if the request accepts
+//                            application/octet-stream
+//   POST /leaves          { "leaves": [{ "index", "leaf_hash" }] } -> { "root" }
+//
+// Handlers keep no per-connection state, so the router can sit behind a load
+// balancer as-is; replicas serving the same tree must be sent the same leaves.
+
+type SharedService<V, S> = Arc<ProofService<V, S>>;
+
+#[derive(Serialize)]
+struct RootJson {
+    root: String,
+}
+
+#[derive(Serialize)]
+struct ProofJson {
+    root: String,
+    siblings: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct LeafJson {
+    index: u64,
+    leaf_hash: String,
+}
+
+#[derive(Deserialize)]
+struct LeavesJson {
+    leaves: Vec<LeafJson>,
+}
+
+fn error(status: StatusCode, message: String) -> Response {
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+fn tree_error(e: DbTreeError) -> Response {
+    error(StatusCode::BAD_REQUEST, e.to_string())
+}
+
+async fn get_root<V, S>(State(service): State<SharedService<V, S>>) -> Json<RootJson>
+where
+    V: Leafable,
+    <V::Hasher as TreeHasher>::HashOut: WireHash,
+    S: NodeStore<V>,
+{
+    Json(RootJson {
+        root: hash_to_hex(&service.get_root()),
+    })
+}
+
+async fn get_proof<V, S>(
+    State(service): State<SharedService<V, S>>,
+    Path(index): Path<u64>,
+    headers: HeaderMap,
+) -> Response
+where
+    V: Leafable,
+    <V::Hasher as TreeHasher>::HashOut: WireHash,
+    S: NodeStore<V>,
+{
+    let response = match service.get_proof(index as u128) {
+        Ok(response) => response,
+        Err(e) => return tree_error(e),
+    };
+    let binary = headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/octet-stream"));
+    if binary {
+        return (
+            [(header::CONTENT_TYPE, "application/octet-stream")],
+            response.to_bytes(),
+        )
+            .into_response();
+    }
+    Json(ProofJson {
+        root: hash_to_hex(&response.root),
+        siblings: response.siblings.iter().map(hash_to_hex).collect(),
+    })
+    .into_response()
+}
+
+async fn post_leaves<V, S>(
+    State(service): State<SharedService<V, S>>,
+    Json(request): Json<LeavesJson>,
+) -> Response
+where
+    V: Leafable,
+    V::Hasher: BatchHasher,
+    <V::Hasher as TreeHasher>::HashOut: WireHash,
+    S: NodeStore<V>,
+{
+    let mut leaves = vec![];
+    for leaf in request.leaves {
+        match hash_from_hex(&leaf.leaf_hash) {
+            Some(hash) => leaves.push((leaf.index as u128, hash)),
+            None => {
+                let message = format!("invalid leaf hash at index {}", leaf.index);
+                return error(StatusCode::BAD_REQUEST, message);
+            }
+        }
+    }
+    match service.update_leaves(&leaves) {
+        Ok(root) => Json(RootJson {
+            root: hash_to_hex(&root),
+        })
+        .into_response(),
+        Err(e) => tree_error(e),
+    }
+}
+
+pub fn router<V, S>(service: SharedService<V, S>) -> Router
+where
+    V: Leafable + Send + Sync + 'static,
+    V::Hasher: BatchHasher + Send + Sync,
+    <V::Hasher as TreeHasher>::HashOut: WireHash + Send + Sync,
+    S: NodeStore<V> + Send + 'static,
+{
+    Router::new()
+        .route("/root", get(get_root::<V, S>))
+        .route("/proof/{index}", get(get_proof::<V, S>))
+        .route("/leaves", post(post_leaves::<V, S>))
+        .with_state(service)
+}
+
+// Serves `router(service)` on `addr` until the server fails.
+pub async fn serve<V, S>(service: SharedService<V, S>, addr: SocketAddr) -> anyhow::Result<()>
+where
+    V: Leafable + Send + Sync + 'static,
+    V::Hasher: BatchHasher + Send + Sync,
+    <V::Hasher as TreeHasher>::HashOut: WireHash + Send + Sync,
+    S: NodeStore<V> + Send + 'static,
+{
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(listener, router(service)).await?;
+    Ok(())
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use std::sync::Arc;
+
+    use axum::{
+        body::Body,
+        http::{header, Request, StatusCode},
+        Router,
+    };
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    use crate::{
+        merkle_tree::MerkleTree,
+        mock_db::MockDB,
+        service::{hash_to_hex, ProofResponse, ProofService},
+        traits::Leafable,
+    };
+
+    use super::router;
+
+    type Leaf = u32;
+
+    async fn send(router: &Router, request: Request<Body>) -> (StatusCode, Vec<u8>) {
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, body.to_vec())
+    }
+
+    fn get(uri: &str) -> Request<Body> {
+        Request::get(uri).body(Body::empty()).unwrap()
+    }
+
+    fn post_json(uri: &str, body: String) -> Request<Body> {
+        Request::post(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_router() {
+        let mut db = MockDB::<Leaf>::new();
+        let tree = MerkleTree::new(&mut db, 4, Leaf::empty_leaf().hash());
+        let service = Arc::new(ProofService::new(tree, db));
+        let router = router(service.clone());
+
+        let leaf = hash_to_hex(&7u32.hash());
+        let body = json!({ "leaves": [{ "index": 5, "leaf_hash": leaf }] });
+        let (status, body) = send(&router, post_json("/leaves", body.to_string())).await;
+        assert_eq!(status, StatusCode::OK);
+        let root = hash_to_hex(&service.get_root());
+        assert_eq!(
+            serde_json::from_slice::<Value>(&body).unwrap()["root"],
+            root
+        );
+
+        let (status, body) = send(&router, get("/proof/5")).await;
+        assert_eq!(status, StatusCode::OK);
+        let proof: Value = serde_json::from_slice(&body).unwrap();
+        let expected = service.get_proof(5).unwrap();
+        assert_eq!(proof["root"], root);
+        assert_eq!(
+            proof["siblings"],
+            json!(expected
+                .siblings
+                .iter()
+                .map(hash_to_hex)
+                .collect::<Vec<_>>())
+        );
+
+        let request = Request::get("/proof/5")
+            .header(header::ACCEPT, "application/octet-stream")
+            .body(Body::empty())
+            .unwrap();
+        let (status, body) = send(&router, request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(ProofResponse::from_bytes(&body), Some(expected));
+
+        // an index outside the tree and a malformed index are client errors
+        let (status, _) = send(&router, get("/proof/16")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = send(&router, get("/proof/five")).await;
+        assert!(status.is_client_error());
+        let body = json!({ "leaves": [{ "index": 16, "leaf_hash": leaf }] });
+        let (status, _) = send(&router, post_json("/leaves", body.to_string())).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // malformed bodies leave the tree untouched
+        let (status, _) = send(&router, post_json("/leaves", "{".to_string())).await;
+        assert!(status.is_client_error());
+        let body = json!({ "leaves": [{ "index": 1, "leaf_hash": "0x12" }] });
+        let (status, _) = send(&router, post_json("/leaves", body.to_string())).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(hash_to_hex(&service.get_root()), root);
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    hash::Hash,
+};
+
+use crate::{
+    error::DbTreeError,
+    merkle_tree::MerkleTree,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+// Indices of every non-empty leaf by leaf hash. The empty leaf hash is never
+// indexed, and a hash is dropped once no leaf holds it.
+#[derive(Clone, Debug)]
+pub(crate) struct ReverseIndex<H> {
+    empty_leaf_hash: H,
+    indices: HashMap<H, BTreeSet<u128>>,
+}
+
+impl<H: Clone + Eq + Hash> ReverseIndex<H> {
+    pub(crate) fn update(&mut self, index: u128, old: H, new: H) {
+        if old == new {
+            return;
+        }
+        if let Some(indices) = self.indices.get_mut(&old) {
+            indices.remove(&index);
+            if indices.is_empty() {
+                self.indices.remove(&old);
+            }
+        }
+        if new != self.empty_leaf_hash {
+            self.indices.entry(new).or_default().insert(index);
+        }
+    }
+}
+
+impl<V: Leafable> MerkleTree<V> {
+    // Builds the reverse index from the current leaves and keeps it up to date
+    // on every later update. Compacted trees read their leaves from `db`.
+    pub fn enable_reverse_index<S: NodeStore<V>>(&mut self, db: &S) -> Result<(), DbTreeError> {
+        let mut reverse_index = ReverseIndex {
+            empty_leaf_hash: self.zero_hashes[self.height].clone(),
+            indices: HashMap::new(),
+        };
+        self.visit_leaves(db, |index, hash| {
+            reverse_index.indices.entry(hash).or_default().insert(index);
+        })?;
+        self.reverse_index = Some(reverse_index);
+        Ok(())
+    }
+
+    pub fn disable_reverse_index(&mut self) {
+        self.reverse_index = None;
+    }
+
+    pub fn has_reverse_index(&self) -> bool {
+        self.reverse_index.is_some()
+    }
+
+    // The smallest index holding `leaf_hash`. Always None for the empty leaf
+    // hash. Fails if the reverse index is not enabled.
+    pub fn find_index(
+        &self,
+        leaf_hash: &<V::Hasher as TreeHasher>::HashOut,
+    ) -> Result<Option<u128>, DbTreeError> {
+        Ok(self.find_indices(leaf_hash)?.next())
+    }
+
+    // Every index holding `leaf_hash` in increasing order.
+    pub fn find_indices(
+        &self,
+        leaf_hash: &<V::Hasher as TreeHasher>::HashOut,
+    ) -> Result<impl Iterator<Item = u128> + '_, DbTreeError> {
+        let reverse_index = self
+            .reverse_index
+            .as_ref()
+            .ok_or(DbTreeError::ReverseIndexDisabled)?;
+        Ok(reverse_index
+            .indices
+            .get(leaf_hash)
+            .into_iter()
+            .flatten()
+            .copied())
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        error::DbTreeError, leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB,
+        traits::Leafable,
+    };
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_reverse_index() {
+        let height = 8;
+        let mut mock_db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = Leaf::empty_leaf().hash();
+        let mut tree = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+        tree.update_leaf_at(&mut mock_db, 7, 1u32.hash()).unwrap();
+        assert_eq!(
+            tree.find_index(&1u32.hash()),
+            Err(DbTreeError::ReverseIndexDisabled)
+        );
+        tree.compact(&mock_db, 2).unwrap();
+
+        // leaves set before enabling are read back through the store
+        tree.enable_reverse_index(&mock_db).unwrap();
+        assert_eq!(tree.find_index(&1u32.hash()).unwrap(), Some(7));
+
+        tree.update_leaf_at(&mut mock_db, 3, 1u32.hash()).unwrap();
+        tree.update_leaf_at(&mut mock_db, 9, 2u32.hash()).unwrap();
+        let leaves = [(9, 3u32.hash()), (20, 2u32.hash())]
+            .map(|(i, h)| (LeafIndex::new(i, height).unwrap(), h));
+        tree.update_leaves(&mut mock_db, &leaves).unwrap();
+        assert_eq!(
+            tree.find_indices(&1u32.hash()).unwrap().collect::<Vec<_>>(),
+            [3, 7]
+        );
+        assert_eq!(tree.find_index(&2u32.hash()).unwrap(), Some(20));
+        assert_eq!(tree.find_index(&3u32.hash()).unwrap(), Some(9));
+
+        tree.delete_leaf(&mut mock_db, LeafIndex::new(3, height).unwrap())
+            .unwrap();
+        tree.push(&mut mock_db, 4u32.hash()).unwrap();
+        assert_eq!(tree.find_index(&1u32.hash()).unwrap(), Some(7));
+        assert_eq!(tree.find_index(&4u32.hash()).unwrap(), Some(21));
+        assert_eq!(tree.find_index(&empty_leaf_hash).unwrap(), None);
+
+        let mut rebuilt = tree.clone();
+        rebuilt.enable_reverse_index(&mock_db).unwrap();
+        for h in [1u32, 2, 3, 4].map(|i| i.hash()) {
+            assert!(tree
+                .find_indices(&h)
+                .unwrap()
+                .eq(rebuilt.find_indices(&h).unwrap()));
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use crate::{
+    historical_proof::{HistoricalProof, RootHistoryTree},
+    leaf_index::LeafIndex,
+    merkle_tree::{MerkleProof, MerkleTree},
+    mock_db::MockDB,
+    node_store::NodeStore,
+    traits::{HashLeaf, Leafable, TreeHasher},
+};
+
+// Height of a `RootHistory`, one leaf per `u64` version number.
+pub const ROOT_HISTORY_HEIGHT: usize = 64;
+
+// `RootHistory` is the append-only chain of committed roots of a tree: leaf
+// `v` of its tree is the root of version `v`. Its own root commits to every
+// version, so a verifier that trusts it can check that a root was the state
+// at a version, and with a `HistoricalProof` which leaves were in it. Its
+// nodes are kept in memory.
+#[derive(Clone, Debug)]
+pub struct RootHistory<V: Leafable> {
+    tree: RootHistoryTree<V>,
+    db: MockDB<HashLeaf<V::Hasher>>,
+    len: u64,
+}
+
+impl<V: Leafable> RootHistory<V> {
+    pub fn new() -> Self {
+        let mut db = MockDB::new();
+        let tree = MerkleTree::new(
+            &mut db,
+            ROOT_HISTORY_HEIGHT,
+            HashLeaf::<V::Hasher>::empty_leaf().hash(),
+        );
+        Self { tree, db, len: 0 }
+    }
+
+    // one past the last recorded version
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // The root of the history, committing to the root of every version.
+    pub fn get_root(&self) -> <V::Hasher as TreeHasher>::HashOut {
+        self.tree.get_root()
+    }
+
+    pub fn tree(&self) -> &RootHistoryTree<V> {
+        &self.tree
+    }
+
+    pub fn store(&self) -> &MockDB<HashLeaf<V::Hasher>> {
+        &self.db
+    }
+
+    // Records `root` as the next version and returns its number.
+    pub fn push(&mut self, root: <V::Hasher as TreeHasher>::HashOut) -> anyhow::Result<u64> {
+        let version = self.len;
+        self.record(version, root)?;
+        Ok(version)
+    }
+
+    // Records `root` as the root of `version`. Versions skipped since the
+    // last one keep the empty leaf and have no root. Fails if `version` is
+    // already recorded or older, as the history is append-only, and for
+    // `u64::MAX`, after which `len` would not fit in a u64.
+    pub fn record(
+        &mut self,
+        version: u64,
+        root: <V::Hasher as TreeHasher>::HashOut,
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            version >= self.len,
+            "version {} is older than the next version {}",
+            version,
+            self.len
+        );
+        let len = version
+            .checked_add(1)
+            .ok_or_else(|| anyhow::anyhow!("version {} is too large to record", version))?;
+        self.tree.update_leaf(&mut self.db, index(version), root)?;
+        self.len = len;
+        Ok(())
+    }
+
+    // the root of `version`, if it was recorded
+    pub fn get(&self, version: u64) -> Option<<V::Hasher as TreeHasher>::HashOut> {
+        if version >= self.len {
+            return None;
+        }
+        let root = self
+            .tree
+            .get_node_hash_unchecked(index(version).to_node_key());
+        (root != HashLeaf::<V::Hasher>::empty_leaf().hash()).then_some(root)
+    }
+
+    // A proof that the root of `version` is leaf `version` of the history,
+    // against `get_root`.
+    pub fn prove(&self, version: u64) -> Option<MerkleProof<HashLeaf<V::Hasher>>> {
+        self.get(version)?;
+        self.tree.prove(index(version)).ok()
+    }
+
+    // Proves `index` at the root of `version` of `tree`, whose nodes of that
+    // version are in `db`, against `get_root`.
+    pub fn prove_historical<S: NodeStore<V>>(
+        &self,
+        tree: &MerkleTree<V>,
+        db: &S,
+        version: u64,
+        index: impl Into<LeafIndex>,
+    ) -> anyhow::Result<HistoricalProof<V>> {
+        tree.prove_historical(db, &self.tree, &self.db, version as u128, index)
+    }
+}
+
+fn index(version: u64) -> LeafIndex {
+    LeafIndex::new(version as u128, ROOT_HISTORY_HEIGHT).unwrap()
+}
+
+impl<V: Leafable> Default for RootHistory<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        leaf_index::LeafIndex,
+        ref_counted_db::RefCountedDB,
+        traits::Leafable,
+        versioned_tree::{RetentionPolicy, VersionedMerkleTree},
+    };
+
+    use super::{RootHistory, ROOT_HISTORY_HEIGHT};
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_root_history() {
+        let mut history = RootHistory::<Leaf>::new();
+        assert!(history.is_empty());
+        let roots: Vec<_> = (1..=5u32).map(|i| i.hash()).collect();
+        for (i, root) in roots.iter().enumerate() {
+            assert_eq!(history.push(*root).unwrap(), i as u64);
+        }
+        assert_eq!(history.get(2), Some(roots[2]));
+        assert_eq!(history.get(5), None);
+        assert!(history.prove(5).is_none());
+        let proof = history.prove(3).unwrap();
+        let version = LeafIndex::new(3, ROOT_HISTORY_HEIGHT).unwrap();
+        assert!(proof
+            .verify_hash(roots[3], version, history.get_root())
+            .is_ok());
+        assert!(proof
+            .verify_hash(roots[2], version, history.get_root())
+            .is_err());
+        // append-only, and skipped versions have no root
+        assert!(history.record(4, roots[0]).is_err());
+        history.record(7, roots[0]).unwrap();
+        assert_eq!(history.len(), 8);
+        assert_eq!(history.get(6), None);
+        let root = history.get_root();
+        assert!(history.record(u64::MAX, roots[1]).is_err());
+        assert_eq!(history.len(), 8);
+        assert_eq!(history.get_root(), root);
+        history.record(u64::MAX - 1, roots[1]).unwrap();
+        assert_eq!(history.len(), u64::MAX);
+        assert!(history.push(roots[2]).is_err());
+
+        // a versioned tree records every commit
+        let height = 8;
+        let mut db = RefCountedDB::new(crate::mock_db::MockDB::<Leaf>::new());
+        let mut tree = VersionedMerkleTree::<Leaf>::new(
+            &mut db,
+            height,
+            Leaf::empty_leaf().hash(),
+            RetentionPolicy::KeepAll,
+        );
+        tree.enable_root_history();
+        let index = |i: u128| LeafIndex::new(i, height).unwrap();
+        for i in 0..3u32 {
+            tree.update_leaf(&mut db, index(1), (i + 1).hash()).unwrap();
+            tree.commit(&mut db, i as u64);
+        }
+        let history = tree.root_history().unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.get(1), Some(tree.get_version(1).unwrap().root));
+        let proof = tree.prove_historical(&db, 1, index(1)).unwrap();
+        assert!(proof
+            .verify(2u32.hash(), index(1), history.get_root())
+            .is_ok());
+        assert!(proof
+            .verify(3u32.hash(), index(1), history.get_root())
+            .is_err());
+        assert!(tree.prove_historical(&db, 3, index(1)).is_none());
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    leaf_index::LeafIndex,
+    merkle_tree::{MerkleProof, MerkleTree},
+    node::Node,
+    node_key::NodeKey,
+    traits::{Leafable, TreeHasher},
+};
+
+// `RootIndex` keeps the non-zero nodes of each committed root in memory, so
+// that historical proofs are `height` map lookups instead of a walk through
+// the node store. Nodes are keyed by hash and shared between roots, with the
+// number of roots and nodes referring to each, so a commit only adds the
+// nodes that changed since the roots already committed.
+#[derive(Clone, Debug)]
+pub struct RootIndex<V: Leafable> {
+    // the height of the committed trees, which tells the nodes from the leaves
+    height: usize,
+    roots: HashSet<<V::Hasher as TreeHasher>::HashOut>,
+    nodes: HashMap<<V::Hasher as TreeHasher>::HashOut, (Node<V>, usize)>,
+}
+
+impl<V: Leafable> RootIndex<V> {
+    pub fn new() -> Self {
+        Self {
+            height: 0,
+            roots: HashSet::new(),
+            nodes: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.roots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+
+    pub fn contains(&self, root: <V::Hasher as TreeHasher>::HashOut) -> bool {
+        self.roots.contains(&root)
+    }
+
+    // the number of distinct nodes kept for all the committed roots
+    pub fn num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    // Records the current state of `tree` under its current root. The tree
+    // must not be compacted, otherwise the snapshot would be incomplete.
+    pub fn commit(&mut self, tree: &MerkleTree<V>) {
+        assert!(!tree.is_compacted());
+        self.height = tree.height();
+        if self.roots.insert(tree.get_root()) {
+            self.retain(tree, NodeKey::root());
+        }
+    }
+
+    pub fn remove(&mut self, root: <V::Hasher as TreeHasher>::HashOut) -> bool {
+        if !self.roots.remove(&root) {
+            return false;
+        }
+        self.release(root, 0);
+        true
+    }
+
+    // Takes a reference to the node at `key` of `tree`, adding it and its
+    // children unless it is already kept.
+    fn retain(&mut self, tree: &MerkleTree<V>, key: NodeKey) {
+        let depth = key.depth();
+        let hash = tree.get_node_hash_unchecked(key);
+        if depth == tree.height() || hash == tree.zero_hashes[depth] {
+            return;
+        }
+        if let Some((_, count)) = self.nodes.get_mut(&hash) {
+            *count += 1;
+            return;
+        }
+        let node = Node::new(
+            tree.get_node_hash_unchecked(key.child(false)),
+            tree.get_node_hash_unchecked(key.child(true)),
+        );
+        self.nodes.insert(hash, (node, 1));
+        self.retain(tree, key.child(false));
+        self.retain(tree, key.child(true));
+    }
+
+    // Drops a reference to the node `hash`, and the node and its references
+    // to its children once nothing refers to it.
+    fn release(&mut self, hash: <V::Hasher as TreeHasher>::HashOut, depth: usize) {
+        if depth == self.height {
+            return;
+        }
+        let Some((_, count)) = self.nodes.get_mut(&hash) else {
+            // an empty subtree
+            return;
+        };
+        *count -= 1;
+        if *count == 0 {
+            let (node, _) = self.nodes.remove(&hash).unwrap();
+            self.release(node.left, depth + 1);
+            self.release(node.right, depth + 1);
+        }
+    }
+
+    // Returns `None` if `root` has not been committed to the index. `tree`
+    // provides the zero hashes and must have the same height and empty leaf as
+    // the tree that was committed.
+    pub fn prove(
+        &self,
+        tree: &MerkleTree<V>,
+        root: <V::Hasher as TreeHasher>::HashOut,
+        index: impl Into<LeafIndex>,
+    ) -> Option<MerkleProof<V>> {
+        let index = index.into();
+        assert_eq!(index.height(), tree.height());
+        if !self.roots.contains(&root) {
+            return None;
+        }
+        let path = index.to_node_key().to_path();
+
+        let mut siblings = Vec::with_capacity(path.len());
+        let mut hash = root;
+        for (depth, &bit) in path.iter().enumerate() {
+            if hash == tree.zero_hashes[depth] {
+                // the rest of the path is inside an empty subtree
+                siblings.extend_from_slice(&tree.zero_hashes[depth + 1..]);
+                break;
+            }
+            let (node, _) = &self.nodes[&hash];
+            siblings.push(node.child(!bit));
+            hash = node.child(bit);
+        }
+        siblings.reverse();
+        Some(MerkleProof { siblings })
+    }
+}
+
+impl<V: Leafable> Default for RootIndex<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable,
+    };
+
+    use super::RootIndex;
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_root_index_shares_nodes() {
+        let height = 16;
+        let mut db = MockDB::<Leaf>::new();
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        let mut index = RootIndex::new();
+        index.commit(&tree);
+        assert_eq!(index.num_nodes(), 0);
+
+        let mut roots = vec![];
+        for i in 0..4u128 {
+            let leaf_index = LeafIndex::new(i, height).unwrap();
+            tree.update_leaf(&mut db, leaf_index, (i as u32 + 1).hash())
+                .unwrap();
+            index.commit(&tree);
+            roots.push((tree.get_root(), tree.prove(leaf_index).unwrap()));
+        }
+        // each commit adds the changed path only
+        assert_eq!(index.num_nodes(), 4 * height);
+
+        for (i, (root, proof)) in roots.iter().enumerate() {
+            let leaf_index = LeafIndex::new(i as u128, height).unwrap();
+            assert_eq!(index.prove(&tree, *root, leaf_index).as_ref(), Some(proof));
+        }
+
+        for (root, _) in &roots[..3] {
+            assert!(index.remove(*root));
+        }
+        assert!(!index.remove(roots[0].0));
+        assert_eq!(index.len(), 2);
+        // the path of the last root, and the node above leaves 0 and 1
+        assert_eq!(index.num_nodes(), height + 1);
+        let leaf_index = LeafIndex::new(3, height).unwrap();
+        assert_eq!(
+            index.prove(&tree, tree.get_root(), leaf_index).as_ref(),
+            Some(&roots[3].1)
+        );
+    }
+}
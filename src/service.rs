@@ -0,0 +1,331 @@
+use std::sync::Mutex;
+
+#[cfg(feature = "zkp")]
+use intmax2_zkp::utils::poseidon_hash_out::PoseidonHashOut;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    batch_hasher::BatchHasher,
+    bn254_poseidon_hasher::Fr,
+    error::{DbTreeError, ProofError},
+    leaf_index::LeafIndex,
+    merkle_tree::{MerkleProof, MerkleTree},
+    node_key::NodeKey,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+// Transport independent core of the proof servers: UpdateLeaf, GetRoot,
+// GetProof and VerifyProof on one tree and its store behind a lock, so that
+// a proof and the root it verifies against are always read together.
+pub struct ProofService<V: Leafable, S: NodeStore<V>> {
+    state: Mutex<(MerkleTree<V>, S)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofResponse<H> {
+    pub root: H,
+    pub siblings: Vec<H>,
+}
+
+// Binary encoding: the number of siblings (u32 LE), then the root and every
+// sibling as a `WireHash` prefixed with its length (u32 LE).
+impl<H: WireHash> ProofResponse<H> {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = (self.siblings.len() as u32).to_le_bytes().to_vec();
+        for hash in std::iter::once(&self.root).chain(&self.siblings) {
+            let wire = hash.to_wire();
+            bytes.extend((wire.len() as u32).to_le_bytes());
+            bytes.extend(wire);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Option<Self> {
+        let mut take = |n: usize| {
+            let (head, tail) = bytes.split_at_checked(n)?;
+            bytes = tail;
+            Some(head)
+        };
+        let num_siblings = u32::from_le_bytes(take(4)?.try_into().unwrap());
+        let mut hashes = vec![];
+        for _ in 0..=num_siblings {
+            let len = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+            hashes.push(H::from_wire(take(len)?)?);
+        }
+        if !bytes.is_empty() {
+            return None;
+        }
+        let root = hashes.remove(0);
+        Some(Self {
+            root,
+            siblings: hashes,
+        })
+    }
+}
+
+impl<V: Leafable, S: NodeStore<V>> ProofService<V, S> {
+    pub fn new(tree: MerkleTree<V>, db: S) -> Self {
+        Self {
+            state: Mutex::new((tree, db)),
+        }
+    }
+
+    pub fn into_inner(self) -> (MerkleTree<V>, S) {
+        self.state
+            .into_inner()
+            .expect("proof service lock poisoned")
+    }
+
+    // Returns the new root.
+    pub fn update_leaf(
+        &self,
+        index: u128,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Result<<V::Hasher as TreeHasher>::HashOut, DbTreeError> {
+        let mut state = self.state.lock().expect("proof service lock poisoned");
+        let (tree, db) = &mut *state;
+        let index = LeafIndex::new(index, tree.height())?;
+        tree.update_leaf(db, index, leaf_hash)?;
+        Ok(tree.get_root())
+    }
+
+    pub fn get_root(&self) -> <V::Hasher as TreeHasher>::HashOut {
+        let state = self.state.lock().expect("proof service lock poisoned");
+        state.0.get_root()
+    }
+
+    pub fn get_proof(
+        &self,
+        index: u128,
+    ) -> Result<ProofResponse<<V::Hasher as TreeHasher>::HashOut>, DbTreeError> {
+        let state = self.state.lock().expect("proof service lock poisoned");
+        let (tree, db) = &*state;
+        let index = LeafIndex::new(index, tree.height())?;
+        let root = tree.get_root();
+        // in memory unless the tree is compacted and the path not prefetched
+        let proof = match tree.prove(index) {
+            Ok(proof) => proof,
+            Err(_) => tree
+                .prove_with_given_root(db, root.clone(), index)
+                .map_err(|e| store_error(e, index))?,
+        };
+        Ok(ProofResponse {
+            root,
+            siblings: proof.siblings,
+        })
+    }
+
+    // `MerkleTree::prefetch` on the served tree, ahead of a batch of
+    // `get_proof` calls.
+    pub fn prefetch(&self, indices: &[u128]) -> anyhow::Result<usize> {
+        let mut state = self.state.lock().expect("proof service lock poisoned");
+        let (tree, db) = &mut *state;
+        let indices = indices
+            .iter()
+            .map(|&index| LeafIndex::new(index, tree.height()))
+            .collect::<Result<Vec<_>, _>>()?;
+        tree.prefetch(&*db, &indices)
+    }
+
+    // Updates every leaf as one batch, or none if an index is out of range.
+    // Returns the new root.
+    pub fn update_leaves(
+        &self,
+        leaves: &[(u128, <V::Hasher as TreeHasher>::HashOut)],
+    ) -> Result<<V::Hasher as TreeHasher>::HashOut, DbTreeError>
+    where
+        V::Hasher: BatchHasher,
+    {
+        let mut state = self.state.lock().expect("proof service lock poisoned");
+        let (tree, db) = &mut *state;
+        let leaves = leaves
+            .iter()
+            .map(|(index, leaf_hash)| {
+                Ok((LeafIndex::new(*index, tree.height())?, leaf_hash.clone()))
+            })
+            .collect::<Result<Vec<_>, DbTreeError>>()?;
+        tree.update_leaves(db, &leaves)?;
+        Ok(tree.get_root())
+    }
+
+    // Verifies a proof of any height against any root; it does not need to
+    // come from this tree.
+    pub fn verify_proof(
+        &self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+        root: <V::Hasher as TreeHasher>::HashOut,
+        siblings: Vec<<V::Hasher as TreeHasher>::HashOut>,
+    ) -> Result<bool, DbTreeError> {
+        let index = LeafIndex::new(index, siblings.len())?;
+        let proof = MerkleProof::<V> { siblings };
+        Ok(proof.verify_hash(leaf_hash, index, root).is_ok())
+    }
+}
+
+// `prove_with_given_root` errors give the depth of the bad node; the service
+// reports its key.
+fn store_error(error: ProofError, index: LeafIndex) -> DbTreeError {
+    let key = |depth: usize| {
+        let shift = (index.height() - depth) as u32;
+        NodeKey::new(depth, index.index().checked_shr(shift).unwrap_or(0))
+    };
+    match error {
+        ProofError::TruncatedPath { expected, actual } => {
+            DbTreeError::InvalidIndexLength { expected, actual }
+        }
+        ProofError::UnknownRoot => DbTreeError::MissingNode {
+            key: NodeKey::root(),
+        },
+        ProofError::MissingNode { depth } => DbTreeError::MissingNode { key: key(depth) },
+        ProofError::CorruptNode { depth } => DbTreeError::CorruptNode { key: key(depth) },
+    }
+}
+
+// Byte encoding of hashes for transports without serde, e.g. protobuf
+// `bytes` fields.
+pub trait WireHash: Sized {
+    fn to_wire(&self) -> Vec<u8>;
+
+    // None if `bytes` is not the encoding of a hash
+    fn from_wire(bytes: &[u8]) -> Option<Self>;
+}
+
+impl WireHash for [u8; 32] {
+    fn to_wire(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+
+    fn from_wire(bytes: &[u8]) -> Option<Self> {
+        bytes.try_into().ok()
+    }
+}
+
+// 32 bytes big endian, as Solidity verifiers take field elements
+impl WireHash for Fr {
+    fn to_wire(&self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+
+    fn from_wire(bytes: &[u8]) -> Option<Self> {
+        Fr::from_be_bytes(bytes.try_into().ok()?)
+    }
+}
+
+// `0x` prefixed lower case hex of the wire encoding, as Ethereum tooling
+// expects from JSON APIs.
+pub fn hash_to_hex<H: WireHash>(hash: &H) -> String {
+    let mut hex = String::from("0x");
+    for byte in hash.to_wire() {
+        hex.push_str(&format!("{:02x}", byte));
+    }
+    hex
+}
+
+// Accepts upper and lower case, with or without the `0x` prefix.
+pub fn hash_from_hex<H: WireHash>(hex: &str) -> Option<H> {
+    let hex = hex.strip_prefix("0x").unwrap_or(hex);
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect::<Option<Vec<_>>>()?;
+    H::from_wire(&bytes)
+}
+
+#[cfg(feature = "zkp")]
+const GOLDILOCKS_ORDER: u64 = 0xffff_ffff_0000_0001;
+
+// the 4 field elements as little endian u64s
+#[cfg(feature = "zkp")]
+impl WireHash for PoseidonHashOut {
+    fn to_wire(&self) -> Vec<u8> {
+        self.to_u64_vec()
+            .into_iter()
+            .flat_map(u64::to_le_bytes)
+            .collect()
+    }
+
+    fn from_wire(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != 32 {
+            return None;
+        }
+        let elements: Vec<u64> = bytes
+            .chunks(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        if elements.iter().any(|&x| x >= GOLDILOCKS_ORDER) {
+            return None;
+        }
+        Some(PoseidonHashOut::from_u64_vec(&elements))
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use intmax2_zkp::utils::poseidon_hash_out::PoseidonHashOut;
+
+    use crate::{merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable};
+
+    use super::{hash_from_hex, hash_to_hex, ProofResponse, ProofService, WireHash};
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_proof_service() {
+        let height = 8;
+        let mut db = MockDB::<Leaf>::new();
+        let tree = MerkleTree::new(&mut db, height, Leaf::empty_leaf().hash());
+        let service = ProofService::new(tree, db);
+
+        let root = service.update_leaf(3, 7u32.hash()).unwrap();
+        assert_eq!(service.get_root(), root);
+        assert!(service.update_leaf(256, 7u32.hash()).is_err());
+        // nothing to load while the tree is not compacted
+        assert_eq!(service.prefetch(&[3]).unwrap(), 0);
+        assert!(service.prefetch(&[256]).is_err());
+        let response = service.get_proof(3).unwrap();
+        assert_eq!(response.root, root);
+        assert!(service
+            .verify_proof(7u32.hash(), 3, root, response.siblings.clone())
+            .unwrap());
+        assert!(!service
+            .verify_proof(8u32.hash(), 3, root, response.siblings)
+            .unwrap());
+
+        let leaves = [(4, 1u32.hash()), (5, 2u32.hash())];
+        let root = service.update_leaves(&leaves).unwrap();
+        assert!(service
+            .update_leaves(&[(6, 3u32.hash()), (256, 3u32.hash())])
+            .is_err());
+        assert_eq!(service.get_root(), root);
+
+        let response = service.get_proof(5).unwrap();
+        let bytes = response.to_bytes();
+        assert_eq!(bytes.len(), 4 + 9 * (4 + 32));
+        assert_eq!(ProofResponse::from_bytes(&bytes), Some(response));
+        assert_eq!(
+            ProofResponse::<PoseidonHashOut>::from_bytes(&bytes[1..]),
+            None
+        );
+        assert_eq!(
+            ProofResponse::<PoseidonHashOut>::from_bytes(&[bytes.clone(), vec![0]].concat()),
+            None
+        );
+
+        let (tree, _) = service.into_inner();
+        assert_eq!(tree.get_root(), root);
+
+        let hash = PoseidonHashOut::hash_inputs_u32(&[1, 2]);
+        assert_eq!(PoseidonHashOut::from_wire(&hash.to_wire()), Some(hash));
+        assert_eq!(PoseidonHashOut::from_wire(&[0xff; 32]), None);
+        assert_eq!(<[u8; 32]>::from_wire(&[1; 31]), None);
+        assert_eq!(hash_from_hex(&hash_to_hex(&hash)), Some(hash));
+        assert_eq!(hash_to_hex(&[0xab; 32]).len(), 66);
+        assert_eq!(hash_from_hex(&"AB".repeat(32)), Some([0xab; 32]));
+        assert_eq!(hash_from_hex::<[u8; 32]>("0xabc"), None);
+    }
+}
@@ -0,0 +1,90 @@
+use sha2::{Digest, Sha256};
+
+use crate::{
+    batch_hasher::BatchHasher,
+    domain::TaggedHasher,
+    traits::{HashLeaf, TreeHasher},
+};
+
+// SHA-256 of `left || right`, for trees that are checked by systems without
+// a zk or Ethereum stack, e.g. transparency logs and audit tooling.
+#[derive(Clone, Debug)]
+pub struct Sha256Hasher;
+
+// A leaf given by its SHA-256 hash; the empty leaf is 32 zero bytes.
+pub type Sha256Leaf = HashLeaf<Sha256Hasher>;
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+impl TreeHasher for Sha256Hasher {
+    type HashOut = [u8; 32];
+
+    fn two_to_one(left: [u8; 32], right: [u8; 32]) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(left);
+        hasher.update(right);
+        hasher.finalize().into()
+    }
+}
+
+impl BatchHasher for Sha256Hasher {}
+
+// SHA-256 of `tag || left || right`; the tags are the RFC 6962 prefixes
+impl TaggedHasher for Sha256Hasher {
+    fn hash_tagged(tag: u8, left: [u8; 32], right: [u8; 32]) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update([tag]);
+        hasher.update(left);
+        hasher.update(right);
+        hasher.finalize().into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        merkle_tree::MerkleTree,
+        mock_db::MockDB,
+        testkit,
+        traits::{HashLeaf, Leafable},
+    };
+
+    use super::{sha256, Sha256Hasher, Sha256Leaf};
+
+    #[test]
+    fn test_sha256_tree() {
+        assert_eq!(
+            sha256(b"abc")[..4],
+            [0xba, 0x78, 0x16, 0xbf],
+            "sha256 of \"abc\""
+        );
+
+        let height = 3;
+        let mut mock_db = MockDB::<Sha256Leaf>::new();
+        let empty_leaf_hash = Sha256Leaf::empty_leaf().hash();
+        let mut merkle_tree = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+        let leaf: Sha256Leaf = HashLeaf(sha256(b"entry"));
+        merkle_tree
+            .update_leaf_at(&mut mock_db, 5, leaf.hash())
+            .unwrap();
+
+        // walk up from leaf 5 (binary 101) next to empty subtrees
+        let concat = |l: [u8; 32], r: [u8; 32]| sha256(&[l, r].concat());
+        let zero1 = concat([0; 32], [0; 32]);
+        let zero2 = concat(zero1, zero1);
+        let expected = concat(zero2, concat(concat([0; 32], leaf.0), zero1));
+        assert_eq!(merkle_tree.get_root(), expected);
+        merkle_tree
+            .prove_at(5)
+            .unwrap()
+            .verify_at(&leaf, 5, expected)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_sha256_conformance() {
+        testkit::check_hasher::<Sha256Hasher>(|i| sha256(&i.to_le_bytes()));
+    }
+}
@@ -0,0 +1,263 @@
+use std::{
+    sync::mpsc,
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    batch_hasher::BatchHasher,
+    concurrent::split_shard_index,
+    error::DbTreeError,
+    leaf_index::LeafIndex,
+    merkle_tree::{MerkleProof, MerkleTree},
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+    zero_hashes::ZeroHashes,
+};
+
+// Indices sent to a worker are already local to its shard.
+enum Job<V: Leafable> {
+    Update {
+        leaves: Vec<(LeafIndex, <V::Hasher as TreeHasher>::HashOut)>,
+        reply: mpsc::Sender<<V::Hasher as TreeHasher>::HashOut>,
+    },
+    Prove {
+        index: LeafIndex,
+        reply: mpsc::Sender<MerkleProof<V>>,
+    },
+}
+
+struct Worker<V: Leafable, S> {
+    jobs: mpsc::Sender<Job<V>>,
+    handle: JoinHandle<(MerkleTree<V>, S)>,
+}
+
+// A `MerkleTree` split at `shard_depth` into `2^shard_depth` subtrees, each
+// owned by a worker thread together with its store, under a small top tree
+// whose leaves are the subtree roots. A batch is cut by shard and the shards
+// are updated at the same time on their workers, so sustained update loads
+// use one core per shard; only the top tree is updated on the calling
+// thread. Unlike `ConcurrentMerkleTree` there are no locks: the tree has a
+// single owner and every shard is only ever touched by its worker.
+pub struct ShardedMerkleTree<V: Leafable, S: NodeStore<V>> {
+    height: usize,
+    shard_depth: usize,
+    workers: Vec<Worker<V, S>>,
+    top: MerkleTree<V>,
+    top_db: S,
+}
+
+impl<V, S> ShardedMerkleTree<V, S>
+where
+    V: Leafable + Send + 'static,
+    V::Hasher: BatchHasher,
+    <V::Hasher as TreeHasher>::HashOut: Send,
+    S: NodeStore<V> + Send + 'static,
+{
+    // Starts one worker per shard. `new_store` is called once per shard and
+    // once for the top tree.
+    pub fn new(
+        height: usize,
+        shard_depth: usize,
+        empty_leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        mut new_store: impl FnMut() -> S,
+    ) -> Self {
+        assert!(shard_depth <= height, "shard depth larger than height");
+        assert!(shard_depth < 32, "too many shards");
+        let shard_height = height - shard_depth;
+        let zero_hashes = ZeroHashes::<V::Hasher>::new(empty_leaf_hash, height);
+        let workers = (0..1usize << shard_depth)
+            .map(|_| {
+                let mut db = new_store();
+                let tree = MerkleTree::with_zero_hashes(&mut db, shard_height, &zero_hashes);
+                spawn_worker(tree, db)
+            })
+            .collect();
+        let mut top_db = new_store();
+        let top = MerkleTree::new(&mut top_db, shard_depth, zero_hashes.get(shard_height));
+        Self {
+            height,
+            shard_depth,
+            workers,
+            top,
+            top_db,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn num_shards(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn get_root(&self) -> <V::Hasher as TreeHasher>::HashOut {
+        self.top.get_root()
+    }
+
+    // Returns the new root.
+    pub fn update_leaf(
+        &mut self,
+        index: impl Into<LeafIndex>,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Result<<V::Hasher as TreeHasher>::HashOut, DbTreeError> {
+        self.update_leaves(&[(index.into(), leaf_hash)])
+    }
+
+    // Updates every shard of the batch in parallel, then the top tree, and
+    // returns the new root. If an index appears more than once, the last leaf
+    // hash wins; an invalid index fails the whole batch before any shard is
+    // touched.
+    pub fn update_leaves(
+        &mut self,
+        leaves: &[(LeafIndex, <V::Hasher as TreeHasher>::HashOut)],
+    ) -> Result<<V::Hasher as TreeHasher>::HashOut, DbTreeError> {
+        let mut by_shard = vec![vec![]; self.workers.len()];
+        for (index, leaf_hash) in leaves {
+            let (shard, local) = split_shard_index(*index, self.height, self.shard_depth)?;
+            by_shard[shard].push((local, leaf_hash.clone()));
+        }
+        let pending: Vec<_> = by_shard
+            .into_iter()
+            .enumerate()
+            .filter(|(_, leaves)| !leaves.is_empty())
+            .map(|(shard, leaves)| {
+                let (reply, response) = mpsc::channel();
+                self.send(shard, Job::Update { leaves, reply });
+                (shard, response)
+            })
+            .collect();
+        let mut shard_roots = Vec::with_capacity(pending.len());
+        for (shard, response) in pending {
+            let root = response.recv().expect("shard worker panicked");
+            let top_index = LeafIndex::new(shard as u128, self.shard_depth).unwrap();
+            shard_roots.push((top_index, root));
+        }
+        if !shard_roots.is_empty() {
+            self.top
+                .update_leaves_unchecked(&mut self.top_db, &shard_roots);
+        }
+        Ok(self.top.get_root())
+    }
+
+    pub fn prove(&self, index: impl Into<LeafIndex>) -> Result<MerkleProof<V>, DbTreeError> {
+        let (shard, local) = split_shard_index(index.into(), self.height, self.shard_depth)?;
+        let (reply, response) = mpsc::channel();
+        self.send(
+            shard,
+            Job::Prove {
+                index: local,
+                reply,
+            },
+        );
+        let mut siblings = response.recv().expect("shard worker panicked").siblings;
+        let top_index = LeafIndex::new(shard as u128, self.shard_depth)?;
+        siblings.extend(self.top.prove(top_index)?.siblings);
+        Ok(MerkleProof { siblings })
+    }
+
+    // Stops the workers and returns the shards in index order.
+    pub fn into_shards(self) -> Vec<(MerkleTree<V>, S)> {
+        self.workers
+            .into_iter()
+            .map(|worker| {
+                drop(worker.jobs);
+                worker.handle.join().expect("shard worker panicked")
+            })
+            .collect()
+    }
+
+    fn send(&self, shard: usize, job: Job<V>) {
+        self.workers[shard]
+            .jobs
+            .send(job)
+            .expect("shard worker panicked");
+    }
+}
+
+// The worker stops once the sender is dropped and hands the shard back
+// through its `JoinHandle`.
+fn spawn_worker<V, S>(mut tree: MerkleTree<V>, mut db: S) -> Worker<V, S>
+where
+    V: Leafable + Send + 'static,
+    V::Hasher: BatchHasher,
+    <V::Hasher as TreeHasher>::HashOut: Send,
+    S: NodeStore<V> + Send + 'static,
+{
+    let (jobs, queue) = mpsc::channel::<Job<V>>();
+    let handle = thread::spawn(move || {
+        for job in queue {
+            match job {
+                Job::Update { leaves, reply } => {
+                    tree.update_leaves_unchecked(&mut db, &leaves);
+                    let _ = reply.send(tree.get_root());
+                }
+                Job::Prove { index, reply } => {
+                    let proof = tree.prove(index).expect("index is local to the shard");
+                    let _ = reply.send(proof);
+                }
+            }
+        }
+        (tree, db)
+    });
+    Worker { jobs, handle }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        error::DbTreeError, leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB,
+        traits::Leafable,
+    };
+
+    use super::ShardedMerkleTree;
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_sharded_tree() {
+        let height = 10;
+        let empty_leaf_hash = Leaf::empty_leaf().hash();
+        let mut tree = ShardedMerkleTree::<Leaf, _>::new(height, 3, empty_leaf_hash, MockDB::new);
+        assert_eq!(tree.num_shards(), 8);
+        let index = move |i: u32| LeafIndex::new(i as u128 * 37 % 1024, height).unwrap();
+
+        let mut db = MockDB::<Leaf>::new();
+        let mut expected = MerkleTree::new(&mut db, height, empty_leaf_hash);
+        assert_eq!(tree.get_root(), expected.get_root());
+        for round in 0..5u32 {
+            let leaves: Vec<_> = (0..100u32)
+                .map(|i| (index(i + round * 30), (i * round + 1).hash()))
+                .collect();
+            let root = tree.update_leaves(&leaves).unwrap();
+            expected.update_leaves(&mut db, &leaves).unwrap();
+            assert_eq!(root, expected.get_root());
+        }
+        let root = tree.update_leaf(index(7), 99u32.hash()).unwrap();
+        expected
+            .update_leaf(&mut db, index(7), 99u32.hash())
+            .unwrap();
+        assert_eq!(root, expected.get_root());
+
+        let proof = tree.prove(index(7)).unwrap();
+        assert_eq!(proof, expected.prove(index(7)).unwrap());
+        proof.verify(&99u32, index(7), root).unwrap();
+
+        let bad = LeafIndex::new(1, height - 1).unwrap();
+        assert_eq!(
+            tree.update_leaves(&[(index(1), 5u32.hash()), (bad, 5u32.hash())]),
+            Err(DbTreeError::InvalidIndexLength {
+                expected: height,
+                actual: height - 1
+            })
+        );
+        assert_eq!(tree.get_root(), root);
+
+        let shards = tree.into_shards();
+        assert_eq!(shards.len(), 8);
+        assert_eq!(
+            shards.iter().map(|(tree, _)| tree.len()).sum::<u128>(),
+            expected.len()
+        );
+    }
+}
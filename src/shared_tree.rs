@@ -0,0 +1,199 @@
+use std::sync::{Arc, RwLock};
+
+use crate::{
+    batch_hasher::BatchHasher,
+    error::DbTreeError,
+    leaf_index::LeafIndex,
+    merkle_tree::{MerkleProof, MerkleTree},
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+type Shared<V, S> = Arc<RwLock<(MerkleTree<V>, S)>>;
+
+// The single writer of a tree split by `MerkleTree::into_shared`. Updates are
+// staged in the writer and applied together by `commit`, so readers only
+// ever see committed roots.
+pub struct SharedTreeWriter<V: Leafable, S: NodeStore<V>> {
+    shared: Shared<V, S>,
+    height: usize,
+    pending: Vec<(LeafIndex, <V::Hasher as TreeHasher>::HashOut)>,
+}
+
+// A cheap to clone handle that proves against the latest committed root.
+// Readers share one lock with the writer and only exclude it for the length
+// of a single proof.
+pub struct SharedTreeReader<V: Leafable, S: NodeStore<V>> {
+    shared: Shared<V, S>,
+}
+
+impl<V: Leafable, S: NodeStore<V>> Clone for SharedTreeReader<V, S> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<V: Leafable> MerkleTree<V> {
+    pub fn into_shared<S: NodeStore<V>>(
+        self,
+        db: S,
+    ) -> (SharedTreeWriter<V, S>, SharedTreeReader<V, S>) {
+        let height = self.height();
+        let shared = Arc::new(RwLock::new((self, db)));
+        let writer = SharedTreeWriter {
+            shared: shared.clone(),
+            height,
+            pending: vec![],
+        };
+        (writer, SharedTreeReader { shared })
+    }
+}
+
+impl<V: Leafable, S: NodeStore<V>> SharedTreeWriter<V, S> {
+    pub fn reader(&self) -> SharedTreeReader<V, S> {
+        SharedTreeReader {
+            shared: self.shared.clone(),
+        }
+    }
+
+    // Stages an update; later updates of the same index win.
+    pub fn update_leaf(
+        &mut self,
+        index: impl Into<LeafIndex>,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Result<(), DbTreeError> {
+        let index = index.into();
+        if index.height() != self.height {
+            return Err(DbTreeError::InvalidIndexLength {
+                expected: self.height,
+                actual: index.height(),
+            });
+        }
+        self.pending.push((index, leaf_hash));
+        Ok(())
+    }
+
+    pub fn num_pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn discard(&mut self) {
+        self.pending.clear();
+    }
+
+    // Applies the staged updates in one batch under the write lock and
+    // returns the new committed root.
+    pub fn commit(&mut self) -> <V::Hasher as TreeHasher>::HashOut
+    where
+        V::Hasher: BatchHasher,
+    {
+        let mut state = self.shared.write().expect("shared tree lock poisoned");
+        let (tree, db) = &mut *state;
+        if !self.pending.is_empty() {
+            tree.update_leaves_unchecked(db, &self.pending);
+            self.pending.clear();
+        }
+        tree.get_root()
+    }
+
+    // Commits and takes the tree back once all readers are dropped, or
+    // returns the writer unchanged if a reader is still alive.
+    pub fn try_into_inner(mut self) -> Result<(MerkleTree<V>, S), Self>
+    where
+        V::Hasher: BatchHasher,
+    {
+        self.commit();
+        let height = self.height;
+        Arc::try_unwrap(self.shared)
+            .map(|lock| lock.into_inner().expect("shared tree lock poisoned"))
+            .map_err(|shared| Self {
+                shared,
+                height,
+                pending: vec![],
+            })
+    }
+}
+
+impl<V: Leafable, S: NodeStore<V>> SharedTreeReader<V, S> {
+    pub fn get_root(&self) -> <V::Hasher as TreeHasher>::HashOut {
+        self.shared
+            .read()
+            .expect("shared tree lock poisoned")
+            .0
+            .get_root()
+    }
+
+    // A proof of `index` together with the committed root it verifies
+    // against.
+    pub fn prove(
+        &self,
+        index: impl Into<LeafIndex>,
+    ) -> Result<(MerkleProof<V>, <V::Hasher as TreeHasher>::HashOut), DbTreeError> {
+        let state = self.shared.read().expect("shared tree lock poisoned");
+        let (tree, db) = &*state;
+        let index = index.into();
+        tree.check_leaf_index(index)?;
+        let root = tree.get_root();
+        let proof = tree
+            .prove_with_given_root(db, root.clone(), index)
+            .expect("the current root is always in the store");
+        Ok((proof, root))
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use std::thread;
+
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable,
+    };
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_shared_tree() {
+        let height = 8;
+        let mut db = MockDB::<Leaf>::new();
+        let tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        let empty_root = tree.get_root();
+        let (mut writer, reader) = tree.into_shared(db);
+        let index = move |i: u128| LeafIndex::new(i, height).unwrap();
+
+        writer.update_leaf(index(1), 5u32.hash()).unwrap();
+        writer.update_leaf(index(2), 6u32.hash()).unwrap();
+        assert_eq!(
+            reader.get_root(),
+            empty_root,
+            "staged updates are not visible"
+        );
+        let root = writer.commit();
+        assert_eq!(reader.get_root(), root);
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let reader = reader.clone();
+                thread::spawn(move || {
+                    for _ in 0..20 {
+                        let (proof, root) = reader.prove(index(2)).unwrap();
+                        proof.verify(&6u32, index(2), root).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for i in 10..30 {
+            writer.update_leaf(index(i), (i as u32).hash()).unwrap();
+            writer.commit();
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let writer = writer.try_into_inner().err().unwrap();
+        drop(reader);
+        let (tree, _db) = writer.try_into_inner().ok().unwrap();
+        assert_eq!(tree.len(), 22);
+    }
+}
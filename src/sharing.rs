@@ -0,0 +1,136 @@
+use hashbrown::HashMap;
+
+use crate::{
+    merkle_tree::MerkleTree,
+    node_store::NodeStore,
+    ref_counted_db::RefCountedDB,
+    traits::{Leafable, TreeHasher},
+    versioned_tree::VersionedMerkleTree,
+};
+
+// How the store nodes of several roots are shared, to estimate what keeping
+// N versions really costs: `total` is what the store holds for all of them,
+// against the sum of `reachable` if each version were stored on its own.
+// Leaves and stored zero nodes are not counted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SharingReport {
+    // distinct nodes reachable from each root, in the order of the roots
+    pub reachable: Vec<usize>,
+    // nodes reachable from this root and no other one
+    pub unique: Vec<usize>,
+    // distinct nodes reachable from two or more roots
+    pub shared: usize,
+    // distinct nodes reachable from any root
+    pub total: usize,
+}
+
+impl SharingReport {
+    // Nodes the roots would take stored separately, per node they take in a
+    // shared store; 1.0 when nothing is shared.
+    pub fn sharing_ratio(&self) -> f64 {
+        if self.total == 0 {
+            return 1.0;
+        }
+        self.reachable.iter().sum::<usize>() as f64 / self.total as f64
+    }
+}
+
+impl<V: Leafable> MerkleTree<V> {
+    // Walks every root through `db`, a store read per distinct node of each
+    // root. Fails if a node is missing from the store.
+    pub fn sharing_report<S: NodeStore<V>>(
+        &self,
+        db: &S,
+        roots: &[<V::Hasher as TreeHasher>::HashOut],
+    ) -> anyhow::Result<SharingReport> {
+        // the roots reaching each node, by their position in `roots`
+        let mut owners: HashMap<_, Vec<usize>> = HashMap::new();
+        let mut reachable = vec![0; roots.len()];
+        for (i, root) in roots.iter().enumerate() {
+            for visit in self.walk_depth_first(db, root.clone()) {
+                owners.entry(visit?.hash).or_default().push(i);
+                reachable[i] += 1;
+            }
+        }
+        let mut unique = vec![0; roots.len()];
+        let mut shared = 0;
+        for owners in owners.values() {
+            match owners.as_slice() {
+                [owner] => unique[*owner] += 1,
+                _ => shared += 1,
+            }
+        }
+        Ok(SharingReport {
+            reachable,
+            unique,
+            shared,
+            total: owners.len(),
+        })
+    }
+}
+
+impl<V: Leafable> VersionedMerkleTree<V> {
+    // `MerkleTree::sharing_report` over the retained versions, oldest first,
+    // then the pinned ones that are no longer retained.
+    pub fn sharing_report<S: NodeStore<V>>(
+        &self,
+        db: &RefCountedDB<V, S>,
+    ) -> anyhow::Result<SharingReport> {
+        let mut roots: Vec<_> = self
+            .versions()
+            .map(|version| version.root.clone())
+            .collect();
+        for version in self.pinned() {
+            if !roots.contains(&version.root) {
+                roots.push(version.root.clone());
+            }
+        }
+        self.tree().sharing_report(db, &roots)
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        leaf_index::LeafIndex,
+        mock_db::MockDB,
+        ref_counted_db::RefCountedDB,
+        traits::Leafable,
+        versioned_tree::{RetentionPolicy, VersionedMerkleTree},
+    };
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_sharing_report() {
+        let height = 4;
+        let mut db = RefCountedDB::new(MockDB::<Leaf>::new());
+        let mut tree = VersionedMerkleTree::<Leaf>::new(
+            &mut db,
+            height,
+            Leaf::empty_leaf().hash(),
+            RetentionPolicy::KeepAll,
+        );
+        let index = |i: u128| LeafIndex::new(i, height).unwrap();
+        tree.update_leaf(&mut db, index(0), 1u32.hash()).unwrap();
+        tree.update_leaf(&mut db, index(15), 2u32.hash()).unwrap();
+        tree.commit(&mut db, 0);
+        // rewrites the path of leaf 15 only
+        tree.update_leaf(&mut db, index(15), 3u32.hash()).unwrap();
+        tree.commit(&mut db, 1);
+
+        let report = tree.sharing_report(&db).unwrap();
+        // 4 nodes on each path, the root being on both
+        assert_eq!(report.reachable, vec![7, 7]);
+        assert_eq!(report.unique, vec![4, 4]);
+        assert_eq!(report.shared, 3);
+        assert_eq!(report.total, 11);
+        assert!((report.sharing_ratio() - 14.0 / 11.0).abs() < 1e-9);
+
+        // a root reported twice shares everything with itself
+        let root = tree.tree().get_root();
+        let report = tree.tree().sharing_report(&db, &[root, root]).unwrap();
+        assert_eq!(report.unique, vec![0, 0]);
+        assert_eq!(report.shared, 7);
+    }
+}
@@ -0,0 +1,465 @@
+use std::sync::{Arc, RwLock};
+
+use crate::{
+    error::{CommitError, DbTreeError},
+    leaf_index::LeafIndex,
+    merkle_tree::MerkleProof,
+    traits::{Leafable, TreeHasher},
+    zero_hashes::ZeroHashes,
+};
+
+// Node of a persistent tree. Nodes are never modified: an update copies the
+// path from the leaf to the root and shares every other subtree with the
+// previous version. Leaves and empty subtrees have no children.
+#[derive(Debug)]
+struct SnapshotNode<H> {
+    hash: H,
+    children: Option<(NodeRef<H>, NodeRef<H>)>,
+}
+
+type NodeRef<H> = Arc<SnapshotNode<H>>;
+
+// An immutable version of a `SnapshotTree`. Reading a snapshot once it is
+// handed out takes no lock, so a long proof run over it never blocks the
+// writer, and it always sees whole batches. Nodes that only old snapshots use
+// are freed when the last snapshot holding them is dropped.
+#[derive(Debug)]
+pub struct Snapshot<V: Leafable> {
+    version: u64,
+    height: usize,
+    root: NodeRef<<V::Hasher as TreeHasher>::HashOut>,
+    // empty[h] is the empty subtree of height h, shared by all versions
+    empty: Arc<Vec<NodeRef<<V::Hasher as TreeHasher>::HashOut>>>,
+}
+
+// A tree whose writer publishes a new `Snapshot` per batch. The latest
+// snapshot sits behind a `RwLock`: readers and producers take its read lock
+// only to clone the `Arc`, so they do not wait for each other, and a commit
+// takes the write lock only to compare the root and swap in the new
+// snapshot. Getting the latest snapshot can therefore wait for a commit,
+// but never for hashing, which producers do on their own.
+pub struct SnapshotTree<V: Leafable> {
+    current: Arc<RwLock<Arc<Snapshot<V>>>>,
+}
+
+// Cheap to clone handle that hands out the latest snapshot.
+pub struct SnapshotReader<V: Leafable> {
+    current: Arc<RwLock<Arc<Snapshot<V>>>>,
+}
+
+// Cheap to clone handle for producers that prepare batches concurrently and
+// commit them with a compare-and-swap of the root, so that none of them holds
+// a lock while hashing.
+pub struct SnapshotProducer<V: Leafable> {
+    current: Arc<RwLock<Arc<Snapshot<V>>>>,
+}
+
+// A batch applied to a snapshot but not published yet.
+pub struct PreparedBatch<V: Leafable> {
+    base_root: <V::Hasher as TreeHasher>::HashOut,
+    root: NodeRef<<V::Hasher as TreeHasher>::HashOut>,
+}
+
+impl<V: Leafable> Clone for SnapshotProducer<V> {
+    fn clone(&self) -> Self {
+        Self {
+            current: self.current.clone(),
+        }
+    }
+}
+
+impl<V: Leafable> Clone for SnapshotReader<V> {
+    fn clone(&self) -> Self {
+        Self {
+            current: self.current.clone(),
+        }
+    }
+}
+
+impl<V: Leafable> Snapshot<V> {
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get_root(&self) -> <V::Hasher as TreeHasher>::HashOut {
+        self.root.hash.clone()
+    }
+
+    pub fn get_leaf_hash(
+        &self,
+        index: impl Into<LeafIndex>,
+    ) -> Result<<V::Hasher as TreeHasher>::HashOut, DbTreeError> {
+        let (leaf, _) = self.walk(index.into())?;
+        Ok(leaf)
+    }
+
+    pub fn prove(&self, index: impl Into<LeafIndex>) -> Result<MerkleProof<V>, DbTreeError> {
+        let (_, proof) = self.walk(index.into())?;
+        Ok(proof)
+    }
+
+    // Applies the batch to a copy of this snapshot without publishing it. If
+    // an index appears more than once, the last leaf hash wins; an invalid
+    // index fails the whole batch.
+    pub fn prepare(
+        &self,
+        leaves: &[(LeafIndex, <V::Hasher as TreeHasher>::HashOut)],
+    ) -> Result<PreparedBatch<V>, DbTreeError> {
+        let mut sorted = Vec::with_capacity(leaves.len());
+        for (i, (index, leaf_hash)) in leaves.iter().enumerate() {
+            if index.height() != self.height {
+                return Err(DbTreeError::InvalidIndexLength {
+                    expected: self.height,
+                    actual: index.height(),
+                });
+            }
+            sorted.push((index.index(), i, leaf_hash.clone()));
+        }
+        sorted.sort_by_key(|&(index, i, _)| (index, std::cmp::Reverse(i)));
+        sorted.dedup_by_key(|(index, _, _)| *index);
+        let sorted: Vec<_> = sorted
+            .into_iter()
+            .map(|(index, _, leaf_hash)| (index, leaf_hash))
+            .collect();
+
+        let root = if sorted.is_empty() {
+            self.root.clone()
+        } else {
+            update::<V::Hasher>(&self.root, self.height, &sorted, &self.empty)
+        };
+        Ok(PreparedBatch {
+            base_root: self.get_root(),
+            root,
+        })
+    }
+
+    // The leaf hash at `index` and its proof.
+    fn walk(
+        &self,
+        index: LeafIndex,
+    ) -> Result<(<V::Hasher as TreeHasher>::HashOut, MerkleProof<V>), DbTreeError> {
+        if index.height() != self.height {
+            return Err(DbTreeError::InvalidIndexLength {
+                expected: self.height,
+                actual: index.height(),
+            });
+        }
+        let mut node = &self.root;
+        let mut siblings = Vec::with_capacity(self.height);
+        for (h, is_right) in index.to_be_path().into_iter().enumerate() {
+            let child_height = self.height - h - 1;
+            let (left, right) = match &node.children {
+                Some((left, right)) => (left, right),
+                None => (&self.empty[child_height], &self.empty[child_height]),
+            };
+            let (next, sibling) = if is_right {
+                (right, left)
+            } else {
+                (left, right)
+            };
+            siblings.push(sibling.hash.clone());
+            node = next;
+        }
+        siblings.reverse();
+        Ok((node.hash.clone(), MerkleProof { siblings }))
+    }
+}
+
+impl<V: Leafable> SnapshotTree<V> {
+    pub fn new(height: usize, empty_leaf_hash: <V::Hasher as TreeHasher>::HashOut) -> Self {
+        let zero_hashes = ZeroHashes::<V::Hasher>::new(empty_leaf_hash, height);
+        let mut empty: Vec<NodeRef<_>> = Vec::with_capacity(height + 1);
+        for h in 0..=height {
+            empty.push(Arc::new(SnapshotNode {
+                hash: zero_hashes.get(h),
+                children: None,
+            }));
+        }
+        let snapshot = Snapshot {
+            version: 0,
+            height,
+            root: empty[height].clone(),
+            empty: Arc::new(empty),
+        };
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(snapshot))),
+        }
+    }
+
+    pub fn reader(&self) -> SnapshotReader<V> {
+        SnapshotReader {
+            current: self.current.clone(),
+        }
+    }
+
+    pub fn producer(&self) -> SnapshotProducer<V> {
+        SnapshotProducer {
+            current: self.current.clone(),
+        }
+    }
+
+    pub fn snapshot(&self) -> Arc<Snapshot<V>> {
+        self.current.read().expect("snapshot lock poisoned").clone()
+    }
+
+    // Prepares the batch on the latest snapshot and commits it, preparing it
+    // again if a producer committed in between, and returns the published
+    // version. If an index appears more than once, the last leaf hash wins;
+    // an invalid index fails the whole batch. Needs `&mut self` so that
+    // batches of one writer never race.
+    pub fn update_leaves(
+        &mut self,
+        leaves: &[(LeafIndex, <V::Hasher as TreeHasher>::HashOut)],
+    ) -> Result<Arc<Snapshot<V>>, DbTreeError> {
+        loop {
+            let batch = self.snapshot().prepare(leaves)?;
+            if let Ok(snapshot) = commit(&self.current, batch) {
+                return Ok(snapshot);
+            }
+        }
+    }
+
+    pub fn update_leaf(
+        &mut self,
+        index: impl Into<LeafIndex>,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Result<Arc<Snapshot<V>>, DbTreeError> {
+        self.update_leaves(&[(index.into(), leaf_hash)])
+    }
+}
+
+impl<V: Leafable> SnapshotReader<V> {
+    pub fn snapshot(&self) -> Arc<Snapshot<V>> {
+        self.current.read().expect("snapshot lock poisoned").clone()
+    }
+}
+
+impl<V: Leafable> PreparedBatch<V> {
+    // Root of the snapshot the batch was prepared on.
+    pub fn base_root(&self) -> <V::Hasher as TreeHasher>::HashOut {
+        self.base_root.clone()
+    }
+
+    pub fn root(&self) -> <V::Hasher as TreeHasher>::HashOut {
+        self.root.hash.clone()
+    }
+}
+
+impl<V: Leafable> SnapshotProducer<V> {
+    pub fn snapshot(&self) -> Arc<Snapshot<V>> {
+        self.current.read().expect("snapshot lock poisoned").clone()
+    }
+
+    // Publishes the batch as the next version if the latest snapshot still
+    // has the root the batch was prepared on. Otherwise the batch is dropped
+    // and has to be prepared again.
+    pub fn commit(
+        &self,
+        batch: PreparedBatch<V>,
+    ) -> Result<Arc<Snapshot<V>>, CommitError<<V::Hasher as TreeHasher>::HashOut>> {
+        commit(&self.current, batch)
+    }
+}
+
+fn commit<V: Leafable>(
+    current: &RwLock<Arc<Snapshot<V>>>,
+    batch: PreparedBatch<V>,
+) -> Result<Arc<Snapshot<V>>, CommitError<<V::Hasher as TreeHasher>::HashOut>> {
+    let mut current = current.write().expect("snapshot lock poisoned");
+    if current.root.hash != batch.base_root {
+        return Err(CommitError::RootMoved {
+            expected: batch.base_root,
+            actual: current.get_root(),
+        });
+    }
+    let snapshot = Arc::new(Snapshot {
+        version: current.version + 1,
+        height: current.height,
+        root: batch.root,
+        empty: current.empty.clone(),
+    });
+    *current = snapshot.clone();
+    Ok(snapshot)
+}
+
+// Copies the paths to `leaves` (sorted, unique, indices local to the subtree
+// of `height` at `node`). Subtrees that become empty are replaced by the
+// shared empty node.
+fn update<H: TreeHasher>(
+    node: &NodeRef<H::HashOut>,
+    height: usize,
+    leaves: &[(u128, H::HashOut)],
+    empty: &[NodeRef<H::HashOut>],
+) -> NodeRef<H::HashOut> {
+    if height == 0 {
+        let hash = leaves.last().unwrap().1.clone();
+        if hash == empty[0].hash {
+            return empty[0].clone();
+        }
+        return Arc::new(SnapshotNode {
+            hash,
+            children: None,
+        });
+    }
+    let (left, right) = match &node.children {
+        Some((left, right)) => (left.clone(), right.clone()),
+        None => (empty[height - 1].clone(), empty[height - 1].clone()),
+    };
+    let half = 1u128 << (height - 1);
+    let split = leaves.partition_point(|&(index, _)| index < half);
+    let (to_left, to_right) = leaves.split_at(split);
+    let left = if to_left.is_empty() {
+        left
+    } else {
+        update::<H>(&left, height - 1, to_left, empty)
+    };
+    let right = if to_right.is_empty() {
+        right
+    } else {
+        let to_right: Vec<_> = to_right
+            .iter()
+            .map(|(index, hash)| (index - half, hash.clone()))
+            .collect();
+        update::<H>(&right, height - 1, &to_right, empty)
+    };
+    let hash = H::two_to_one(left.hash.clone(), right.hash.clone());
+    if hash == empty[height].hash {
+        return empty[height].clone();
+    }
+    Arc::new(SnapshotNode {
+        hash,
+        children: Some((left, right)),
+    })
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use std::thread;
+
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable,
+    };
+
+    use crate::error::CommitError;
+
+    use super::SnapshotTree;
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_snapshot_reads() {
+        let height = 8;
+        let empty_leaf_hash = Leaf::empty_leaf().hash();
+        let mut tree = SnapshotTree::<Leaf>::new(height, empty_leaf_hash);
+        let index = move |i: u128| LeafIndex::new(i, height).unwrap();
+
+        let mut db = MockDB::<Leaf>::new();
+        let mut expected = MerkleTree::<Leaf>::new(&mut db, height, empty_leaf_hash);
+        assert_eq!(tree.snapshot().get_root(), expected.get_root());
+
+        let leaves: Vec<_> = (0..50)
+            .map(|i| (index(i * 5), (i as u32 + 1).hash()))
+            .collect();
+        let first = tree.update_leaves(&leaves).unwrap();
+        expected.update_leaves(&mut db, &leaves).unwrap();
+        assert_eq!(first.get_root(), expected.get_root());
+        assert_eq!(first.version(), 1);
+
+        // a reader keeps proving against its snapshot while batches land
+        let reader = tree.reader();
+        let old = reader.snapshot();
+        let handle = thread::spawn(move || {
+            for _ in 0..20 {
+                let proof = old.prove(index(10)).unwrap();
+                proof.verify(&3u32, index(10), old.get_root()).unwrap();
+                assert_eq!(old.get_leaf_hash(index(10)).unwrap(), 3u32.hash());
+            }
+        });
+        for i in 0..20u32 {
+            let batch = [(index(10), (i + 100).hash()), (index(11), (i + 200).hash())];
+            let snapshot = tree.update_leaves(&batch).unwrap();
+            expected.update_leaves(&mut db, &batch).unwrap();
+            assert_eq!(snapshot.get_root(), expected.get_root());
+        }
+        handle.join().unwrap();
+
+        let latest = reader.snapshot();
+        assert_eq!(latest.version(), 21);
+        assert_eq!(
+            latest.prove(index(11)).unwrap(),
+            expected.prove(index(11)).unwrap()
+        );
+
+        // clearing every leaf gives back the empty tree
+        let cleared: Vec<_> = (0..256).map(|i| (index(i), empty_leaf_hash)).collect();
+        let snapshot = tree.update_leaves(&cleared).unwrap();
+        assert_eq!(
+            snapshot.get_root(),
+            MerkleTree::<Leaf>::new(&mut db, height, empty_leaf_hash).get_root()
+        );
+    }
+
+    #[test]
+    fn test_compare_and_swap() {
+        let height = 8;
+        let empty_leaf_hash = Leaf::empty_leaf().hash();
+        let mut tree = SnapshotTree::<Leaf>::new(height, empty_leaf_hash);
+        let index = move |i: u128| LeafIndex::new(i, height).unwrap();
+
+        // the second batch prepared on the same snapshot loses the race
+        let producer = tree.producer();
+        let base = producer.snapshot();
+        let first = base.prepare(&[(index(1), 1u32.hash())]).unwrap();
+        let second = base.prepare(&[(index(2), 2u32.hash())]).unwrap();
+        let committed = producer.commit(first).unwrap();
+        assert_eq!(
+            producer.commit(second).err(),
+            Some(CommitError::RootMoved {
+                expected: base.get_root(),
+                actual: committed.get_root(),
+            })
+        );
+        let retried = committed.prepare(&[(index(2), 2u32.hash())]).unwrap();
+        assert_eq!(producer.commit(retried).unwrap().version(), 2);
+
+        // producers retry until each of their batches lands
+        let handles: Vec<_> = (0..4u32)
+            .map(|t| {
+                let producer = producer.clone();
+                thread::spawn(move || {
+                    for i in 0..10u32 {
+                        let leaf = 10 + t * 10 + i;
+                        let batch = [(index(leaf as u128), leaf.hash())];
+                        while producer
+                            .commit(producer.snapshot().prepare(&batch).unwrap())
+                            .is_err()
+                        {}
+                    }
+                })
+            })
+            .collect();
+        for i in 0..10u32 {
+            tree.update_leaf(index(100 + i as u128), i.hash()).unwrap();
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let mut db = MockDB::<Leaf>::new();
+        let mut expected = MerkleTree::<Leaf>::new(&mut db, height, empty_leaf_hash);
+        let leaves: Vec<_> = [(1, 1u32), (2, 2)]
+            .into_iter()
+            .chain((10..50).map(|leaf| (leaf, leaf as u32)))
+            .map(|(i, leaf)| (index(i), leaf.hash()))
+            .chain((0..10u32).map(|i| (index(100 + i as u128), i.hash())))
+            .collect();
+        expected.update_leaves(&mut db, &leaves).unwrap();
+        let latest = tree.snapshot();
+        assert_eq!(latest.get_root(), expected.get_root());
+        assert_eq!(latest.version(), 52);
+    }
+}
@@ -0,0 +1,133 @@
+use std::marker::PhantomData;
+
+use crate::{
+    batch_hasher::BatchHasher,
+    merkle_tree::MerkleProof,
+    traits::{HashLeaf, Leafable, TreeHasher},
+};
+
+// `H` with the two children of every node hashed in sorted order, i.e.
+// `H::two_to_one(min(l, r), max(l, r))`, as in OpenZeppelin's `MerkleProof`
+// and merkletreejs with `sortPairs`. With `Keccak256Hasher` and byte array
+// hashes, whose order is the `bytes32` order of Solidity, proofs can be passed
+// to `MerkleProof.verify` as they are. Roots agree with merkletreejs when all
+// `2^height` leaves are set, since other trees are padded with empty leaves
+// here rather than promoting the odd node.
+#[derive(Clone, Debug)]
+pub struct SortedPairs<H>(PhantomData<H>);
+
+// A leaf of a `SortedPairs` tree given by its hash.
+pub type SortedPairsLeaf<H> = HashLeaf<SortedPairs<H>>;
+
+impl<H: TreeHasher> TreeHasher for SortedPairs<H>
+where
+    H::HashOut: Ord,
+{
+    type HashOut = H::HashOut;
+
+    fn two_to_one(left: H::HashOut, right: H::HashOut) -> H::HashOut {
+        if left <= right {
+            H::two_to_one(left, right)
+        } else {
+            H::two_to_one(right, left)
+        }
+    }
+}
+
+impl<H: BatchHasher> BatchHasher for SortedPairs<H>
+where
+    H::HashOut: Ord,
+{
+    fn two_to_one_many(pairs: &[(H::HashOut, H::HashOut)]) -> Vec<H::HashOut> {
+        let sorted: Vec<_> = pairs
+            .iter()
+            .map(|(left, right)| {
+                if left <= right {
+                    (left.clone(), right.clone())
+                } else {
+                    (right.clone(), left.clone())
+                }
+            })
+            .collect();
+        H::two_to_one_many(&sorted)
+    }
+}
+
+// Sorted pair proofs do not depend on the side of each sibling, so they are
+// checked without the leaf index, like OpenZeppelin's `processProof` and
+// `verify`. `verify` and `verify_hash` keep working and accept any index.
+impl<V, H> MerkleProof<V>
+where
+    V: Leafable<Hasher = SortedPairs<H>>,
+    H: TreeHasher,
+    H::HashOut: Ord,
+{
+    pub fn process_proof(&self, leaf_hash: H::HashOut) -> H::HashOut {
+        self.siblings.iter().fold(leaf_hash, |node, sibling| {
+            SortedPairs::<H>::two_to_one(node, sibling.clone())
+        })
+    }
+
+    pub fn verify_sorted(&self, leaf_hash: H::HashOut, merkle_root: H::HashOut) -> bool {
+        self.process_proof(leaf_hash) == merkle_root
+    }
+}
+
+#[cfg(all(test, feature = "keccak"))]
+mod test {
+    use crate::{
+        batch_hasher::BatchHasher,
+        keccak_hasher::{keccak256, Keccak256Hasher},
+        leaf_index::LeafIndex,
+        merkle_tree::MerkleTree,
+        mock_db::MockDB,
+        traits::{HashLeaf, TreeHasher},
+    };
+
+    use super::{SortedPairs, SortedPairsLeaf};
+
+    type Hasher = SortedPairs<Keccak256Hasher>;
+
+    #[test]
+    fn test_sorted_pairs() {
+        let sorted = |a: [u8; 32], b: [u8; 32]| {
+            let (lo, hi) = if a <= b { (a, b) } else { (b, a) };
+            keccak256(&[lo, hi].concat())
+        };
+        let leaves: Vec<[u8; 32]> = (0..4u8).map(|i| keccak256(&[i])).collect();
+        assert_eq!(
+            Hasher::two_to_one(leaves[0], leaves[1]),
+            Hasher::two_to_one(leaves[1], leaves[0])
+        );
+        let pairs = vec![(leaves[3], leaves[2]), (leaves[0], leaves[1])];
+        assert_eq!(
+            Hasher::two_to_one_many(&pairs),
+            vec![sorted(leaves[3], leaves[2]), sorted(leaves[0], leaves[1])]
+        );
+
+        // a full tree, as merkletreejs builds it with `sortPairs`
+        let height = 2;
+        let mut mock_db = MockDB::<SortedPairsLeaf<Keccak256Hasher>>::new();
+        let tree = MerkleTree::from_leaf_hashes(&mut mock_db, height, [0; 32], &leaves);
+        let root = tree.get_root();
+        assert_eq!(
+            root,
+            sorted(sorted(leaves[0], leaves[1]), sorted(leaves[2], leaves[3]))
+        );
+        for (i, leaf) in leaves.iter().enumerate() {
+            let proof = tree
+                .prove(LeafIndex::new(i as u128, height).unwrap())
+                .unwrap();
+            assert!(proof.verify_sorted(*leaf, root));
+            proof
+                .verify(
+                    &HashLeaf(*leaf),
+                    LeafIndex::new(3 - i as u128, height).unwrap(),
+                    root,
+                )
+                .unwrap();
+        }
+        let proof = tree.prove(LeafIndex::new(0, height).unwrap()).unwrap();
+        assert!(!proof.verify_sorted(leaves[2], root));
+    }
+}
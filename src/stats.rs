@@ -0,0 +1,120 @@
+use hashbrown::HashSet;
+
+use crate::{
+    memory::MemoryStats, merkle_tree::MerkleTree, node_store::NodeStore, traits::Leafable,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TreeStats {
+    pub height: usize,
+    // non-empty nodes per depth on the current root, from the root to the
+    // leaves, so the last entry is the number of non-empty leaves
+    pub nodes_per_level: Vec<u128>,
+    // empty subtrees per depth whose parent is not empty
+    pub zero_subtrees_per_level: Vec<u128>,
+    pub num_leaves: u128,
+    pub memory: MemoryStats,
+    // distinct store nodes reachable from the current root, counting stored
+    // zero nodes as reachable
+    pub reachable_store_nodes: usize,
+}
+
+impl TreeStats {
+    // Fraction of the leaf slots that lie in empty subtrees.
+    pub fn zero_coverage(&self) -> f64 {
+        self.zero_subtrees_per_level
+            .iter()
+            .enumerate()
+            .map(|(depth, &count)| count as f64 / 2f64.powi(depth as i32))
+            .sum()
+    }
+
+    // Store nodes that no longer belong to the current root, e.g. old
+    // versions that were never garbage collected. None if the store cannot
+    // count its nodes.
+    pub fn unreachable_store_nodes(&self) -> Option<usize> {
+        self.memory
+            .store_nodes
+            .map(|nodes| nodes.saturating_sub(self.reachable_store_nodes))
+    }
+}
+
+impl<V: Leafable> MerkleTree<V> {
+    // Walks every non-empty node of the current root through `db`, so it
+    // costs a store read per node. Fails if a node is missing from the store.
+    pub fn stats<S: NodeStore<V>>(&self, db: &S) -> anyhow::Result<TreeStats> {
+        let mut nodes_per_level = vec![0u128; self.height + 1];
+        let mut zero_subtrees_per_level = vec![0u128; self.height + 1];
+        let mut reachable: HashSet<_> = self.zero_hashes[..self.height]
+            .iter()
+            .filter(|&hash| db.contains(hash.clone()))
+            .cloned()
+            .collect();
+        let mut stack = vec![(self.get_root(), 0)];
+        while let Some((hash, depth)) = stack.pop() {
+            if hash == self.zero_hashes[depth] {
+                zero_subtrees_per_level[depth] += 1;
+                continue;
+            }
+            nodes_per_level[depth] += 1;
+            if depth == self.height {
+                continue;
+            }
+            let (left, right) = db
+                .try_with_node(hash.clone(), |node| (node.left.clone(), node.right.clone()))
+                .map_err(|_| anyhow::anyhow!("node at depth {} in the store is corrupted", depth))?
+                .ok_or_else(|| {
+                    anyhow::anyhow!("cannot find node at depth {} in the store", depth)
+                })?;
+            reachable.insert(hash);
+            stack.push((left, depth + 1));
+            stack.push((right, depth + 1));
+        }
+        Ok(TreeStats {
+            height: self.height,
+            nodes_per_level,
+            zero_subtrees_per_level,
+            num_leaves: self.num_leaves,
+            memory: self.memory_stats(db),
+            reachable_store_nodes: reachable.len(),
+        })
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable,
+    };
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_stats() {
+        let height = 4;
+        let mut db = MockDB::<Leaf>::new();
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        let empty = tree.stats(&db).unwrap();
+        assert_eq!(empty.nodes_per_level, vec![0; 5]);
+        assert_eq!(empty.zero_subtrees_per_level, vec![1, 0, 0, 0, 0]);
+        assert_eq!(empty.zero_coverage(), 1.0);
+        assert_eq!(empty.unreachable_store_nodes(), Some(0));
+
+        for i in [0, 5] {
+            tree.update_leaf(&mut db, LeafIndex::new(i, height).unwrap(), 1u32.hash())
+                .unwrap();
+        }
+        let stats = tree.stats(&db).unwrap();
+        assert_eq!(stats.nodes_per_level, vec![1, 1, 2, 2, 2]);
+        assert_eq!(stats.zero_subtrees_per_level, vec![0, 1, 0, 2, 2]);
+        assert_eq!(stats.num_leaves, 2);
+        assert_eq!(stats.zero_coverage(), 14.0 / 16.0);
+        // the path written by the first update is stale
+        assert!(stats.unreachable_store_nodes().unwrap() > 0);
+
+        db.collect_garbage(&[tree.get_root()]);
+        let collected = tree.stats(&db).unwrap();
+        assert_eq!(collected.unreachable_store_nodes(), Some(0));
+        assert_eq!(collected.nodes_per_level, stats.nodes_per_level);
+    }
+}
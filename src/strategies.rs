@@ -0,0 +1,282 @@
+use hashbrown::HashMap;
+use proptest::{collection, prelude::*, test_runner::TestCaseError};
+
+use crate::{
+    batch_hasher::BatchHasher,
+    leaf_index::LeafIndex,
+    merkle_tree::MerkleTree,
+    node_store::NodeStore,
+    reference_tree::ReferenceTree,
+    traits::{HashLeaf, Leafable, TreeHasher},
+};
+
+// Proptest strategies and invariants for property tests of code built on a
+// `MerkleTree`. The invariants return a `TestCaseError` instead of
+// panicking, so they can be used with `?` inside `proptest!`.
+
+pub type Batch<V> = Vec<(LeafIndex, V)>;
+
+// Any leaf index of a tree of `height`.
+pub fn leaf_index(height: usize) -> impl Strategy<Value = LeafIndex> + Clone {
+    assert!(height <= 128, "height must be at most 128");
+    any::<u128>().prop_map(move |i| {
+        let i = if height == 128 {
+            i
+        } else {
+            i & ((1u128 << height) - 1)
+        };
+        LeafIndex::new(i, height).unwrap()
+    })
+}
+
+// Mostly indices among the first `width` leaves, so that updates overwrite
+// each other and share ancestors, and sometimes any index.
+pub fn clustered_leaf_index(
+    height: usize,
+    width: u128,
+) -> impl Strategy<Value = LeafIndex> + Clone {
+    let width = if height < 128 {
+        width.clamp(1, 1 << height)
+    } else {
+        width.max(1)
+    };
+    let dense = (0..width).prop_map(move |i| LeafIndex::new(i, height).unwrap());
+    prop_oneof![3 => dense, 1 => leaf_index(height)]
+}
+
+// `HashLeaf`s made from random `u64`s by `leaf_hash`, which should meet the
+// requirements of `testkit`.
+pub fn hash_leaf<H: TreeHasher>(
+    leaf_hash: fn(u64) -> H::HashOut,
+) -> impl Strategy<Value = HashLeaf<H>> + Clone {
+    any::<u64>().prop_map(move |i| HashLeaf(leaf_hash(i)))
+}
+
+// Sequences of up to `max_batches` batches of up to `max_batch_len` updates.
+// Some updates reset a leaf to the empty leaf.
+pub fn update_batches<V: Leafable + 'static>(
+    height: usize,
+    leaf: impl Strategy<Value = V> + Clone + 'static,
+    max_batches: usize,
+    max_batch_len: usize,
+) -> impl Strategy<Value = Vec<Batch<V>>> {
+    let leaf = prop_oneof![4 => leaf, 1 => Just(V::empty_leaf())];
+    let update = (clustered_leaf_index(height, 64), leaf);
+    collection::vec(
+        collection::vec(update, 1..=max_batch_len.max(1)),
+        1..=max_batches.max(1),
+    )
+}
+
+// Each of `leaves`, given with its current value, has a proof that verifies
+// against the current root, and the proof read back from `db` is the same.
+pub fn check_proofs<V: Leafable, S: NodeStore<V>>(
+    tree: &MerkleTree<V>,
+    db: &S,
+    leaves: &[(LeafIndex, V)],
+) -> Result<(), TestCaseError> {
+    let root = tree.get_root();
+    for (index, leaf) in leaves {
+        let proof = tree
+            .prove(*index)
+            .map_err(|e| TestCaseError::fail(format!("cannot prove {:?}: {}", index, e)))?;
+        proof.verify(leaf, *index, root.clone()).map_err(|e| {
+            TestCaseError::fail(format!("proof of {:?} does not verify: {}", index, e))
+        })?;
+        let stored = tree
+            .prove_with_given_root(db, root.clone(), *index)
+            .map_err(|e| TestCaseError::fail(format!("cannot prove {:?}: {}", index, e)))?;
+        prop_assert_eq!(
+            stored,
+            proof,
+            "proof of {:?} read from the store differs",
+            index
+        );
+    }
+    Ok(())
+}
+
+// For each past root and the leaves as they were at that root, proofs read
+// from `db` verify against that root. Holds as long as no version was
+// garbage collected.
+pub fn check_history<V: Leafable, S: NodeStore<V>>(
+    tree: &MerkleTree<V>,
+    db: &S,
+    history: &[(<V::Hasher as TreeHasher>::HashOut, Batch<V>)],
+) -> Result<(), TestCaseError> {
+    for (root, leaves) in history {
+        for (index, leaf) in leaves {
+            let proof = tree
+                .prove_with_given_root(db, root.clone(), *index)
+                .map_err(|e| {
+                    TestCaseError::fail(format!("cannot prove {:?} at {:?}: {}", index, root, e))
+                })?;
+            proof.verify(leaf, *index, root.clone()).map_err(|e| {
+                TestCaseError::fail(format!(
+                    "proof of {:?} at {:?} does not verify: {}",
+                    index, root, e
+                ))
+            })?;
+        }
+    }
+    Ok(())
+}
+
+// Applies `batches` with one `update_leaves` each, checking `check_proofs`
+// for every leaf touched so far after each batch and `check_history` for
+// every root at the end. Leaves not touched yet count as empty.
+pub fn check_update_sequence<V: Leafable, S: NodeStore<V>>(
+    tree: &mut MerkleTree<V>,
+    db: &mut S,
+    batches: &[Batch<V>],
+) -> Result<(), TestCaseError>
+where
+    V::Hasher: BatchHasher,
+{
+    let mut model: HashMap<LeafIndex, V> = HashMap::new();
+    let mut roots = vec![];
+    for batch in batches {
+        let hashes: Vec<_> = batch
+            .iter()
+            .map(|(index, leaf)| (*index, leaf.hash()))
+            .collect();
+        tree.update_leaves(db, &hashes)
+            .map_err(|e| TestCaseError::fail(format!("cannot update leaves: {}", e)))?;
+        for (index, leaf) in batch {
+            model.insert(*index, leaf.clone());
+        }
+        let leaves: Batch<V> = model.iter().map(|(i, v)| (*i, v.clone())).collect();
+        check_proofs(tree, db, &leaves)?;
+        roots.push((tree.get_root(), model.clone()));
+    }
+    let history: Vec<_> = roots
+        .into_iter()
+        .map(|(root, leaves_at_root)| {
+            let leaves = model
+                .keys()
+                .map(|index| {
+                    let leaf = leaves_at_root
+                        .get(index)
+                        .cloned()
+                        .unwrap_or_else(V::empty_leaf);
+                    (*index, leaf)
+                })
+                .collect();
+            (root, leaves)
+        })
+        .collect();
+    check_history(tree, db, &history)
+}
+
+#[derive(Clone, Debug)]
+pub enum TreeOp<V> {
+    Update(LeafIndex, V),
+    Batch(Batch<V>),
+    Prove(LeafIndex),
+}
+
+// Sequences of up to `max_ops` operations on a tree of `height`, with
+// batches of up to 8 updates. Indices are spread over the whole tree.
+pub fn tree_ops<V: Leafable + 'static>(
+    height: usize,
+    leaf: impl Strategy<Value = V> + Clone + 'static,
+    max_ops: usize,
+) -> impl Strategy<Value = Vec<TreeOp<V>>> {
+    let leaf = prop_oneof![4 => leaf, 1 => Just(V::empty_leaf())];
+    let update = (leaf_index(height), leaf);
+    let op = prop_oneof![
+        2 => update.clone().prop_map(|(index, leaf)| TreeOp::Update(index, leaf)),
+        1 => collection::vec(update, 1..=8).prop_map(TreeOp::Batch),
+        2 => leaf_index(height).prop_map(TreeOp::Prove),
+    ];
+    collection::vec(op, 1..=max_ops.max(1))
+}
+
+// Applies `ops` to `tree` and to a `ReferenceTree` of the same height, and
+// compares the roots after every operation and the proofs of `Prove`, both
+// from memory and read from `db`. `tree` must start out empty.
+pub fn check_against_reference<V: Leafable, S: NodeStore<V>>(
+    tree: &mut MerkleTree<V>,
+    db: &mut S,
+    ops: &[TreeOp<V>],
+) -> Result<(), TestCaseError>
+where
+    V::Hasher: BatchHasher,
+{
+    let mut reference = ReferenceTree::<V>::new(tree.height());
+    prop_assert_eq!(tree.get_root(), reference.get_root(), "empty roots differ");
+    for (i, op) in ops.iter().enumerate() {
+        match op {
+            TreeOp::Update(index, leaf) => {
+                tree.update_leaf(db, *index, leaf.hash())
+                    .map_err(|e| TestCaseError::fail(format!("op {}: {}", i, e)))?;
+                reference.update_leaf(*index, leaf.hash());
+            }
+            TreeOp::Batch(leaves) => {
+                let hashes: Vec<_> = leaves
+                    .iter()
+                    .map(|(index, leaf)| (*index, leaf.hash()))
+                    .collect();
+                tree.update_leaves(db, &hashes)
+                    .map_err(|e| TestCaseError::fail(format!("op {}: {}", i, e)))?;
+                for (index, hash) in hashes {
+                    reference.update_leaf(index, hash);
+                }
+            }
+            TreeOp::Prove(index) => {
+                let expected = reference.prove(*index);
+                let proof = tree
+                    .prove(*index)
+                    .map_err(|e| TestCaseError::fail(format!("op {}: {}", i, e)))?;
+                prop_assert_eq!(&proof, &expected, "op {}: proofs of {:?} differ", i, index);
+                let stored = tree
+                    .prove_with_given_root(&*db, tree.get_root(), *index)
+                    .map_err(|e| TestCaseError::fail(format!("op {}: {}", i, e)))?;
+                prop_assert_eq!(
+                    &stored,
+                    &expected,
+                    "op {}: proofs of {:?} read from the store differ",
+                    i,
+                    index
+                );
+            }
+        }
+        prop_assert_eq!(
+            tree.get_root(),
+            reference.get_root(),
+            "op {}: roots differ after {:?}",
+            i,
+            op
+        );
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use proptest::prelude::*;
+
+    use crate::{merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable};
+
+    use super::{check_against_reference, check_update_sequence, tree_ops, update_batches};
+
+    type Leaf = u32;
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(16))]
+
+        #[test]
+        fn test_update_sequence(batches in update_batches(10, any::<Leaf>(), 6, 12)) {
+            let mut db = MockDB::<Leaf>::new();
+            let mut tree = MerkleTree::<Leaf>::new(&mut db, 10, Leaf::empty_leaf().hash());
+            check_update_sequence(&mut tree, &mut db, &batches)?;
+        }
+
+        #[test]
+        fn test_against_reference(ops in tree_ops(6, any::<Leaf>(), 24)) {
+            let mut db = MockDB::<Leaf>::new();
+            let mut tree = MerkleTree::<Leaf>::new(&mut db, 6, Leaf::empty_leaf().hash());
+            check_against_reference(&mut tree, &mut db, &ops)?;
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::{
+    fmt,
+    sync::mpsc::{channel, Receiver, Sender},
+};
+
+use crate::{
+    merkle_tree::MerkleTree,
+    traits::{Leafable, TreeHasher},
+};
+
+// A leaf change, sent to subscribers once the update that made it is done.
+// Every leaf of a batch update gets its own event with the root after the
+// whole batch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LeafUpdate<H> {
+    pub index: u128,
+    pub old_leaf_hash: H,
+    pub new_leaf_hash: H,
+    pub new_root: H,
+}
+
+// Subscribers are not cloned with the tree, so a clone never reports updates
+// to receivers of the original.
+pub(crate) struct Subscribers<H> {
+    senders: Vec<Sender<LeafUpdate<H>>>,
+    // (index, old leaf hash, new leaf hash) of the update in progress
+    pending: Vec<(u128, H, H)>,
+}
+
+impl<H> Default for Subscribers<H> {
+    fn default() -> Self {
+        Self {
+            senders: vec![],
+            pending: vec![],
+        }
+    }
+}
+
+impl<H> Clone for Subscribers<H> {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl<H> fmt::Debug for Subscribers<H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Subscribers({})", self.senders.len())
+    }
+}
+
+impl<H: Clone + Eq> Subscribers<H> {
+    pub(crate) fn record(&mut self, index: u128, old: H, new: H) {
+        if !self.senders.is_empty() && old != new {
+            self.pending.push((index, old, new));
+        }
+    }
+
+    // Sends the recorded changes and drops subscribers whose receiver is gone.
+    pub(crate) fn notify(&mut self, new_root: H) {
+        for (index, old_leaf_hash, new_leaf_hash) in std::mem::take(&mut self.pending) {
+            let event = LeafUpdate {
+                index,
+                old_leaf_hash,
+                new_leaf_hash,
+                new_root: new_root.clone(),
+            };
+            self.senders
+                .retain(|sender| sender.send(event.clone()).is_ok());
+        }
+    }
+}
+
+impl<V: Leafable> MerkleTree<V> {
+    // Returns a receiver of every later leaf change of this tree. Updates that
+    // do not change a leaf hash are not reported. The channel is unbounded,
+    // so a subscriber that stops reading should drop its receiver.
+    pub fn subscribe(&mut self) -> Receiver<LeafUpdate<<V::Hasher as TreeHasher>::HashOut>> {
+        let (sender, receiver) = channel();
+        self.subscribers.senders.push(sender);
+        receiver
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable,
+    };
+
+    use super::LeafUpdate;
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_subscribe() {
+        let height = 8;
+        let mut db = MockDB::<Leaf>::new();
+        let empty = Leaf::empty_leaf().hash();
+        let mut tree = MerkleTree::new(&mut db, height, empty);
+        let receiver = tree.subscribe();
+
+        tree.update_leaf_at(&mut db, 4, 1u32.hash()).unwrap();
+        let root = tree.get_root();
+        let leaves = [(4, 2u32.hash()), (5, empty), (6, 3u32.hash())]
+            .map(|(i, h)| (LeafIndex::new(i, height).unwrap(), h));
+        tree.update_leaves(&mut db, &leaves).unwrap();
+        let events: Vec<_> = receiver.try_iter().collect();
+        let event = |index, old_leaf_hash, new_leaf_hash, new_root| LeafUpdate {
+            index,
+            old_leaf_hash,
+            new_leaf_hash,
+            new_root,
+        };
+        assert_eq!(
+            events,
+            [
+                event(4, empty, 1u32.hash(), root),
+                event(4, 1u32.hash(), 2u32.hash(), tree.get_root()),
+                event(6, empty, 3u32.hash(), tree.get_root()),
+            ]
+        );
+
+        // clones do not report to the original subscribers
+        let mut clone = tree.clone();
+        clone.update_leaf_at(&mut db, 7, 4u32.hash()).unwrap();
+        assert!(receiver.try_recv().is_err());
+
+        drop(receiver);
+        tree.update_leaf_at(&mut db, 7, 4u32.hash()).unwrap();
+        assert!(tree.subscribers.senders.is_empty());
+    }
+}
@@ -0,0 +1,109 @@
+use crate::{
+    merkle_tree::MerkleTree, metrics::MetricsHook, node_key::NodeKey, node_store::NodeStore,
+    subscription::Subscribers, traits::Leafable,
+};
+
+impl<V: Leafable> MerkleTree<V> {
+    // The subtree at `path` as a tree of its own, of height
+    // `height - path.depth()`, whose leaf i is leaf i of the subtree. It uses
+    // the nodes already in `db`, so nothing is written, and its root is the
+    // hash of `path`; `graft` puts a subtree back. Subscribers and the
+    // reverse index are not carried over. Fails if a node under `path` is
+    // missing from `db`.
+    pub fn extract_subtree<S: NodeStore<V>>(&self, db: &S, path: NodeKey) -> anyhow::Result<Self> {
+        self.check_node_key(path)?;
+        let hash = self.get_node_hash_with_store(db, path)?;
+        let (nodes, leaves) = self.subtree(db, path, hash)?;
+        let height = self.height - path.depth();
+        // the position of `key` in the subtree
+        let relative = |key: NodeKey| {
+            let depth = key.depth() - path.depth();
+            let mask = 1u128.checked_shl(depth as u32).map_or(u128::MAX, |m| m - 1);
+            NodeKey::new(depth, key.index & mask)
+        };
+
+        Ok(Self {
+            height,
+            node_hashes: nodes
+                .into_iter()
+                .map(|(key, hash, _)| (relative(key), hash))
+                .chain(
+                    leaves
+                        .iter()
+                        .map(|(key, hash)| (relative(*key), hash.clone())),
+                )
+                .collect(),
+            zero_hashes: self.zero_hashes[path.depth()..].to_vec(),
+            cache_depth: height,
+            num_leaves: leaves.len() as u128,
+            last_leaf: leaves.iter().map(|(key, _)| relative(*key).index).max(),
+            reverse_index: None,
+            subscribers: Subscribers::default(),
+            metrics: MetricsHook::default(),
+        })
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, node_key::NodeKey,
+        traits::Leafable,
+    };
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_extract_subtree() {
+        let height = 10;
+        let mut db = MockDB::<Leaf>::new();
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        let index = move |i: u128| LeafIndex::new(i, height).unwrap();
+        for i in [1, 300, 258, 511, 700] {
+            tree.update_leaf(&mut db, index(i), (i as u32).hash())
+                .unwrap();
+        }
+
+        // leaves 256..512
+        let path = NodeKey::new(2, 1);
+        let mut subtree = tree.extract_subtree(&db, path).unwrap();
+        assert_eq!(subtree.height(), 8);
+        assert_eq!(subtree.get_root(), tree.get_node_hash(path).unwrap());
+        assert_eq!(subtree.len(), 3);
+        assert_eq!(subtree.next_free_index(), 256);
+        let sub_index = |i: u128| LeafIndex::new(i, 8).unwrap();
+        assert_eq!(
+            subtree.prove(sub_index(44)).unwrap().siblings,
+            tree.prove(index(300)).unwrap().siblings[..8]
+        );
+        assert!(subtree.verify_integrity(&db, subtree.get_root()).is_ok());
+
+        // the subtree is updated on its own and grafted back
+        subtree
+            .update_leaf(&mut db, sub_index(7), 9u32.hash())
+            .unwrap();
+        let source = db.clone();
+        tree.graft(&mut db, path, subtree.get_root(), &source)
+            .unwrap();
+        let mut expected = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        for (i, leaf) in [
+            (1, 1),
+            (300, 300),
+            (258, 258),
+            (511, 511),
+            (700, 700),
+            (263, 9),
+        ] {
+            expected
+                .update_leaf(&mut db, index(i), (leaf as u32).hash())
+                .unwrap();
+        }
+        assert_eq!(tree.get_root(), expected.get_root());
+
+        // the root and the leaves are subtrees too
+        assert_eq!(tree.extract_subtree(&db, NodeKey::root()).unwrap(), tree);
+        let leaf = tree.extract_subtree(&db, index(700).to_node_key()).unwrap();
+        assert_eq!(leaf.get_root(), 700u32.hash());
+        assert_eq!(leaf.len(), 1);
+    }
+}
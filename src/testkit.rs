@@ -0,0 +1,218 @@
+use crate::{
+    batch_hasher::BatchHasher,
+    leaf_index::LeafIndex,
+    merkle_tree::{usize_le_bits, MerkleTree},
+    mock_db::MockDB,
+    node::Node,
+    node_key::NodeKey,
+    node_store::NodeStore,
+    traits::{HashLeaf, TreeHasher},
+    zero_hashes::ZeroHashes,
+};
+
+// Conformance checks for new `TreeHasher` and `NodeStore` implementations.
+// The checks run on `HashLeaf` trees, so they only need a way to make leaf
+// hashes: `leaf_hash(i)` must be distinct for distinct `i` and never equal to
+// `HashOut::default()`, the empty leaf. Every check panics on the first
+// violation.
+
+const HEIGHT: usize = 8;
+
+// Runs every hasher check on a `MockDB`.
+pub fn check_hasher<H: BatchHasher>(leaf_hash: impl Fn(u64) -> H::HashOut) {
+    check_zero_hashes::<H, _>(&mut MockDB::new());
+    check_endianness::<H>(&leaf_hash);
+    check_proof_roundtrip::<H, _>(&mut MockDB::new(), &leaf_hash);
+    check_batch_hashing::<H>(&leaf_hash);
+}
+
+// Runs every check that goes through a store on stores made by `new_store`.
+pub fn check_store<H, S>(new_store: impl Fn() -> S, leaf_hash: impl Fn(u64) -> H::HashOut)
+where
+    H: BatchHasher,
+    S: NodeStore<HashLeaf<H>>,
+{
+    check_node_store::<H, _>(&mut new_store(), &leaf_hash);
+    check_zero_hashes::<H, _>(&mut new_store());
+    check_proof_roundtrip::<H, _>(&mut new_store(), &leaf_hash);
+}
+
+// The zero hash of each level is the hash of two zero hashes of the level
+// below, and a new tree stores exactly that chain.
+pub fn check_zero_hashes<H: TreeHasher, S: NodeStore<HashLeaf<H>>>(db: &mut S) {
+    let empty_leaf_hash = H::HashOut::default();
+    let zero_hashes = ZeroHashes::<H>::new(empty_leaf_hash.clone(), HEIGHT);
+    assert_eq!(zero_hashes.empty_leaf_hash(), empty_leaf_hash);
+    for height in 0..HEIGHT {
+        let child = zero_hashes.get(height);
+        assert_eq!(
+            zero_hashes.get(height + 1),
+            H::two_to_one(child.clone(), child),
+            "zero hash of height {} is not the hash of its children",
+            height + 1
+        );
+    }
+
+    let tree = MerkleTree::<HashLeaf<H>>::new(db, HEIGHT, empty_leaf_hash);
+    assert_eq!(tree.get_root(), zero_hashes.get(HEIGHT));
+    for height in 1..=HEIGHT {
+        let child = zero_hashes.get(height - 1);
+        assert_eq!(
+            db.get(zero_hashes.get(height)),
+            Some(Node::new(child.clone(), child)),
+            "zero node of height {} is not in the store",
+            height
+        );
+    }
+}
+
+// Leaf indices are little endian bits: leaf 1 is the right child of the
+// leftmost node above the leaves.
+pub fn check_endianness<H: BatchHasher>(leaf_hash: impl Fn(u64) -> H::HashOut) {
+    let height = 3;
+    let mut db = MockDB::<HashLeaf<H>>::new();
+    let empty_leaf_hash = H::HashOut::default();
+    let zero_hashes = ZeroHashes::<H>::new(empty_leaf_hash.clone(), height);
+    let mut tree = MerkleTree::new(&mut db, height, empty_leaf_hash.clone());
+    let leaf = leaf_hash(1);
+    tree.update_leaf(&mut db, LeafIndex::new(1, height).unwrap(), leaf.clone())
+        .unwrap();
+
+    let node = H::two_to_one(empty_leaf_hash, leaf.clone());
+    let node = H::two_to_one(node, zero_hashes.get(1));
+    let root = H::two_to_one(node, zero_hashes.get(2));
+    assert_eq!(
+        tree.get_root(),
+        root,
+        "leaf 1 is not at little endian index 1"
+    );
+    assert_eq!(tree.get_node_hash_unchecked(NodeKey::new(height, 1)), leaf);
+    assert_eq!(
+        LeafIndex::from_le_bits(&usize_le_bits(6, height)).unwrap(),
+        LeafIndex::from_be_path(&[true, true, false]).unwrap()
+    );
+}
+
+// Proofs of set and unset leaves verify against the root, including proofs
+// read back from the store, and fail for a wrong index or leaf.
+pub fn check_proof_roundtrip<H, S>(db: &mut S, leaf_hash: impl Fn(u64) -> H::HashOut)
+where
+    H: BatchHasher,
+    S: NodeStore<HashLeaf<H>>,
+{
+    let mut tree = MerkleTree::<HashLeaf<H>>::new(db, HEIGHT, H::HashOut::default());
+    let indices = [0u64, 1, 2, 77, 128, 255];
+    for &i in &indices {
+        tree.update_leaf_at(db, i, leaf_hash(i)).unwrap();
+    }
+    let root = tree.get_root();
+    for &i in indices.iter().chain(&[3, 200]) {
+        let hash = if indices.contains(&i) {
+            leaf_hash(i)
+        } else {
+            H::HashOut::default()
+        };
+        let proof = tree.prove_at(i).unwrap();
+        let index = LeafIndex::new(i as u128, HEIGHT).unwrap();
+        assert_eq!(
+            tree.prove_with_given_root(&*db, root.clone(), index)
+                .unwrap(),
+            proof,
+            "proof of leaf {} read from the store differs",
+            i
+        );
+        proof
+            .verify_at(&HashLeaf(hash.clone()), i, root.clone())
+            .unwrap();
+        // unset leaves in an empty subtree have the same proofs
+        if indices.contains(&i) {
+            assert!(
+                proof
+                    .verify_at(&HashLeaf(hash), i ^ 1, root.clone())
+                    .is_err(),
+                "proof of leaf {} verifies at leaf {}",
+                i,
+                i ^ 1
+            );
+        }
+    }
+    let proof = tree.prove_at(77).unwrap();
+    assert!(proof.verify_at(&HashLeaf(leaf_hash(78)), 77, root).is_err());
+}
+
+// `two_to_one_many` agrees with `two_to_one`, and bulk updates give the same
+// root as single updates.
+pub fn check_batch_hashing<H: BatchHasher>(leaf_hash: impl Fn(u64) -> H::HashOut) {
+    let pairs: Vec<_> = (0..9)
+        .map(|i| (leaf_hash(2 * i), leaf_hash(2 * i + 1)))
+        .collect();
+    let hashes = H::two_to_one_many(&pairs);
+    assert_eq!(hashes.len(), pairs.len());
+    for ((left, right), h) in pairs.into_iter().zip(hashes) {
+        assert_eq!(H::two_to_one(left, right), h);
+    }
+
+    let mut db = MockDB::<HashLeaf<H>>::new();
+    let empty_leaf_hash = H::HashOut::default();
+    let leaf_hashes: Vec<_> = (0..20).map(&leaf_hash).collect();
+    let mut single = MerkleTree::new(&mut db, HEIGHT, empty_leaf_hash.clone());
+    for (i, h) in leaf_hashes.iter().enumerate() {
+        single.update_leaf_at(&mut db, i as u64, h.clone()).unwrap();
+    }
+    let leaves: Vec<_> = leaf_hashes
+        .iter()
+        .enumerate()
+        .map(|(i, h)| (LeafIndex::new(i as u128, HEIGHT).unwrap(), h.clone()))
+        .collect();
+    let mut batched = MerkleTree::new(&mut db, HEIGHT, empty_leaf_hash.clone());
+    batched.update_leaves(&mut db, &leaves).unwrap();
+    let built = MerkleTree::from_leaf_hashes(&mut db, HEIGHT, empty_leaf_hash, &leaf_hashes);
+    assert_eq!(batched.get_root(), single.get_root());
+    assert_eq!(built.get_root(), single.get_root());
+}
+
+// Basic `NodeStore` semantics, including the default methods, leaf data and
+// leaf metadata.
+pub fn check_node_store<H: TreeHasher, S: NodeStore<HashLeaf<H>>>(
+    db: &mut S,
+    leaf_hash: impl Fn(u64) -> H::HashOut,
+) {
+    let nodes: Vec<_> = (0..4)
+        .map(|i| {
+            let node = Node::<HashLeaf<H>>::new(leaf_hash(2 * i), leaf_hash(2 * i + 1));
+            (node.hash(), node)
+        })
+        .collect();
+    let (key, node) = nodes[0].clone();
+    assert!(!db.contains(key.clone()));
+    db.insert(key.clone(), node.clone());
+    assert!(db.contains(key.clone()));
+    assert_eq!(db.get(key.clone()), Some(node.clone()));
+    assert_eq!(
+        db.with_node(key.clone(), |node| node.child(true)),
+        Some(node.right.clone())
+    );
+    assert_eq!(db.remove(key.clone()), Some(node));
+    assert!(db.get(key.clone()).is_none());
+    assert!(db.with_node(key, |_| ()).is_none());
+
+    db.insert_batch(nodes.clone());
+    for (key, node) in nodes {
+        assert_eq!(db.get(key), Some(node));
+    }
+
+    let leaf = leaf_hash(100);
+    assert!(db.get_leaf_data(leaf.clone()).is_none());
+    db.insert_leaf_data(leaf.clone(), vec![1, 2, 3]);
+    assert_eq!(db.get_leaf_data(leaf.clone()), Some(vec![1, 2, 3]));
+    assert_eq!(db.remove_leaf_data(leaf.clone()), Some(vec![1, 2, 3]));
+    assert!(db.get_leaf_data(leaf.clone()).is_none());
+
+    db.insert_leaf_metadata(leaf.clone(), 1, vec![4]);
+    db.insert_leaf_metadata(leaf.clone(), 2, vec![5]);
+    assert_eq!(db.get_leaf_metadata(leaf.clone(), 1), Some(vec![4]));
+    assert_eq!(db.get_leaf_metadata(leaf.clone(), 2), Some(vec![5]));
+    assert!(db.get_leaf_metadata(leaf.clone(), 3).is_none());
+    db.remove_leaf_metadata(leaf.clone());
+    assert!(db.get_leaf_metadata(leaf, 1).is_none());
+}
@@ -0,0 +1,281 @@
+use hashbrown::HashSet;
+
+use crate::{
+    error::CorruptNode,
+    merkle_tree::MerkleTree,
+    node::Node,
+    node_key::NodeKey,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+// What a migration moved to the cold tier.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MigrationStats {
+    pub nodes: usize,
+    pub leaves: usize,
+}
+
+// `TieredStore` puts a fast `hot` store, e.g. memory, in front of a large and
+// slow `cold` one, e.g. files or an object store. Writes go to the hot tier,
+// and reads fall back to the cold tier on a miss, so proofs of old versions
+// work on nodes that were moved there. `migrate` moves the nodes only old
+// versions use below `hot_depth` to the cold tier; the levels above it stay
+// hot, as every historical proof reads them. Leaf metadata is never moved.
+#[derive(Clone, Debug)]
+pub struct TieredStore<H, C> {
+    hot: H,
+    cold: C,
+    hot_depth: usize,
+}
+
+impl<H, C> TieredStore<H, C> {
+    pub fn new(hot: H, cold: C, hot_depth: usize) -> Self {
+        Self {
+            hot,
+            cold,
+            hot_depth,
+        }
+    }
+
+    pub fn hot(&self) -> &H {
+        &self.hot
+    }
+
+    pub fn cold(&self) -> &C {
+        &self.cold
+    }
+
+    pub fn hot_depth(&self) -> usize {
+        self.hot_depth
+    }
+
+    pub fn into_inner(self) -> (H, C) {
+        (self.hot, self.cold)
+    }
+
+    // Moves to the cold tier the nodes below `hot_depth`, and the leaf data,
+    // of the versions `old` of `tree` that none of the versions `recent`
+    // share. Subtrees shared with a recent version stay hot. Every version is
+    // still proven from the store after, old ones partly from the cold tier.
+    // Fails if a node of one of the versions is in neither tier; nodes moved
+    // until then stay moved.
+    pub fn migrate<V: Leafable>(
+        &mut self,
+        tree: &MerkleTree<V>,
+        recent: &[<V::Hasher as TreeHasher>::HashOut],
+        old: &[<V::Hasher as TreeHasher>::HashOut],
+    ) -> anyhow::Result<MigrationStats>
+    where
+        H: NodeStore<V>,
+        C: NodeStore<V>,
+    {
+        let live = tree.live_nodes(self, recent)?;
+        let shared: HashSet<_> = live
+            .nodes
+            .into_iter()
+            .map(|(hash, _)| hash)
+            .chain(live.leaves.into_iter().map(|(_, hash)| hash))
+            .collect();
+
+        let mut stats = MigrationStats::default();
+        let mut moved = vec![];
+        let mut visited = HashSet::new();
+        let mut stack: Vec<_> = old
+            .iter()
+            .map(|root| (NodeKey::root(), root.clone()))
+            .collect();
+        while let Some((key, hash)) = stack.pop() {
+            if hash == tree.zero_hashes[key.depth()]
+                || shared.contains(&hash)
+                || !visited.insert(hash.clone())
+            {
+                continue;
+            }
+            if key.depth() == tree.height {
+                if let Some(data) = self.hot.remove_leaf_data(hash.clone()) {
+                    self.cold.insert_leaf_data(hash, data);
+                    stats.leaves += 1;
+                }
+                continue;
+            }
+            let node = self
+                .get(hash.clone())
+                .ok_or_else(|| anyhow::anyhow!("cannot find node at depth {}", key.depth()))?;
+            stack.push((key.child(false), node.left.clone()));
+            stack.push((key.child(true), node.right.clone()));
+            if key.depth() >= self.hot_depth {
+                if let Some(node) = self.hot.remove(hash.clone()) {
+                    moved.push((hash, node));
+                }
+            }
+        }
+        stats.nodes = moved.len();
+        self.cold.insert_batch(moved);
+        Ok(stats)
+    }
+}
+
+impl<V: Leafable, H: NodeStore<V>, C: NodeStore<V>> NodeStore<V> for TieredStore<H, C> {
+    fn insert(&mut self, key: <V::Hasher as TreeHasher>::HashOut, node: Node<V>) {
+        self.hot.insert(key, node)
+    }
+
+    fn get(&self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>> {
+        self.hot.get(key.clone()).or_else(|| self.cold.get(key))
+    }
+
+    // removes the node from both tiers
+    fn remove(&mut self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>> {
+        let hot = self.hot.remove(key.clone());
+        let cold = self.cold.remove(key);
+        hot.or(cold)
+    }
+
+    fn contains(&self, key: <V::Hasher as TreeHasher>::HashOut) -> bool {
+        self.hot.contains(key.clone()) || self.cold.contains(key)
+    }
+
+    fn insert_leaf_data(&mut self, leaf_hash: <V::Hasher as TreeHasher>::HashOut, data: Vec<u8>) {
+        self.hot.insert_leaf_data(leaf_hash, data)
+    }
+
+    fn get_leaf_data(&self, leaf_hash: <V::Hasher as TreeHasher>::HashOut) -> Option<Vec<u8>> {
+        self.hot
+            .get_leaf_data(leaf_hash.clone())
+            .or_else(|| self.cold.get_leaf_data(leaf_hash))
+    }
+
+    fn remove_leaf_data(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Option<Vec<u8>> {
+        let hot = self.hot.remove_leaf_data(leaf_hash.clone());
+        let cold = self.cold.remove_leaf_data(leaf_hash);
+        hot.or(cold)
+    }
+
+    fn insert_leaf_metadata(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+        metadata: Vec<u8>,
+    ) {
+        self.hot.insert_leaf_metadata(leaf_hash, index, metadata)
+    }
+
+    fn get_leaf_metadata(
+        &self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+    ) -> Option<Vec<u8>> {
+        self.hot
+            .get_leaf_metadata(leaf_hash.clone(), index)
+            .or_else(|| self.cold.get_leaf_metadata(leaf_hash, index))
+    }
+
+    fn remove_leaf_metadata(&mut self, leaf_hash: <V::Hasher as TreeHasher>::HashOut) {
+        self.hot.remove_leaf_metadata(leaf_hash.clone());
+        self.cold.remove_leaf_metadata(leaf_hash)
+    }
+
+    fn remove_leaf_metadata_at(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+    ) {
+        self.hot.remove_leaf_metadata_at(leaf_hash.clone(), index);
+        self.cold.remove_leaf_metadata_at(leaf_hash, index)
+    }
+
+    fn with_node<R>(
+        &self,
+        key: <V::Hasher as TreeHasher>::HashOut,
+        f: impl FnOnce(&Node<V>) -> R,
+    ) -> Option<R> {
+        if self.hot.contains(key.clone()) {
+            return self.hot.with_node(key, f);
+        }
+        self.cold.with_node(key, f)
+    }
+
+    fn try_with_node<R>(
+        &self,
+        key: <V::Hasher as TreeHasher>::HashOut,
+        f: impl FnOnce(&Node<V>) -> R,
+    ) -> Result<Option<R>, CorruptNode<<V::Hasher as TreeHasher>::HashOut>> {
+        if self.hot.contains(key.clone()) {
+            return self.hot.try_with_node(key, f);
+        }
+        self.cold.try_with_node(key, f)
+    }
+
+    // nodes in both tiers are counted twice
+    fn num_nodes(&self) -> Option<usize> {
+        Some(self.hot.num_nodes()? + self.cold.num_nodes()?)
+    }
+
+    fn insert_batch(&mut self, nodes: Vec<(<V::Hasher as TreeHasher>::HashOut, Node<V>)>) {
+        self.hot.insert_batch(nodes)
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, node_store::NodeStore,
+        traits::Leafable,
+    };
+
+    use super::TieredStore;
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_tiered_store() {
+        let height = 8;
+        let mut db = TieredStore::new(MockDB::<Leaf>::new(), MockDB::new(), 2);
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        let index = move |i: u128| LeafIndex::new(i, height).unwrap();
+        let mut roots = vec![];
+        for i in 0..30 {
+            let leaf = i as u32 + 1;
+            tree.update_leaf_data(&mut db, index(i * 5), &leaf).unwrap();
+            roots.push(tree.get_root());
+        }
+        let proofs: Vec<_> = roots
+            .iter()
+            .map(|root| tree.prove_with_given_root(&db, *root, index(10)).unwrap())
+            .collect();
+        let hot_before = db.hot().len();
+
+        let (old, recent) = roots.split_at(25);
+        let stats = db.migrate(&tree, recent, old).unwrap();
+        assert!(stats.nodes > 0);
+        assert_eq!(db.cold().len(), stats.nodes);
+        assert_eq!(db.hot().len(), hot_before - stats.nodes);
+        assert_eq!(db.num_nodes(), Some(hot_before));
+        // every leaf is still in a recent version, so no data moved
+        assert_eq!(stats.leaves, 0);
+        assert_eq!(tree.get_leaf(&db, index(10)).unwrap(), Some(3));
+
+        // old versions are proven from both tiers, recent ones from the hot
+        // tier alone
+        for (root, proof) in roots.iter().zip(&proofs) {
+            assert_eq!(
+                tree.prove_with_given_root(&db, *root, index(10)).as_ref(),
+                Ok(proof)
+            );
+        }
+        for root in recent {
+            assert!(tree
+                .prove_with_given_root(db.hot(), *root, index(10))
+                .is_ok());
+        }
+        assert!(tree
+            .prove_with_given_root(db.hot(), old[3], index(10))
+            .is_err());
+        // migrating again moves nothing
+        assert_eq!(db.migrate(&tree, recent, old).unwrap().nodes, 0);
+    }
+}
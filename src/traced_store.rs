@@ -0,0 +1,164 @@
+use crate::{
+    error::CorruptNode,
+    node::Node,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+// `TracedStore` wraps a `NodeStore` and enters a `tracing` span for each of
+// its operations, so that time spent in the backend shows up below the span
+// of the tree operation that caused it. Single node operations are traced at
+// TRACE level, batches at DEBUG level.
+#[derive(Clone, Debug)]
+pub struct TracedStore<S> {
+    inner: S,
+}
+
+impl<S> TracedStore<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<V: Leafable, S: NodeStore<V>> NodeStore<V> for TracedStore<S> {
+    fn insert(&mut self, key: <V::Hasher as TreeHasher>::HashOut, node: Node<V>) {
+        enter_span!(TRACE, "store_insert", key = ?key);
+        self.inner.insert(key, node)
+    }
+
+    fn get(&self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>> {
+        enter_span!(TRACE, "store_get", key = ?key);
+        self.inner.get(key)
+    }
+
+    fn remove(&mut self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>> {
+        enter_span!(TRACE, "store_remove", key = ?key);
+        self.inner.remove(key)
+    }
+
+    fn contains(&self, key: <V::Hasher as TreeHasher>::HashOut) -> bool {
+        enter_span!(TRACE, "store_contains", key = ?key);
+        self.inner.contains(key)
+    }
+
+    fn insert_leaf_data(&mut self, leaf_hash: <V::Hasher as TreeHasher>::HashOut, data: Vec<u8>) {
+        enter_span!(TRACE, "store_insert_leaf_data", leaf_hash = ?leaf_hash, len = data.len());
+        self.inner.insert_leaf_data(leaf_hash, data)
+    }
+
+    fn get_leaf_data(&self, leaf_hash: <V::Hasher as TreeHasher>::HashOut) -> Option<Vec<u8>> {
+        enter_span!(TRACE, "store_get_leaf_data", leaf_hash = ?leaf_hash);
+        self.inner.get_leaf_data(leaf_hash)
+    }
+
+    fn remove_leaf_data(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Option<Vec<u8>> {
+        enter_span!(TRACE, "store_remove_leaf_data", leaf_hash = ?leaf_hash);
+        self.inner.remove_leaf_data(leaf_hash)
+    }
+
+    fn insert_leaf_metadata(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+        metadata: Vec<u8>,
+    ) {
+        enter_span!(TRACE, "store_insert_leaf_metadata", leaf_hash = ?leaf_hash, index = %index);
+        self.inner.insert_leaf_metadata(leaf_hash, index, metadata)
+    }
+
+    fn get_leaf_metadata(
+        &self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+    ) -> Option<Vec<u8>> {
+        enter_span!(TRACE, "store_get_leaf_metadata", leaf_hash = ?leaf_hash, index = %index);
+        self.inner.get_leaf_metadata(leaf_hash, index)
+    }
+
+    fn remove_leaf_metadata(&mut self, leaf_hash: <V::Hasher as TreeHasher>::HashOut) {
+        enter_span!(TRACE, "store_remove_leaf_metadata", leaf_hash = ?leaf_hash);
+        self.inner.remove_leaf_metadata(leaf_hash)
+    }
+
+    fn remove_leaf_metadata_at(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+    ) {
+        enter_span!(TRACE, "store_remove_leaf_metadata_at", leaf_hash = ?leaf_hash, index = index);
+        self.inner.remove_leaf_metadata_at(leaf_hash, index)
+    }
+
+    fn with_node<R>(
+        &self,
+        key: <V::Hasher as TreeHasher>::HashOut,
+        f: impl FnOnce(&Node<V>) -> R,
+    ) -> Option<R> {
+        enter_span!(TRACE, "store_get", key = ?key);
+        self.inner.with_node(key, f)
+    }
+
+    fn try_with_node<R>(
+        &self,
+        key: <V::Hasher as TreeHasher>::HashOut,
+        f: impl FnOnce(&Node<V>) -> R,
+    ) -> Result<Option<R>, CorruptNode<<V::Hasher as TreeHasher>::HashOut>> {
+        enter_span!(TRACE, "store_get", key = ?key);
+        self.inner.try_with_node(key, f)
+    }
+
+    fn num_nodes(&self) -> Option<usize> {
+        self.inner.num_nodes()
+    }
+
+    fn insert_batch(&mut self, nodes: Vec<(<V::Hasher as TreeHasher>::HashOut, Node<V>)>) {
+        enter_span!(DEBUG, "store_insert_batch", batch_size = nodes.len());
+        self.inner.insert_batch(nodes)
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable,
+    };
+
+    use super::TracedStore;
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_traced_store() {
+        let height = 8;
+        let empty_leaf_hash = Leaf::empty_leaf().hash();
+        let mut db = TracedStore::new(MockDB::<Leaf>::new());
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, empty_leaf_hash);
+        let mut plain_db = MockDB::<Leaf>::new();
+        let mut plain = MerkleTree::<Leaf>::new(&mut plain_db, height, empty_leaf_hash);
+
+        let leaves: Vec<_> = (0..10)
+            .map(|i| (LeafIndex::new(i * 3, height).unwrap(), (i as u32).hash()))
+            .collect();
+        tree.update_leaves(&mut db, &leaves).unwrap();
+        plain.update_leaves(&mut plain_db, &leaves).unwrap();
+        assert_eq!(tree.get_root(), plain.get_root());
+        assert_eq!(db.inner().len(), plain_db.len());
+        let index = LeafIndex::new(6, height).unwrap();
+        assert_eq!(
+            tree.prove_with_given_root(&db, tree.get_root(), index)
+                .unwrap(),
+            plain.prove(index).unwrap()
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use core::{fmt::Debug, hash::Hash};
+
+#[cfg(feature = "zkp")]
+use intmax2_zkp::utils::{
+    leafable::Leafable as ZkpLeafable, leafable_hasher::LeafableHasher as ZkpLeafableHasher,
+};
+
+// Two-to-one hash function of a `MerkleTree`.
+pub trait TreeHasher: Debug + Clone {
+    type HashOut: Clone + Eq + Hash + Default + Debug;
+
+    fn two_to_one(left: Self::HashOut, right: Self::HashOut) -> Self::HashOut;
+}
+
+// Values stored in the leaves of a `MerkleTree`.
+pub trait Leafable: Debug + Clone {
+    type Hasher: TreeHasher;
+
+    fn empty_leaf() -> Self;
+
+    fn hash(&self) -> <Self::Hasher as TreeHasher>::HashOut;
+}
+
+// A leaf whose value is its own, already computed, hash. The empty leaf is
+// `HashOut::default()`.
+#[derive(Clone, Debug)]
+pub struct HashLeaf<H: TreeHasher>(pub H::HashOut);
+
+impl<H: TreeHasher> Leafable for HashLeaf<H> {
+    type Hasher = H;
+
+    fn empty_leaf() -> Self {
+        HashLeaf(H::HashOut::default())
+    }
+
+    fn hash(&self) -> H::HashOut {
+        self.0.clone()
+    }
+}
+
+// Every intmax2_zkp hasher and leaf can be used as-is.
+#[cfg(feature = "zkp")]
+impl<H: ZkpLeafableHasher> TreeHasher for H {
+    type HashOut = H::HashOut;
+
+    fn two_to_one(left: Self::HashOut, right: Self::HashOut) -> Self::HashOut {
+        <H as ZkpLeafableHasher>::two_to_one(left, right)
+    }
+}
+
+#[cfg(feature = "zkp")]
+impl<V: ZkpLeafable> Leafable for V {
+    type Hasher = V::LeafableHasher;
+
+    fn empty_leaf() -> Self {
+        <V as ZkpLeafable>::empty_leaf()
+    }
+
+    fn hash(&self) -> <Self::Hasher as TreeHasher>::HashOut {
+        <V as ZkpLeafable>::hash(self)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use crate::{
+        batch_hasher::BatchHasher, leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB,
+    };
+
+    use super::{HashLeaf, Leafable, TreeHasher};
+
+    // a toy hasher that only implements the crate-local traits
+    #[derive(Clone, Debug)]
+    struct XorHasher;
+
+    impl TreeHasher for XorHasher {
+        type HashOut = u64;
+
+        fn two_to_one(left: u64, right: u64) -> u64 {
+            left.rotate_left(5) ^ right ^ 0x9e37_79b9
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct Leaf(u64);
+
+    impl Leafable for Leaf {
+        type Hasher = XorHasher;
+
+        fn empty_leaf() -> Self {
+            Leaf(0)
+        }
+
+        fn hash(&self) -> u64 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_local_leafable() {
+        let height = 8;
+
+        let mut mock_db = MockDB::<Leaf>::new();
+        let empty_leaf_hash = Leaf::empty_leaf().hash();
+        let mut merkle_tree = MerkleTree::new(&mut mock_db, height, empty_leaf_hash);
+        for i in [1u64, 2, 200] {
+            merkle_tree
+                .update_leaf_at(&mut mock_db, i, Leaf(i).hash())
+                .unwrap();
+        }
+        let root = merkle_tree.get_root();
+        let proof = merkle_tree.prove_at(2).unwrap();
+        proof.verify_at(&Leaf(2), 2, root).unwrap();
+        assert!(proof.verify_at(&Leaf(3), 2, root).is_err());
+    }
+
+    // a hasher whose digest is heap allocated and not `Copy`
+    #[derive(Clone, Debug)]
+    struct VecHasher;
+
+    impl TreeHasher for VecHasher {
+        type HashOut = Vec<u8>;
+
+        fn two_to_one(left: Vec<u8>, right: Vec<u8>) -> Vec<u8> {
+            let h = left
+                .iter()
+                .chain(&right)
+                .fold(0xcbf2_9ce4_8422_2325u64, |h, &b| {
+                    (h ^ b as u64).wrapping_mul(0x100_0000_01b3)
+                });
+            h.to_le_bytes().to_vec()
+        }
+    }
+
+    impl BatchHasher for VecHasher {}
+
+    #[test]
+    fn test_non_copy_hash_out() {
+        let height = 8;
+
+        let mut mock_db = MockDB::<HashLeaf<VecHasher>>::new();
+        let mut merkle_tree = MerkleTree::new(&mut mock_db, height, vec![]);
+        merkle_tree
+            .update_leaf_at(&mut mock_db, 3, vec![3])
+            .unwrap();
+        let leaves: Vec<_> = (10..20u8)
+            .map(|i| (LeafIndex::new(i as u128, height).unwrap(), vec![i; 3]))
+            .collect();
+        merkle_tree.update_leaves(&mut mock_db, &leaves).unwrap();
+        let root = merkle_tree.get_root();
+        for (index, leaf_hash) in leaves {
+            let proof = merkle_tree
+                .prove_with_given_root(&mock_db, root.clone(), index)
+                .unwrap();
+            assert_eq!(proof, merkle_tree.prove(index).unwrap());
+            proof.verify_hash(leaf_hash, index, root.clone()).unwrap();
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::VecDeque;
+
+use hashbrown::HashSet;
+
+use crate::{
+    merkle_tree::MerkleTree,
+    node_key::NodeKey,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+// A node reached by a `NodeWalk`: its key, hash and children. The children
+// of a node at depth `height - 1` are leaf hashes.
+#[derive(Debug)]
+pub struct Visit<V: Leafable> {
+    pub key: NodeKey,
+    pub hash: <V::Hasher as TreeHasher>::HashOut,
+    pub left: <V::Hasher as TreeHasher>::HashOut,
+    pub right: <V::Hasher as TreeHasher>::HashOut,
+}
+
+impl<V: Leafable> Clone for Visit<V> {
+    fn clone(&self) -> Self {
+        Self {
+            key: self.key,
+            hash: self.hash.clone(),
+            left: self.left.clone(),
+            right: self.right.clone(),
+        }
+    }
+}
+
+// `NodeWalk` iterates over the nodes reachable from a root, depth first (a
+// node before its left subtree, then its right one) or breadth first (by
+// depth, left to right). Leaves and empty subtrees are not visited. By
+// default a node is visited once, at the first position it is reached from,
+// so a subtree shared between positions is walked once; `all_positions`
+// walks it at every position instead. A node missing from the store is
+// yielded as an error and its subtree is skipped.
+pub struct NodeWalk<'a, V: Leafable, S> {
+    db: &'a S,
+    zero_hashes: &'a [<V::Hasher as TreeHasher>::HashOut],
+    pending: VecDeque<(NodeKey, <V::Hasher as TreeHasher>::HashOut)>,
+    breadth_first: bool,
+    // None once `all_positions` is set
+    visited: Option<HashSet<<V::Hasher as TreeHasher>::HashOut>>,
+}
+
+impl<'a, V: Leafable, S: NodeStore<V>> NodeWalk<'a, V, S> {
+    // `zero_hashes` are by depth, as in `MerkleTree`.
+    pub(crate) fn new(
+        db: &'a S,
+        zero_hashes: &'a [<V::Hasher as TreeHasher>::HashOut],
+        root: <V::Hasher as TreeHasher>::HashOut,
+        breadth_first: bool,
+    ) -> Self {
+        Self {
+            db,
+            zero_hashes,
+            pending: VecDeque::from([(NodeKey::root(), root)]),
+            breadth_first,
+            visited: Some(HashSet::new()),
+        }
+    }
+
+    pub fn all_positions(mut self) -> Self {
+        self.visited = None;
+        self
+    }
+}
+
+impl<V: Leafable, S: NodeStore<V>> Iterator for NodeWalk<'_, V, S> {
+    type Item = anyhow::Result<Visit<V>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let height = self.zero_hashes.len() - 1;
+        loop {
+            let (key, hash) = if self.breadth_first {
+                self.pending.pop_front()?
+            } else {
+                self.pending.pop_back()?
+            };
+            if key.depth() == height || hash == self.zero_hashes[key.depth()] {
+                continue;
+            }
+            if let Some(visited) = self.visited.as_mut() {
+                if !visited.insert(hash.clone()) {
+                    continue;
+                }
+            }
+            let node = match self.db.get(hash.clone()) {
+                Some(node) => node,
+                None => {
+                    return Some(Err(anyhow::anyhow!(
+                        "cannot find node at depth {}",
+                        key.depth()
+                    )))
+                }
+            };
+            let children = [
+                (key.child(false), node.left.clone()),
+                (key.child(true), node.right.clone()),
+            ];
+            if self.breadth_first {
+                self.pending.extend(children);
+            } else {
+                self.pending.extend(children.into_iter().rev());
+            }
+            return Some(Ok(Visit {
+                key,
+                hash,
+                left: node.left,
+                right: node.right,
+            }));
+        }
+    }
+}
+
+impl<V: Leafable> MerkleTree<V> {
+    pub fn walk_depth_first<'a, S: NodeStore<V>>(
+        &'a self,
+        db: &'a S,
+        root: <V::Hasher as TreeHasher>::HashOut,
+    ) -> NodeWalk<'a, V, S> {
+        NodeWalk::new(db, &self.zero_hashes, root, false)
+    }
+
+    pub fn walk_breadth_first<'a, S: NodeStore<V>>(
+        &'a self,
+        db: &'a S,
+        root: <V::Hasher as TreeHasher>::HashOut,
+    ) -> NodeWalk<'a, V, S> {
+        NodeWalk::new(db, &self.zero_hashes, root, true)
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, node_key::NodeKey,
+        traits::Leafable,
+    };
+
+    use super::Visit;
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_node_walk() {
+        let height = 3;
+        let mut db = MockDB::<Leaf>::new();
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        // the subtrees at (1, 0) and (1, 1) are the same
+        for i in [0u128, 3, 4, 7] {
+            tree.update_leaf(&mut db, LeafIndex::new(i, height).unwrap(), 5u32.hash())
+                .unwrap();
+        }
+        let root = tree.get_root();
+        let keys = |walk: Vec<anyhow::Result<Visit<Leaf>>>| {
+            walk.into_iter()
+                .map(|visit| visit.map(|visit| (visit.key.depth(), visit.key.index)))
+                .collect::<anyhow::Result<Vec<_>>>()
+                .unwrap()
+        };
+        assert_eq!(
+            keys(tree.walk_depth_first(&db, root).collect()),
+            vec![(0, 0), (1, 0), (2, 0), (2, 1)]
+        );
+        assert_eq!(
+            keys(tree.walk_breadth_first(&db, root).collect()),
+            vec![(0, 0), (1, 0), (2, 0), (2, 1)]
+        );
+        assert_eq!(
+            keys(tree.walk_depth_first(&db, root).all_positions().collect()),
+            vec![(0, 0), (1, 0), (2, 0), (2, 1), (1, 1), (2, 2), (2, 3)]
+        );
+        assert_eq!(
+            keys(tree.walk_breadth_first(&db, root).all_positions().collect()),
+            vec![(0, 0), (1, 0), (1, 1), (2, 0), (2, 1), (2, 2), (2, 3)]
+        );
+        let first = tree.walk_depth_first(&db, root).next().unwrap().unwrap();
+        assert_eq!(first.key, NodeKey::root());
+        assert_eq!(first.hash, root);
+        assert_eq!(first.left, first.right);
+
+        // a missing node is an error
+        assert!(tree
+            .walk_depth_first(&MockDB::new(), root)
+            .collect::<anyhow::Result<Vec<_>>>()
+            .is_err());
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{error::VerifyError, traits::TreeHasher};
+
+// Proof verification over a stream of siblings, for verifiers that cannot
+// allocate: the siblings are read one at a time from the leaf up and the
+// index bits are read from the integer, so no `Vec` of either is built.
+// `MerkleProof` verifies through the same code.
+
+// The root that `siblings`, from the leaf up, and `index` lead to from
+// `leaf_hash`, and the number of siblings read. Reads bit `i` of `index` for
+// sibling `i`; bits past the last sibling are not checked.
+pub fn root_from_siblings<T: TreeHasher>(
+    leaf_hash: T::HashOut,
+    index: u128,
+    siblings: impl IntoIterator<Item = T::HashOut>,
+) -> (T::HashOut, usize) {
+    let mut state = leaf_hash;
+    let mut height = 0;
+    for sibling in siblings {
+        state = if height < 128 && (index >> height) & 1 == 1 {
+            T::two_to_one(sibling, state)
+        } else {
+            T::two_to_one(state, sibling)
+        };
+        height += 1;
+    }
+    (state, height)
+}
+
+// Checks that `siblings` prove `leaf_hash` at `index` of a tree of `height`
+// levels with root `merkle_root`, with the errors of `MerkleProof::verify`.
+pub fn verify_siblings<T: TreeHasher>(
+    leaf_hash: T::HashOut,
+    index: u128,
+    height: usize,
+    siblings: impl IntoIterator<Item = T::HashOut>,
+    merkle_root: T::HashOut,
+) -> Result<(), VerifyError<T::HashOut>> {
+    if height < 128 && index >> height != 0 {
+        return Err(VerifyError::IndexOutOfRange { index, height });
+    }
+    let (computed_root, proof_height) = root_from_siblings::<T>(leaf_hash.clone(), index, siblings);
+    if proof_height != height {
+        return Err(VerifyError::InvalidIndexLength {
+            expected: proof_height,
+            actual: height,
+        });
+    }
+    if computed_root != merkle_root {
+        return Err(VerifyError::RootMismatch {
+            leaf_hash,
+            index,
+            computed_root,
+            expected_root: merkle_root,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use intmax2_zkp::utils::leafable_hasher::PoseidonLeafableHasher;
+
+    use crate::{
+        error::VerifyError, leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB,
+        traits::Leafable,
+    };
+
+    use super::verify_siblings;
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_verify_siblings() {
+        let height = 10;
+        let mut db = MockDB::<Leaf>::new();
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        tree.update_leaf(&mut db, LeafIndex::new(5, height).unwrap(), 5u32.hash())
+            .unwrap();
+        tree.update_leaf(&mut db, LeafIndex::new(900, height).unwrap(), 9u32.hash())
+            .unwrap();
+        let root = tree.get_root();
+        let proof = tree.prove(LeafIndex::new(900, height).unwrap()).unwrap();
+        // the first `count` siblings of the proof
+        let verify = |leaf, index, count| {
+            let siblings = proof.siblings.iter().take(count).cloned();
+            verify_siblings::<PoseidonLeafableHasher>(leaf, index, height, siblings, root)
+        };
+        assert!(verify(9u32.hash(), 900, height).is_ok());
+        assert!(matches!(
+            verify(9u32.hash(), 5, height),
+            Err(VerifyError::RootMismatch { .. })
+        ));
+        assert_eq!(
+            verify(9u32.hash(), 1024, height),
+            Err(VerifyError::IndexOutOfRange {
+                index: 1024,
+                height
+            })
+        );
+        assert_eq!(
+            verify(9u32.hash(), 900, height - 1),
+            Err(VerifyError::InvalidIndexLength {
+                expected: height - 1,
+                actual: height
+            })
+        );
+    }
+}
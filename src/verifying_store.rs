@@ -0,0 +1,213 @@
+use crate::{
+    error::CorruptNode,
+    node::Node,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+// `VerifyingStore` wraps a `NodeStore` and rehashes the children of every
+// node it reads, so that a record damaged on disk is caught when it is read
+// instead of ending up in a proof that does not verify. Node keys are the
+// hash of the children, so this needs no checksum in the backend; it costs
+// one hash per node read. Proofs from the store fail with
+// `ProofError::CorruptNode`, tree reads and updates that go through the store
+// with `DbTreeError::CorruptNode`, and only `get`, `contains` and `with_node`
+// treat a corrupted node as missing. Leaf data is not checked.
+#[derive(Clone, Debug)]
+pub struct VerifyingStore<S> {
+    inner: S,
+}
+
+impl<S> VerifyingStore<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+fn check<V: Leafable>(
+    key: &<V::Hasher as TreeHasher>::HashOut,
+    node: &Node<V>,
+) -> Result<(), CorruptNode<<V::Hasher as TreeHasher>::HashOut>> {
+    let computed = <V::Hasher as TreeHasher>::two_to_one(node.left.clone(), node.right.clone());
+    if computed != *key {
+        return Err(CorruptNode {
+            key: key.clone(),
+            computed,
+        });
+    }
+    Ok(())
+}
+
+impl<V: Leafable, S: NodeStore<V>> NodeStore<V> for VerifyingStore<S> {
+    fn insert(&mut self, key: <V::Hasher as TreeHasher>::HashOut, node: Node<V>) {
+        self.inner.insert(key, node)
+    }
+
+    fn get(&self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>> {
+        self.try_with_node(key, |node| node.clone()).ok().flatten()
+    }
+
+    fn remove(&mut self, key: <V::Hasher as TreeHasher>::HashOut) -> Option<Node<V>> {
+        self.inner.remove(key)
+    }
+
+    fn contains(&self, key: <V::Hasher as TreeHasher>::HashOut) -> bool {
+        self.try_with_node(key, |_| ()).ok().flatten().is_some()
+    }
+
+    fn insert_leaf_data(&mut self, leaf_hash: <V::Hasher as TreeHasher>::HashOut, data: Vec<u8>) {
+        self.inner.insert_leaf_data(leaf_hash, data)
+    }
+
+    fn get_leaf_data(&self, leaf_hash: <V::Hasher as TreeHasher>::HashOut) -> Option<Vec<u8>> {
+        self.inner.get_leaf_data(leaf_hash)
+    }
+
+    fn remove_leaf_data(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Option<Vec<u8>> {
+        self.inner.remove_leaf_data(leaf_hash)
+    }
+
+    fn insert_leaf_metadata(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+        metadata: Vec<u8>,
+    ) {
+        self.inner.insert_leaf_metadata(leaf_hash, index, metadata)
+    }
+
+    fn get_leaf_metadata(
+        &self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+    ) -> Option<Vec<u8>> {
+        self.inner.get_leaf_metadata(leaf_hash, index)
+    }
+
+    fn remove_leaf_metadata(&mut self, leaf_hash: <V::Hasher as TreeHasher>::HashOut) {
+        self.inner.remove_leaf_metadata(leaf_hash)
+    }
+
+    fn remove_leaf_metadata_at(
+        &mut self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: u128,
+    ) {
+        self.inner.remove_leaf_metadata_at(leaf_hash, index)
+    }
+
+    fn with_node<R>(
+        &self,
+        key: <V::Hasher as TreeHasher>::HashOut,
+        f: impl FnOnce(&Node<V>) -> R,
+    ) -> Option<R> {
+        self.try_with_node(key, f).ok().flatten()
+    }
+
+    fn try_with_node<R>(
+        &self,
+        key: <V::Hasher as TreeHasher>::HashOut,
+        f: impl FnOnce(&Node<V>) -> R,
+    ) -> Result<Option<R>, CorruptNode<<V::Hasher as TreeHasher>::HashOut>> {
+        let checked = self.inner.try_with_node(key.clone(), |node| {
+            check(&key, node)?;
+            Ok(f(node))
+        })?;
+        checked.transpose()
+    }
+
+    fn num_nodes(&self) -> Option<usize> {
+        self.inner.num_nodes()
+    }
+
+    fn insert_batch(&mut self, nodes: Vec<(<V::Hasher as TreeHasher>::HashOut, Node<V>)>) {
+        self.inner.insert_batch(nodes)
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        error::{DbTreeError, ProofError},
+        leaf_index::LeafIndex,
+        merkle_tree::MerkleTree,
+        mock_db::MockDB,
+        node::Node,
+        node_key::NodeKey,
+        node_store::NodeStore,
+        service::ProofService,
+        traits::Leafable,
+    };
+
+    use super::VerifyingStore;
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_verifying_store() {
+        let height = 8;
+        let mut db = VerifyingStore::new(MockDB::<Leaf>::new());
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        let index = move |i: u128| LeafIndex::new(i, height).unwrap();
+        for i in 0..8 {
+            tree.update_leaf(&mut db, index(i), (i as u32 + 1).hash())
+                .unwrap();
+        }
+        let root = tree.get_root();
+        let proof = tree.prove_with_given_root(&db, root, index(2)).unwrap();
+        assert_eq!(proof, tree.prove(index(2)).unwrap());
+        let mut compacted = tree.clone();
+        compacted.compact(&db, 0).unwrap();
+
+        // flip the children of a node on the path
+        let key = tree.get_node_hash(NodeKey::new(6, 0)).unwrap();
+        let node = db.get(key).unwrap();
+        let mut inner = db.into_inner();
+        inner.insert(
+            key,
+            Node {
+                left: node.right,
+                right: node.left,
+            },
+        );
+        let db = VerifyingStore::new(inner);
+        assert_eq!(
+            tree.prove_with_given_root(&db, root, index(2)),
+            Err(ProofError::CorruptNode { depth: 6 })
+        );
+        assert!(db.get(key).is_none());
+        // reads and updates through the store report the corrupted node, and
+        // leave the tree as it was
+        let error = DbTreeError::CorruptNode {
+            key: NodeKey::new(6, 0),
+        };
+        let mut db = db;
+        assert_eq!(
+            compacted.update_leaf(&mut db, index(2), 9u32.hash()),
+            Err(error)
+        );
+        assert_eq!(compacted.get_root(), root);
+        assert_eq!(
+            compacted.get_node_hash_with_store(&db, NodeKey::new(height, 2)),
+            Err(error)
+        );
+        let service = ProofService::new(compacted, db);
+        assert_eq!(service.get_proof(2).unwrap_err(), error);
+        let db = service.into_inner().1;
+        // the unchecked store hands out the bad node
+        assert!(tree
+            .prove_with_given_root(db.inner(), root, index(2))
+            .is_ok());
+    }
+}
@@ -0,0 +1,471 @@
+use std::collections::VecDeque;
+
+use crate::{
+    batch_hasher::BatchHasher,
+    error::DbTreeError,
+    historical_proof::HistoricalProof,
+    leaf_index::LeafIndex,
+    merkle_tree::{MerkleProof, MerkleTree},
+    node_store::NodeStore,
+    ref_counted_db::RefCountedDB,
+    root_history::RootHistory,
+    root_index::RootIndex,
+    traits::{Leafable, TreeHasher},
+};
+
+// Decides which committed versions are kept. The latest version is never
+// expired.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetentionPolicy {
+    KeepAll,
+    // keep the last N committed versions
+    KeepLast(usize),
+    // keep versions whose timestamp is at most T older than the latest one
+    KeepNewerThan(u64),
+}
+
+#[derive(Clone, Debug)]
+pub struct Version<V: Leafable> {
+    pub version: u64,
+    pub root: <V::Hasher as TreeHasher>::HashOut,
+    pub timestamp: u64,
+}
+
+// `VersionedMerkleTree` is a `MerkleTree` whose roots are committed as
+// numbered versions and kept alive in a `RefCountedDB` according to a
+// `RetentionPolicy`. Expired versions are scheduled for deletion and their
+// nodes are removed from the store by `prune`.
+#[derive(Clone, Debug)]
+pub struct VersionedMerkleTree<V: Leafable> {
+    tree: MerkleTree<V>,
+    policy: RetentionPolicy,
+    versions: VecDeque<Version<V>>, // oldest first
+    next_version: u64,
+    head: <V::Hasher as TreeHasher>::HashOut, // retained current root
+    expired: Vec<<V::Hasher as TreeHasher>::HashOut>,
+    root_index: Option<RootIndex<V>>,
+    root_history: Option<RootHistory<V>>,
+    // versions kept past the policy until unpinned, by root
+    pinned: Vec<Version<V>>,
+}
+
+impl<V: Leafable> VersionedMerkleTree<V> {
+    pub fn new<S: NodeStore<V>>(
+        db: &mut RefCountedDB<V, S>,
+        height: usize,
+        empty_leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        policy: RetentionPolicy,
+    ) -> Self {
+        let tree = MerkleTree::new(db, height, empty_leaf_hash);
+        let head = tree.get_root();
+        db.retain(head.clone());
+        Self {
+            tree,
+            policy,
+            versions: VecDeque::new(),
+            next_version: 0,
+            head,
+            expired: vec![],
+            root_index: None,
+            root_history: None,
+            pinned: vec![],
+        }
+    }
+
+    // Index every version committed from now on, so that `prove_version` does
+    // not have to walk the store.
+    pub fn enable_root_index(&mut self) {
+        if self.root_index.is_none() {
+            self.root_index = Some(RootIndex::new());
+        }
+    }
+
+    pub fn root_index(&self) -> Option<&RootIndex<V>> {
+        self.root_index.as_ref()
+    }
+
+    // Record the root of every version committed from now on in a
+    // `RootHistory`, so that `prove_historical` can prove a version's root
+    // against the root of the history.
+    pub fn enable_root_history(&mut self) {
+        if self.root_history.is_none() {
+            self.root_history = Some(RootHistory::new());
+        }
+    }
+
+    pub fn root_history(&self) -> Option<&RootHistory<V>> {
+        self.root_history.as_ref()
+    }
+
+    pub fn tree(&self) -> &MerkleTree<V> {
+        &self.tree
+    }
+
+    pub fn policy(&self) -> RetentionPolicy {
+        self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: RetentionPolicy) {
+        self.policy = policy;
+        self.expire();
+    }
+
+    pub fn versions(&self) -> impl Iterator<Item = &Version<V>> {
+        self.versions.iter()
+    }
+
+    pub fn latest_version(&self) -> Option<&Version<V>> {
+        self.versions.back()
+    }
+
+    // A retained or pinned version.
+    pub fn get_version(&self, version: u64) -> Option<&Version<V>> {
+        self.versions
+            .iter()
+            .chain(&self.pinned)
+            .find(|v| v.version == version)
+    }
+
+    // Keeps the latest retained version with `root`, and its nodes, past the
+    // retention policy until `unpin`, e.g. while an on-chain withdrawal
+    // refers to it. Returns false if no retained version has `root`. Pinning
+    // a pinned root does nothing.
+    pub fn pin<S: NodeStore<V>>(
+        &mut self,
+        db: &mut RefCountedDB<V, S>,
+        root: <V::Hasher as TreeHasher>::HashOut,
+    ) -> bool {
+        if self.is_pinned(root.clone()) {
+            return true;
+        }
+        let version = match self.versions.iter().rev().find(|v| v.root == root) {
+            Some(version) => version.clone(),
+            None => return false,
+        };
+        db.retain(root);
+        self.pinned.push(version);
+        true
+    }
+
+    // Drops the pin on `root`. If its version has expired in the meantime,
+    // its nodes that no retained version shares are deleted right away;
+    // returns their number.
+    pub fn unpin<S: NodeStore<V>>(
+        &mut self,
+        db: &mut RefCountedDB<V, S>,
+        root: <V::Hasher as TreeHasher>::HashOut,
+    ) -> usize {
+        let position = match self.pinned.iter().position(|v| v.root == root) {
+            Some(position) => position,
+            None => return 0,
+        };
+        self.pinned.remove(position);
+        let still_retained = self.versions.iter().any(|v| v.root == root);
+        if let Some(root_index) = self.root_index.as_mut() {
+            if !still_retained {
+                root_index.remove(root.clone());
+            }
+        }
+        db.release(root)
+    }
+
+    pub fn is_pinned(&self, root: <V::Hasher as TreeHasher>::HashOut) -> bool {
+        self.pinned.iter().any(|v| v.root == root)
+    }
+
+    pub fn pinned(&self) -> impl Iterator<Item = &Version<V>> {
+        self.pinned.iter()
+    }
+
+    // number of expired roots waiting for `prune`
+    pub fn pending_prune(&self) -> usize {
+        self.expired.len()
+    }
+
+    pub fn update_leaf<S: NodeStore<V>>(
+        &mut self,
+        db: &mut RefCountedDB<V, S>,
+        index: impl Into<LeafIndex>,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Result<(), DbTreeError> {
+        self.tree.update_leaf(db, index, leaf_hash)?;
+        self.move_head(db);
+        Ok(())
+    }
+
+    pub fn update_leaves<S: NodeStore<V>>(
+        &mut self,
+        db: &mut RefCountedDB<V, S>,
+        leaves: &[(LeafIndex, <V::Hasher as TreeHasher>::HashOut)],
+    ) -> Result<(), DbTreeError>
+    where
+        V::Hasher: BatchHasher,
+    {
+        self.tree.update_leaves(db, leaves)?;
+        self.move_head(db);
+        Ok(())
+    }
+
+    // Moves the head reference to the current root so that intermediate roots
+    // between commits are released right away.
+    fn move_head<S: NodeStore<V>>(&mut self, db: &mut RefCountedDB<V, S>) {
+        let root = self.tree.get_root();
+        db.retain(root.clone());
+        let old_head = std::mem::replace(&mut self.head, root);
+        db.release(old_head);
+    }
+
+    // Commits the current root as a new version and schedules expired
+    // versions for deletion. Returns the new version number.
+    pub fn commit<S: NodeStore<V>>(&mut self, db: &mut RefCountedDB<V, S>, timestamp: u64) -> u64 {
+        let version = self.next_version;
+        self.next_version += 1;
+        let root = self.tree.get_root();
+        db.retain(root.clone());
+        self.versions.push_back(Version {
+            version,
+            root,
+            timestamp,
+        });
+        if let Some(root_index) = self.root_index.as_mut() {
+            root_index.commit(&self.tree);
+        }
+        if let Some(root_history) = self.root_history.as_mut() {
+            root_history
+                .record(version, self.tree.get_root())
+                .expect("versions are committed in order");
+        }
+        self.expire();
+        version
+    }
+
+    // Deletes the nodes of all expired versions that are not shared with a
+    // retained version. Returns the number of deleted nodes.
+    pub fn prune<S: NodeStore<V>>(&mut self, db: &mut RefCountedDB<V, S>) -> usize {
+        self.expired.drain(..).map(|root| db.release(root)).sum()
+    }
+
+    pub fn prove_version<S: NodeStore<V>>(
+        &self,
+        db: &RefCountedDB<V, S>,
+        version: u64,
+        index: impl Into<LeafIndex>,
+    ) -> Option<MerkleProof<V>> {
+        let index = index.into();
+        let root = self.get_version(version)?.root.clone();
+        if let Some(proof) = self
+            .root_index
+            .as_ref()
+            .and_then(|root_index| root_index.prove(&self.tree, root.clone(), index))
+        {
+            return Some(proof);
+        }
+        self.tree.prove_with_given_root(db, root, index).ok()
+    }
+
+    // `prove_version` together with a proof that the root of `version` is in
+    // the root history. `None` if the root history is not enabled, or
+    // `version` is not retained or was committed before it was.
+    pub fn prove_historical<S: NodeStore<V>>(
+        &self,
+        db: &RefCountedDB<V, S>,
+        version: u64,
+        index: impl Into<LeafIndex>,
+    ) -> Option<HistoricalProof<V>> {
+        let root_history = self.root_history.as_ref()?;
+        self.get_version(version)?;
+        root_history
+            .prove_historical(&self.tree, db, version, index)
+            .ok()
+    }
+
+    fn expire(&mut self) {
+        let latest_timestamp = match self.versions.back() {
+            Some(latest) => latest.timestamp,
+            None => return,
+        };
+        while self.versions.len() > 1 {
+            let oldest = &self.versions[0];
+            let expired = match self.policy {
+                RetentionPolicy::KeepAll => false,
+                RetentionPolicy::KeepLast(n) => self.versions.len() > n,
+                RetentionPolicy::KeepNewerThan(age) => {
+                    latest_timestamp.saturating_sub(oldest.timestamp) > age
+                }
+            };
+            if !expired {
+                break;
+            }
+            let oldest = self.versions.pop_front().unwrap();
+            // the same root can be committed as several versions
+            let still_retained = self.versions.iter().any(|v| v.root == oldest.root)
+                || self.is_pinned(oldest.root.clone());
+            if let Some(root_index) = self.root_index.as_mut() {
+                if !still_retained {
+                    root_index.remove(oldest.root.clone());
+                }
+            }
+            self.expired.push(oldest.root);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use intmax2_zkp::utils::poseidon_hash_out::PoseidonHashOut;
+
+    use crate::{
+        leaf_index::LeafIndex, mock_db::MockDB, node_store::NodeStore,
+        ref_counted_db::RefCountedDB, traits::Leafable,
+    };
+
+    use super::{RetentionPolicy, VersionedMerkleTree};
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_retention_policy() {
+        let height = 16;
+
+        let mut db = RefCountedDB::new(MockDB::<Leaf>::new());
+        let empty_leaf_hash = PoseidonHashOut::hash_inputs_u32(&[]);
+        let mut tree = VersionedMerkleTree::new(
+            &mut db,
+            height,
+            empty_leaf_hash,
+            RetentionPolicy::KeepLast(3),
+        );
+
+        let mut roots = vec![];
+        for i in 0..5 {
+            let leaf = i as u32;
+            tree.update_leaf(
+                &mut db,
+                LeafIndex::new(i as u128, height).unwrap(),
+                leaf.hash(),
+            )
+            .unwrap();
+            tree.commit(&mut db, i as u64 * 10);
+            roots.push(tree.tree().get_root());
+        }
+        let versions: Vec<u64> = tree.versions().map(|v| v.version).collect();
+        assert_eq!(versions, vec![2, 3, 4]);
+        assert_eq!(tree.pending_prune(), 2);
+
+        assert!(tree.prune(&mut db) > 0);
+        assert_eq!(tree.pending_prune(), 0);
+        assert!(db.get(roots[0]).is_none());
+        assert!(db.get(roots[1]).is_none());
+
+        // retained versions can still be proven
+        let leaf_index = LeafIndex::new(2, height).unwrap();
+        let proof = tree.prove_version(&db, 2, leaf_index).unwrap();
+        assert_eq!(proof.get_root(&2, leaf_index), roots[2]);
+        assert!(tree
+            .prove_version(&db, 1, LeafIndex::new(1, height).unwrap())
+            .is_none());
+
+        // versions committed at 20 and 30 are more than 5 older than 40
+        tree.set_policy(RetentionPolicy::KeepNewerThan(5));
+        let versions: Vec<u64> = tree.versions().map(|v| v.version).collect();
+        assert_eq!(versions, vec![4]);
+        tree.prune(&mut db);
+        assert!(db.get(roots[3]).is_none());
+        assert!(db.get(roots[4]).is_some());
+    }
+
+    #[test]
+    fn test_root_index() {
+        let height = 16;
+
+        let mut db = RefCountedDB::new(MockDB::<Leaf>::new());
+        let empty_leaf_hash = PoseidonHashOut::hash_inputs_u32(&[]);
+        let mut tree =
+            VersionedMerkleTree::new(&mut db, height, empty_leaf_hash, RetentionPolicy::KeepAll);
+        tree.enable_root_index();
+
+        let mut roots = vec![];
+        for i in 0..5 {
+            let leaf = i as u32;
+            tree.update_leaf(
+                &mut db,
+                LeafIndex::new(i as u128, height).unwrap(),
+                leaf.hash(),
+            )
+            .unwrap();
+            tree.commit(&mut db, i as u64);
+            roots.push(tree.tree().get_root());
+        }
+        let root_index = tree.root_index().unwrap();
+        assert_eq!(root_index.len(), 5);
+        for (version, &root) in roots.iter().enumerate() {
+            for index in 0..5 {
+                let leaf_index = LeafIndex::new(index as u128, height).unwrap();
+                let indexed = root_index.prove(tree.tree(), root, leaf_index).unwrap();
+                let walked = tree
+                    .tree()
+                    .prove_with_given_root(&db, root, leaf_index)
+                    .unwrap();
+                assert_eq!(indexed, walked);
+                let proof = tree.prove_version(&db, version as u64, leaf_index).unwrap();
+                assert_eq!(proof, walked);
+            }
+        }
+
+        tree.set_policy(RetentionPolicy::KeepLast(2));
+        assert_eq!(tree.root_index().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_pinned_roots() {
+        let height = 16;
+
+        let mut db = RefCountedDB::new(MockDB::<Leaf>::new());
+        let empty_leaf_hash = PoseidonHashOut::hash_inputs_u32(&[]);
+        let mut tree = VersionedMerkleTree::new(
+            &mut db,
+            height,
+            empty_leaf_hash,
+            RetentionPolicy::KeepLast(1),
+        );
+        tree.enable_root_index();
+
+        let mut roots = vec![];
+        for i in 0..3 {
+            tree.update_leaf(
+                &mut db,
+                LeafIndex::new(i as u128, height).unwrap(),
+                (i as u32 + 1).hash(),
+            )
+            .unwrap();
+            tree.commit(&mut db, i as u64);
+            roots.push(tree.tree().get_root());
+            if i == 0 {
+                assert!(tree.pin(&mut db, roots[0]));
+                assert!(tree.pin(&mut db, roots[0]));
+            }
+        }
+        // version 1 expired before it could be pinned
+        assert!(!tree.pin(&mut db, roots[1]));
+        tree.prune(&mut db);
+        assert!(db.get(roots[0]).is_some());
+        assert!(db.get(roots[1]).is_none());
+        assert!(tree.is_pinned(roots[0]));
+        assert_eq!(
+            tree.pinned().map(|v| v.version).collect::<Vec<_>>(),
+            vec![0]
+        );
+
+        // the pinned version is still proven, through the root index too
+        let leaf_index = LeafIndex::new(0, height).unwrap();
+        let proof = tree.prove_version(&db, 0, leaf_index).unwrap();
+        assert_eq!(proof.get_root(&1, leaf_index), roots[0]);
+        assert_eq!(tree.root_index().unwrap().len(), 2);
+
+        assert!(tree.unpin(&mut db, roots[0]) > 0);
+        assert_eq!(tree.unpin(&mut db, roots[0]), 0);
+        assert!(db.get(roots[0]).is_none());
+        assert!(tree.get_version(0).is_none());
+        assert_eq!(tree.root_index().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,327 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context as _;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    batch_hasher::BatchHasher,
+    durability::{Durability, SyncedFile},
+    leaf_index::LeafIndex,
+    merkle_tree::MerkleTree,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+// One logged batch of (leaf index, leaf hash).
+pub type WalRecord<H> = Vec<(u128, H)>;
+
+// Size of the record header: body length, CRC32 of the body and CRC32 of
+// these first 8 bytes, all u32 LE. The header checksum tells a damaged
+// length, which would make the rest of the log look like a torn record,
+// from a record cut short by a crash.
+const HEADER_LEN: usize = 12;
+
+// Log of the leaf updates applied since the last checkpoint. Each record is
+// one batch, written and synced before the batch touches the tree, so after
+// a crash the checkpoint plus the log give back every acknowledged update.
+// Records set leaves to absolute values, so replaying a record that already
+// made it into the checkpoint is harmless; this is what lets
+// `checkpoint_logged` write the checkpoint first and clear the log after.
+//
+// A record is a header followed by the JSON list of (index, leaf hash).
+// A torn record at the end, left by a crash during `append`, is dropped on
+// open; a bad record or header anywhere else is an error.
+//
+// Records are synced as set by `set_durability`, every one by default. The
+// log replays whatever made it to disk, so a laxer policy can lose the last
+// acknowledged batches.
+#[derive(Debug)]
+pub struct WriteAheadLog<H> {
+    file: SyncedFile,
+    path: PathBuf,
+    records: usize,
+    _hash: PhantomData<H>,
+}
+
+impl<H: Serialize + DeserializeOwned> WriteAheadLog<H> {
+    // Opens or creates the log at `path` and returns it with the batches it
+    // holds, oldest first.
+    pub fn open<P: AsRef<Path>>(path: P) -> anyhow::Result<(Self, Vec<WalRecord<H>>)> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("cannot open write-ahead log {}", path.display()))?;
+        let batches = read_records(&mut file, &path)?
+            .iter()
+            .map(|body| serde_json::from_slice(body))
+            .collect::<Result<Vec<_>, _>>()?;
+        let wal = Self {
+            file: SyncedFile::new(file, Durability::default()),
+            path,
+            records: batches.len(),
+            _hash: PhantomData,
+        };
+        Ok((wal, batches))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Number of records since the log was last cleared.
+    pub fn len(&self) -> usize {
+        self.records
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records == 0
+    }
+
+    pub fn durability(&self) -> Durability {
+        self.file.durability()
+    }
+
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.file.set_durability(durability);
+    }
+
+    // Appends one batch, synced to disk as the durability policy says.
+    pub fn append(&mut self, leaves: &[(u128, H)]) -> anyhow::Result<()> {
+        append_record(&mut self.file, &serde_json::to_vec(leaves)?)?;
+        self.records += 1;
+        self.file.committed()?;
+        Ok(())
+    }
+
+    // Syncs every appended batch to disk, whatever the policy.
+    pub fn sync(&mut self) -> anyhow::Result<()> {
+        Ok(self.file.sync()?)
+    }
+
+    // Drops every record, once they are all in a checkpoint.
+    pub fn clear(&mut self) -> anyhow::Result<()> {
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.sync_all()?;
+        self.records = 0;
+        Ok(())
+    }
+}
+
+// Reads the records of a log file and leaves the file positioned after the
+// last good one, truncating a torn record at the end. A record is torn if
+// its header or its body runs past the end of the file, or if it is the last
+// one and its body does not match its checksum.
+pub(crate) fn read_records(file: &mut File, path: &Path) -> anyhow::Result<Vec<Vec<u8>>> {
+    let mut bytes = vec![];
+    file.read_to_end(&mut bytes)?;
+
+    let mut records = vec![];
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let Some(header) = bytes.get(offset..offset + HEADER_LEN) else {
+            break;
+        };
+        let header_checksum = u32::from_le_bytes(header[8..].try_into().unwrap());
+        anyhow::ensure!(
+            crc32fast::hash(&header[..8]) == header_checksum,
+            "corrupted log {}: bad record header at byte {}",
+            path.display(),
+            offset
+        );
+        let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+        let checksum = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        let end = offset + HEADER_LEN + len;
+        let Some(body) = bytes.get(offset + HEADER_LEN..end) else {
+            break;
+        };
+        if crc32fast::hash(body) != checksum {
+            anyhow::ensure!(
+                end == bytes.len(),
+                "corrupted log {}: bad record at byte {}",
+                path.display(),
+                offset
+            );
+            break;
+        }
+        records.push(body.to_vec());
+        offset = end;
+    }
+    if offset < bytes.len() {
+        // drop the torn record so that new records follow the last good one
+        file.set_len(offset as u64)?;
+        file.sync_all()?;
+    }
+    file.seek(SeekFrom::Start(offset as u64))?;
+    Ok(records)
+}
+
+// Appends one record, leaving the sync to the caller. A crash midway leaves
+// a torn record, which `read_records` drops.
+pub(crate) fn append_record(file: &mut File, body: &[u8]) -> anyhow::Result<()> {
+    let mut record = Vec::with_capacity(HEADER_LEN + body.len());
+    record.extend((body.len() as u32).to_le_bytes());
+    record.extend(crc32fast::hash(body).to_le_bytes());
+    record.extend(crc32fast::hash(&record).to_le_bytes());
+    record.extend(body);
+    file.write_all(&record)?;
+    Ok(())
+}
+
+impl<V: Leafable> MerkleTree<V>
+where
+    <V::Hasher as TreeHasher>::HashOut: Serialize + DeserializeOwned,
+{
+    // `update_leaves` that logs the batch to `wal` first. Nothing is logged
+    // or applied if an index is invalid or a node on an update path is
+    // missing from `db`.
+    pub fn update_leaves_logged<S: NodeStore<V>>(
+        &mut self,
+        db: &mut S,
+        wal: &mut WriteAheadLog<<V::Hasher as TreeHasher>::HashOut>,
+        leaves: &[(LeafIndex, <V::Hasher as TreeHasher>::HashOut)],
+    ) -> anyhow::Result<()>
+    where
+        V::Hasher: BatchHasher,
+    {
+        let keys = self.check_leaf_indices(leaves)?;
+        self.check_update_paths(&*db, keys)?;
+        let record: Vec<_> = leaves
+            .iter()
+            .map(|(index, hash)| (index.index(), hash.clone()))
+            .collect();
+        wal.append(&record)?;
+        self.update_leaves_unchecked(db, leaves);
+        Ok(())
+    }
+
+    // Writes a checkpoint to `path` and then clears `wal`.
+    pub fn checkpoint_logged<S: NodeStore<V>, P: AsRef<Path>>(
+        &self,
+        db: &S,
+        path: P,
+        wal: &mut WriteAheadLog<<V::Hasher as TreeHasher>::HashOut>,
+    ) -> anyhow::Result<()> {
+        self.checkpoint(db, path)?;
+        wal.clear()
+    }
+
+    // Restores the checkpoint at `path` into `db` and replays the batches
+    // logged at `wal_path` since, returning the tree and the log to keep
+    // appending to.
+    pub fn recover<S: NodeStore<V>, P: AsRef<Path>, Q: AsRef<Path>>(
+        db: &mut S,
+        path: P,
+        wal_path: Q,
+    ) -> anyhow::Result<(Self, WriteAheadLog<<V::Hasher as TreeHasher>::HashOut>)>
+    where
+        V::Hasher: BatchHasher,
+    {
+        let mut tree = Self::restore(db, path)?;
+        let (wal, batches) = WriteAheadLog::open(wal_path)?;
+        for batch in batches {
+            let leaves = batch
+                .into_iter()
+                .map(|(index, hash)| Ok((LeafIndex::new(index, tree.height)?, hash)))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            tree.update_leaves_unchecked(db, &leaves);
+        }
+        Ok((tree, wal))
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use std::{fs::OpenOptions, io::Write};
+
+    use intmax2_zkp::utils::poseidon_hash_out::PoseidonHashOut;
+
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable,
+    };
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_recover_from_wal() {
+        let height = 8;
+        let dir = std::env::temp_dir().join(format!("db_tree_wal_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tree.json");
+        let wal_path = dir.join("tree.wal");
+        let index = move |i: u128| LeafIndex::new(i, height).unwrap();
+
+        let mut db = MockDB::<Leaf>::new();
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        tree.checkpoint(&db, &path).unwrap();
+        let (mut wal, pending) = super::WriteAheadLog::open(&wal_path).unwrap();
+        assert!(pending.is_empty());
+        for i in 0..3 {
+            let leaves: Vec<_> = (0..5)
+                .map(|j| (index(i * 10 + j), (j as u32 + 1).hash()))
+                .collect();
+            tree.update_leaves_logged(&mut db, &mut wal, &leaves)
+                .unwrap();
+        }
+        let bad = [(LeafIndex::new(1, height + 1).unwrap(), 1u32.hash())];
+        assert!(tree.update_leaves_logged(&mut db, &mut wal, &bad).is_err());
+        let mut compacted = tree.clone();
+        compacted.compact(&db, 2).unwrap();
+        let leaves = [(index(1), 1u32.hash())];
+        assert!(compacted
+            .update_leaves_logged(&mut MockDB::new(), &mut wal, &leaves)
+            .is_err());
+        assert_eq!(wal.len(), 3);
+        // crash in the middle of writing a fourth record
+        drop(wal);
+        OpenOptions::new()
+            .append(true)
+            .open(&wal_path)
+            .unwrap()
+            .write_all(&[100, 0, 0, 0, 1, 2])
+            .unwrap();
+
+        let mut recovered_db = MockDB::<Leaf>::new();
+        let (recovered, mut wal) =
+            MerkleTree::<Leaf>::recover(&mut recovered_db, &path, &wal_path).unwrap();
+        assert_eq!(recovered.get_root(), tree.get_root());
+        assert_eq!(wal.len(), 3);
+
+        // appends after recovery follow the last good record
+        let mut tree = recovered;
+        let mut db = recovered_db;
+        tree.update_leaves_logged(&mut db, &mut wal, &[(index(200), 9u32.hash())])
+            .unwrap();
+        tree.checkpoint_logged(&db, &path, &mut wal).unwrap();
+        assert!(wal.is_empty());
+        drop(wal);
+        let (recovered, wal) =
+            MerkleTree::<Leaf>::recover(&mut MockDB::new(), &path, &wal_path).unwrap();
+        assert_eq!(recovered.get_root(), tree.get_root());
+        assert!(wal.is_empty());
+
+        // a damaged length in the middle is an error, not a torn tail that
+        // would drop the good records after it
+        let (mut wal, _) = super::WriteAheadLog::<PoseidonHashOut>::open(&wal_path).unwrap();
+        for i in 0..3u32 {
+            wal.append(&[(i as u128, i.hash())]).unwrap();
+        }
+        drop(wal);
+        let mut bytes = std::fs::read(&wal_path).unwrap();
+        bytes[2] ^= 1;
+        std::fs::write(&wal_path, &bytes).unwrap();
+        assert!(super::WriteAheadLog::<PoseidonHashOut>::open(&wal_path).is_err());
+        assert_eq!(std::fs::read(&wal_path).unwrap(), bytes);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,122 @@
+use intmax2_zkp::utils::{
+    leafable_hasher::PoseidonLeafableHasher, poseidon_hash_out::PoseidonHashOut,
+};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    leaf_index::LeafIndex,
+    merkle_tree::{MerkleProof, MerkleTree},
+    mock_db::MockDB,
+    service::WireHash,
+    traits::HashLeaf,
+};
+
+// Browser bindings for Poseidon trees given by leaf hashes. A hash crosses the
+// boundary as its 32 byte `WireHash` encoding.
+type Leaf = HashLeaf<PoseidonLeafableHasher>;
+
+fn hash_from_bytes(bytes: &[u8]) -> Result<PoseidonHashOut, JsError> {
+    PoseidonHashOut::from_wire(bytes)
+        .ok_or_else(|| JsError::new("hash must be 32 bytes of canonical field elements"))
+}
+
+fn hash_to_bytes(hash: PoseidonHashOut) -> Vec<u8> {
+    hash.to_wire()
+}
+
+// A tree in its own in-memory store; the empty leaf is the zero hash.
+#[wasm_bindgen]
+pub struct WasmMerkleTree {
+    tree: MerkleTree<Leaf>,
+    db: MockDB<Leaf>,
+}
+
+#[wasm_bindgen]
+impl WasmMerkleTree {
+    // indices are u64s (BigInts in JS), so larger trees could not be filled
+    #[wasm_bindgen(constructor)]
+    pub fn new(height: usize) -> Result<WasmMerkleTree, JsError> {
+        if height > 64 {
+            return Err(JsError::new("height must be at most 64"));
+        }
+        let mut db = MockDB::new();
+        let tree = MerkleTree::new(&mut db, height, PoseidonHashOut::default());
+        Ok(Self { tree, db })
+    }
+
+    pub fn height(&self) -> usize {
+        self.tree.height()
+    }
+
+    #[wasm_bindgen(js_name = updateLeaf)]
+    pub fn update_leaf(&mut self, index: u64, leaf_hash: &[u8]) -> Result<(), JsError> {
+        let leaf_hash = hash_from_bytes(leaf_hash)?;
+        self.tree.update_leaf_at(&mut self.db, index, leaf_hash)?;
+        Ok(())
+    }
+
+    pub fn root(&self) -> Vec<u8> {
+        hash_to_bytes(self.tree.get_root())
+    }
+
+    pub fn prove(&self, index: u64) -> Result<WasmMerkleProof, JsError> {
+        Ok(WasmMerkleProof {
+            proof: self.tree.prove_at(index)?,
+        })
+    }
+}
+
+#[wasm_bindgen]
+pub struct WasmMerkleProof {
+    proof: MerkleProof<Leaf>,
+}
+
+#[wasm_bindgen]
+impl WasmMerkleProof {
+    pub fn height(&self) -> usize {
+        self.proof.height()
+    }
+
+    // Whether `leaf_hash` is at `index` of the tree with `root`.
+    pub fn verify(&self, leaf_hash: &[u8], index: u64, root: &[u8]) -> Result<bool, JsError> {
+        let leaf_hash = hash_from_bytes(leaf_hash)?;
+        let root = hash_from_bytes(root)?;
+        let index = LeafIndex::new(index as u128, self.proof.height())?;
+        Ok(self.proof.verify_hash(leaf_hash, index, root).is_ok())
+    }
+
+    #[wasm_bindgen(js_name = toJson)]
+    pub fn to_json(&self) -> Result<String, JsError> {
+        Ok(serde_json::to_string(&self.proof)?)
+    }
+
+    #[wasm_bindgen(js_name = fromJson)]
+    pub fn from_json(json: &str) -> Result<WasmMerkleProof, JsError> {
+        Ok(Self {
+            proof: serde_json::from_str(json)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use intmax2_zkp::utils::poseidon_hash_out::PoseidonHashOut;
+
+    use super::{hash_from_bytes, hash_to_bytes, WasmMerkleProof, WasmMerkleTree};
+
+    #[test]
+    fn test_wasm_tree() {
+        let mut tree = WasmMerkleTree::new(8).unwrap();
+        let leaf = hash_to_bytes(PoseidonHashOut::hash_inputs_u32(&[7]));
+        tree.update_leaf(5, &leaf).unwrap();
+        assert!(tree.update_leaf(256, &leaf).is_err());
+        assert!(tree.update_leaf(0, &leaf[1..]).is_err());
+        assert!(hash_from_bytes(&[0xff; 32]).is_err());
+
+        let root = tree.root();
+        let proof = WasmMerkleProof::from_json(&tree.prove(5).unwrap().to_json().unwrap()).unwrap();
+        assert!(proof.verify(&leaf, 5, &root).unwrap());
+        assert!(!proof.verify(&leaf, 4, &root).unwrap());
+        assert!(proof.verify(&leaf, 300, &root).is_err());
+    }
+}
@@ -0,0 +1,304 @@
+use crate::{
+    big_index::{BigIndex, MAX_WIDE_HEIGHT},
+    error::{DbTreeError, VerifyError},
+    merkle_tree::{check_height, MerkleProof},
+    node::Node,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+    zero_hashes::ZeroHashes,
+};
+
+// `WideTree` is a sparse Merkle tree of up to `MAX_WIDE_HEIGHT` levels, for
+// indices wider than `MerkleTree` takes. It keeps only its root in memory and
+// reads every path from the store, like a `MerkleTree` compacted to depth 0,
+// so each update or proof reads `height` nodes. Nodes are stored the same
+// way, so a `WideTree` of height up to `MAX_HEIGHT` has the roots, nodes and
+// proofs of the `MerkleTree` of that height.
+#[derive(Clone, Debug)]
+pub struct WideTree<V: Leafable> {
+    height: usize,
+    zero_hashes: Vec<<V::Hasher as TreeHasher>::HashOut>,
+    root: <V::Hasher as TreeHasher>::HashOut,
+}
+
+// siblings from the root down, and the leaf hash
+type Path<V> = (
+    Vec<<<V as Leafable>::Hasher as TreeHasher>::HashOut>,
+    <<V as Leafable>::Hasher as TreeHasher>::HashOut,
+);
+
+impl<V: Leafable> WideTree<V> {
+    // Panics if `height` is larger than `MAX_WIDE_HEIGHT`; see `try_new`.
+    pub fn new<S: NodeStore<V>>(
+        db: &mut S,
+        height: usize,
+        empty_leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Self {
+        Self::try_new(db, height, empty_leaf_hash).unwrap()
+    }
+
+    pub fn try_new<S: NodeStore<V>>(
+        db: &mut S,
+        height: usize,
+        empty_leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Result<Self, DbTreeError> {
+        let tree = Self::try_from_root(height, empty_leaf_hash, None)?;
+        if height > 0 && !db.contains(tree.zero_hashes[0].clone()) {
+            for depth in 0..height {
+                let child = tree.zero_hashes[depth + 1].clone();
+                db.insert(
+                    tree.zero_hashes[depth].clone(),
+                    Node {
+                        left: child.clone(),
+                        right: child,
+                    },
+                );
+            }
+        }
+        Ok(tree)
+    }
+
+    // The version `root` of a tree whose nodes are already in the store, or
+    // the empty tree for `None`. Panics if `height` is larger than
+    // `MAX_WIDE_HEIGHT`; see `try_from_root`.
+    pub fn from_root(
+        height: usize,
+        empty_leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        root: Option<<V::Hasher as TreeHasher>::HashOut>,
+    ) -> Self {
+        Self::try_from_root(height, empty_leaf_hash, root).unwrap()
+    }
+
+    pub fn try_from_root(
+        height: usize,
+        empty_leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        root: Option<<V::Hasher as TreeHasher>::HashOut>,
+    ) -> Result<Self, DbTreeError> {
+        check_height(height, MAX_WIDE_HEIGHT)?;
+        let zero_hashes = ZeroHashes::<V::Hasher>::new(empty_leaf_hash, height).by_depth(height);
+        Ok(Self {
+            height,
+            root: root.unwrap_or_else(|| zero_hashes[0].clone()),
+            zero_hashes,
+        })
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get_root(&self) -> <V::Hasher as TreeHasher>::HashOut {
+        self.root.clone()
+    }
+
+    pub fn get_leaf_hash<S: NodeStore<V>>(
+        &self,
+        db: &S,
+        index: &BigIndex,
+    ) -> anyhow::Result<<V::Hasher as TreeHasher>::HashOut> {
+        Ok(self.path(db, index)?.1)
+    }
+
+    pub fn prove<S: NodeStore<V>>(
+        &self,
+        db: &S,
+        index: &BigIndex,
+    ) -> anyhow::Result<MerkleProof<V>> {
+        let (mut siblings, _) = self.path(db, index)?;
+        siblings.reverse();
+        Ok(MerkleProof { siblings })
+    }
+
+    // Fails, without changing the tree, if a node on the path of `index` is
+    // missing from `db`.
+    pub fn update_leaf<S: NodeStore<V>>(
+        &mut self,
+        db: &mut S,
+        index: &BigIndex,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+    ) -> anyhow::Result<()> {
+        let (siblings, _) = self.path(&*db, index)?;
+        let mut hash = leaf_hash;
+        let mut batch = vec![];
+        for (depth, sibling) in siblings.into_iter().enumerate().rev() {
+            let (left, right) = if index.bit(self.height - depth - 1) {
+                (sibling, hash)
+            } else {
+                (hash, sibling)
+            };
+            let zero = &self.zero_hashes[depth + 1];
+            hash = if left == *zero && right == *zero {
+                self.zero_hashes[depth].clone()
+            } else {
+                let hash = <V::Hasher as TreeHasher>::two_to_one(left.clone(), right.clone());
+                batch.push((hash.clone(), Node { left, right }));
+                hash
+            };
+        }
+        db.insert_batch(batch);
+        self.root = hash;
+        Ok(())
+    }
+
+    // The siblings on the path of `index` from the root down, and the leaf
+    // hash.
+    fn path<S: NodeStore<V>>(&self, db: &S, index: &BigIndex) -> anyhow::Result<Path<V>> {
+        if index.height() != self.height {
+            return Err(DbTreeError::InvalidIndexLength {
+                expected: self.height,
+                actual: index.height(),
+            }
+            .into());
+        }
+        let mut siblings = Vec::with_capacity(self.height);
+        let mut hash = self.root.clone();
+        for depth in 0..self.height {
+            if hash == self.zero_hashes[depth] {
+                // the rest of the path is inside an empty subtree
+                siblings.extend_from_slice(&self.zero_hashes[depth + 1..]);
+                return Ok((siblings, self.zero_hashes[self.height].clone()));
+            }
+            let bit = index.bit(self.height - depth - 1);
+            let (child, sibling) = db
+                .try_with_node(hash, |node| (node.child(bit), node.child(!bit)))
+                .map_err(|_| anyhow::anyhow!("node at depth {} in the store is corrupted", depth))?
+                .ok_or_else(|| anyhow::anyhow!("cannot find node at depth {}", depth))?;
+            siblings.push(sibling);
+            hash = child;
+        }
+        Ok((siblings, hash))
+    }
+}
+
+impl<V: Leafable> MerkleProof<V> {
+    // `get_root_from_hash` for a `BigIndex`. Panics if `index` is not for a
+    // tree of the proof's height.
+    pub fn get_root_from_big_index(
+        &self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: &BigIndex,
+    ) -> <V::Hasher as TreeHasher>::HashOut {
+        assert_eq!(
+            index.height(),
+            self.height(),
+            "index height does not match the proof height"
+        );
+        let mut state = leaf_hash;
+        for (i, sibling) in self.siblings.iter().enumerate() {
+            state = if index.bit(i) {
+                <V::Hasher as TreeHasher>::two_to_one(sibling.clone(), state)
+            } else {
+                <V::Hasher as TreeHasher>::two_to_one(state, sibling.clone())
+            }
+        }
+        state
+    }
+
+    // `verify_hash` for a `BigIndex`.
+    pub fn verify_big_index(
+        &self,
+        leaf_hash: <V::Hasher as TreeHasher>::HashOut,
+        index: &BigIndex,
+        merkle_root: <V::Hasher as TreeHasher>::HashOut,
+    ) -> Result<(), VerifyError<<V::Hasher as TreeHasher>::HashOut>> {
+        if index.height() != self.height() {
+            return Err(VerifyError::InvalidIndexLength {
+                expected: self.height(),
+                actual: index.height(),
+            });
+        }
+        let computed_root = self.get_root_from_big_index(leaf_hash.clone(), index);
+        if computed_root != merkle_root {
+            return Err(VerifyError::RootMismatch {
+                leaf_hash,
+                index: index.low_u128(),
+                computed_root,
+                expected_root: merkle_root,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use crate::{
+        big_index::BigIndex, error::DbTreeError, leaf_index::LeafIndex, merkle_tree::MerkleTree,
+        mock_db::MockDB, traits::Leafable,
+    };
+
+    use super::WideTree;
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_wide_tree() {
+        let mut db = MockDB::<Leaf>::new();
+        let mut tree = WideTree::<Leaf>::new(&mut db, 256, Leaf::empty_leaf().hash());
+        let key = |first: u8, last: u8| {
+            let mut key = [0u8; 32];
+            key[0] = first;
+            key[31] = last;
+            BigIndex::from_be_bytes(key, 256).unwrap()
+        };
+        // two keys that differ in the last bit only
+        let keys = [key(0xff, 0), key(0xff, 1), key(0x01, 0x80)];
+        for (i, index) in keys.iter().enumerate() {
+            tree.update_leaf(&mut db, index, (i as u32 + 1).hash())
+                .unwrap();
+        }
+        let root = tree.get_root();
+        for (i, index) in keys.iter().enumerate() {
+            let leaf_hash = (i as u32 + 1).hash();
+            assert_eq!(tree.get_leaf_hash(&db, index).unwrap(), leaf_hash);
+            let proof = tree.prove(&db, index).unwrap();
+            assert_eq!(proof.height(), 256);
+            assert!(proof.verify_big_index(leaf_hash, index, root).is_ok());
+            assert!(proof.verify_big_index(9u32.hash(), index, root).is_err());
+        }
+        let empty = key(0x02, 0);
+        assert_eq!(
+            tree.get_leaf_hash(&db, &empty).unwrap(),
+            Leaf::empty_leaf().hash()
+        );
+
+        // reopened from its root, and cleared back to the empty root
+        let mut reopened = WideTree::<Leaf>::from_root(256, Leaf::empty_leaf().hash(), Some(root));
+        for index in &keys {
+            reopened
+                .update_leaf(&mut db, index, Leaf::empty_leaf().hash())
+                .unwrap();
+        }
+        assert_eq!(
+            reopened.get_root(),
+            WideTree::<Leaf>::from_root(256, Leaf::empty_leaf().hash(), None).get_root()
+        );
+
+        // below 128 levels it is the same tree as a `MerkleTree`
+        let height = 20;
+        let mut wide = WideTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        let mut narrow = MerkleTree::<Leaf>::new(&mut db, height, Leaf::empty_leaf().hash());
+        for i in [0u128, 3, 77_777] {
+            let index = LeafIndex::new(i, height).unwrap();
+            wide.update_leaf(&mut db, &index.into(), (i as u32).hash())
+                .unwrap();
+            narrow
+                .update_leaf(&mut db, index, (i as u32).hash())
+                .unwrap();
+        }
+        assert_eq!(wide.get_root(), narrow.get_root());
+        let index = LeafIndex::new(3, height).unwrap();
+        assert_eq!(
+            wide.prove(&db, &index.into()).unwrap(),
+            narrow.prove(index).unwrap()
+        );
+
+        assert_eq!(
+            WideTree::<Leaf>::try_from_root(257, Leaf::empty_leaf().hash(), None).unwrap_err(),
+            DbTreeError::HeightTooLarge {
+                height: 257,
+                max: 256
+            }
+        );
+    }
+}
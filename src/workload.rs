@@ -0,0 +1,318 @@
+use hashbrown::HashSet;
+
+use crate::{
+    error::DbTreeError,
+    leaf_index::LeafIndex,
+    merkle_tree::MerkleTree,
+    node_store::NodeStore,
+    traits::{Leafable, TreeHasher},
+};
+
+// Where inserts put new leaves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Distribution {
+    // at 0, 1, 2, ..., the densest layout
+    Sequential,
+    // anywhere in the tree, the sparsest layout
+    Uniform,
+    // within `clusters` runs of `cluster_size` leaves at random offsets
+    Clustered { clusters: u32, cluster_size: u32 },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WorkloadConfig {
+    pub seed: u64,
+    pub height: usize,
+    pub distribution: Distribution,
+    // relative frequencies of the operations
+    pub insert_weight: u32,
+    pub update_weight: u32,
+    pub prove_weight: u32,
+}
+
+impl Default for WorkloadConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            height: 32,
+            distribution: Distribution::Uniform,
+            insert_weight: 4,
+            update_weight: 4,
+            prove_weight: 2,
+        }
+    }
+}
+
+// `value` is turned into a leaf hash by whoever applies the operation, so
+// one workload drives trees of any hasher.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WorkloadOp {
+    // sets a leaf the workload never set before
+    Insert { index: LeafIndex, value: u64 },
+    // overwrites a leaf set before
+    Update { index: LeafIndex, value: u64 },
+    // proves a leaf set before
+    Prove { index: LeafIndex },
+}
+
+impl WorkloadOp {
+    pub fn index(&self) -> LeafIndex {
+        match self {
+            WorkloadOp::Insert { index, .. }
+            | WorkloadOp::Update { index, .. }
+            | WorkloadOp::Prove { index } => *index,
+        }
+    }
+
+    // Applies the operation to `tree`, making leaf hashes with `leaf_hash`.
+    // Proofs are built and dropped.
+    pub fn apply<V: Leafable, S: NodeStore<V>>(
+        &self,
+        tree: &mut MerkleTree<V>,
+        db: &mut S,
+        leaf_hash: impl Fn(u64) -> <V::Hasher as TreeHasher>::HashOut,
+    ) -> Result<(), DbTreeError> {
+        match *self {
+            WorkloadOp::Insert { index, value } | WorkloadOp::Update { index, value } => {
+                tree.update_leaf(db, index, leaf_hash(value))
+            }
+            WorkloadOp::Prove { index } => tree.prove(index).map(|_| ()),
+        }
+    }
+}
+
+// An endless, reproducible stream of operations: the stream depends only on
+// the config, not on the platform or on how it is consumed, so a config and
+// a count are enough to replay a failure. The first operation is always an
+// insert, and inserts into a full tree or full clusters become updates.
+#[derive(Clone, Debug)]
+pub struct Workload {
+    config: WorkloadConfig,
+    rng: SplitMix64,
+    // bases of the clusters, if any
+    clusters: Vec<u128>,
+    cluster_size: u128,
+    inserted: Vec<LeafIndex>,
+    occupied: HashSet<u128>,
+    capacity: u128,
+}
+
+impl Workload {
+    pub fn new(config: WorkloadConfig) -> Self {
+        assert!(config.height <= 128, "height must be at most 128");
+        assert!(
+            config.insert_weight + config.update_weight + config.prove_weight > 0,
+            "at least one operation must have a weight"
+        );
+        let mut rng = SplitMix64(config.seed);
+        let mut clusters = vec![];
+        let mut size = 0;
+        let mut capacity = max_index(config.height).saturating_add(1);
+        if let Distribution::Clustered {
+            clusters: num_clusters,
+            cluster_size,
+        } = config.distribution
+        {
+            assert!(
+                num_clusters > 0 && cluster_size > 0,
+                "clusters must not be empty"
+            );
+            size = (cluster_size as u128).min(capacity);
+            let max_base = max_index(config.height) - (size - 1);
+            for _ in 0..num_clusters {
+                clusters.push(rng.up_to(max_base));
+            }
+            // overlapping clusters make this an upper bound
+            capacity = capacity.min(size.saturating_mul(num_clusters as u128));
+        }
+        Self {
+            config,
+            rng,
+            clusters,
+            cluster_size: size,
+            inserted: vec![],
+            occupied: HashSet::new(),
+            capacity,
+        }
+    }
+
+    pub fn config(&self) -> &WorkloadConfig {
+        &self.config
+    }
+
+    // Leaves set so far, in insertion order.
+    pub fn inserted(&self) -> &[LeafIndex] {
+        &self.inserted
+    }
+
+    fn index(&self, index: u128) -> LeafIndex {
+        LeafIndex::new(index, self.config.height).unwrap()
+    }
+
+    // A position that was not inserted yet, or None if none was found.
+    fn new_position(&mut self) -> Option<u128> {
+        if self.inserted.len() as u128 >= self.capacity {
+            return None;
+        }
+        // retries are rare unless the tree or the clusters are nearly full
+        for _ in 0..64 {
+            let position = match self.config.distribution {
+                Distribution::Sequential => self.inserted.len() as u128,
+                Distribution::Uniform => self.rng.up_to(max_index(self.config.height)),
+                Distribution::Clustered { .. } => {
+                    let cluster = self.rng.up_to(self.clusters.len() as u128 - 1) as usize;
+                    self.clusters[cluster] + self.rng.up_to(self.cluster_size - 1)
+                }
+            };
+            if self.occupied.insert(position) {
+                return Some(position);
+            }
+        }
+        None
+    }
+
+    fn existing(&mut self) -> LeafIndex {
+        let i = self.rng.up_to(self.inserted.len() as u128 - 1) as usize;
+        self.inserted[i]
+    }
+}
+
+impl Iterator for Workload {
+    type Item = WorkloadOp;
+
+    fn next(&mut self) -> Option<WorkloadOp> {
+        let config = &self.config;
+        let total = config.insert_weight + config.update_weight + config.prove_weight;
+        let roll = self.rng.up_to(total as u128 - 1) as u32;
+        let prove = roll >= config.insert_weight + config.update_weight;
+        if roll < config.insert_weight || self.inserted.is_empty() {
+            // never fails on an empty workload
+            if let Some(position) = self.new_position() {
+                let index = self.index(position);
+                self.inserted.push(index);
+                let value = self.rng.next_u64();
+                return Some(WorkloadOp::Insert { index, value });
+            }
+        }
+        let index = self.existing();
+        if prove {
+            Some(WorkloadOp::Prove { index })
+        } else {
+            let value = self.rng.next_u64();
+            Some(WorkloadOp::Update { index, value })
+        }
+    }
+}
+
+// Largest leaf index of a tree of `height`.
+fn max_index(height: usize) -> u128 {
+    if height == 128 {
+        u128::MAX
+    } else {
+        (1u128 << height) - 1
+    }
+}
+
+// SplitMix64, chosen because it is tiny and its output is fixed by its
+// definition, so streams stay the same across versions of this crate.
+#[derive(Clone, Debug)]
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in 0..=max, by rejection so that every value is equally likely.
+    fn up_to(&mut self, max: u128) -> u128 {
+        if max == u128::MAX {
+            return (self.next_u64() as u128) << 64 | self.next_u64() as u128;
+        }
+        let bound = max + 1;
+        let zone = u128::MAX - (u128::MAX - bound + 1) % bound;
+        loop {
+            let x = (self.next_u64() as u128) << 64 | self.next_u64() as u128;
+            if x <= zone {
+                return x % bound;
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use hashbrown::HashSet;
+
+    use crate::{merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable};
+
+    use super::{Distribution, Workload, WorkloadConfig, WorkloadOp};
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_workload() {
+        let config = WorkloadConfig {
+            seed: 42,
+            height: 10,
+            ..Default::default()
+        };
+        let ops: Vec<_> = Workload::new(config.clone()).take(200).collect();
+        assert_eq!(
+            ops,
+            Workload::new(config.clone()).take(200).collect::<Vec<_>>()
+        );
+        let other = WorkloadConfig { seed: 43, ..config };
+        assert_ne!(ops, Workload::new(other).take(200).collect::<Vec<_>>());
+
+        // inserts are new leaves, updates and proofs are of inserted leaves
+        let mut inserted = HashSet::new();
+        for op in &ops {
+            match op {
+                WorkloadOp::Insert { index, .. } => assert!(inserted.insert(*index)),
+                _ => assert!(inserted.contains(&op.index())),
+            }
+        }
+
+        let clustered = WorkloadConfig {
+            seed: 1,
+            height: 20,
+            distribution: Distribution::Clustered {
+                clusters: 2,
+                cluster_size: 4,
+            },
+            insert_weight: 1,
+            update_weight: 0,
+            prove_weight: 0,
+        };
+        let mut workload = Workload::new(clustered);
+        let ops: Vec<_> = workload.by_ref().take(20).collect();
+        let inserts = ops
+            .iter()
+            .filter(|op| matches!(op, WorkloadOp::Insert { .. }))
+            .count();
+        assert!(inserts <= 8 && inserts == workload.inserted().len());
+
+        let sequential = WorkloadConfig {
+            height: 8,
+            distribution: Distribution::Sequential,
+            ..Default::default()
+        };
+        let mut workload = Workload::new(sequential);
+        let mut db = MockDB::<Leaf>::new();
+        let mut tree = MerkleTree::<Leaf>::new(&mut db, 8, Leaf::empty_leaf().hash());
+        for op in workload.by_ref().take(100) {
+            op.apply(&mut tree, &mut db, |value| (value as u32).hash())
+                .unwrap();
+        }
+        let inserted = workload.inserted();
+        assert!(inserted
+            .iter()
+            .enumerate()
+            .all(|(i, index)| index.index() == i as u128));
+        assert_eq!(tree.len(), inserted.len() as u128);
+    }
+}
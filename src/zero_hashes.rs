@@ -0,0 +1,153 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{error::DbTreeError, traits::TreeHasher};
+
+// Roots of empty subtrees for one hasher and empty leaf hash. The table is
+// behind an `Arc`, so trees (and threads) built from the same `ZeroHashes`
+// share it instead of rehashing `height` times each.
+#[derive(Clone, Debug)]
+pub struct ZeroHashes<H: TreeHasher> {
+    // hashes = [H(zero_leaf), H(H(zero_leaf), H(zero_leaf)), ...], i.e.
+    // hashes[i] is the root of an empty subtree of height i
+    hashes: Arc<Vec<H::HashOut>>,
+}
+
+impl<H: TreeHasher> ZeroHashes<H> {
+    pub fn new(empty_leaf_hash: H::HashOut, max_height: usize) -> Self {
+        let mut hashes = Vec::with_capacity(max_height + 1);
+        hashes.push(empty_leaf_hash);
+        extend::<H>(&mut hashes, max_height);
+        Self {
+            hashes: Arc::new(hashes),
+        }
+    }
+
+    // Uses `hashes[i]` as the root of an empty subtree of height i, e.g. a
+    // table published with another implementation. Every level must be the
+    // hash of two of the level below, as the zero nodes are stored like any
+    // other node and verifiers recompute them from the empty leaf. Fails on
+    // an empty table.
+    pub fn from_levels(hashes: Vec<H::HashOut>) -> Result<Self, DbTreeError> {
+        if hashes.is_empty() {
+            return Err(DbTreeError::InvalidZeroHashCount {
+                expected: 1,
+                actual: 0,
+            });
+        }
+        for (height, pair) in hashes.windows(2).enumerate() {
+            if pair[1] != H::two_to_one(pair[0].clone(), pair[0].clone()) {
+                return Err(DbTreeError::InconsistentZeroHash { height: height + 1 });
+            }
+        }
+        Ok(Self {
+            hashes: Arc::new(hashes),
+        })
+    }
+
+    pub fn empty_leaf_hash(&self) -> H::HashOut {
+        self.hashes[0].clone()
+    }
+
+    pub fn max_height(&self) -> usize {
+        self.hashes.len() - 1
+    }
+
+    // root of an empty subtree of `height`
+    pub fn get(&self, height: usize) -> H::HashOut {
+        self.hashes[height].clone()
+    }
+
+    // zero hashes of a tree of `height` indexed by depth, as stored in
+    // `MerkleTree`
+    pub fn by_depth(&self, height: usize) -> Vec<H::HashOut> {
+        assert!(height <= self.max_height());
+        self.hashes[..=height].iter().rev().cloned().collect()
+    }
+
+    fn extended(&self, max_height: usize) -> Self {
+        let mut hashes = self.hashes.as_ref().clone();
+        extend::<H>(&mut hashes, max_height);
+        Self {
+            hashes: Arc::new(hashes),
+        }
+    }
+}
+
+fn extend<H: TreeHasher>(hashes: &mut Vec<H::HashOut>, max_height: usize) {
+    while hashes.len() <= max_height {
+        let h = hashes.last().unwrap().clone();
+        hashes.push(H::two_to_one(h.clone(), h));
+    }
+}
+
+// Cache of `ZeroHashes` keyed by empty leaf hash. Requesting a taller table
+// than the cached one extends it from the cached top.
+#[derive(Clone, Debug)]
+pub struct ZeroHashCache<H: TreeHasher> {
+    tables: HashMap<H::HashOut, ZeroHashes<H>>,
+}
+
+impl<H: TreeHasher> ZeroHashCache<H> {
+    pub fn new() -> Self {
+        Self {
+            tables: HashMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, empty_leaf_hash: H::HashOut, height: usize) -> ZeroHashes<H> {
+        let table = match self.tables.get(&empty_leaf_hash) {
+            Some(table) if table.max_height() >= height => return table.clone(),
+            Some(table) => table.extended(height),
+            None => ZeroHashes::new(empty_leaf_hash.clone(), height),
+        };
+        self.tables.insert(empty_leaf_hash, table.clone());
+        table
+    }
+}
+
+impl<H: TreeHasher> Default for ZeroHashCache<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "zkp"))]
+mod test {
+    use intmax2_zkp::utils::{
+        leafable_hasher::PoseidonLeafableHasher, poseidon_hash_out::PoseidonHashOut,
+    };
+
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable,
+    };
+
+    use super::{ZeroHashCache, ZeroHashes};
+
+    type Leaf = u32;
+
+    #[test]
+    fn test_zero_hash_cache() {
+        let empty_leaf_hash = PoseidonHashOut::hash_inputs_u32(&[]);
+        let mut cache = ZeroHashCache::<PoseidonLeafableHasher>::new();
+        let small = cache.get(empty_leaf_hash, 8);
+        let large = cache.get(empty_leaf_hash, 32);
+        assert_eq!(large.max_height(), 32);
+        assert_eq!(large.by_depth(8), small.by_depth(8));
+        assert_eq!(
+            large.by_depth(32),
+            ZeroHashes::<PoseidonLeafableHasher>::new(empty_leaf_hash, 32).by_depth(32)
+        );
+
+        let mut mock_db = MockDB::<Leaf>::new();
+        let mut tree1 = MerkleTree::with_zero_hashes(&mut mock_db, 32, &large);
+        let mut tree2 = MerkleTree::new(&mut mock_db, 32, empty_leaf_hash);
+        assert_eq!(mock_db.len(), 32);
+        tree1
+            .update_leaf(&mut mock_db, LeafIndex::new(5, 32).unwrap(), 5u32.hash())
+            .unwrap();
+        tree2
+            .update_leaf(&mut mock_db, LeafIndex::new(5, 32).unwrap(), 5u32.hash())
+            .unwrap();
+        assert_eq!(tree1.get_root(), tree2.get_root());
+    }
+}
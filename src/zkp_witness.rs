@@ -0,0 +1,179 @@
+use intmax2_zkp::utils::{
+    leafable::Leafable as ZkpLeafable,
+    leafable_hasher::PoseidonLeafableHasher,
+    poseidon_hash_out::{PoseidonHashOut, PoseidonHashOutTarget},
+    trees::merkle_tree::MerkleProof as ZkpMerkleProof,
+};
+use plonky2::{
+    field::types::Field,
+    iop::{target::Target, witness::WitnessWrite},
+};
+
+use crate::{
+    error::DbTreeError,
+    leaf_index::LeafIndex,
+    merkle_tree::{MerkleProof, MerkleTree},
+    node_store::NodeStore,
+};
+
+// A proof laid out the way intmax2_zkp's in-circuit Merkle verification
+// takes it: siblings from the leaf level up, as in intmax2_zkp's own
+// `MerkleProof`, and the index as a single target that the circuit splits
+// into little endian bits.
+#[derive(Clone, Debug)]
+pub struct MerkleWitness<V: ZkpLeafable<LeafableHasher = PoseidonLeafableHasher>> {
+    pub leaf_hash: PoseidonHashOut,
+    pub index: u64,
+    pub root: PoseidonHashOut,
+    pub proof: ZkpMerkleProof<V>,
+}
+
+impl<V: ZkpLeafable<LeafableHasher = PoseidonLeafableHasher>> MerkleWitness<V> {
+    pub fn height(&self) -> usize {
+        self.proof.siblings.len()
+    }
+
+    // The bits the circuit gets from splitting the index target, for circuits
+    // that take the path as bool targets instead.
+    pub fn index_bits(&self) -> Vec<bool> {
+        (0..self.height())
+            .map(|i| (self.index >> i) & 1 == 1)
+            .collect()
+    }
+
+    // Assigns the siblings, the index and the root. `siblings` are the
+    // sibling targets of a proof target of the same height, from the leaf
+    // level up; the leaf target is left to the caller since it is usually
+    // the leaf data rather than its hash.
+    pub fn set_witness<F: Field, W: WitnessWrite<F>>(
+        &self,
+        witness: &mut W,
+        siblings: &[PoseidonHashOutTarget],
+        index: Target,
+        root: PoseidonHashOutTarget,
+    ) {
+        assert_eq!(
+            siblings.len(),
+            self.height(),
+            "sibling targets do not match the proof height"
+        );
+        for (target, sibling) in siblings.iter().zip(&self.proof.siblings) {
+            target.set_witness(witness, *sibling);
+        }
+        witness.set_target(index, F::from_canonical_u64(self.index));
+        root.set_witness(witness, self.root);
+    }
+}
+
+impl<V: ZkpLeafable<LeafableHasher = PoseidonLeafableHasher>> MerkleProof<V> {
+    // Panics if `index` is not for a tree of the proof's height, or if the
+    // tree is 64 levels high, as the index has to fit in one Goldilocks
+    // element.
+    pub fn to_zkp_witness(
+        &self,
+        leaf_hash: PoseidonHashOut,
+        index: impl Into<LeafIndex>,
+        root: PoseidonHashOut,
+    ) -> MerkleWitness<V> {
+        let index = index.into();
+        assert_eq!(
+            index.height(),
+            self.height(),
+            "index height does not match the proof height"
+        );
+        assert!(
+            index.height() < 64,
+            "the index of a tree of height {} does not fit in a field element",
+            index.height()
+        );
+        MerkleWitness {
+            leaf_hash,
+            index: index.index() as u64,
+            root,
+            proof: ZkpMerkleProof::from_siblings(self.siblings.clone()),
+        }
+    }
+}
+
+impl<V: ZkpLeafable<LeafableHasher = PoseidonLeafableHasher>> MerkleTree<V> {
+    // `to_zkp_witness` of the current leaf at `index` against the current
+    // root.
+    pub fn prove_zkp_witness<S: NodeStore<V>>(
+        &self,
+        db: &S,
+        index: impl Into<LeafIndex>,
+    ) -> Result<MerkleWitness<V>, DbTreeError> {
+        let index = index.into();
+        let proof = self.prove(index)?;
+        let leaf_hash = self.get_node_hash_with_store(db, index.to_node_key())?;
+        Ok(proof.to_zkp_witness(leaf_hash, index, self.get_root()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use intmax2_zkp::utils::poseidon_hash_out::PoseidonHashOutTarget;
+    use plonky2::{
+        field::{
+            goldilocks_field::GoldilocksField,
+            types::{Field, PrimeField64},
+        },
+        iop::{
+            target::Target,
+            witness::{PartialWitness, Witness},
+        },
+    };
+
+    use crate::{
+        leaf_index::LeafIndex, merkle_tree::MerkleTree, mock_db::MockDB, traits::Leafable,
+    };
+
+    type Leaf = u32;
+    type F = GoldilocksField;
+
+    #[test]
+    fn test_zkp_witness() {
+        let height = 8;
+        let mut db = MockDB::<Leaf>::new();
+        let mut tree = MerkleTree::new(&mut db, height, Leaf::empty_leaf().hash());
+        for i in 0..10 {
+            tree.update_leaf_at(&mut db, i, (i as u32 + 1).hash())
+                .unwrap();
+        }
+        let index = LeafIndex::new(6, height).unwrap();
+        let witness = tree.prove_zkp_witness(&db, index).unwrap();
+        assert_eq!(witness.leaf_hash, 7u32.hash());
+        assert_eq!(witness.index_bits(), index.to_le_bits());
+        assert_eq!(witness.proof.siblings, tree.prove(index).unwrap().siblings);
+
+        let mut next = 0;
+        let mut hash_target = || {
+            let elements = std::array::from_fn(|_| {
+                next += 1;
+                Target::VirtualTarget { index: next }
+            });
+            PoseidonHashOutTarget { elements }
+        };
+        let siblings: Vec<_> = (0..height).map(|_| hash_target()).collect();
+        let root = hash_target();
+        let index_target = Target::VirtualTarget { index: 0 };
+        let mut pw = PartialWitness::<F>::new();
+        witness.set_witness(&mut pw, &siblings, index_target, root);
+
+        let read = |target: &PoseidonHashOutTarget| -> Vec<u64> {
+            target
+                .elements
+                .iter()
+                .map(|&t| pw.try_get_target(t).unwrap().to_canonical_u64())
+                .collect()
+        };
+        assert_eq!(
+            pw.try_get_target(index_target),
+            Some(F::from_canonical_u64(6))
+        );
+        assert_eq!(read(&root), tree.get_root().to_u64_vec());
+        for (target, sibling) in siblings.iter().zip(&witness.proof.siblings) {
+            assert_eq!(read(target), sibling.to_u64_vec());
+        }
+    }
+}